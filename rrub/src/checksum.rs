@@ -0,0 +1,322 @@
+/*
+ * Table driven reflected CRC32 and CRC64 variants, and the unreflected CRC32 bzip2 uses.
+ * https://reveng.sourceforge.io/crc-catalogue/17plus.htm#crc.cat-bits.32
+ * https://reveng.sourceforge.io/crc-catalogue/17plus.htm#crc.cat-bits.64
+*/
+
+const CRC32_POLY: u32 = 0xEDB8_8320;
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+const CRC64_XZ_POLY: u64 = 0xC96C_5795_D787_0F42;
+/// The CRC32 polynomial unreflected, bits taken most significant first.
+const CRC32_BZIP2_POLY: u32 = 0x04C1_1DB7;
+
+static CRC32_TABLE: [u32; 256] = crc_table(CRC32_POLY);
+static CRC32_BZIP2_TABLE: [u32; 256] = crc_table_msb(CRC32_BZIP2_POLY);
+static CRC32C_TABLE: [u32; 256] = crc_table(CRC32C_POLY);
+static CRC64_XZ_TABLE: [u64; 256] = crc64_table(CRC64_XZ_POLY);
+
+const fn crc_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ poly,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    return table;
+}
+
+const fn crc_table_msb(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc >> 31 {
+                1 => (crc << 1) ^ poly,
+                _ => crc << 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    return table;
+}
+
+const fn crc64_table(poly: u64) -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ poly,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    return table;
+}
+
+fn update(table: &[u32; 256], mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    return crc;
+}
+
+/// Raw CRC32 (ISO-HDLC) update without the initial and final inversion.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    return update(&CRC32_TABLE, crc, data);
+}
+
+/// CRC32 as used by gzip, zip and xz.
+pub fn crc32(data: &[u8]) -> u32 {
+    return !crc32_update(!0, data);
+}
+
+/// Raw CRC32C (Castagnoli) update without the initial and final inversion, filesystems like
+/// ext4 chain these with their own seeds.
+pub fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    return update(&CRC32C_TABLE, crc, data);
+}
+
+pub fn crc32c(data: &[u8]) -> u32 {
+    return !crc32c_update(!0, data);
+}
+
+/// CRC32 as bzip2 checks blocks with, which takes bits most significant first.
+pub fn crc32_bzip2(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = CRC32_BZIP2_TABLE[((crc >> 24) ^ byte as u32) as usize] ^ (crc << 8);
+    }
+    return !crc;
+}
+
+/// CRC64 as used by xz.
+pub fn crc64_xz(data: &[u8]) -> u64 {
+    let mut crc = !0u64;
+    for &byte in data {
+        crc = CRC64_XZ_TABLE[((crc ^ byte as u64) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    return !crc;
+}
+
+/// Adler-32 as used by zlib streams.
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // 5552 is the largest run that can't overflow the 32 bit sums before reducing them.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    return (b << 16) | a;
+}
+
+const XXH32_PRIME1: u32 = 0x9E37_79B1;
+const XXH32_PRIME2: u32 = 0x85EB_CA77;
+const XXH32_PRIME3: u32 = 0xC2B2_AE3D;
+const XXH32_PRIME4: u32 = 0x27D4_EB2F;
+const XXH32_PRIME5: u32 = 0x1656_67B1;
+
+fn xxh32_round(acc: u32, lane: u32) -> u32 {
+    return acc
+        .wrapping_add(lane.wrapping_mul(XXH32_PRIME2))
+        .rotate_left(13)
+        .wrapping_mul(XXH32_PRIME1);
+}
+
+/// XXH32 as used by LZ4 frame checksums.
+/// https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md
+pub fn xxh32(data: &[u8], seed: u32) -> u32 {
+    let read32 = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let mut rest = data;
+
+    let mut hash = match data.len() >= 16 {
+        true => {
+            let mut acc = [
+                seed.wrapping_add(XXH32_PRIME1).wrapping_add(XXH32_PRIME2),
+                seed.wrapping_add(XXH32_PRIME2),
+                seed,
+                seed.wrapping_sub(XXH32_PRIME1),
+            ];
+
+            while rest.len() >= 16 {
+                for (i, acc) in acc.iter_mut().enumerate() {
+                    *acc = xxh32_round(*acc, read32(&rest[i * 4..]));
+                }
+                rest = &rest[16..];
+            }
+
+            acc[0]
+                .rotate_left(1)
+                .wrapping_add(acc[1].rotate_left(7))
+                .wrapping_add(acc[2].rotate_left(12))
+                .wrapping_add(acc[3].rotate_left(18))
+        }
+        false => seed.wrapping_add(XXH32_PRIME5),
+    };
+
+    hash = hash.wrapping_add(data.len() as u32);
+
+    while rest.len() >= 4 {
+        hash = hash
+            .wrapping_add(read32(rest).wrapping_mul(XXH32_PRIME3))
+            .rotate_left(17)
+            .wrapping_mul(XXH32_PRIME4);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash = hash
+            .wrapping_add((byte as u32).wrapping_mul(XXH32_PRIME5))
+            .rotate_left(11)
+            .wrapping_mul(XXH32_PRIME1);
+    }
+
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(XXH32_PRIME2);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(XXH32_PRIME3);
+    hash ^= hash >> 16;
+
+    return hash;
+}
+
+const XXH64_PRIME1: u64 = 0x9E37_79B1_85EB_CA87;
+const XXH64_PRIME2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const XXH64_PRIME3: u64 = 0x1656_67B1_9E37_79F9;
+const XXH64_PRIME4: u64 = 0x85EB_CA77_C2B2_AE63;
+const XXH64_PRIME5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh64_round(acc: u64, lane: u64) -> u64 {
+    return acc
+        .wrapping_add(lane.wrapping_mul(XXH64_PRIME2))
+        .rotate_left(31)
+        .wrapping_mul(XXH64_PRIME1);
+}
+
+fn xxh64_merge(acc: u64, value: u64) -> u64 {
+    return (acc ^ xxh64_round(0, value))
+        .wrapping_mul(XXH64_PRIME1)
+        .wrapping_add(XXH64_PRIME4);
+}
+
+/// XXH64 as used by zstd frame checksums and btrfs.
+/// https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md
+pub fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let read64 = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let mut rest = data;
+
+    let mut hash = match data.len() >= 32 {
+        true => {
+            let mut acc = [
+                seed.wrapping_add(XXH64_PRIME1).wrapping_add(XXH64_PRIME2),
+                seed.wrapping_add(XXH64_PRIME2),
+                seed,
+                seed.wrapping_sub(XXH64_PRIME1),
+            ];
+
+            while rest.len() >= 32 {
+                for (i, acc) in acc.iter_mut().enumerate() {
+                    *acc = xxh64_round(*acc, read64(&rest[i * 8..]));
+                }
+                rest = &rest[32..];
+            }
+
+            let mut hash = acc[0]
+                .rotate_left(1)
+                .wrapping_add(acc[1].rotate_left(7))
+                .wrapping_add(acc[2].rotate_left(12))
+                .wrapping_add(acc[3].rotate_left(18));
+            for acc in acc {
+                hash = xxh64_merge(hash, acc);
+            }
+            hash
+        }
+        false => seed.wrapping_add(XXH64_PRIME5),
+    };
+
+    hash = hash.wrapping_add(data.len() as u64);
+
+    while rest.len() >= 8 {
+        hash ^= xxh64_round(0, read64(rest));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(XXH64_PRIME1)
+            .wrapping_add(XXH64_PRIME4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        hash ^=
+            (u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64).wrapping_mul(XXH64_PRIME1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(XXH64_PRIME2)
+            .wrapping_add(XXH64_PRIME3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash ^= (byte as u64).wrapping_mul(XXH64_PRIME5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH64_PRIME1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH64_PRIME2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH64_PRIME3);
+    hash ^= hash >> 32;
+
+    return hash;
+}
+
+/// Fletcher-4 as ZFS checksums blocks with, four running sums over little endian 32 bit words.
+pub fn fletcher4(data: &[u8]) -> [u64; 4] {
+    let mut sums = [0u64; 4];
+    for word in data.chunks_exact(4) {
+        sums[0] = sums[0].wrapping_add(u32::from_le_bytes(word.try_into().unwrap()) as u64);
+        sums[1] = sums[1].wrapping_add(sums[0]);
+        sums[2] = sums[2].wrapping_add(sums[1]);
+        sums[3] = sums[3].wrapping_add(sums[2]);
+    }
+    return sums;
+}
+
+/// Fletcher-2, the older ZFS data checksum, two pairs of sums over 64 bit words taken in turns.
+pub fn fletcher2(data: &[u8]) -> [u64; 4] {
+    let mut sums = [0u64; 4];
+    for pair in data.chunks_exact(16) {
+        sums[0] = sums[0].wrapping_add(u64::from_le_bytes(pair[..8].try_into().unwrap()));
+        sums[1] = sums[1].wrapping_add(u64::from_le_bytes(pair[8..].try_into().unwrap()));
+        sums[2] = sums[2].wrapping_add(sums[0]);
+        sums[3] = sums[3].wrapping_add(sums[1]);
+    }
+    return sums;
+}
@@ -0,0 +1,241 @@
+pub mod aes;
+pub mod argon2;
+pub mod blake2b;
+pub mod pbkdf2;
+pub mod sha1;
+pub mod sha2;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec};
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    ptr::write_volatile,
+    sync::atomic::{AtomicBool, Ordering, compiler_fence},
+};
+
+use zerocopy::FromZeros;
+
+use crate::crypto::{
+    pbkdf2::pbkdf2,
+    sha1::Sha1,
+    sha2::{Sha256, Sha512},
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CryptoError {
+    /// Cipher, hash or key derivation function that isn't implemented.
+    Unsupported,
+    InvalidParameters,
+    /// No key slot could be opened with the passphrase.
+    WrongPassphrase,
+    /// Keys were wiped for the handover, nothing can be decrypted any more.
+    KeysWiped,
+}
+
+pub trait Hash: Clone {
+    const BLOCK_SIZE: usize;
+    const OUTPUT_SIZE: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    /// Write the `OUTPUT_SIZE` byte digest to the start of `output`.
+    fn finalize(self, output: &mut [u8]);
+
+    fn digest(data: &[u8], output: &mut [u8]) {
+        let mut hash = Self::new();
+        hash.update(data);
+        hash.finalize(output);
+    }
+}
+
+/// Buffers input into whole blocks for the compression function of a Merkle–Damgård hash.
+#[derive(Clone)]
+pub struct BlockBuffer<const N: usize> {
+    buffer: [u8; N],
+    buffered: usize,
+    length: u128,
+}
+
+impl<const N: usize> BlockBuffer<N> {
+    pub fn new() -> Self {
+        return BlockBuffer {
+            buffer: [0u8; N],
+            buffered: 0,
+            length: 0,
+        };
+    }
+
+    pub fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8])) {
+        self.length += data.len() as u128;
+
+        if self.buffered > 0 {
+            let count = data.len().min(N - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&data[..count]);
+            self.buffered += count;
+            data = &data[count..];
+            if self.buffered < N {
+                return;
+            }
+            compress(&self.buffer);
+            self.buffered = 0;
+        }
+
+        let mut blocks = data.chunks_exact(N);
+        for block in &mut blocks {
+            compress(block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    /// Append the padding and the big endian bit length, which takes the last `length_size`
+    /// bytes of the final block.
+    pub fn pad(&mut self, length_size: usize, mut compress: impl FnMut(&[u8])) {
+        let bits = self.length * 8;
+        self.buffer[self.buffered] = 0x80;
+        self.buffer[self.buffered + 1..].fill(0);
+        if self.buffered + 1 > N - length_size {
+            compress(&self.buffer);
+            self.buffer.fill(0);
+        }
+        self.buffer[N - length_size..].copy_from_slice(&bits.to_be_bytes()[16 - length_size..]);
+        compress(&self.buffer);
+    }
+}
+
+/// Hash functions selectable by the name LUKS and cryptsetup use for them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub fn from_name(name: &str) -> Option<HashAlgorithm> {
+        return match name.to_ascii_lowercase().as_str() {
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "sha512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        };
+    }
+
+    pub fn output_size(self) -> usize {
+        return match self {
+            HashAlgorithm::Sha1 => Sha1::OUTPUT_SIZE,
+            HashAlgorithm::Sha256 => Sha256::OUTPUT_SIZE,
+            HashAlgorithm::Sha512 => Sha512::OUTPUT_SIZE,
+        };
+    }
+
+    /// Hash the concatenation of `parts`, writing `output_size` bytes to `output`.
+    pub fn digest(self, parts: &[&[u8]], output: &mut [u8]) {
+        fn run<H: Hash>(parts: &[&[u8]], output: &mut [u8]) {
+            let mut hash = H::new();
+            for part in parts {
+                hash.update(part);
+            }
+            hash.finalize(output);
+        }
+
+        match self {
+            HashAlgorithm::Sha1 => run::<Sha1>(parts, output),
+            HashAlgorithm::Sha256 => run::<Sha256>(parts, output),
+            HashAlgorithm::Sha512 => run::<Sha512>(parts, output),
+        }
+    }
+
+    pub fn pbkdf2(self, password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+        match self {
+            HashAlgorithm::Sha1 => pbkdf2::<Sha1>(password, salt, iterations, output),
+            HashAlgorithm::Sha256 => pbkdf2::<Sha256>(password, salt, iterations, output),
+            HashAlgorithm::Sha512 => pbkdf2::<Sha512>(password, salt, iterations, output),
+        }
+    }
+}
+
+/// Addresses and sizes of every live secret. rrub runs on a single thread without interrupt
+/// handlers touching it, so the map is never accessed concurrently.
+struct Keyring(UnsafeCell<BTreeMap<usize, usize>>);
+
+unsafe impl Sync for Keyring {}
+
+static KEYRING: Keyring = Keyring(UnsafeCell::new(BTreeMap::new()));
+static KEYS_WIPED: AtomicBool = AtomicBool::new(false);
+
+fn wipe(address: usize, size: usize) {
+    let start = address as *mut u8;
+    for offset in 0..size {
+        unsafe { write_volatile(start.add(offset), 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrite a buffer with zeros in a way the compiler can't optimise out, for secrets on the
+/// stack.
+pub fn zeroize(buffer: &mut [u8]) {
+    wipe(buffer.as_mut_ptr() as usize, buffer.len());
+}
+
+/// Heap allocated key material, zeroed when dropped and by `wipe_keys` even while it's still in
+/// use.
+pub struct Secret<T: ?Sized + FromZeros>(Box<T>);
+
+impl<T: ?Sized + FromZeros> Secret<T> {
+    fn register(mut value: Box<T>) -> Secret<T> {
+        let keyring = unsafe { &mut *KEYRING.0.get() };
+        keyring.insert(&raw mut *value as *mut u8 as usize, size_of_val(&*value));
+        return Secret(value);
+    }
+}
+
+impl<T: FromZeros> Secret<T> {
+    pub fn zeroed() -> Secret<T> {
+        return Secret::register(Box::new(T::new_zeroed()));
+    }
+}
+
+impl Secret<[u8]> {
+    pub fn bytes(length: usize) -> Secret<[u8]> {
+        return Secret::register(vec![0u8; length].into_boxed_slice());
+    }
+}
+
+impl<T: ?Sized + FromZeros> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return &self.0;
+    }
+}
+
+impl<T: ?Sized + FromZeros> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        return &mut self.0;
+    }
+}
+
+impl<T: ?Sized + FromZeros> Drop for Secret<T> {
+    fn drop(&mut self) {
+        let address = &raw mut *self.0 as *mut u8 as usize;
+        wipe(address, size_of_val(&*self.0));
+        let keyring = unsafe { &mut *KEYRING.0.get() };
+        keyring.remove(&address);
+    }
+}
+
+/// Zero every key, passphrase and derived secret still in memory. Devices that depend on them
+/// fail all further reads.
+pub fn wipe_keys() {
+    KEYS_WIPED.store(true, Ordering::SeqCst);
+    let keyring = unsafe { &*KEYRING.0.get() };
+    for (&address, &size) in keyring {
+        wipe(address, size);
+    }
+}
+
+pub fn keys_wiped() -> bool {
+    return KEYS_WIPED.load(Ordering::SeqCst);
+}
@@ -0,0 +1,267 @@
+/*
+ * https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197-upd1.pdf
+ * https://doi.org/10.6028/NIST.SP.800-38E
+*/
+
+use zerocopy::FromZeros;
+
+use crate::crypto::CryptoError;
+
+const MAX_ROUNDS: usize = 14;
+const ROUND_KEY_WORDS: usize = 4 * (MAX_ROUNDS + 1);
+
+static SBOX: [u8; 256] = sbox();
+static INVERSE_SBOX: [u8; 256] = inverse_sbox(&sbox());
+/// Round tables combining SubBytes, ShiftRows and MixColumns for one byte position, the other
+/// positions are rotations of it.
+static ENCRYPT_TABLE: [u32; 256] = encrypt_table(&sbox());
+static DECRYPT_TABLE: [u32; 256] = decrypt_table(&inverse_sbox(&sbox()));
+
+const fn multiply(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1B } else { 0 };
+        b >>= 1;
+    }
+    return product;
+}
+
+const fn sbox() -> [u8; 256] {
+    let mut sbox = [0u8; 256];
+    sbox[0] = 0x63;
+
+    // p walks through every non zero element by multiplying by 3, while q divides by 3 and so
+    // stays the multiplicative inverse of p.
+    let mut p = 1u8;
+    let mut q = 1u8;
+    loop {
+        p = p ^ (p << 1) ^ if p & 0x80 != 0 { 0x1B } else { 0 };
+        q ^= q << 1;
+        q ^= q << 2;
+        q ^= q << 4;
+        if q & 0x80 != 0 {
+            q ^= 0x09;
+        }
+        sbox[p as usize] =
+            q ^ q.rotate_left(1) ^ q.rotate_left(2) ^ q.rotate_left(3) ^ q.rotate_left(4) ^ 0x63;
+        if p == 1 {
+            break;
+        }
+    }
+    return sbox;
+}
+
+const fn inverse_sbox(sbox: &[u8; 256]) -> [u8; 256] {
+    let mut inverse = [0u8; 256];
+    let mut x = 0;
+    while x < 256 {
+        inverse[sbox[x] as usize] = x as u8;
+        x += 1;
+    }
+    return inverse;
+}
+
+const fn encrypt_table(sbox: &[u8; 256]) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut x = 0;
+    while x < 256 {
+        let s = sbox[x];
+        table[x] = u32::from_le_bytes([multiply(s, 2), s, s, multiply(s, 3)]);
+        x += 1;
+    }
+    return table;
+}
+
+const fn decrypt_table(inverse_sbox: &[u8; 256]) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut x = 0;
+    while x < 256 {
+        let s = inverse_sbox[x];
+        table[x] = u32::from_le_bytes([
+            multiply(s, 14),
+            multiply(s, 9),
+            multiply(s, 13),
+            multiply(s, 11),
+        ]);
+        x += 1;
+    }
+    return table;
+}
+
+/// InvMixColumns of a single column, for the round keys of the equivalent inverse cipher.
+fn inverse_mix_column(word: u32) -> u32 {
+    let [a, b, c, d] = word.to_le_bytes();
+    return u32::from_le_bytes([
+        multiply(a, 14) ^ multiply(b, 11) ^ multiply(c, 13) ^ multiply(d, 9),
+        multiply(a, 9) ^ multiply(b, 14) ^ multiply(c, 11) ^ multiply(d, 13),
+        multiply(a, 13) ^ multiply(b, 9) ^ multiply(c, 14) ^ multiply(d, 11),
+        multiply(a, 11) ^ multiply(b, 13) ^ multiply(c, 9) ^ multiply(d, 14),
+    ]);
+}
+
+fn sub_word(word: u32) -> u32 {
+    return u32::from_le_bytes(word.to_le_bytes().map(|byte| SBOX[byte as usize]));
+}
+
+/// AES with 128, 192 or 256 bit keys. Columns are kept as little endian words, so byte 0 of the
+/// state is the low byte of the first word.
+#[derive(FromZeros)]
+pub struct Aes {
+    rounds: u32,
+    encrypt_keys: [u32; ROUND_KEY_WORDS],
+    decrypt_keys: [u32; ROUND_KEY_WORDS],
+}
+
+impl Aes {
+    pub fn set_key(&mut self, key: &[u8]) -> Result<(), CryptoError> {
+        let rounds = match key.len() {
+            16 => 10,
+            24 => 12,
+            32 => 14,
+            _ => return Err(CryptoError::InvalidParameters),
+        };
+        let key_words = key.len() / 4;
+        let words = 4 * (rounds + 1);
+
+        for (word, bytes) in self.encrypt_keys.iter_mut().zip(key.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        let mut round_constant = 1u8;
+        for i in key_words..words {
+            let mut word = self.encrypt_keys[i - 1];
+            if i % key_words == 0 {
+                word = sub_word(word.rotate_right(8)) ^ round_constant as u32;
+                round_constant = multiply(round_constant, 2);
+            } else if key_words > 6 && i % key_words == 4 {
+                word = sub_word(word);
+            }
+            self.encrypt_keys[i] = self.encrypt_keys[i - key_words] ^ word;
+        }
+
+        // Round keys in reverse order, with InvMixColumns applied to all but the first and last.
+        for round in 0..=rounds {
+            for column in 0..4 {
+                let word = self.encrypt_keys[(rounds - round) * 4 + column];
+                self.decrypt_keys[round * 4 + column] = match round == 0 || round == rounds {
+                    true => word,
+                    false => inverse_mix_column(word),
+                };
+            }
+        }
+
+        self.rounds = rounds as u32;
+        return Ok(());
+    }
+
+    fn load(block: &[u8], keys: &[u32]) -> [u32; 4] {
+        return core::array::from_fn(|column| {
+            u32::from_le_bytes(block[column * 4..column * 4 + 4].try_into().unwrap()) ^ keys[column]
+        });
+    }
+
+    fn store(block: &mut [u8], state: [u32; 4]) {
+        for (bytes, word) in block.chunks_exact_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        let keys = &self.encrypt_keys;
+        let mut state = Self::load(block, keys);
+        let byte = |word: u32, index: u32| (word >> (8 * index)) as usize & 0xFF;
+
+        for round in 1..self.rounds as usize {
+            state = core::array::from_fn(|column| {
+                ENCRYPT_TABLE[byte(state[column], 0)]
+                    ^ ENCRYPT_TABLE[byte(state[(column + 1) % 4], 1)].rotate_left(8)
+                    ^ ENCRYPT_TABLE[byte(state[(column + 2) % 4], 2)].rotate_left(16)
+                    ^ ENCRYPT_TABLE[byte(state[(column + 3) % 4], 3)].rotate_left(24)
+                    ^ keys[round * 4 + column]
+            });
+        }
+
+        let last = self.rounds as usize * 4;
+        let state: [u32; 4] = core::array::from_fn(|column| {
+            u32::from_le_bytes([
+                SBOX[byte(state[column], 0)],
+                SBOX[byte(state[(column + 1) % 4], 1)],
+                SBOX[byte(state[(column + 2) % 4], 2)],
+                SBOX[byte(state[(column + 3) % 4], 3)],
+            ]) ^ keys[last + column]
+        });
+        Self::store(block, state);
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        let keys = &self.decrypt_keys;
+        let mut state = Self::load(block, keys);
+        let byte = |word: u32, index: u32| (word >> (8 * index)) as usize & 0xFF;
+
+        for round in 1..self.rounds as usize {
+            state = core::array::from_fn(|column| {
+                DECRYPT_TABLE[byte(state[column], 0)]
+                    ^ DECRYPT_TABLE[byte(state[(column + 3) % 4], 1)].rotate_left(8)
+                    ^ DECRYPT_TABLE[byte(state[(column + 2) % 4], 2)].rotate_left(16)
+                    ^ DECRYPT_TABLE[byte(state[(column + 1) % 4], 3)].rotate_left(24)
+                    ^ keys[round * 4 + column]
+            });
+        }
+
+        let last = self.rounds as usize * 4;
+        let state: [u32; 4] = core::array::from_fn(|column| {
+            u32::from_le_bytes([
+                INVERSE_SBOX[byte(state[column], 0)],
+                INVERSE_SBOX[byte(state[(column + 3) % 4], 1)],
+                INVERSE_SBOX[byte(state[(column + 2) % 4], 2)],
+                INVERSE_SBOX[byte(state[(column + 1) % 4], 3)],
+            ]) ^ keys[last + column]
+        });
+        Self::store(block, state);
+    }
+}
+
+/// AES-XTS, the data unit being a sector whose number is the tweak.
+#[derive(FromZeros)]
+pub struct Xts {
+    data: Aes,
+    tweak: Aes,
+}
+
+impl Xts {
+    /// The key is the data key followed by the tweak key of the same size.
+    pub fn set_key(&mut self, key: &[u8]) -> Result<(), CryptoError> {
+        if !key.len().is_multiple_of(2) {
+            return Err(CryptoError::InvalidParameters);
+        }
+        let (data, tweak) = key.split_at(key.len() / 2);
+        self.data.set_key(data)?;
+        self.tweak.set_key(tweak)?;
+        return Ok(());
+    }
+
+    /// Decrypt one data unit in place, which must be a multiple of the 16 byte block size.
+    pub fn decrypt(&self, sector: u64, data: &mut [u8]) {
+        let mut tweak = [0u8; 16];
+        tweak[..8].copy_from_slice(&sector.to_le_bytes());
+        self.tweak.encrypt_block(&mut tweak);
+
+        for block in data.chunks_exact_mut(16) {
+            for (byte, tweak) in block.iter_mut().zip(tweak) {
+                *byte ^= tweak;
+            }
+            self.data.decrypt_block(block);
+            for (byte, tweak) in block.iter_mut().zip(tweak) {
+                *byte ^= tweak;
+            }
+
+            // Multiply the tweak by x in GF(2^128), little endian.
+            let value = u128::from_le_bytes(tweak);
+            let carry = (value >> 127) as u8;
+            tweak = (value << 1).to_le_bytes();
+            tweak[0] ^= 0x87 * carry;
+        }
+    }
+}
@@ -0,0 +1,297 @@
+/*
+ * https://datatracker.ietf.org/doc/html/rfc9106
+ * https://github.com/P-H-C/phc-winner-argon2/blob/master/src/ref.c
+*/
+
+use crate::crypto::{
+    CryptoError,
+    blake2b::{Blake2b, MAX_OUTPUT_SIZE},
+    zeroize,
+};
+
+const VERSION: u32 = 0x13;
+const SYNC_POINTS: u32 = 4;
+const WORDS_PER_BLOCK: usize = 128;
+pub const BLOCK_SIZE: usize = WORDS_PER_BLOCK * 8;
+
+pub type Block = [u64; WORDS_PER_BLOCK];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Variant {
+    Argon2i = 1,
+    Argon2id = 2,
+}
+
+/// Blocks of memory actually used for a memory cost in KiB, rounded down to a multiple of the
+/// segments.
+pub fn memory_blocks(memory_cost: u32, lanes: u32) -> usize {
+    let segments = SYNC_POINTS as usize * lanes.max(1) as usize;
+    return (memory_cost as usize).max(2 * segments) / segments * segments;
+}
+
+/// The variable length hash H' used for the first blocks and the tag.
+fn hash_long(parts: &[&[u8]], output: &mut [u8]) {
+    let length = (output.len() as u32).to_le_bytes();
+    if output.len() <= MAX_OUTPUT_SIZE {
+        let mut hash = Blake2b::new(output.len());
+        hash.update(&length);
+        for part in parts {
+            hash.update(part);
+        }
+        hash.finalize(output);
+        return;
+    }
+
+    let mut hash = Blake2b::new(MAX_OUTPUT_SIZE);
+    hash.update(&length);
+    for part in parts {
+        hash.update(part);
+    }
+    let mut previous = [0u8; MAX_OUTPUT_SIZE];
+    hash.finalize(&mut previous);
+
+    // Every intermediate hash contributes its first half, the last one is sized to fit.
+    let mut position = 0;
+    while output.len() - position > MAX_OUTPUT_SIZE {
+        output[position..position + MAX_OUTPUT_SIZE / 2]
+            .copy_from_slice(&previous[..MAX_OUTPUT_SIZE / 2]);
+        position += MAX_OUTPUT_SIZE / 2;
+
+        let size = (output.len() - position).min(MAX_OUTPUT_SIZE);
+        let mut hash = Blake2b::new(size);
+        hash.update(&previous);
+        hash.finalize(&mut previous);
+    }
+    let rest = output.len() - position;
+    output[position..].copy_from_slice(&previous[..rest]);
+    zeroize(&mut previous);
+}
+
+fn blamka(x: u64, y: u64) -> u64 {
+    return x.wrapping_add(y).wrapping_add(
+        2u64.wrapping_mul(x & 0xFFFF_FFFF)
+            .wrapping_mul(y & 0xFFFF_FFFF),
+    );
+}
+
+fn mix(v: &mut Block, a: usize, b: usize, c: usize, d: usize) {
+    v[a] = blamka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = blamka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = blamka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = blamka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// The BLAKE2b round without message words, on 16 words of a block given by index.
+fn round(v: &mut Block, i: [usize; 16]) {
+    mix(v, i[0], i[4], i[8], i[12]);
+    mix(v, i[1], i[5], i[9], i[13]);
+    mix(v, i[2], i[6], i[10], i[14]);
+    mix(v, i[3], i[7], i[11], i[15]);
+    mix(v, i[0], i[5], i[10], i[15]);
+    mix(v, i[1], i[6], i[11], i[12]);
+    mix(v, i[2], i[7], i[8], i[13]);
+    mix(v, i[3], i[4], i[9], i[14]);
+}
+
+/// The compression function G, returning the new block, which the caller stores or XORs in.
+fn compress(x: &Block, y: &Block) -> Block {
+    let mut r = [0u64; WORDS_PER_BLOCK];
+    for (r, (x, y)) in r.iter_mut().zip(x.iter().zip(y)) {
+        *r = x ^ y;
+    }
+    let mut z = r;
+
+    // Rows of eight 16 byte registers, then columns.
+    for row in 0..8 {
+        round(&mut z, core::array::from_fn(|i| row * 16 + i));
+    }
+    for column in 0..8 {
+        round(
+            &mut z,
+            core::array::from_fn(|i| (i / 2) * 16 + column * 2 + i % 2),
+        );
+    }
+
+    for (z, r) in z.iter_mut().zip(r) {
+        *z ^= r;
+    }
+    return z;
+}
+
+struct Addresses {
+    input: Block,
+    block: Block,
+}
+
+impl Addresses {
+    fn next(&mut self) {
+        let zero = [0u64; WORDS_PER_BLOCK];
+        self.input[6] += 1;
+        let first = compress(&zero, &self.input);
+        self.block = compress(&zero, &first);
+    }
+}
+
+/// Derive `output` from a password. `memory` must hold at least `memory_blocks` blocks, the
+/// memory cost is still the one hashed into the result.
+#[allow(clippy::too_many_arguments)]
+pub fn argon2(
+    variant: Variant,
+    password: &[u8],
+    salt: &[u8],
+    passes: u32,
+    memory_cost: u32,
+    lanes: u32,
+    memory: &mut [Block],
+    output: &mut [u8],
+) -> Result<(), CryptoError> {
+    let block_count = memory_blocks(memory_cost, lanes);
+    if passes == 0
+        || lanes == 0
+        || lanes > 0xFF_FFFF
+        || output.len() < 4
+        || memory.len() < block_count
+    {
+        return Err(CryptoError::InvalidParameters);
+    }
+    let memory = &mut memory[..block_count];
+    let lane_length = block_count / lanes as usize;
+    let segment_length = lane_length / SYNC_POINTS as usize;
+
+    let mut initial = [0u8; MAX_OUTPUT_SIZE + 8];
+    let mut hash = Blake2b::new(MAX_OUTPUT_SIZE);
+    for value in [
+        lanes,
+        output.len() as u32,
+        memory_cost,
+        passes,
+        VERSION,
+        variant as u32,
+    ] {
+        hash.update(&value.to_le_bytes());
+    }
+    for field in [password, salt, &[], &[]] {
+        hash.update(&(field.len() as u32).to_le_bytes());
+        hash.update(field);
+    }
+    hash.finalize(&mut initial);
+
+    let mut bytes = [0u8; BLOCK_SIZE];
+    for lane in 0..lanes as usize {
+        for index in 0..2 {
+            initial[MAX_OUTPUT_SIZE..MAX_OUTPUT_SIZE + 4]
+                .copy_from_slice(&(index as u32).to_le_bytes());
+            initial[MAX_OUTPUT_SIZE + 4..].copy_from_slice(&(lane as u32).to_le_bytes());
+            hash_long(&[&initial], &mut bytes);
+            let block = &mut memory[lane * lane_length + index];
+            for (word, chunk) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+                *word = u64::from_le_bytes(chunk.try_into().unwrap());
+            }
+        }
+    }
+    zeroize(&mut initial);
+
+    // Lanes only reference other lanes' finished segments, so they are filled one after another.
+    for pass in 0..passes as usize {
+        for slice in 0..SYNC_POINTS as usize {
+            for lane in 0..lanes as usize {
+                let independent = variant == Variant::Argon2i || (pass == 0 && slice < 2);
+                let mut addresses = Addresses {
+                    input: [0u64; WORDS_PER_BLOCK],
+                    block: [0u64; WORDS_PER_BLOCK],
+                };
+                addresses.input[..6].copy_from_slice(&[
+                    pass as u64,
+                    lane as u64,
+                    slice as u64,
+                    block_count as u64,
+                    passes as u64,
+                    variant as u64,
+                ]);
+
+                let start = match pass == 0 && slice == 0 {
+                    true => {
+                        if independent {
+                            addresses.next();
+                        }
+                        2
+                    }
+                    false => 0,
+                };
+
+                for index in start..segment_length {
+                    let offset = lane * lane_length + slice * segment_length + index;
+                    let previous = match offset % lane_length {
+                        0 => offset + lane_length - 1,
+                        _ => offset - 1,
+                    };
+
+                    let random = match independent {
+                        true => {
+                            if index % WORDS_PER_BLOCK == 0 {
+                                addresses.next();
+                            }
+                            addresses.block[index % WORDS_PER_BLOCK]
+                        }
+                        false => memory[previous][0],
+                    };
+
+                    let reference_lane = match pass == 0 && slice == 0 {
+                        true => lane,
+                        false => ((random >> 32) % lanes as u64) as usize,
+                    };
+                    let same_lane = reference_lane == lane;
+
+                    // Blocks that may be referenced: all finished ones of this lane, or of other
+                    // lanes all but the segments still being filled.
+                    let area = match (pass, same_lane) {
+                        (0, true) => slice * segment_length + index - 1,
+                        (0, false) => slice * segment_length - (index == 0) as usize,
+                        (_, true) => lane_length - segment_length + index - 1,
+                        (_, false) => lane_length - segment_length - (index == 0) as usize,
+                    } as u64;
+                    let relative = random & 0xFFFF_FFFF;
+                    let relative = (relative * relative) >> 32;
+                    let relative = area - 1 - ((area * relative) >> 32);
+                    let first = match pass == 0 || slice == SYNC_POINTS as usize - 1 {
+                        true => 0,
+                        false => (slice + 1) * segment_length,
+                    } as u64;
+                    let reference = reference_lane * lane_length
+                        + ((first + relative) % lane_length as u64) as usize;
+
+                    let block = compress(&memory[previous], &memory[reference]);
+                    match pass {
+                        0 => memory[offset] = block,
+                        _ => {
+                            for (word, new) in memory[offset].iter_mut().zip(block) {
+                                *word ^= new;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut last = memory[lane_length - 1];
+    for lane in 1..lanes as usize {
+        for (word, other) in last
+            .iter_mut()
+            .zip(&memory[lane * lane_length + lane_length - 1])
+        {
+            *word ^= other;
+        }
+    }
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(last) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    hash_long(&[&bytes], output);
+    zeroize(&mut bytes);
+
+    return Ok(());
+}
@@ -0,0 +1,81 @@
+/*
+ * https://datatracker.ietf.org/doc/html/rfc2104
+ * https://datatracker.ietf.org/doc/html/rfc8018#section-5.2
+*/
+
+use crate::crypto::{Hash, zeroize};
+
+/// Digests are at most 64 bytes and blocks at most 128 bytes for every hash in use.
+const MAX_OUTPUT_SIZE: usize = 64;
+const MAX_BLOCK_SIZE: usize = 128;
+
+#[derive(Clone)]
+pub struct Hmac<H: Hash> {
+    inner: H,
+    outer: H,
+}
+
+impl<H: Hash> Hmac<H> {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; MAX_BLOCK_SIZE];
+        match key.len() > H::BLOCK_SIZE {
+            true => H::digest(key, &mut block),
+            false => block[..key.len()].copy_from_slice(key),
+        }
+
+        let mut inner = H::new();
+        let mut outer = H::new();
+        let mut padded = [0u8; MAX_BLOCK_SIZE];
+        for (pad, byte) in padded.iter_mut().zip(block).take(H::BLOCK_SIZE) {
+            *pad = byte ^ 0x36;
+        }
+        inner.update(&padded[..H::BLOCK_SIZE]);
+        for (pad, byte) in padded.iter_mut().zip(block).take(H::BLOCK_SIZE) {
+            *pad = byte ^ 0x5C;
+        }
+        outer.update(&padded[..H::BLOCK_SIZE]);
+
+        zeroize(&mut block);
+        zeroize(&mut padded);
+        return Hmac { inner, outer };
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finalize(self, output: &mut [u8]) {
+        let mut digest = [0u8; MAX_OUTPUT_SIZE];
+        self.inner.finalize(&mut digest);
+        let mut outer = self.outer;
+        outer.update(&digest[..H::OUTPUT_SIZE]);
+        outer.finalize(output);
+    }
+}
+
+/// PBKDF2 with HMAC, filling all of `output`.
+pub fn pbkdf2<H: Hash>(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    let keyed = Hmac::<H>::new(password);
+
+    for (index, chunk) in output.chunks_mut(H::OUTPUT_SIZE).enumerate() {
+        let mut hmac = keyed.clone();
+        hmac.update(salt);
+        hmac.update(&(index as u32 + 1).to_be_bytes());
+        let mut block = [0u8; MAX_OUTPUT_SIZE];
+        hmac.finalize(&mut block);
+
+        let mut sum = block;
+        for _ in 1..iterations {
+            let mut hmac = keyed.clone();
+            hmac.update(&block[..H::OUTPUT_SIZE]);
+            hmac.finalize(&mut block);
+            for (sum, byte) in sum.iter_mut().zip(block) {
+                *sum ^= byte;
+            }
+        }
+
+        chunk.copy_from_slice(&sum[..chunk.len()]);
+        zeroize(&mut sum);
+        zeroize(&mut block);
+    }
+}
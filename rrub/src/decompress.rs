@@ -0,0 +1,219 @@
+pub mod bzip2;
+pub mod gzip;
+pub mod inflate;
+pub mod lz4;
+pub mod lzjb;
+pub mod lzma;
+pub mod lzo;
+pub mod xz;
+pub mod zle;
+pub mod zstd;
+
+use alloc::vec::Vec;
+
+use log::debug;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecompressError {
+    /// Malformed or unsupported compressed stream.
+    InvalidData,
+    /// Compressed stream ended before the end marker.
+    UnexpectedEnd,
+    /// Decompressed data is larger than the caller allowed.
+    OutputLimit,
+    ChecksumMismatch,
+}
+
+/// A compressed file format, recognised by the magic bytes its streams start with. Streams are
+/// decompressed whole from memory, as kernels and initrds are read whole before they're booted.
+/// Filesystem drivers call the block decoders of each format's module instead, their blocks
+/// having no magic and a size known up front.
+pub trait Decompressor: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether `input` starts with a stream in this format.
+    fn detect(&self, input: &[u8]) -> bool;
+
+    /// Size `input` decompresses to, when the format records it, for the output to be allocated
+    /// once. It's a hint read off the headers or trailer, nothing is checked.
+    fn content_size(&self, input: &[u8]) -> Option<u64>;
+
+    /// Decompress the stream at the start of `input`, appending to `output` which may not grow
+    /// past `limit`. Returns the number of input bytes the stream took, whatever follows it being
+    /// left to the caller.
+    fn decompress_stream(
+        &self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<usize, DecompressError>;
+}
+
+pub struct Gzip;
+pub struct Zstd;
+pub struct Xz;
+pub struct Lz4;
+pub struct Bzip2;
+
+impl Decompressor for Gzip {
+    fn name(&self) -> &'static str {
+        return "gzip";
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        return input.starts_with(gzip::MAGIC);
+    }
+
+    fn content_size(&self, input: &[u8]) -> Option<u64> {
+        return gzip::content_size(input);
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<usize, DecompressError> {
+        return gzip::decompress_member(input, output, limit);
+    }
+}
+
+impl Decompressor for Zstd {
+    fn name(&self) -> &'static str {
+        return "zstd";
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        return input.starts_with(&zstd::FRAME_MAGIC.to_le_bytes());
+    }
+
+    fn content_size(&self, input: &[u8]) -> Option<u64> {
+        return zstd::content_size(input);
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<usize, DecompressError> {
+        return zstd::decompress_frames(input, output, limit);
+    }
+}
+
+impl Decompressor for Xz {
+    fn name(&self) -> &'static str {
+        return "xz";
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        return input.starts_with(xz::STREAM_MAGIC);
+    }
+
+    fn content_size(&self, input: &[u8]) -> Option<u64> {
+        return xz::content_size(input);
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<usize, DecompressError> {
+        return xz::decompress_streams(input, output, limit);
+    }
+}
+
+impl Decompressor for Lz4 {
+    fn name(&self) -> &'static str {
+        return "lz4";
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        return input.starts_with(&lz4::FRAME_MAGIC.to_le_bytes())
+            || input.starts_with(&lz4::LEGACY_MAGIC.to_le_bytes());
+    }
+
+    fn content_size(&self, input: &[u8]) -> Option<u64> {
+        return lz4::content_size(input);
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<usize, DecompressError> {
+        return match input.starts_with(&lz4::LEGACY_MAGIC.to_le_bytes()) {
+            true => lz4::decompress_legacy(input, output, limit),
+            false => lz4::decompress_frame(input, output, limit),
+        };
+    }
+}
+
+impl Decompressor for Bzip2 {
+    fn name(&self) -> &'static str {
+        return "bzip2";
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        return input.starts_with(bzip2::MAGIC)
+            && input
+                .get(bzip2::MAGIC.len())
+                .is_some_and(|level| (b'1'..=b'9').contains(level));
+    }
+
+    fn content_size(&self, _input: &[u8]) -> Option<u64> {
+        // Nothing in a bzip2 stream records its size.
+        return None;
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<usize, DecompressError> {
+        return bzip2::decompress_stream(input, output, limit);
+    }
+}
+
+static DECOMPRESSORS: [&dyn Decompressor; 5] = [&Gzip, &Zstd, &Xz, &Lz4, &Bzip2];
+
+/// The format `input` is compressed in, if it starts like any.
+pub fn detect(input: &[u8]) -> Option<&'static dyn Decompressor> {
+    return DECOMPRESSORS
+        .iter()
+        .copied()
+        .find(|decompressor| decompressor.detect(input));
+}
+
+/// Decompress `input` as whatever format it's in, which may not grow past `limit`. Streams can
+/// follow each other with zero padding in between, in any format, as initrds are put together.
+/// Returns `None` when `input` isn't compressed.
+pub fn decompress(input: &[u8], limit: usize) -> Result<Option<Vec<u8>>, DecompressError> {
+    let Some(decompressor) = detect(input) else {
+        return Ok(None);
+    };
+
+    // Allocated once where the size is known, the heap never frees what growing leaves behind.
+    let mut output = Vec::new();
+    if let Some(size) = decompressor.content_size(input) {
+        output.reserve_exact(size.min(limit as u64) as usize);
+    }
+    let mut position = 0;
+    while let Some(decompressor) = detect(&input[position..]) {
+        position += decompressor.decompress_stream(&input[position..], &mut output, limit)?;
+        while input.get(position) == Some(&0) {
+            position += 1;
+        }
+    }
+
+    if position < input.len() {
+        debug!(
+            "Ignoring {} bytes after compressed data",
+            input.len() - position
+        );
+    }
+    return Ok(Some(output));
+}
@@ -0,0 +1,348 @@
+/*
+ * https://github.com/dsnet/compress/blob/master/doc/bzip2-format.pdf
+ * Block decoding follows decompress.c from bzip2.
+*/
+
+use alloc::{vec, vec::Vec};
+
+use crate::{checksum::crc32_bzip2, decompress::DecompressError};
+
+pub const MAGIC: &[u8; 3] = b"BZh";
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+const END_MAGIC: u64 = 0x1772_4538_5090;
+/// Blocks are at most this many bytes times the level in the header.
+const BLOCK_SIZE_UNIT: usize = 100_000;
+
+const RUN_A: u16 = 0;
+const RUN_B: u16 = 1;
+const MIN_GROUPS: usize = 2;
+const MAX_GROUPS: usize = 6;
+/// Symbols coded with each table before the next selector.
+const GROUP_SIZE: usize = 50;
+const MAX_CODE_LENGTH: usize = 20;
+/// bzip2 ignores selectors past this, encoders never need more.
+const MAX_SELECTORS: usize = 18002;
+const MAX_ALPHABET: usize = 258;
+/// Runs of this many equal bytes are followed by a count of further repeats.
+const RUN_LENGTH: usize = 4;
+
+/// Reads bits most significant first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        return BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        };
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, DecompressError> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            self.position += 1;
+            self.buffer = (self.buffer << 8) | byte as u64;
+            self.count += 8;
+        }
+        self.count -= count;
+        return Ok(((self.buffer >> self.count) & ((1 << count) - 1)) as u32);
+    }
+}
+
+/// Canonical Huffman code, codes assigned by length and then by symbol.
+struct Huffman {
+    /// Largest code of each length.
+    limit: [i32; MAX_CODE_LENGTH + 1],
+    /// Added to a code of each length to index `symbols`.
+    offset: [i32; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0i32; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..=MAX_CODE_LENGTH as u8 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == length) {
+                symbols.push(symbol as u16);
+            }
+        }
+
+        let mut limit = [0; MAX_CODE_LENGTH + 1];
+        let mut offset = [0; MAX_CODE_LENGTH + 1];
+        let (mut code, mut index) = (0, 0);
+        for length in 1..=MAX_CODE_LENGTH {
+            offset[length] = index - code;
+            code += counts[length];
+            index += counts[length];
+            limit[length] = code - 1;
+            code <<= 1;
+        }
+
+        return Huffman {
+            limit,
+            offset,
+            symbols,
+        };
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DecompressError> {
+        let mut code = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code = (code << 1) | reader.bits(1)? as i32;
+            if code <= self.limit[length] {
+                return self
+                    .symbols
+                    .get((code + self.offset[length]) as usize)
+                    .copied()
+                    .ok_or(DecompressError::InvalidData);
+            }
+        }
+        return Err(DecompressError::InvalidData);
+    }
+}
+
+/// Read the Huffman tables and the symbols they code, undoing the move-to-front and zero run
+/// length steps. Returns the Burrows-Wheeler transformed block and how often each byte occurs.
+fn read_block(
+    reader: &mut BitReader,
+    max_size: usize,
+) -> Result<(Vec<u8>, [usize; 256]), DecompressError> {
+    // Bytes the block uses, as a bitmap of 16 ranges of 16.
+    let ranges = reader.bits(16)?;
+    let mut used = Vec::new();
+    for range in 0..16 {
+        if ranges & (0x8000 >> range) == 0 {
+            continue;
+        }
+        let bits = reader.bits(16)?;
+        for i in 0..16 {
+            if bits & (0x8000 >> i) != 0 {
+                used.push((range * 16 + i) as u8);
+            }
+        }
+    }
+    if used.is_empty() {
+        return Err(DecompressError::InvalidData);
+    }
+    // Both run symbols and the end of block symbol, with one byte of `used` taking the place of
+    // the move-to-front index zero runs stand for.
+    let alphabet = used.len() + 2;
+    let end = (alphabet - 1) as u16;
+
+    let groups = reader.bits(3)? as usize;
+    let selector_count = reader.bits(15)? as usize;
+    if !(MIN_GROUPS..=MAX_GROUPS).contains(&groups) || selector_count == 0 {
+        return Err(DecompressError::InvalidData);
+    }
+
+    // Selectors are move-to-front coded, each index in unary.
+    let mut order: Vec<u8> = (0..groups as u8).collect();
+    let mut selectors = Vec::with_capacity(selector_count.min(MAX_SELECTORS));
+    for i in 0..selector_count {
+        let mut index = 0;
+        while reader.bits(1)? == 1 {
+            index += 1;
+            if index >= groups {
+                return Err(DecompressError::InvalidData);
+            }
+        }
+        let group = order[index];
+        order.copy_within(0..index, 1);
+        order[0] = group;
+        if i < MAX_SELECTORS {
+            selectors.push(group);
+        }
+    }
+
+    // Code lengths are delta coded from the previous symbol's.
+    let mut tables = Vec::with_capacity(groups);
+    for _ in 0..groups {
+        let mut lengths = [0u8; MAX_ALPHABET];
+        let mut length = reader.bits(5)? as usize;
+        for symbol_length in &mut lengths[..alphabet] {
+            loop {
+                if !(1..=MAX_CODE_LENGTH).contains(&length) {
+                    return Err(DecompressError::InvalidData);
+                }
+                if reader.bits(1)? == 0 {
+                    break;
+                }
+                match reader.bits(1)? {
+                    0 => length += 1,
+                    _ => length -= 1,
+                }
+            }
+            *symbol_length = length as u8;
+        }
+        tables.push(Huffman::new(&lengths[..alphabet]));
+    }
+
+    let mut front = [0u8; 256];
+    front[..used.len()].copy_from_slice(&used);
+    let mut block = Vec::with_capacity(max_size);
+    let mut counts = [0usize; 256];
+    // Zero runs are written in bijective base 2, RUN_A and RUN_B being the digits 1 and 2.
+    let mut run = 0;
+    let mut weight = 1;
+
+    for decoded in 0.. {
+        let selector = *selectors
+            .get(decoded / GROUP_SIZE)
+            .ok_or(DecompressError::InvalidData)?;
+        let symbol = tables[selector as usize].decode(reader)?;
+
+        if symbol == RUN_A || symbol == RUN_B {
+            run += weight << symbol;
+            weight <<= 1;
+            if run > max_size {
+                return Err(DecompressError::InvalidData);
+            }
+            continue;
+        }
+
+        if run > 0 {
+            if block.len() + run > max_size {
+                return Err(DecompressError::InvalidData);
+            }
+            block.resize(block.len() + run, front[0]);
+            counts[front[0] as usize] += run;
+            run = 0;
+            weight = 1;
+        }
+
+        if symbol == end {
+            break;
+        }
+
+        let index = (symbol - 1) as usize;
+        let byte = front[index];
+        front.copy_within(0..index, 1);
+        front[0] = byte;
+        if block.len() == max_size {
+            return Err(DecompressError::InvalidData);
+        }
+        block.push(byte);
+        counts[byte as usize] += 1;
+    }
+
+    return Ok((block, counts));
+}
+
+/// Decode one block onto the end of `output`, returning its CRC.
+fn decode_block(
+    reader: &mut BitReader,
+    max_size: usize,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<u32, DecompressError> {
+    let crc = reader.bits(32)?;
+    // Randomised blocks were only written by bzip2 0.9.0 and older.
+    if reader.bits(1)? != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+    let origin = reader.bits(24)? as usize;
+
+    let (block, counts) = read_block(reader, max_size)?;
+    if origin >= block.len() {
+        return Err(DecompressError::InvalidData);
+    }
+
+    // Undo the Burrows-Wheeler transform, `next` linking each byte to the one following it.
+    let mut starts = [0; 256];
+    let mut sum = 0;
+    for (start, count) in starts.iter_mut().zip(counts) {
+        *start = sum;
+        sum += count;
+    }
+    let mut next = vec![0u32; block.len()];
+    for (i, &byte) in block.iter().enumerate() {
+        next[starts[byte as usize]] = i as u32;
+        starts[byte as usize] += 1;
+    }
+
+    let start = output.len();
+    let mut position = next[origin] as usize;
+    let mut last = None;
+    let mut repeats = 0;
+    for _ in 0..block.len() {
+        let byte = block[position];
+        position = next[position] as usize;
+
+        if repeats == RUN_LENGTH {
+            if output.len() + byte as usize > limit {
+                return Err(DecompressError::OutputLimit);
+            }
+            output.resize(output.len() + byte as usize, last.unwrap_or(0));
+            repeats = 0;
+            continue;
+        }
+
+        repeats = match last == Some(byte) {
+            true => repeats + 1,
+            false => 1,
+        };
+        last = Some(byte);
+        if output.len() == limit {
+            return Err(DecompressError::OutputLimit);
+        }
+        output.push(byte);
+    }
+
+    if crc32_bzip2(&output[start..]) != crc {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+    return Ok(crc);
+}
+
+/// Decompress one bzip2 stream, appending to `output` which may not grow past `limit`. Returns
+/// the number of input bytes the stream took.
+pub fn decompress_stream(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    if !input.starts_with(MAGIC) {
+        return Err(DecompressError::InvalidData);
+    }
+    let level = match input.get(MAGIC.len()) {
+        Some(&level @ b'1'..=b'9') => (level - b'0') as usize,
+        Some(_) => return Err(DecompressError::InvalidData),
+        None => return Err(DecompressError::UnexpectedEnd),
+    };
+    let header_size = MAGIC.len() + 1;
+
+    let mut reader = BitReader::new(&input[header_size..]);
+    let mut combined: u32 = 0;
+    loop {
+        let magic = ((reader.bits(24)? as u64) << 24) | reader.bits(24)? as u64;
+        match magic {
+            BLOCK_MAGIC => {
+                let crc = decode_block(&mut reader, level * BLOCK_SIZE_UNIT, output, limit)?;
+                combined = combined.rotate_left(1) ^ crc;
+            }
+            END_MAGIC => {
+                if reader.bits(32)? != combined {
+                    return Err(DecompressError::ChecksumMismatch);
+                }
+                // The stream ends at the byte boundary after the combined CRC.
+                return Ok(header_size + reader.position);
+            }
+            _ => return Err(DecompressError::InvalidData),
+        }
+    }
+}
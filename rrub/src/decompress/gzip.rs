@@ -0,0 +1,96 @@
+/*
+ * https://www.rfc-editor.org/rfc/rfc1952
+*/
+
+use alloc::vec::Vec;
+
+use crate::{
+    checksum::crc32,
+    decompress::{DecompressError, inflate::inflate},
+};
+
+pub const MAGIC: &[u8; 2] = b"\x1F\x8B";
+const METHOD_DEFLATE: u8 = 8;
+const HEADER_SIZE: usize = 10;
+const TRAILER_SIZE: usize = 8;
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+const FLAG_RESERVED: u8 = 0xE0;
+
+/// Deflate expands its input at most this many times, a 258 byte match for every two bits.
+const MAX_RATIO: u64 = 1032;
+
+/// Size `input` decompresses to going by the trailer of its last member, which is all of it for
+/// a file of one member. The trailer only keeps the size modulo 2^32.
+pub fn content_size(input: &[u8]) -> Option<u64> {
+    let trailer = input.get(input.len().checked_sub(4)?..)?;
+    let size = u32::from_le_bytes(trailer.try_into().unwrap()) as u64;
+    // Padding or data of another format after the member, rather than a size.
+    return (size <= input.len() as u64 * MAX_RATIO).then_some(size);
+}
+
+/// Decompress one gzip member, appending to `output` which may not grow past `limit`. Returns
+/// the number of input bytes the member took.
+pub fn decompress_member(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    let header = input
+        .get(..HEADER_SIZE)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    if header[..2] != *MAGIC || header[2] != METHOD_DEFLATE || header[3] & FLAG_RESERVED != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+    let flags = header[3];
+
+    let mut position = HEADER_SIZE;
+    if flags & FLAG_EXTRA != 0 {
+        let length = input
+            .get(position..position + 2)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        position += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+    }
+    // File name and comment, both terminated.
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let length = input
+                .get(position..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            position += length + 1;
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        let checksum = input
+            .get(position..position + 2)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        if u16::from_le_bytes([checksum[0], checksum[1]]) != crc32(&input[..position]) as u16 {
+            return Err(DecompressError::ChecksumMismatch);
+        }
+        position += 2;
+    }
+
+    let start = output.len();
+    position += inflate(
+        input
+            .get(position..)
+            .ok_or(DecompressError::UnexpectedEnd)?,
+        output,
+        limit,
+    )?;
+
+    let trailer = input
+        .get(position..position + TRAILER_SIZE)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    let checksum = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+    if checksum != crc32(&output[start..]) || size != (output.len() - start) as u32 {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+
+    return Ok(position + TRAILER_SIZE);
+}
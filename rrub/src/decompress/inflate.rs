@@ -0,0 +1,328 @@
+/*
+ * https://www.rfc-editor.org/rfc/rfc1951
+ * https://www.rfc-editor.org/rfc/rfc1950
+ * Decoding follows zlib's puff.c, canonical Huffman codes are decoded one bit at a time from
+ * per length symbol counts.
+*/
+
+use alloc::vec::Vec;
+
+use crate::{checksum::adler32, decompress::DecompressError};
+
+const MAX_BITS: usize = 15;
+const MAX_LITERAL_CODES: usize = 286;
+const MAX_DISTANCE_CODES: usize = 30;
+const FIXED_LITERAL_CODES: usize = 288;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    input: &'a [u8],
+    position: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(input: &'a [u8]) -> BitReader<'a> {
+        return BitReader {
+            input,
+            position: 0,
+            bits: 0,
+            count: 0,
+        };
+    }
+
+    fn bits(&mut self, need: u32) -> Result<u32, DecompressError> {
+        let mut value = self.bits;
+        while self.count < need {
+            let byte = *self
+                .input
+                .get(self.position)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            self.position += 1;
+            value |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+
+        self.bits = value.checked_shr(need).unwrap_or(0);
+        self.count -= need;
+        return Ok(value & ((1u32 << need) - 1));
+    }
+
+    /// Drop the remaining bits of the current byte.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+struct Huffman {
+    /// Number of symbols of each code length.
+    count: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code.
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    /// Build a decoding table from code lengths, incomplete codes are only allowed for single
+    /// code distance trees as produced by some encoders.
+    fn new(lengths: &[u8]) -> Result<Huffman, DecompressError> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            count[length as usize] += 1;
+        }
+
+        let mut left: i32 = 1;
+        for &codes in &count[1..] {
+            left = (left << 1) - codes as i32;
+            if left < 0 {
+                return Err(DecompressError::InvalidData);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + count[length];
+        }
+
+        let mut symbol = alloc::vec![0u16; lengths.len()];
+        for (value, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbol[offsets[length as usize] as usize] = value as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        return Ok(Huffman { count, symbol });
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DecompressError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.count[length] as i32;
+            if code - count < first {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        return Err(DecompressError::InvalidData);
+    }
+}
+
+fn stored(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), DecompressError> {
+    reader.align();
+
+    let header = reader
+        .input
+        .get(reader.position..reader.position + 4)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    if length != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(DecompressError::InvalidData);
+    }
+    reader.position += 4;
+
+    let data = reader
+        .input
+        .get(reader.position..reader.position + length as usize)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    if output.len() + data.len() > limit {
+        return Err(DecompressError::OutputLimit);
+    }
+    output.extend_from_slice(data);
+    reader.position += length as usize;
+
+    return Ok(());
+}
+
+fn codes(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), DecompressError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0..256 => {
+                if output.len() >= limit {
+                    return Err(DecompressError::OutputLimit);
+                }
+                output.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(DecompressError::InvalidData);
+                }
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(DecompressError::InvalidData);
+                }
+                let distance = DISTANCE_BASE[index] as usize
+                    + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+
+                if distance > output.len() {
+                    return Err(DecompressError::InvalidData);
+                }
+                if output.len() + length > limit {
+                    return Err(DecompressError::OutputLimit);
+                }
+
+                // Matches may overlap their own output, so copy byte by byte.
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), DecompressError> {
+    let mut lengths = [0u8; FIXED_LITERAL_CODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    return Ok((
+        Huffman::new(&lengths)?,
+        Huffman::new(&[5u8; MAX_DISTANCE_CODES])?,
+    ));
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), DecompressError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+    if literal_count > MAX_LITERAL_CODES || distance_count > MAX_DISTANCE_CODES {
+        return Err(DecompressError::InvalidData);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_table = Huffman::new(&code_lengths)?;
+
+    let mut lengths = [0u8; MAX_LITERAL_CODES + MAX_DISTANCE_CODES];
+    let total = literal_count + distance_count;
+    let mut index = 0;
+    while index < total {
+        let symbol = code_table.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..16 => (symbol as u8, 1),
+            16 => match index {
+                0 => return Err(DecompressError::InvalidData),
+                _ => (lengths[index - 1], 3 + reader.bits(2)? as usize),
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+
+        if index + repeat > total {
+            return Err(DecompressError::InvalidData);
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(DecompressError::InvalidData);
+    }
+
+    return Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..total])?,
+    ));
+}
+
+/// Decompress a raw deflate stream, appending to `output` which may not grow past `limit`.
+/// Returns the number of input bytes consumed.
+pub fn inflate(input: &[u8], output: &mut Vec<u8>, limit: usize) -> Result<usize, DecompressError> {
+    let mut reader = BitReader::new(input);
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => stored(&mut reader, output, limit)?,
+            1 => {
+                let (literals, distances) = fixed_tables()?;
+                codes(&mut reader, output, limit, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                codes(&mut reader, output, limit, &literals, &distances)?;
+            }
+            _ => return Err(DecompressError::InvalidData),
+        }
+
+        if last {
+            return Ok(reader.position);
+        }
+    }
+}
+
+/// Decompress a zlib stream, checking its header and Adler-32 trailer.
+pub fn zlib_decompress(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let [cmf, flags, ..] = *input else {
+        return Err(DecompressError::UnexpectedEnd);
+    };
+
+    // Only deflate, and preset dictionaries are never used for data we read.
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 || !(((cmf as u16) << 8) | flags as u16).is_multiple_of(31) {
+        return Err(DecompressError::InvalidData);
+    }
+    if flags & 0x20 != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+
+    let mut output = Vec::new();
+    let consumed = 2 + inflate(&input[2..], &mut output, limit)?;
+
+    let trailer = input
+        .get(consumed..consumed + 4)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    if u32::from_be_bytes(trailer.try_into().unwrap()) != adler32(&output) {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+
+    return Ok(output);
+}
@@ -0,0 +1,234 @@
+/*
+ * https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md
+ * https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md
+ * The legacy frame is what `lz4 -l` writes and the kernel decompresses initrds and kernels from.
+*/
+
+use alloc::vec::Vec;
+
+use crate::{checksum::xxh32, decompress::DecompressError};
+
+const MIN_MATCH: usize = 4;
+
+pub const FRAME_MAGIC: u32 = 0x184D_2204;
+pub const LEGACY_MAGIC: u32 = 0x184C_2102;
+
+const FRAME_VERSION_MASK: u8 = 0xC0;
+const FRAME_VERSION: u8 = 0x40;
+const FRAME_BLOCK_CHECKSUM: u8 = 0x10;
+const FRAME_CONTENT_SIZE: u8 = 0x08;
+const FRAME_CONTENT_CHECKSUM: u8 = 0x04;
+const FRAME_DICTIONARY_ID: u8 = 0x01;
+const FRAME_RESERVED: u8 = 0x02;
+const BLOCK_UNCOMPRESSED: u32 = 0x8000_0000;
+
+const LEGACY_BLOCK_SIZE: usize = 8 << 20;
+/// The most a legacy block can compress to, anything larger being past the end of the stream.
+const LEGACY_MAX_COMPRESSED: usize = LEGACY_BLOCK_SIZE + LEGACY_BLOCK_SIZE / 255 + 16;
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Input<'_> {
+    fn byte(&mut self) -> Result<usize, DecompressError> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        self.position += 1;
+        return Ok(byte as usize);
+    }
+
+    /// Lengths of 15 continue in following bytes, each adding up to 255.
+    fn length(&mut self, base: usize) -> Result<usize, DecompressError> {
+        let mut length = base;
+        if base == 15 {
+            loop {
+                let byte = self.byte()?;
+                length += byte;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        return Ok(length);
+    }
+}
+
+/// Decompress a single LZ4 block, appending to `output` which may not grow past `limit`. The
+/// block ends with a sequence of literals only, at the end of `input`.
+pub fn decompress_block(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), DecompressError> {
+    let mut input = Input {
+        data: input,
+        position: 0,
+    };
+
+    loop {
+        let token = input.byte()?;
+
+        let literals = input.length(token >> 4)?;
+        let data = input
+            .data
+            .get(input.position..input.position + literals)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        if output.len() + literals > limit {
+            return Err(DecompressError::OutputLimit);
+        }
+        output.extend_from_slice(data);
+        input.position += literals;
+
+        if input.position == input.data.len() {
+            return Ok(());
+        }
+
+        let distance = input.byte()? | input.byte()? << 8;
+        let length = input.length(token & 0xF)? + MIN_MATCH;
+        if distance == 0 || distance > output.len() {
+            return Err(DecompressError::InvalidData);
+        }
+        if output.len() + length > limit {
+            return Err(DecompressError::OutputLimit);
+        }
+
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+fn read_u32(input: &[u8], position: usize) -> Result<u32, DecompressError> {
+    let bytes = input
+        .get(position..position + 4)
+        .ok_or(DecompressError::UnexpectedEnd)?;
+    return Ok(u32::from_le_bytes(bytes.try_into().unwrap()));
+}
+
+/// Size the frame at the start of `input` decompresses to, if its descriptor records it.
+pub fn content_size(input: &[u8]) -> Option<u64> {
+    if read_u32(input, 0).ok()? != FRAME_MAGIC || input.get(4)? & FRAME_CONTENT_SIZE == 0 {
+        return None;
+    }
+    return Some(u64::from_le_bytes(input.get(6..14)?.try_into().unwrap()));
+}
+
+/// Decompress one LZ4 frame, appending to `output` which may not grow past `limit`. Returns the
+/// number of input bytes the frame took.
+pub fn decompress_frame(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    if read_u32(input, 0)? != FRAME_MAGIC {
+        return Err(DecompressError::InvalidData);
+    }
+
+    let flags = *input.get(4).ok_or(DecompressError::UnexpectedEnd)?;
+    if flags & FRAME_VERSION_MASK != FRAME_VERSION || flags & FRAME_RESERVED != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+    // Flags and block size, then the optional content size and dictionary ID.
+    let mut position = 6;
+    let content_size = match flags & FRAME_CONTENT_SIZE != 0 {
+        true => {
+            let bytes = input
+                .get(position..position + 8)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            position += 8;
+            Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+        }
+        false => None,
+    };
+    if flags & FRAME_DICTIONARY_ID != 0 {
+        // Dictionaries are never used by the data we read.
+        return Err(DecompressError::InvalidData);
+    }
+    let header_checksum = *input.get(position).ok_or(DecompressError::UnexpectedEnd)?;
+    if header_checksum != (xxh32(&input[4..position], 0) >> 8) as u8 {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+    position += 1;
+
+    let start = output.len();
+    loop {
+        let header = read_u32(input, position)?;
+        position += 4;
+        if header == 0 {
+            break;
+        }
+
+        let size = (header & !BLOCK_UNCOMPRESSED) as usize;
+        let block = input
+            .get(position..position + size)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        if header & BLOCK_UNCOMPRESSED != 0 {
+            if output.len() + size > limit {
+                return Err(DecompressError::OutputLimit);
+            }
+            output.extend_from_slice(block);
+        } else {
+            // Linked blocks refer back into earlier ones, which are still in `output`.
+            decompress_block(block, output, limit)?;
+        }
+        position += size;
+
+        if flags & FRAME_BLOCK_CHECKSUM != 0 {
+            if read_u32(input, position)? != xxh32(block, 0) {
+                return Err(DecompressError::ChecksumMismatch);
+            }
+            position += 4;
+        }
+    }
+
+    if content_size.is_some_and(|size| size != (output.len() - start) as u64) {
+        return Err(DecompressError::InvalidData);
+    }
+    if flags & FRAME_CONTENT_CHECKSUM != 0 {
+        if read_u32(input, position)? != xxh32(&output[start..], 0) {
+            return Err(DecompressError::ChecksumMismatch);
+        }
+        position += 4;
+    }
+
+    return Ok(position);
+}
+
+/// Decompress a legacy LZ4 stream, appending to `output` which may not grow past `limit`. It has
+/// no end marker, so it ends with the input, at padding, or at anything that can't be a block.
+/// Returns the number of input bytes the stream took.
+pub fn decompress_legacy(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    if read_u32(input, 0)? != LEGACY_MAGIC {
+        return Err(DecompressError::InvalidData);
+    }
+
+    let mut position = 4;
+    while let Ok(size) = read_u32(input, position) {
+        // Streams concatenated by the kernel's build repeat the magic.
+        if size == LEGACY_MAGIC {
+            position += 4;
+            continue;
+        }
+        let size = size as usize;
+        if size == 0 || size > LEGACY_MAX_COMPRESSED {
+            break;
+        }
+
+        let block = input
+            .get(position + 4..position + 4 + size)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        decompress_block(block, output, limit)?;
+        position += 4 + size;
+    }
+
+    return Ok(position);
+}
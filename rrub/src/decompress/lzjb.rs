@@ -0,0 +1,54 @@
+/*
+ * LZJB decompression, as ZFS uses it for metadata on pools without lz4
+ * https://github.com/openzfs/zfs/blob/master/module/zfs/lzjb.c
+*/
+
+use alloc::vec::Vec;
+
+use crate::decompress::DecompressError;
+
+const MATCH_BITS: u32 = 6;
+const MATCH_MIN: usize = 3;
+const OFFSET_MASK: usize = (1 << (16 - MATCH_BITS)) - 1;
+
+/// Decompress into exactly `size` bytes, LZJB streams carry no end marker or length of their own.
+pub fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut output = Vec::with_capacity(size);
+    let mut position = 0;
+    let byte = |position: &mut usize| {
+        let value = *input.get(*position).ok_or(DecompressError::UnexpectedEnd)?;
+        *position += 1;
+        return Ok::<usize, DecompressError>(value as usize);
+    };
+
+    // Every item is preceded by a bit of a copy map byte, set for matches and clear for literals.
+    let mut copy_map = 0;
+    let mut copy_mask = 0x80u32;
+    while output.len() < size {
+        copy_mask <<= 1;
+        if copy_mask == 0x100 {
+            copy_mask = 1;
+            copy_map = byte(&mut position)? as u32;
+        }
+
+        if copy_map & copy_mask == 0 {
+            output.push(byte(&mut position)? as u8);
+            continue;
+        }
+
+        let high = byte(&mut position)?;
+        let low = byte(&mut position)?;
+        let length = (high >> (8 - MATCH_BITS)) + MATCH_MIN;
+        let offset = ((high << 8) | low) & OFFSET_MASK;
+        let start = output
+            .len()
+            .checked_sub(offset)
+            .ok_or(DecompressError::InvalidData)?;
+        // Matches overlap their output when the offset is shorter than the length.
+        for index in start..start + length.min(size - output.len()) {
+            output.push(output[index]);
+        }
+    }
+
+    return Ok(output);
+}
@@ -0,0 +1,502 @@
+/*
+ * LZMA, LZMA2 and MicroLZMA decompression, following the reference decoder in the LZMA SDK.
+ * https://github.com/jljusten/LZMA-SDK/blob/master/DOC/lzma-specification.txt
+ * https://tukaani.org/xz/xz-file-format.txt
+*/
+
+use alloc::{vec, vec::Vec};
+
+use crate::decompress::DecompressError;
+
+const PROBABILITY_BITS: u32 = 11;
+const PROBABILITY_INIT: u16 = 1 << (PROBABILITY_BITS - 1);
+const MOVE_BITS: u32 = 5;
+const TOP_VALUE: u32 = 1 << 24;
+
+const STATES: usize = 12;
+const LITERAL_STATES: usize = 7;
+const MAX_POSITION_STATES: usize = 1 << 4;
+const LENGTH_TO_POSITION_STATES: usize = 4;
+const END_POSITION_MODEL_INDEX: u32 = 14;
+const FULL_DISTANCES: usize = 1 << (END_POSITION_MODEL_INDEX >> 1);
+const ALIGN_BITS: u32 = 4;
+const MATCH_MIN_LENGTH: usize = 2;
+
+/// Largest properties byte, `(pb * 5 + lp) * 9 + lc` with each at its maximum.
+const MAX_PROPERTIES: u8 = (4 * 5 + 4) * 9 + 8;
+/// LZMA2 only allows literal coders with at most 4 context bits.
+const LZMA2_MAX_LITERAL_BITS: u32 = 4;
+
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    /// Start decoding at the first byte of `data`, which the encoder always writes as zero.
+    fn new(data: &'a [u8]) -> Result<Self, DecompressError> {
+        if data.first() != Some(&0) {
+            return Err(DecompressError::InvalidData);
+        }
+        return Self::without_leading_zero(data, 1);
+    }
+
+    fn without_leading_zero(data: &'a [u8], position: usize) -> Result<Self, DecompressError> {
+        let code = data
+            .get(position..position + 4)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        let decoder = RangeDecoder {
+            data,
+            position: position + 4,
+            range: u32::MAX,
+            code: u32::from_be_bytes(code.try_into().unwrap()),
+        };
+        if decoder.code == decoder.range {
+            return Err(DecompressError::InvalidData);
+        }
+        return Ok(decoder);
+    }
+
+    fn normalize(&mut self) -> Result<(), DecompressError> {
+        if self.range < TOP_VALUE {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            self.position += 1;
+            self.range <<= 8;
+            self.code = (self.code << 8) | byte as u32;
+        }
+        return Ok(());
+    }
+
+    fn bit(&mut self, probability: &mut u16) -> Result<usize, DecompressError> {
+        let bound = (self.range >> PROBABILITY_BITS) * *probability as u32;
+        let bit = match self.code < bound {
+            true => {
+                self.range = bound;
+                *probability += ((1 << PROBABILITY_BITS) - *probability) >> MOVE_BITS;
+                0
+            }
+            false => {
+                self.range -= bound;
+                self.code -= bound;
+                *probability -= *probability >> MOVE_BITS;
+                1
+            }
+        };
+        self.normalize()?;
+        return Ok(bit);
+    }
+
+    fn direct_bits(&mut self, count: u32) -> Result<u32, DecompressError> {
+        let mut result = 0;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit = (self.code >= self.range) as u32;
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            result = (result << 1) | bit;
+            self.normalize()?;
+        }
+        return Ok(result);
+    }
+
+    fn tree(&mut self, probabilities: &mut [u16], bits: u32) -> Result<usize, DecompressError> {
+        let mut symbol = 1;
+        for _ in 0..bits {
+            symbol = (symbol << 1) | self.bit(&mut probabilities[symbol])?;
+        }
+        return Ok(symbol - (1 << bits));
+    }
+
+    fn reverse_tree(
+        &mut self,
+        probabilities: &mut [u16],
+        bits: u32,
+    ) -> Result<usize, DecompressError> {
+        let mut index = 1;
+        let mut symbol = 0;
+        for i in 0..bits {
+            let bit = self.bit(&mut probabilities[index])?;
+            index = (index << 1) | bit;
+            symbol |= bit << i;
+        }
+        return Ok(symbol);
+    }
+
+    fn is_finished(&self) -> bool {
+        return self.code == 0;
+    }
+}
+
+struct LengthDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 1 << 3]; MAX_POSITION_STATES],
+    mid: [[u16; 1 << 3]; MAX_POSITION_STATES],
+    high: [u16; 1 << 8],
+}
+
+impl LengthDecoder {
+    fn new() -> Self {
+        return LengthDecoder {
+            choice: PROBABILITY_INIT,
+            choice2: PROBABILITY_INIT,
+            low: [[PROBABILITY_INIT; 1 << 3]; MAX_POSITION_STATES],
+            mid: [[PROBABILITY_INIT; 1 << 3]; MAX_POSITION_STATES],
+            high: [PROBABILITY_INIT; 1 << 8],
+        };
+    }
+
+    fn decode(
+        &mut self,
+        rc: &mut RangeDecoder,
+        position_state: usize,
+    ) -> Result<usize, DecompressError> {
+        if rc.bit(&mut self.choice)? == 0 {
+            return rc.tree(&mut self.low[position_state], 3);
+        }
+        if rc.bit(&mut self.choice2)? == 0 {
+            return Ok(8 + rc.tree(&mut self.mid[position_state], 3)?);
+        }
+        return Ok(16 + rc.tree(&mut self.high, 8)?);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Properties {
+    literal_context_bits: u32,
+    literal_position_bits: u32,
+    position_bits: u32,
+}
+
+impl Properties {
+    fn new(byte: u8) -> Result<Self, DecompressError> {
+        if byte > MAX_PROPERTIES {
+            return Err(DecompressError::InvalidData);
+        }
+        return Ok(Properties {
+            literal_context_bits: (byte % 9) as u32,
+            literal_position_bits: ((byte / 9) % 5) as u32,
+            position_bits: (byte / 45) as u32,
+        });
+    }
+}
+
+struct LzmaDecoder {
+    properties: Properties,
+    literals: Vec<u16>,
+    is_match: [u16; STATES * MAX_POSITION_STATES],
+    is_rep: [u16; STATES],
+    is_rep_g0: [u16; STATES],
+    is_rep_g1: [u16; STATES],
+    is_rep_g2: [u16; STATES],
+    is_rep0_long: [u16; STATES * MAX_POSITION_STATES],
+    position_slot: [[u16; 1 << 6]; LENGTH_TO_POSITION_STATES],
+    position: [u16; 1 + FULL_DISTANCES - END_POSITION_MODEL_INDEX as usize],
+    align: [u16; 1 << ALIGN_BITS],
+    length: LengthDecoder,
+    rep_length: LengthDecoder,
+    state: usize,
+    reps: [usize; 4],
+    /// Start of the dictionary in the output, matches may not reach before it.
+    dictionary_start: usize,
+}
+
+impl LzmaDecoder {
+    fn new(properties: Properties) -> Self {
+        let literal_states =
+            0x300 << (properties.literal_context_bits + properties.literal_position_bits);
+        return LzmaDecoder {
+            properties,
+            literals: vec![PROBABILITY_INIT; literal_states],
+            is_match: [PROBABILITY_INIT; STATES * MAX_POSITION_STATES],
+            is_rep: [PROBABILITY_INIT; STATES],
+            is_rep_g0: [PROBABILITY_INIT; STATES],
+            is_rep_g1: [PROBABILITY_INIT; STATES],
+            is_rep_g2: [PROBABILITY_INIT; STATES],
+            is_rep0_long: [PROBABILITY_INIT; STATES * MAX_POSITION_STATES],
+            position_slot: [[PROBABILITY_INIT; 1 << 6]; LENGTH_TO_POSITION_STATES],
+            position: [PROBABILITY_INIT; 1 + FULL_DISTANCES - END_POSITION_MODEL_INDEX as usize],
+            align: [PROBABILITY_INIT; 1 << ALIGN_BITS],
+            length: LengthDecoder::new(),
+            rep_length: LengthDecoder::new(),
+            state: 0,
+            reps: [0; 4],
+            dictionary_start: 0,
+        };
+    }
+
+    /// Reset the probabilities and state, keeping the dictionary.
+    fn reset(&mut self, properties: Properties) {
+        *self = LzmaDecoder::new(properties);
+    }
+
+    fn literal(
+        &mut self,
+        rc: &mut RangeDecoder,
+        output: &mut Vec<u8>,
+    ) -> Result<(), DecompressError> {
+        let Properties {
+            literal_context_bits,
+            literal_position_bits,
+            ..
+        } = self.properties;
+
+        let previous = match output.len() > self.dictionary_start {
+            true => output[output.len() - 1] as usize,
+            false => 0,
+        };
+        let position = output.len() - self.dictionary_start;
+        let literal_state = ((position & ((1 << literal_position_bits) - 1))
+            << literal_context_bits)
+            + (previous >> (8 - literal_context_bits));
+        let probabilities = &mut self.literals[literal_state * 0x300..(literal_state + 1) * 0x300];
+
+        let mut symbol = 1;
+        if self.state >= LITERAL_STATES {
+            // After a match the byte at the last distance predicts the literal until they differ.
+            let mut match_byte = output[output.len() - self.reps[0] - 1] as usize;
+            while symbol < 0x100 {
+                let match_bit = (match_byte >> 7) & 1;
+                match_byte <<= 1;
+                let bit = rc.bit(&mut probabilities[((1 + match_bit) << 8) + symbol])?;
+                symbol = (symbol << 1) | bit;
+                if match_bit != bit {
+                    break;
+                }
+            }
+        }
+        while symbol < 0x100 {
+            symbol = (symbol << 1) | rc.bit(&mut probabilities[symbol])?;
+        }
+
+        output.push((symbol - 0x100) as u8);
+        self.state = match self.state {
+            0..4 => 0,
+            4..10 => self.state - 3,
+            _ => self.state - 6,
+        };
+        return Ok(());
+    }
+
+    fn distance(&mut self, rc: &mut RangeDecoder, length: usize) -> Result<usize, DecompressError> {
+        let length_state = length.min(LENGTH_TO_POSITION_STATES - 1);
+        let slot = rc.tree(&mut self.position_slot[length_state], 6)? as u32;
+        if slot < 4 {
+            return Ok(slot as usize);
+        }
+
+        let direct_bits = (slot >> 1) - 1;
+        let mut distance = ((2 | (slot & 1)) << direct_bits) as usize;
+        if slot < END_POSITION_MODEL_INDEX {
+            distance +=
+                rc.reverse_tree(&mut self.position[distance - slot as usize..], direct_bits)?;
+        } else {
+            distance += (rc.direct_bits(direct_bits - ALIGN_BITS)? as usize) << ALIGN_BITS;
+            distance += rc.reverse_tree(&mut self.align, ALIGN_BITS)?;
+        }
+        return Ok(distance);
+    }
+
+    /// Decode until `output` holds `target` bytes, or until an end marker when `end_marker` is
+    /// allowed. Returns whether the end marker was found. A match running past `target` is cut
+    /// short, as partial decoders of MicroLZMA data expect.
+    fn decode(
+        &mut self,
+        rc: &mut RangeDecoder,
+        output: &mut Vec<u8>,
+        target: usize,
+        end_marker: bool,
+    ) -> Result<bool, DecompressError> {
+        let position_mask = (1 << self.properties.position_bits) - 1;
+
+        while output.len() < target {
+            let position_state = (output.len() - self.dictionary_start) & position_mask;
+            let state = self.state;
+
+            if rc.bit(&mut self.is_match[(state << 4) + position_state])? == 0 {
+                self.literal(rc, output)?;
+                continue;
+            }
+
+            let length;
+            if rc.bit(&mut self.is_rep[state])? == 1 {
+                if output.len() == self.dictionary_start {
+                    return Err(DecompressError::InvalidData);
+                }
+
+                if rc.bit(&mut self.is_rep_g0[state])? == 0 {
+                    if rc.bit(&mut self.is_rep0_long[(state << 4) + position_state])? == 0 {
+                        // Short rep, a single byte from the last distance.
+                        self.state = match state < LITERAL_STATES {
+                            true => 9,
+                            false => 11,
+                        };
+                        output.push(output[output.len() - self.reps[0] - 1]);
+                        continue;
+                    }
+                } else {
+                    let distance;
+                    if rc.bit(&mut self.is_rep_g1[state])? == 0 {
+                        distance = self.reps[1];
+                    } else {
+                        if rc.bit(&mut self.is_rep_g2[state])? == 0 {
+                            distance = self.reps[2];
+                        } else {
+                            distance = self.reps[3];
+                            self.reps[3] = self.reps[2];
+                        }
+                        self.reps[2] = self.reps[1];
+                    }
+                    self.reps[1] = self.reps[0];
+                    self.reps[0] = distance;
+                }
+
+                length = self.rep_length.decode(rc, position_state)?;
+                self.state = match state < LITERAL_STATES {
+                    true => 8,
+                    false => 11,
+                };
+            } else {
+                self.reps = [0, self.reps[0], self.reps[1], self.reps[2]];
+                length = self.length.decode(rc, position_state)?;
+                self.state = match state < LITERAL_STATES {
+                    true => 7,
+                    false => 10,
+                };
+
+                let distance = self.distance(rc, length)?;
+                if distance == u32::MAX as usize {
+                    if !end_marker || !rc.is_finished() {
+                        return Err(DecompressError::InvalidData);
+                    }
+                    return Ok(true);
+                }
+                self.reps[0] = distance;
+            }
+
+            let distance = self.reps[0] + 1;
+            if distance > output.len() - self.dictionary_start {
+                return Err(DecompressError::InvalidData);
+            }
+            let count = (length + MATCH_MIN_LENGTH).min(target - output.len());
+            let start = output.len() - distance;
+            for i in 0..count {
+                output.push(output[start + i]);
+            }
+        }
+
+        return Ok(false);
+    }
+}
+
+fn next_byte(input: &[u8], position: &mut usize) -> Result<usize, DecompressError> {
+    let byte = *input.get(*position).ok_or(DecompressError::UnexpectedEnd)?;
+    *position += 1;
+    return Ok(byte as usize);
+}
+
+/// Decompress an LZMA2 stream, appending to `output` which may not grow past `limit`. Returns the
+/// number of input bytes consumed.
+pub fn lzma2_decompress(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    let mut position = 0;
+    let mut decoder: Option<LzmaDecoder> = None;
+    // Nothing can be referenced before the first dictionary reset.
+    let mut dictionary_start = None;
+
+    loop {
+        let control = next_byte(input, &mut position)?;
+        match control {
+            0x00 => return Ok(position),
+            0x01 | 0x02 => {
+                if control == 0x01 {
+                    dictionary_start = Some(output.len());
+                }
+                if dictionary_start.is_none() {
+                    return Err(DecompressError::InvalidData);
+                }
+
+                let size =
+                    (next_byte(input, &mut position)? << 8 | next_byte(input, &mut position)?) + 1;
+                let data = input
+                    .get(position..position + size)
+                    .ok_or(DecompressError::UnexpectedEnd)?;
+                if output.len() + size > limit {
+                    return Err(DecompressError::OutputLimit);
+                }
+                output.extend_from_slice(data);
+                position += size;
+            }
+            0x80.. => {
+                let unpacked = ((control & 0x1F) << 16
+                    | next_byte(input, &mut position)? << 8
+                    | next_byte(input, &mut position)?)
+                    + 1;
+                let packed =
+                    (next_byte(input, &mut position)? << 8 | next_byte(input, &mut position)?) + 1;
+                let reset = (control >> 5) & 3;
+
+                if reset == 3 {
+                    dictionary_start = Some(output.len());
+                }
+                let Some(dictionary_start) = dictionary_start else {
+                    return Err(DecompressError::InvalidData);
+                };
+
+                if reset >= 2 {
+                    let properties = Properties::new(next_byte(input, &mut position)? as u8)?;
+                    if properties.literal_context_bits + properties.literal_position_bits
+                        > LZMA2_MAX_LITERAL_BITS
+                    {
+                        return Err(DecompressError::InvalidData);
+                    }
+                    decoder = Some(LzmaDecoder::new(properties));
+                } else if reset == 1 {
+                    let decoder = decoder.as_mut().ok_or(DecompressError::InvalidData)?;
+                    decoder.reset(decoder.properties);
+                }
+                // The first LZMA chunk has to set the properties.
+                let decoder = decoder.as_mut().ok_or(DecompressError::InvalidData)?;
+                decoder.dictionary_start = dictionary_start;
+
+                let target = output.len() + unpacked;
+                if target > limit {
+                    return Err(DecompressError::OutputLimit);
+                }
+                let data = input
+                    .get(position..position + packed)
+                    .ok_or(DecompressError::UnexpectedEnd)?;
+                let mut rc = RangeDecoder::new(data)?;
+                decoder.decode(&mut rc, output, target, false)?;
+                if output.len() != target || rc.position != packed {
+                    return Err(DecompressError::InvalidData);
+                }
+                position += packed;
+            }
+            _ => return Err(DecompressError::InvalidData),
+        }
+    }
+}
+
+/// Decompress MicroLZMA data as used by EROFS: the properties byte is stored inverted, the
+/// leading zero byte of the range coder is left out and there is no end marker, so the size of
+/// the output has to be known.
+pub fn microlzma_decompress(input: &[u8], size: usize) -> Result<Vec<u8>, DecompressError> {
+    let properties = Properties::new(!*input.first().ok_or(DecompressError::UnexpectedEnd)?)?;
+    let mut rc = RangeDecoder::without_leading_zero(input, 1)?;
+    let mut decoder = LzmaDecoder::new(properties);
+    let mut output = Vec::with_capacity(size);
+
+    decoder.decode(&mut rc, &mut output, size, false)?;
+
+    return Ok(output);
+}
@@ -0,0 +1,156 @@
+/*
+ * LZO1X decompression, following the instruction decoding of Linux's lib/lzo/lzo1x_decompress_safe.c
+ * https://docs.kernel.org/staging/lzo.html
+*/
+
+use alloc::vec::Vec;
+
+use crate::decompress::DecompressError;
+
+/// Largest offset of a short match following a literal run.
+const M2_MAX_OFFSET: usize = 0x0800;
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Input<'_> {
+    fn byte(&mut self) -> Result<usize, DecompressError> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        self.position += 1;
+        return Ok(byte as usize);
+    }
+
+    fn le16(&mut self) -> Result<usize, DecompressError> {
+        return Ok(self.byte()? | self.byte()? << 8);
+    }
+
+    /// Lengths that don't fit their instruction are extended by a run of zero bytes worth 255
+    /// each, terminated by a non zero byte.
+    fn extended_length(&mut self, base: usize) -> Result<usize, DecompressError> {
+        let mut length = base;
+        loop {
+            match self.byte()? {
+                0 => length += 255,
+                byte => return Ok(length + byte),
+            }
+        }
+    }
+
+    fn literals(
+        &mut self,
+        count: usize,
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<(), DecompressError> {
+        let data = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        if output.len() + count > limit {
+            return Err(DecompressError::OutputLimit);
+        }
+        output.extend_from_slice(data);
+        self.position += count;
+        return Ok(());
+    }
+}
+
+fn copy_match(
+    output: &mut Vec<u8>,
+    distance: usize,
+    length: usize,
+    limit: usize,
+) -> Result<(), DecompressError> {
+    if distance == 0 || distance > output.len() {
+        return Err(DecompressError::InvalidData);
+    }
+    if output.len() + length > limit {
+        return Err(DecompressError::OutputLimit);
+    }
+
+    let start = output.len() - distance;
+    for i in 0..length {
+        output.push(output[start + i]);
+    }
+    return Ok(());
+}
+
+/// Decompress a single LZO1X block, appending to `output` which may not grow past `limit`.
+pub fn decompress(input: &[u8], output: &mut Vec<u8>, limit: usize) -> Result<(), DecompressError> {
+    let mut input = Input {
+        data: input,
+        position: 0,
+    };
+    // Number of literals copied by the previous instruction, 4 meaning a long literal run.
+    let mut state = 0;
+
+    if input.data.first().is_some_and(|&byte| byte > 17) {
+        let count = input.byte()? - 17;
+        input.literals(count, output, limit)?;
+        state = match count {
+            0..4 => count,
+            _ => 4,
+        };
+    }
+
+    loop {
+        let instruction = input.byte()?;
+
+        let (distance, length, next) = match instruction {
+            0..16 if state == 0 => {
+                let count = match instruction {
+                    0 => input.extended_length(15)?,
+                    _ => instruction,
+                } + 3;
+                input.literals(count, output, limit)?;
+                state = 4;
+                continue;
+            }
+            0..16 if state != 4 => {
+                let distance = 1 + (instruction >> 2) + (input.byte()? << 2);
+                (distance, 2, instruction & 3)
+            }
+            0..16 => {
+                let distance = 1 + M2_MAX_OFFSET + (instruction >> 2) + (input.byte()? << 2);
+                (distance, 3, instruction & 3)
+            }
+            64.. => {
+                let distance = 1 + ((instruction >> 2) & 7) + (input.byte()? << 3);
+                (distance, (instruction >> 5) + 1, instruction & 3)
+            }
+            32.. => {
+                let length = match instruction & 31 {
+                    0 => input.extended_length(31)?,
+                    bits => bits,
+                } + 2;
+                let value = input.le16()?;
+                (1 + (value >> 2), length, value & 3)
+            }
+            _ => {
+                let length = match instruction & 7 {
+                    0 => input.extended_length(7)?,
+                    bits => bits,
+                } + 2;
+                let value = input.le16()?;
+                let distance = ((instruction & 8) << 11) + (value >> 2);
+                if distance == 0 {
+                    // End of stream marker, always encoded with a length of 3.
+                    return match length == 3 {
+                        true => Ok(()),
+                        false => Err(DecompressError::InvalidData),
+                    };
+                }
+                (distance + 0x4000, length, value & 3)
+            }
+        };
+
+        copy_match(output, distance, length, limit)?;
+        input.literals(next, output, limit)?;
+        state = next;
+    }
+}
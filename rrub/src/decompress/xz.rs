@@ -0,0 +1,365 @@
+/*
+ * https://tukaani.org/xz/xz-file-format.txt
+ * The x86 BCJ filter follows simple/x86.c from xz.
+*/
+
+use alloc::vec::Vec;
+
+use crate::{
+    checksum::{crc32, crc64_xz},
+    decompress::{DecompressError, lzma::lzma2_decompress},
+};
+
+pub const STREAM_MAGIC: &[u8; 6] = b"\xFD7zXZ\0";
+const FOOTER_MAGIC: &[u8; 2] = b"YZ";
+const STREAM_HEADER_SIZE: usize = 12;
+const STREAM_FOOTER_SIZE: usize = 12;
+
+const CHECK_NONE: u8 = 0x00;
+const CHECK_CRC32: u8 = 0x01;
+const CHECK_CRC64: u8 = 0x04;
+/// Size of the check for each check id, checks other than CRC32 and CRC64 are skipped.
+const CHECK_SIZES: [usize; 16] = [0, 4, 4, 4, 8, 8, 8, 16, 16, 16, 32, 32, 32, 64, 64, 64];
+
+const BLOCK_FILTER_COUNT_MASK: u8 = 0x03;
+const BLOCK_COMPRESSED_SIZE: u8 = 0x40;
+const BLOCK_UNCOMPRESSED_SIZE: u8 = 0x80;
+const BLOCK_RESERVED: u8 = 0x3C;
+
+const FILTER_X86: u64 = 0x04;
+const FILTER_LZMA2: u64 = 0x21;
+
+const MAX_VLI_BYTES: usize = 9;
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], DecompressError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        self.position += count;
+        return Ok(bytes);
+    }
+
+    fn byte(&mut self) -> Result<u8, DecompressError> {
+        return Ok(self.bytes(1)?[0]);
+    }
+
+    fn vli(&mut self) -> Result<u64, DecompressError> {
+        let mut value = 0;
+        for i in 0..MAX_VLI_BYTES {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << (i * 7);
+            if byte & 0x80 == 0 {
+                // Values have to use the shortest encoding.
+                if byte == 0 && i != 0 {
+                    return Err(DecompressError::InvalidData);
+                }
+                return Ok(value);
+            }
+        }
+        return Err(DecompressError::InvalidData);
+    }
+
+    /// Skip the zero padding up to the next multiple of four from `start`.
+    fn padding(&mut self, start: usize) -> Result<(), DecompressError> {
+        while !(self.position - start).is_multiple_of(4) {
+            if self.byte()? != 0 {
+                return Err(DecompressError::InvalidData);
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    return u32::from_le_bytes(bytes[..4].try_into().unwrap());
+}
+
+/// Undo the x86 branch converter, which turns relative CALL and JMP targets into absolute ones to
+/// make them compress better. The last four bytes are never converted.
+fn x86_decode(buffer: &mut [u8], start: u32) {
+    const ALLOWED: [bool; 8] = [true, true, true, false, true, false, false, false];
+    const BIT_NUMBER: [u32; 8] = [0, 1, 2, 2, 3, 3, 3, 3];
+    let is_ms_byte = |byte: u8| byte == 0x00 || byte == 0xFF;
+
+    if buffer.len() < 5 {
+        return;
+    }
+
+    let mut previous_mask = 0u32;
+    let mut previous_position = start.wrapping_sub(5);
+    let mut position = 0;
+    while position <= buffer.len() - 5 {
+        if buffer[position] != 0xE8 && buffer[position] != 0xE9 {
+            position += 1;
+            continue;
+        }
+
+        let now = start.wrapping_add(position as u32);
+        let offset = now.wrapping_sub(previous_position);
+        previous_position = now;
+        match offset > 5 {
+            true => previous_mask = 0,
+            false => {
+                for _ in 0..offset {
+                    previous_mask = (previous_mask & 0x77) << 1;
+                }
+            }
+        }
+
+        let byte = buffer[position + 4];
+        if !is_ms_byte(byte)
+            || !ALLOWED[((previous_mask >> 1) & 0x7) as usize]
+            || (previous_mask >> 1) >= 0x10
+        {
+            position += 1;
+            previous_mask |= 1;
+            if is_ms_byte(byte) {
+                previous_mask |= 0x10;
+            }
+            continue;
+        }
+
+        let mut source = u32::from_le_bytes(buffer[position + 1..position + 5].try_into().unwrap());
+        let mut destination;
+        loop {
+            destination = source.wrapping_sub(now.wrapping_add(5));
+            if previous_mask == 0 {
+                break;
+            }
+            let i = BIT_NUMBER[(previous_mask >> 1) as usize];
+            if !is_ms_byte((destination >> (24 - i * 8)) as u8) {
+                break;
+            }
+            source = destination ^ ((1 << (32 - i * 8)) - 1);
+        }
+
+        // Only 25 bits of the target are kept, the top byte is the sign extension of bit 24.
+        let destination =
+            (destination & 0x01FF_FFFF) | (((destination >> 24) & 1).wrapping_neg() << 24);
+        buffer[position + 1..position + 5].copy_from_slice(&destination.to_le_bytes());
+        position += 5;
+        previous_mask = 0;
+    }
+}
+
+/// Decode a block, returning its unpadded and uncompressed sizes as recorded in the index.
+fn decode_block(
+    input: &mut Input,
+    check: u8,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(u64, u64), DecompressError> {
+    let start = input.position;
+    let header_size = (input.byte()? as usize + 1) * 4;
+    let header = input.bytes(header_size - 1)?;
+    if crc32(&input.data[start..start + header_size - 4]) != read_u32(&header[header_size - 5..]) {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+
+    let mut header = Input {
+        data: &input.data[..start + header_size - 4],
+        position: start + 1,
+    };
+    let flags = header.byte()?;
+    if flags & BLOCK_RESERVED != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+    let compressed_size = match flags & BLOCK_COMPRESSED_SIZE != 0 {
+        true => Some(header.vli()?),
+        false => None,
+    };
+    let uncompressed_size = match flags & BLOCK_UNCOMPRESSED_SIZE != 0 {
+        true => Some(header.vli()?),
+        false => None,
+    };
+
+    // LZMA2 is the only supported compression filter and has to come last, optionally after BCJ.
+    let mut x86_start = None;
+    let filters = (flags & BLOCK_FILTER_COUNT_MASK) + 1;
+    for index in 0..filters {
+        let id = header.vli()?;
+        let size = header.vli()? as usize;
+        let properties = header.bytes(size)?;
+        match (id, properties.len()) {
+            (FILTER_LZMA2, 1) if index == filters - 1 => {}
+            (FILTER_X86, 0) if index != filters - 1 && x86_start.is_none() => {
+                x86_start = Some(0);
+            }
+            (FILTER_X86, 4) if index != filters - 1 && x86_start.is_none() => {
+                x86_start = Some(read_u32(properties));
+            }
+            _ => return Err(DecompressError::InvalidData),
+        }
+    }
+    while header.position < header.data.len() {
+        if header.byte()? != 0 {
+            return Err(DecompressError::InvalidData);
+        }
+    }
+
+    let data_start = input.position;
+    let output_start = output.len();
+    let consumed = lzma2_decompress(&input.data[data_start..], output, limit)?;
+    input.position += consumed;
+
+    let size = (output.len() - output_start) as u64;
+    if compressed_size.is_some_and(|expected| expected != consumed as u64)
+        || uncompressed_size.is_some_and(|expected| expected != size)
+    {
+        return Err(DecompressError::InvalidData);
+    }
+    if let Some(x86_start) = x86_start {
+        x86_decode(&mut output[output_start..], x86_start);
+    }
+
+    input.padding(data_start)?;
+    let stored = input.bytes(CHECK_SIZES[check as usize])?;
+    let data = &output[output_start..];
+    let valid = match check {
+        CHECK_CRC32 => crc32(data) == read_u32(stored),
+        CHECK_CRC64 => crc64_xz(data) == u64::from_le_bytes(stored.try_into().unwrap()),
+        _ => true,
+    };
+    if !valid {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+
+    let unpadded = (header_size + consumed + stored.len()) as u64;
+    return Ok((unpadded, size));
+}
+
+/// Decode one stream, checking its index against the blocks that were decoded.
+fn decode_stream(
+    input: &mut Input,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), DecompressError> {
+    let header = input.bytes(STREAM_HEADER_SIZE)?;
+    if &header[..6] != STREAM_MAGIC {
+        return Err(DecompressError::InvalidData);
+    }
+    let flags = &header[6..8];
+    if flags[0] != 0 || flags[1] & 0xF0 != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+    if crc32(flags) != read_u32(&header[8..]) {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+    let check = flags[1];
+
+    let mut blocks = Vec::new();
+    while input
+        .data
+        .get(input.position)
+        .ok_or(DecompressError::UnexpectedEnd)?
+        != &0
+    {
+        blocks.push(decode_block(input, check, output, limit)?);
+    }
+
+    let index_start = input.position;
+    input.byte()?;
+    if input.vli()? != blocks.len() as u64 {
+        return Err(DecompressError::InvalidData);
+    }
+    for &(unpadded, uncompressed) in &blocks {
+        if input.vli()? != unpadded || input.vli()? != uncompressed {
+            return Err(DecompressError::InvalidData);
+        }
+    }
+    input.padding(index_start)?;
+    let index_end = input.position;
+    if crc32(&input.data[index_start..index_end]) != read_u32(input.bytes(4)?) {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+
+    let footer = input.bytes(STREAM_FOOTER_SIZE)?;
+    let backward_size = (read_u32(&footer[4..]) as usize + 1) * 4;
+    if &footer[10..] != FOOTER_MAGIC
+        || footer[8..10] != *flags
+        || backward_size != index_end + 4 - index_start
+    {
+        return Err(DecompressError::InvalidData);
+    }
+    if crc32(&footer[4..10]) != read_u32(footer) {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+
+    return Ok(());
+}
+
+/// Decompress the xz streams at the start of `input`, appending to `output` which may not grow
+/// past `limit`. Returns the number of input bytes the streams and the padding between them took.
+pub fn decompress_streams(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    let mut input = Input {
+        data: input,
+        position: 0,
+    };
+
+    loop {
+        decode_stream(&mut input, output, limit)?;
+
+        let padding_start = input.position;
+        while input.data.get(input.position) == Some(&0) {
+            input.position += 1;
+        }
+        if !(input.position - padding_start).is_multiple_of(4)
+            || !input.data[input.position..].starts_with(STREAM_MAGIC)
+        {
+            return Ok(padding_start);
+        }
+    }
+}
+
+/// Size `input` decompresses to going by the index of its last stream, which is all of it for a
+/// file of one stream.
+pub fn content_size(input: &[u8]) -> Option<u64> {
+    // Stream padding is zeros and footers end in their magic.
+    let end = input.iter().rposition(|&byte| byte != 0)? + 1;
+    let footer_start = end.checked_sub(STREAM_FOOTER_SIZE)?;
+    let footer = &input[footer_start..end];
+    if &footer[10..] != FOOTER_MAGIC {
+        return None;
+    }
+
+    let backward_size = (read_u32(&footer[4..]) as usize + 1) * 4;
+    let mut index = Input {
+        data: &input[footer_start.checked_sub(backward_size)?..footer_start],
+        position: 0,
+    };
+    if index.byte().ok()? != 0 {
+        return None;
+    }
+    let mut size = 0u64;
+    for _ in 0..index.vli().ok()? {
+        index.vli().ok()?;
+        size = size.checked_add(index.vli().ok()?)?;
+    }
+    return Some(size);
+}
+
+/// Decompress an xz file made of one or more streams, which may be separated by padding.
+pub fn decompress(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut output = Vec::new();
+    if let Some(size) = content_size(input) {
+        output.reserve_exact(size.min(limit as u64) as usize);
+    }
+    let consumed = decompress_streams(input, &mut output, limit)?;
+
+    let padding = &input[consumed..];
+    if padding.iter().any(|&byte| byte != 0) || !padding.len().is_multiple_of(4) {
+        return Err(DecompressError::InvalidData);
+    }
+    return Ok(output);
+}
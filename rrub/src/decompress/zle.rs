@@ -0,0 +1,38 @@
+/*
+ * Zero length encoding, the ZFS compression that only collapses runs of zeros
+ * https://github.com/openzfs/zfs/blob/master/module/zfs/zle.c
+*/
+
+use alloc::vec::Vec;
+
+use crate::decompress::DecompressError;
+
+/// Runs up to this length are literals, longer ones stand for zeros. ZFS always uses 64.
+const LITERAL_MAX: usize = 64;
+
+/// Decompress into exactly `size` bytes. Each run starts with its length less one.
+pub fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut output = Vec::with_capacity(size);
+    let mut position = 0;
+
+    while output.len() < size {
+        let length = 1 + *input.get(position).ok_or(DecompressError::UnexpectedEnd)? as usize;
+        position += 1;
+
+        match length <= LITERAL_MAX {
+            true => {
+                let literals = input
+                    .get(position..position + length)
+                    .ok_or(DecompressError::UnexpectedEnd)?;
+                output.extend_from_slice(literals);
+                position += length;
+            }
+            false => output.resize(output.len() + length - LITERAL_MAX, 0),
+        }
+        if output.len() > size {
+            return Err(DecompressError::OutputLimit);
+        }
+    }
+
+    return Ok(output);
+}
@@ -0,0 +1,923 @@
+/*
+ * https://www.rfc-editor.org/rfc/rfc8878
+ * Structure follows the educational decoder from the zstd repository.
+*/
+
+use alloc::{vec, vec::Vec};
+
+use crate::{checksum::xxh64, decompress::DecompressError};
+
+pub const FRAME_MAGIC: u32 = 0xFD2F_B528;
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A50;
+const SKIPPABLE_MASK: u32 = 0xFFFF_FFF0;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+
+const MAX_HUFFMAN_BITS: u8 = 11;
+const MAX_HUFFMAN_SYMBOLS: usize = 256;
+
+const LITERAL_LENGTH_MAX_LOG: u8 = 9;
+const MATCH_LENGTH_MAX_LOG: u8 = 9;
+const OFFSET_MAX_LOG: u8 = 8;
+const MAX_LITERAL_LENGTH_CODE: usize = 35;
+const MAX_MATCH_LENGTH_CODE: usize = 52;
+const MAX_OFFSET_CODE: usize = 31;
+
+const LITERAL_LENGTH_DEFAULT: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
+    -1, -1, -1, -1,
+];
+const MATCH_LENGTH_DEFAULT: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+];
+const OFFSET_DEFAULT: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+
+const LITERAL_LENGTH_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LITERAL_LENGTH_BITS: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+const MATCH_LENGTH_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const MATCH_LENGTH_BITS: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+
+/// Forward bit stream, least significant bit first.
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl ForwardBits<'_> {
+    fn read(&mut self, count: u8) -> Result<u32, DecompressError> {
+        let mut value = 0u32;
+        for i in 0..count as usize {
+            let bit = self.position + i;
+            let byte = *self
+                .data
+                .get(bit / 8)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            value |= ((byte >> (bit % 8)) as u32 & 1) << i;
+        }
+        self.position += count as usize;
+        return Ok(value);
+    }
+
+    fn peek(&mut self, count: u8) -> Result<u32, DecompressError> {
+        let position = self.position;
+        let value = self.read(count);
+        self.position = position;
+        return value;
+    }
+
+    fn bytes_consumed(&self) -> usize {
+        return self.position.div_ceil(8);
+    }
+}
+
+/// Backward bit stream, read from the highest bit down towards the start of the data. Reading
+/// past the start yields zeros and is detected through `overflowed`.
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    /// Bits left to read, negative once reads went past the start.
+    position: isize,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> Result<BackwardBits<'a>, DecompressError> {
+        // The last byte holds a 1 bit marking where the stream starts.
+        let last = *data.last().ok_or(DecompressError::InvalidData)?;
+        if last == 0 {
+            return Err(DecompressError::InvalidData);
+        }
+
+        let padding = last.leading_zeros() as isize + 1;
+        return Ok(BackwardBits {
+            data,
+            position: data.len() as isize * 8 - padding,
+        });
+    }
+
+    fn read(&mut self, count: u8) -> u64 {
+        if count == 0 {
+            return 0;
+        }
+
+        let count = count as isize;
+        let start = self.position - count;
+        self.position = start;
+
+        // Bits before the start of the stream read as zeros.
+        let (start, missing) = match start < 0 {
+            true => (0usize, (-start) as u32),
+            false => (start as usize, 0),
+        };
+        let available = count - missing as isize;
+        if available <= 0 {
+            return 0;
+        }
+
+        let mut raw = [0u8; 8];
+        let first = start / 8;
+        let end = (first + 8).min(self.data.len());
+        raw[..end - first].copy_from_slice(&self.data[first..end]);
+
+        let value = (u64::from_le_bytes(raw) >> (start % 8)) & ((1u64 << available) - 1);
+        return value << missing;
+    }
+
+    fn overflowed(&self) -> bool {
+        return self.position < 0;
+    }
+
+    fn finished(&self) -> bool {
+        return self.position == 0;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    base: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+struct FseTable {
+    accuracy_log: u8,
+    entries: Vec<FseEntry>,
+}
+
+impl FseTable {
+    fn from_distribution(
+        distribution: &[i16],
+        accuracy_log: u8,
+    ) -> Result<FseTable, DecompressError> {
+        let size = 1usize << accuracy_log;
+        let mut entries = vec![FseEntry::default(); size];
+        let mut next = vec![0u16; distribution.len()];
+
+        // Symbols with a "less than one" probability take the last cells.
+        let mut high = size - 1;
+        for (symbol, &probability) in distribution.iter().enumerate() {
+            if probability == -1 {
+                entries[high].symbol = symbol as u8;
+                high = high.wrapping_sub(1);
+                next[symbol] = 1;
+            }
+        }
+
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut position = 0;
+        for (symbol, &probability) in distribution.iter().enumerate() {
+            if probability <= 0 {
+                continue;
+            }
+            next[symbol] = probability as u16;
+            for _ in 0..probability {
+                entries[position].symbol = symbol as u8;
+                position = (position + step) & (size - 1);
+                while position > high {
+                    position = (position + step) & (size - 1);
+                }
+            }
+        }
+        if position != 0 {
+            return Err(DecompressError::InvalidData);
+        }
+
+        for entry in entries.iter_mut() {
+            let state = next[entry.symbol as usize];
+            next[entry.symbol as usize] += 1;
+
+            let bits = accuracy_log - (15 - state.leading_zeros() as u8);
+            entry.bits = bits;
+            entry.base = ((state as u32) << bits).wrapping_sub(size as u32) as u16;
+        }
+
+        return Ok(FseTable {
+            accuracy_log,
+            entries,
+        });
+    }
+
+    fn rle(symbol: u8) -> FseTable {
+        return FseTable {
+            accuracy_log: 0,
+            entries: vec![FseEntry {
+                symbol,
+                bits: 0,
+                base: 0,
+            }],
+        };
+    }
+
+    /// Parse a compressed distribution, returning the table and the bytes consumed.
+    fn read(
+        data: &[u8],
+        max_log: u8,
+        max_symbol: usize,
+    ) -> Result<(FseTable, usize), DecompressError> {
+        let mut bits = ForwardBits { data, position: 0 };
+
+        let accuracy_log = bits.read(4)? as u8 + 5;
+        if accuracy_log > max_log {
+            return Err(DecompressError::InvalidData);
+        }
+
+        let mut distribution = Vec::new();
+        let mut remaining = (1i32 << accuracy_log) + 1;
+        let mut threshold = 1i32 << accuracy_log;
+        let mut width = accuracy_log + 1;
+
+        while remaining > 1 {
+            if distribution.len() > max_symbol {
+                return Err(DecompressError::InvalidData);
+            }
+
+            let max = 2 * threshold - 1 - remaining;
+            let low = bits.peek(width - 1)? as i32;
+            let count = match low < max {
+                true => {
+                    bits.read(width - 1)?;
+                    low
+                }
+                false => {
+                    let value = bits.read(width)? as i32;
+                    match value >= threshold {
+                        true => value - max,
+                        false => value,
+                    }
+                }
+            } - 1;
+
+            remaining -= count.abs();
+            distribution.push(count as i16);
+
+            if count == 0 {
+                loop {
+                    let repeat = bits.read(2)?;
+                    distribution.extend(core::iter::repeat_n(0, repeat as usize));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+
+            while remaining < threshold {
+                width -= 1;
+                threshold >>= 1;
+            }
+        }
+
+        if remaining != 1 || distribution.len() > max_symbol + 1 {
+            return Err(DecompressError::InvalidData);
+        }
+
+        let table = FseTable::from_distribution(&distribution, accuracy_log)?;
+        return Ok((table, bits.bytes_consumed()));
+    }
+
+    fn init(&self, bits: &mut BackwardBits) -> usize {
+        return bits.read(self.accuracy_log) as usize;
+    }
+
+    fn symbol(&self, state: usize) -> u8 {
+        return self.entries[state].symbol;
+    }
+
+    fn update(&self, state: usize, bits: &mut BackwardBits) -> usize {
+        let entry = self.entries[state];
+        return entry.base as usize + bits.read(entry.bits) as usize;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct HuffmanTable {
+    max_bits: u8,
+    /// (symbol, bits) for every `max_bits` wide prefix.
+    entries: Vec<(u8, u8)>,
+}
+
+impl HuffmanTable {
+    fn from_weights(weights: &[u8]) -> Result<HuffmanTable, DecompressError> {
+        if weights.len() + 1 > MAX_HUFFMAN_SYMBOLS {
+            return Err(DecompressError::InvalidData);
+        }
+
+        let mut total = 0u32;
+        for &weight in weights {
+            if weight > MAX_HUFFMAN_BITS {
+                return Err(DecompressError::InvalidData);
+            }
+            if weight > 0 {
+                total += 1 << (weight - 1);
+            }
+        }
+        if total == 0 {
+            return Err(DecompressError::InvalidData);
+        }
+
+        // The last weight is implied by rounding the total up to a power of two.
+        let max_bits = (32 - total.leading_zeros()) as u8;
+        let left = (1u32 << max_bits) - total;
+        if !left.is_power_of_two() || max_bits > MAX_HUFFMAN_BITS {
+            return Err(DecompressError::InvalidData);
+        }
+        let mut weights = weights.to_vec();
+        weights.push(left.trailing_zeros() as u8 + 1);
+
+        let bits: Vec<u8> = weights
+            .iter()
+            .map(|&weight| match weight {
+                0 => 0,
+                _ => max_bits + 1 - weight,
+            })
+            .collect();
+
+        let mut rank_count = [0u32; MAX_HUFFMAN_BITS as usize + 2];
+        for &bits in &bits {
+            rank_count[bits as usize] += 1;
+        }
+
+        let mut rank_start = [0u32; MAX_HUFFMAN_BITS as usize + 2];
+        for length in (1..=max_bits as usize).rev() {
+            rank_start[length - 1] =
+                rank_start[length] + rank_count[length] * (1 << (max_bits as usize - length));
+        }
+
+        let mut entries = vec![(0u8, 0u8); 1 << max_bits];
+        for (symbol, &length) in bits.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let start = rank_start[length as usize] as usize;
+            let count = 1usize << (max_bits - length);
+            entries[start..start + count].fill((symbol as u8, length));
+            rank_start[length as usize] += count as u32;
+        }
+
+        return Ok(HuffmanTable { max_bits, entries });
+    }
+
+    /// Parse a tree description, returning the table and the bytes consumed.
+    fn read(data: &[u8]) -> Result<(HuffmanTable, usize), DecompressError> {
+        let header = *data.first().ok_or(DecompressError::UnexpectedEnd)? as usize;
+
+        let (weights, consumed) = match header {
+            0..128 => {
+                let compressed = data
+                    .get(1..1 + header)
+                    .ok_or(DecompressError::UnexpectedEnd)?;
+                let (table, used) = FseTable::read(compressed, 6, 255)?;
+                let mut bits = BackwardBits::new(&compressed[used..])?;
+
+                // Two interleaved states share the stream until it runs out.
+                let mut weights = Vec::new();
+                let mut first = table.init(&mut bits);
+                let mut second = table.init(&mut bits);
+                loop {
+                    weights.push(table.symbol(first));
+                    first = table.update(first, &mut bits);
+                    if bits.overflowed() {
+                        weights.push(table.symbol(second));
+                        break;
+                    }
+
+                    weights.push(table.symbol(second));
+                    second = table.update(second, &mut bits);
+                    if bits.overflowed() {
+                        weights.push(table.symbol(first));
+                        break;
+                    }
+
+                    if weights.len() > MAX_HUFFMAN_SYMBOLS {
+                        return Err(DecompressError::InvalidData);
+                    }
+                }
+                (weights, 1 + header)
+            }
+            _ => {
+                let count = header - 127;
+                let packed = data
+                    .get(1..1 + count.div_ceil(2))
+                    .ok_or(DecompressError::UnexpectedEnd)?;
+                let weights = (0..count)
+                    .map(|i| match i % 2 {
+                        0 => packed[i / 2] >> 4,
+                        _ => packed[i / 2] & 0xF,
+                    })
+                    .collect();
+                (weights, 1 + count.div_ceil(2))
+            }
+        };
+
+        return Ok((HuffmanTable::from_weights(&weights)?, consumed));
+    }
+
+    fn decode_stream(
+        &self,
+        data: &[u8],
+        output: &mut Vec<u8>,
+        count: usize,
+    ) -> Result<(), DecompressError> {
+        let mut bits = BackwardBits::new(data)?;
+
+        for _ in 0..count {
+            let position = bits.position;
+            let prefix = bits.read(self.max_bits) as usize;
+            let (symbol, length) = self.entries[prefix];
+            bits.position = position - length as isize;
+            output.push(symbol);
+        }
+
+        if !bits.finished() {
+            return Err(DecompressError::InvalidData);
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Default)]
+struct FrameState {
+    huffman: Option<HuffmanTable>,
+    literal_lengths: Option<FseTable>,
+    offsets: Option<FseTable>,
+    match_lengths: Option<FseTable>,
+    repeat_offsets: [usize; 3],
+    window_size: usize,
+}
+
+struct FrameHeader {
+    window_size: usize,
+    content_size: Option<u64>,
+    has_checksum: bool,
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], DecompressError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        self.position += count;
+        return Ok(bytes);
+    }
+
+    fn byte(&mut self) -> Result<u8, DecompressError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn le(&mut self, count: usize) -> Result<u64, DecompressError> {
+        let mut raw = [0u8; 8];
+        raw[..count].copy_from_slice(self.take(count)?);
+        return Ok(u64::from_le_bytes(raw));
+    }
+}
+
+fn decode_literals(block: &mut Cursor, state: &mut FrameState) -> Result<Vec<u8>, DecompressError> {
+    let first = block.byte()? as usize;
+    let kind = first & 3;
+    let size_format = (first >> 2) & 3;
+
+    if kind < 2 {
+        let size = match size_format {
+            0 | 2 => first >> 3,
+            1 => (first >> 4) + ((block.byte()? as usize) << 4),
+            _ => (first >> 4) + ((block.le(2)? as usize) << 4),
+        };
+        return match kind {
+            0 => Ok(block.take(size)?.to_vec()),
+            _ => Ok(vec![block.byte()?; size]),
+        };
+    }
+
+    let (header_size, field_bits, streams) = match size_format {
+        0 => (3, 10, 1),
+        1 => (3, 10, 4),
+        2 => (4, 14, 4),
+        _ => (5, 18, 4),
+    };
+    let header = (block.le(header_size - 1)? << 8 | first as u64) >> 4;
+    let mask = (1u64 << field_bits) - 1;
+    let regenerated = (header & mask) as usize;
+    let compressed = ((header >> field_bits) & mask) as usize;
+    if regenerated > MAX_BLOCK_SIZE {
+        return Err(DecompressError::InvalidData);
+    }
+
+    let mut data = block.take(compressed)?;
+    if kind == 2 {
+        let (table, used) = HuffmanTable::read(data)?;
+        state.huffman = Some(table);
+        data = &data[used..];
+    }
+    let table = state.huffman.as_ref().ok_or(DecompressError::InvalidData)?;
+
+    let mut literals = Vec::with_capacity(regenerated);
+    match streams {
+        1 => table.decode_stream(data, &mut literals, regenerated)?,
+        _ => {
+            let jump = data.get(..6).ok_or(DecompressError::UnexpectedEnd)?;
+            let sizes = [
+                u16::from_le_bytes([jump[0], jump[1]]) as usize,
+                u16::from_le_bytes([jump[2], jump[3]]) as usize,
+                u16::from_le_bytes([jump[4], jump[5]]) as usize,
+            ];
+            let per_stream = regenerated.div_ceil(4);
+
+            let mut offset = 6;
+            for stream in 0..4 {
+                // The last stream takes the remaining input and output.
+                let (size, count) = match sizes.get(stream) {
+                    Some(&size) => (size, per_stream),
+                    None => (
+                        data.len()
+                            .checked_sub(offset)
+                            .ok_or(DecompressError::InvalidData)?,
+                        regenerated
+                            .checked_sub(3 * per_stream)
+                            .ok_or(DecompressError::InvalidData)?,
+                    ),
+                };
+                let stream = data
+                    .get(offset..offset + size)
+                    .ok_or(DecompressError::UnexpectedEnd)?;
+                table.decode_stream(stream, &mut literals, count)?;
+                offset += size;
+            }
+        }
+    }
+
+    return Ok(literals);
+}
+
+fn read_table(
+    block: &mut Cursor,
+    mode: u8,
+    current: &mut Option<FseTable>,
+    default: &[i16],
+    default_log: u8,
+    max_log: u8,
+    max_symbol: usize,
+) -> Result<(), DecompressError> {
+    match mode {
+        0 => *current = Some(FseTable::from_distribution(default, default_log)?),
+        1 => {
+            let symbol = block.byte()?;
+            if symbol as usize > max_symbol {
+                return Err(DecompressError::InvalidData);
+            }
+            *current = Some(FseTable::rle(symbol));
+        }
+        2 => {
+            let (table, used) = FseTable::read(&block.data[block.position..], max_log, max_symbol)?;
+            block.position += used;
+            *current = Some(table);
+        }
+        // Repeat the table of the previous block.
+        _ if current.is_none() => return Err(DecompressError::InvalidData),
+        _ => {}
+    }
+    return Ok(());
+}
+
+fn decode_block(
+    data: &[u8],
+    state: &mut FrameState,
+    output: &mut Vec<u8>,
+    frame_start: usize,
+    limit: usize,
+) -> Result<(), DecompressError> {
+    let mut block = Cursor { data, position: 0 };
+    let literals = decode_literals(&mut block, state)?;
+
+    let sequence_count = match block.byte()? as usize {
+        0 => 0,
+        count @ 0..128 => count,
+        count @ 128..255 => ((count - 128) << 8) + block.byte()? as usize,
+        _ => block.le(2)? as usize + 0x7F00,
+    };
+
+    let mut literal_position = 0;
+
+    if sequence_count > 0 {
+        let modes = block.byte()?;
+        if modes & 3 != 0 {
+            return Err(DecompressError::InvalidData);
+        }
+        read_table(
+            &mut block,
+            modes >> 6,
+            &mut state.literal_lengths,
+            &LITERAL_LENGTH_DEFAULT,
+            6,
+            LITERAL_LENGTH_MAX_LOG,
+            MAX_LITERAL_LENGTH_CODE,
+        )?;
+        read_table(
+            &mut block,
+            (modes >> 4) & 3,
+            &mut state.offsets,
+            &OFFSET_DEFAULT,
+            5,
+            OFFSET_MAX_LOG,
+            MAX_OFFSET_CODE,
+        )?;
+        read_table(
+            &mut block,
+            (modes >> 2) & 3,
+            &mut state.match_lengths,
+            &MATCH_LENGTH_DEFAULT,
+            6,
+            MATCH_LENGTH_MAX_LOG,
+            MAX_MATCH_LENGTH_CODE,
+        )?;
+
+        let (Some(ll_table), Some(of_table), Some(ml_table)) =
+            (&state.literal_lengths, &state.offsets, &state.match_lengths)
+        else {
+            return Err(DecompressError::InvalidData);
+        };
+
+        let mut bits = BackwardBits::new(&block.data[block.position..])?;
+        let mut ll_state = ll_table.init(&mut bits);
+        let mut of_state = of_table.init(&mut bits);
+        let mut ml_state = ml_table.init(&mut bits);
+
+        for index in 0..sequence_count {
+            let of_code = of_table.symbol(of_state);
+            let ml_code = ml_table.symbol(ml_state) as usize;
+            let ll_code = ll_table.symbol(ll_state) as usize;
+            if of_code as usize > MAX_OFFSET_CODE
+                || ml_code > MAX_MATCH_LENGTH_CODE
+                || ll_code > MAX_LITERAL_LENGTH_CODE
+            {
+                return Err(DecompressError::InvalidData);
+            }
+
+            let offset_value = (1u64 << of_code) as usize + bits.read(of_code) as usize;
+            let match_length = MATCH_LENGTH_BASE[ml_code] as usize
+                + bits.read(MATCH_LENGTH_BITS[ml_code]) as usize;
+            let literal_length = LITERAL_LENGTH_BASE[ll_code] as usize
+                + bits.read(LITERAL_LENGTH_BITS[ll_code]) as usize;
+
+            let repeat = &mut state.repeat_offsets;
+            let offset = match offset_value {
+                4.. => {
+                    let offset = offset_value - 3;
+                    *repeat = [offset, repeat[0], repeat[1]];
+                    offset
+                }
+                _ => {
+                    // Without literals the repeat offsets shift by one.
+                    let index = offset_value - 1 + (literal_length == 0) as usize;
+                    match index {
+                        0 => repeat[0],
+                        1 => {
+                            *repeat = [repeat[1], repeat[0], repeat[2]];
+                            repeat[0]
+                        }
+                        2 => {
+                            *repeat = [repeat[2], repeat[0], repeat[1]];
+                            repeat[0]
+                        }
+                        _ => {
+                            let offset = repeat[0]
+                                .checked_sub(1)
+                                .filter(|&offset| offset != 0)
+                                .ok_or(DecompressError::InvalidData)?;
+                            *repeat = [offset, repeat[0], repeat[1]];
+                            offset
+                        }
+                    }
+                }
+            };
+
+            let literal_end = literal_position + literal_length;
+            let literal = literals
+                .get(literal_position..literal_end)
+                .ok_or(DecompressError::InvalidData)?;
+            if output.len() + literal_length + match_length > limit {
+                return Err(DecompressError::OutputLimit);
+            }
+            output.extend_from_slice(literal);
+            literal_position = literal_end;
+
+            if offset > output.len() - frame_start {
+                return Err(DecompressError::InvalidData);
+            }
+            let start = output.len() - offset;
+            for i in 0..match_length {
+                output.push(output[start + i]);
+            }
+
+            if index + 1 < sequence_count {
+                ll_state = ll_table.update(ll_state, &mut bits);
+                ml_state = ml_table.update(ml_state, &mut bits);
+                of_state = of_table.update(of_state, &mut bits);
+            }
+        }
+
+        if !bits.finished() {
+            return Err(DecompressError::InvalidData);
+        }
+    }
+
+    let rest = &literals[literal_position..];
+    if output.len() + rest.len() > limit {
+        return Err(DecompressError::OutputLimit);
+    }
+    output.extend_from_slice(rest);
+
+    return Ok(());
+}
+
+/// Parse the frame header following the magic.
+fn frame_header(input: &mut Cursor) -> Result<FrameHeader, DecompressError> {
+    let descriptor = input.byte()?;
+    let size_flag = descriptor >> 6;
+    let single_segment = descriptor & 0x20 != 0;
+    let has_checksum = descriptor & 0x04 != 0;
+    let dictionary_flag = descriptor & 3;
+    if descriptor & 0x08 != 0 {
+        return Err(DecompressError::InvalidData);
+    }
+
+    let mut window_size = match single_segment {
+        true => 0,
+        false => {
+            let window = input.byte()?;
+            let exponent = 10 + (window >> 3) as u32;
+            let base = 1usize << exponent;
+            base + (base / 8) * (window & 7) as usize
+        }
+    };
+
+    let dictionary_size = [0, 1, 2, 4][dictionary_flag as usize];
+    if input.le(dictionary_size)? != 0 {
+        // Dictionaries are never used by the data we read.
+        return Err(DecompressError::InvalidData);
+    }
+
+    let content_size = match (size_flag, single_segment) {
+        (0, false) => None,
+        (0, true) => Some(input.le(1)?),
+        (1, _) => Some(input.le(2)? + 256),
+        (2, _) => Some(input.le(4)?),
+        _ => Some(input.le(8)?),
+    };
+    if single_segment {
+        window_size = content_size.unwrap_or(0) as usize;
+    }
+
+    return Ok(FrameHeader {
+        window_size,
+        content_size,
+        has_checksum,
+    });
+}
+
+fn decode_frame(
+    input: &mut Cursor,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), DecompressError> {
+    let FrameHeader {
+        window_size,
+        content_size,
+        has_checksum,
+    } = frame_header(input)?;
+    let mut state = FrameState {
+        repeat_offsets: [1, 4, 8],
+        window_size,
+        ..FrameState::default()
+    };
+
+    let frame_start = output.len();
+    loop {
+        let header = input.le(3)? as usize;
+        let last = header & 1 != 0;
+        let kind = (header >> 1) & 3;
+        let size = header >> 3;
+
+        let max_block = state.window_size.clamp(1, MAX_BLOCK_SIZE);
+        match kind {
+            0 => {
+                if output.len() + size > limit {
+                    return Err(DecompressError::OutputLimit);
+                }
+                output.extend_from_slice(input.take(size)?);
+            }
+            1 => {
+                if output.len() + size > limit {
+                    return Err(DecompressError::OutputLimit);
+                }
+                let byte = input.byte()?;
+                output.resize(output.len() + size, byte);
+            }
+            2 => {
+                if size > max_block {
+                    return Err(DecompressError::InvalidData);
+                }
+                let data = input.take(size)?;
+                decode_block(data, &mut state, output, frame_start, limit)?;
+            }
+            _ => return Err(DecompressError::InvalidData),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    if content_size.is_some_and(|size| size != (output.len() - frame_start) as u64) {
+        return Err(DecompressError::InvalidData);
+    }
+
+    if has_checksum {
+        let checksum = input.le(4)? as u32;
+        if checksum != xxh64(&output[frame_start..], 0) as u32 {
+            return Err(DecompressError::ChecksumMismatch);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Decompress the zstd frames at the start of `input`, appending to `output` which may not grow
+/// past `limit`. Skippable frames are ignored. Returns the number of input bytes the frames took.
+pub fn decompress_frames(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<usize, DecompressError> {
+    let mut cursor = Cursor {
+        data: input,
+        position: 0,
+    };
+    let mut frames = 0;
+
+    while cursor.position + 4 <= input.len() {
+        let start = cursor.position;
+        let magic = cursor.le(4)? as u32;
+
+        if magic == FRAME_MAGIC {
+            decode_frame(&mut cursor, output, limit)?;
+            frames += 1;
+        } else if magic & SKIPPABLE_MASK == SKIPPABLE_MAGIC {
+            let size = cursor.le(4)? as usize;
+            cursor.take(size)?;
+        } else {
+            cursor.position = start;
+            break;
+        }
+    }
+
+    if frames == 0 {
+        return Err(DecompressError::InvalidData);
+    }
+    return Ok(cursor.position);
+}
+
+/// Size the frame at the start of `input` decompresses to, if its header records it.
+pub fn content_size(input: &[u8]) -> Option<u64> {
+    let mut cursor = Cursor {
+        data: input,
+        position: 0,
+    };
+    if cursor.le(4).ok()? != FRAME_MAGIC as u64 {
+        return None;
+    }
+    return frame_header(&mut cursor).ok()?.content_size;
+}
+
+/// Decompress concatenated zstd frames, skippable frames are ignored and trailing zero padding
+/// is allowed.
+pub fn decompress(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut output = Vec::new();
+    if let Some(size) = content_size(input) {
+        output.reserve_exact(size.min(limit as u64) as usize);
+    }
+    let consumed = decompress_frames(input, &mut output, limit)?;
+
+    if input[consumed..].iter().any(|&byte| byte != 0) {
+        return Err(DecompressError::InvalidData);
+    }
+    return Ok(output);
+}
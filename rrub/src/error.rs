@@ -1,9 +1,15 @@
 #[cfg(feature = "uefi")]
 use uefi::Error as FirmwareError;
 
+use crate::{
+    crypto::CryptoError, decompress::DecompressError, firmware::filesystem::FilesystemError,
+    loaders::LoaderError,
+};
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum RrubError {
     CommandError,
+    InvalidConfig,
     UnsupportedResolution(usize, usize),
     UnsupportedColourFormat,
     Overflow,
@@ -11,6 +17,37 @@ pub enum RrubError {
     MemoryFault,
     FirmwareError(FirmwareError),
     UnalignedMemoryAddress,
+    UnalignedBlockAccess,
+    OutOfBounds,
+    ReadOnlyDevice,
+    FilesystemError(FilesystemError),
+    DecompressError(DecompressError),
+    CryptoError(CryptoError),
+    LoaderError(LoaderError),
+}
+
+impl From<FilesystemError> for RrubError {
+    fn from(error: FilesystemError) -> Self {
+        RrubError::FilesystemError(error)
+    }
+}
+
+impl From<DecompressError> for RrubError {
+    fn from(error: DecompressError) -> Self {
+        RrubError::DecompressError(error)
+    }
+}
+
+impl From<CryptoError> for RrubError {
+    fn from(error: CryptoError) -> Self {
+        RrubError::CryptoError(error)
+    }
+}
+
+impl From<LoaderError> for RrubError {
+    fn from(error: LoaderError) -> Self {
+        RrubError::LoaderError(error)
+    }
 }
 
 #[cfg(feature = "uefi")]
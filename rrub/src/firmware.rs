@@ -1,3 +1,4 @@
+pub mod block;
 pub mod filesystem;
 pub mod framebuffer;
 pub mod input;
@@ -5,6 +6,7 @@ pub mod logger;
 pub mod memory;
 mod u_efi;
 
+use alloc::{boxed::Box, vec::Vec};
 use core::ptr::NonNull;
 
 #[cfg(feature = "uefi")]
@@ -13,10 +15,11 @@ pub use u_efi::UefiFirmware;
 use crate::{
     error::RrubError,
     firmware::{
-        filesystem::FilesystemsList,
-        framebuffer::{FrameBuffer, GraphicalDisplay},
+        block::BlockDevice,
+        filesystem::{Filesystem, FilesystemsList},
+        framebuffer::{FrameBuffer, FramebufferInfo, GraphicalDisplay},
         input::{InputBackend, InputHandle},
-        memory::{AllocationType, MemoryMap},
+        memory::{AllocationType, EfiMemoryMap, MemoryMap},
     },
 };
 
@@ -31,8 +34,20 @@ pub trait Firmware: Sized {
     fn init_tty(&self, columns: usize, rows: usize);
     fn init_fb(&self, width: usize, height: usize)
     -> Result<GraphicalDisplay<Self::FB>, RrubError>;
+    /// The display as it is now, for kernels to draw on once the firmware is gone.
+    fn framebuffer(&self) -> Option<FramebufferInfo>;
+    /// Write text to the firmware console.
+    fn print(&self, text: &str);
 
+    /// The memory map as it is now, the firmware staying up. Only `handover` and `handover_efi`
+    /// leave it.
     fn get_memory_map(&self) -> MemoryMap;
+    /// Address of the ACPI root table, which kernels can't find on their own without a BIOS.
+    fn acpi_rsdp(&self) -> Option<u64>;
+    /// Image handle and system table, for a next stage that calls into UEFI itself.
+    fn efi_system(&self) -> Option<(u64, u64)>;
+    /// Fill `buffer` from the firmware's random number generator.
+    fn random(&self, buffer: &mut [u8]) -> Result<(), RrubError>;
     fn allocate_pages(
         &mut self,
         allocation_type: AllocationType,
@@ -40,9 +55,30 @@ pub trait Firmware: Sized {
     ) -> Result<NonNull<u8>, RrubError>;
     unsafe fn deallocate_pages(&mut self, ptr: NonNull<u8>, count: usize) -> Result<(), RrubError>;
 
-    fn get_filesystems(&self) -> Result<FilesystemsList, RrubError>;
+    fn get_block_devices(&self) -> Result<Vec<Box<dyn BlockDevice>>, RrubError>;
+    /// Mount the filesystems on every block device, prompting for the passphrases of LUKS
+    /// containers on the way.
+    fn get_filesystems(&mut self) -> Result<FilesystemsList, RrubError>;
 
-    fn handover(self) -> !;
+    /// Load a PE image through the firmware and start it with `options` as its command line,
+    /// serving `initrds` to it the way Linux EFI stubs ask for them. `file` is the filesystem and
+    /// path the image was read from, which it is told it was loaded from to find files next to
+    /// it. Returns once the image exits, with the display mode and input as they were before.
+    /// `wipe_keys` is for images that don't come back, like kernels, which must not find LUKS keys
+    /// in memory. Applications that return to the menu leave them for the volumes still mounted.
+    fn start_image(
+        &mut self,
+        image: &[u8],
+        file: Option<(&Filesystem, &str)>,
+        options: &str,
+        initrds: &[&[u8]],
+        wipe_keys: bool,
+    ) -> Result<(), RrubError>;
+
+    /// Leave the firmware for good, returning the final memory map for the next stage.
+    fn handover(self) -> MemoryMap;
+    /// Leave the firmware like `handover`, also returning the UEFI memory map when there is one.
+    fn handover_efi(self) -> (MemoryMap, Option<EfiMemoryMap>);
     fn reboot(self) -> !;
     fn shutdown(self) -> !;
 }
@@ -0,0 +1,128 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::cmp::min;
+
+use crate::error::RrubError;
+
+pub trait BlockDevice {
+    /// Size of a single block in bytes.
+    fn block_size(&self) -> usize;
+    /// Number of blocks on the device.
+    fn block_count(&self) -> u64;
+    /// Read whole blocks starting at `lba`, `buffer` length must be a multiple of the block size.
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError>;
+
+    fn is_read_only(&self) -> bool {
+        return true;
+    }
+
+    /// Firmware device path of the device, for images started through the firmware to be told
+    /// where they were loaded from. Devices the firmware doesn't know about have none.
+    fn device_path(&self) -> Option<&[u8]> {
+        return None;
+    }
+
+    /// Names of the device for configs to find it by, see `Filesystem::device_names`.
+    fn device_names(&self) -> Vec<String> {
+        return Vec::new();
+    }
+
+    /// Write whole blocks starting at `lba`, `buffer` length must be a multiple of the block size.
+    fn write_blocks(&mut self, _lba: u64, _buffer: &[u8]) -> Result<(), RrubError> {
+        return Err(RrubError::ReadOnlyDevice);
+    }
+
+    /// Make sure all previous writes reached the device.
+    fn flush(&mut self) -> Result<(), RrubError> {
+        return Ok(());
+    }
+
+    fn size(&self) -> u64 {
+        return self.block_count() * self.block_size() as u64;
+    }
+
+    /// Read an arbitrary byte range, splitting unaligned head and tail blocks through a scratch
+    /// buffer.
+    fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        let block_size = self.block_size();
+
+        if offset
+            .checked_add(buffer.len() as u64)
+            .is_none_or(|end| end > self.size())
+        {
+            return Err(RrubError::OutOfBounds);
+        }
+
+        let mut scratch = vec![0u8; block_size];
+        let mut done = 0;
+
+        while done < buffer.len() {
+            let pos = offset + done as u64;
+            let lba = pos / block_size as u64;
+            let skip = (pos % block_size as u64) as usize;
+            let remaining = buffer.len() - done;
+
+            if skip == 0 && remaining >= block_size {
+                let whole = remaining - (remaining % block_size);
+                self.read_blocks(lba, &mut buffer[done..done + whole])?;
+                done += whole;
+            } else {
+                self.read_blocks(lba, &mut scratch)?;
+                let count = min(block_size - skip, remaining);
+                buffer[done..done + count].copy_from_slice(&scratch[skip..skip + count]);
+                done += count;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Write an arbitrary byte range, partially covered blocks are read, modified and written back.
+    fn write_bytes(&mut self, offset: u64, data: &[u8]) -> Result<(), RrubError> {
+        let block_size = self.block_size();
+
+        if offset
+            .checked_add(data.len() as u64)
+            .is_none_or(|end| end > self.size())
+        {
+            return Err(RrubError::OutOfBounds);
+        }
+
+        let mut scratch = vec![0u8; block_size];
+        let mut done = 0;
+
+        while done < data.len() {
+            let pos = offset + done as u64;
+            let lba = pos / block_size as u64;
+            let skip = (pos % block_size as u64) as usize;
+            let remaining = data.len() - done;
+
+            if skip == 0 && remaining >= block_size {
+                let whole = remaining - (remaining % block_size);
+                self.write_blocks(lba, &data[done..done + whole])?;
+                done += whole;
+            } else {
+                let count = min(block_size - skip, remaining);
+                self.read_blocks(lba, &mut scratch)?;
+                scratch[skip..skip + count].copy_from_slice(&data[done..done + count]);
+                self.write_blocks(lba, &scratch)?;
+                done += count;
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+/// Check a block read or write request against the bounds of a device.
+pub fn check_range(device: &dyn BlockDevice, lba: u64, length: usize) -> Result<(), RrubError> {
+    let block_size = device.block_size();
+
+    if !length.is_multiple_of(block_size) {
+        return Err(RrubError::UnalignedBlockAccess);
+    }
+
+    match lba.checked_add((length / block_size) as u64) {
+        Some(end) if end <= device.block_count() => return Ok(()),
+        _ => return Err(RrubError::OutOfBounds),
+    }
+}
@@ -1,8 +1,38 @@
-use alloc::vec::Vec;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
 
 use serde::Deserialize;
 use uuid::Uuid as RealUuid;
 
+use crate::error::RrubError;
+
+/// Maximum number of symlinks followed while resolving a single path.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Appended to the name new files are written under before they are renamed into place.
+const TEMPORARY_SUFFIX: &str = ".tmp";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FilesystemError {
+    UnknownFilesystem,
+    Corrupted,
+    Unsupported,
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    InvalidPath,
+    TooManySymlinks,
+    ReadOnly,
+    NoSpace,
+    AlreadyExists,
+    ChecksumMismatch,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize)]
 pub struct VolumeId32([u8; 4]);
 
@@ -60,10 +90,397 @@ pub enum Uuid {
     VolumeId64(VolumeId64),
 }
 
+/// Volume IDs are stored little endian and shown as the number they hold, the way blkid and
+/// Windows print FAT and NTFS serials.
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Uuid::RealUuid(uuid) => write!(f, "{}", uuid.hyphenated()),
+            Uuid::VolumeId32(VolumeId32(id)) => {
+                let value = u32::from_le_bytes(*id);
+                write!(f, "{:04X}-{:04X}", value >> 16, value & 0xFFFF)
+            }
+            Uuid::VolumeId64(VolumeId64(id)) => write!(f, "{:016X}", u64::from_le_bytes(*id)),
+        }
+    }
+}
+
+/// Driver specific identifier of a file or directory, such as an inode number or the on disk
+/// position of a directory entry.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NodeId(pub u64);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    Other,
+}
+
 #[derive(Debug, Copy, Clone)]
-pub struct Filesystem {}
+pub struct Metadata {
+    pub file_type: FileType,
+    pub size: u64,
+}
 
 #[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub node: NodeId,
+    pub file_type: FileType,
+}
+
+pub trait FilesystemBackend {
+    /// Short name of the filesystem type, e.g. "vfat".
+    fn fs_type(&self) -> &'static str;
+    fn uuid(&self) -> Uuid;
+    fn label(&self) -> Option<String>;
+
+    fn root(&self) -> NodeId;
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError>;
+    /// List a directory, excluding the "." and ".." entries.
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError>;
+    /// Read from `offset` into `buffer`, returning the number of bytes read which is only short at
+    /// the end of the file.
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError>;
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        return self
+            .read_dir(dir)?
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.node)
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read_link(&mut self, _node: NodeId) -> Result<String, RrubError> {
+        return Err(FilesystemError::Unsupported.into());
+    }
+
+    /// Write `data` at `offset`, growing the file if needed.
+    fn write(&mut self, _node: NodeId, _offset: u64, _data: &[u8]) -> Result<(), RrubError> {
+        return Err(FilesystemError::ReadOnly.into());
+    }
+
+    fn set_len(&mut self, _node: NodeId, _size: u64) -> Result<(), RrubError> {
+        return Err(FilesystemError::ReadOnly.into());
+    }
+
+    /// Create an empty regular file in `dir`.
+    fn create(&mut self, _dir: NodeId, _name: &str) -> Result<NodeId, RrubError> {
+        return Err(FilesystemError::ReadOnly.into());
+    }
+
+    /// Rename an entry of `dir`, returning the possibly changed node of the entry.
+    fn rename(&mut self, _dir: NodeId, _from: &str, _to: &str) -> Result<NodeId, RrubError> {
+        return Err(FilesystemError::ReadOnly.into());
+    }
+
+    /// Write back anything cached and flush the underlying device.
+    fn sync(&mut self) -> Result<(), RrubError> {
+        return Ok(());
+    }
+
+    /// Top directory of a named subvolume, for filesystems that have them.
+    fn subvolume(&mut self, _name: &str) -> Result<NodeId, RrubError> {
+        return Err(FilesystemError::Unsupported.into());
+    }
+
+    /// Names of the subvolumes directly below `parent`, as `subvolume` takes them.
+    fn subvolumes(&mut self, _parent: &str) -> Result<Vec<String>, RrubError> {
+        return Err(FilesystemError::Unsupported.into());
+    }
+}
+
+/// Split a path into its parent directory and final component.
+fn split_path(path: &str) -> Result<(&str, &str), RrubError> {
+    let (parent, name) = path
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .ok_or(FilesystemError::InvalidPath)?;
+
+    if matches!(name, "" | "." | "..") {
+        return Err(FilesystemError::InvalidPath.into());
+    }
+
+    return match parent {
+        "" => Ok(("/", name)),
+        _ => Ok((parent, name)),
+    };
+}
+
+pub struct Filesystem {
+    backend: Box<dyn FilesystemBackend>,
+    /// Directory absolute paths are resolved from.
+    root: NodeId,
+    /// Names of the device the filesystem is on, such as `vg/lv` and the LV UUID of an LVM
+    /// logical volume.
+    device_names: Vec<String>,
+    /// Firmware device path of the device the filesystem is on, see `BlockDevice::device_path`.
+    device_path: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for Filesystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filesystem")
+            .field("fs_type", &self.backend.fs_type())
+            .field("uuid", &self.backend.uuid())
+            .field("label", &self.backend.label())
+            .finish()
+    }
+}
+
+impl Filesystem {
+    pub fn new(backend: Box<dyn FilesystemBackend>) -> Filesystem {
+        let root = backend.root();
+        return Filesystem {
+            backend,
+            root,
+            device_names: Vec::new(),
+            device_path: None,
+        };
+    }
+
+    pub fn device_names(&self) -> &[String] {
+        return &self.device_names;
+    }
+
+    pub fn set_device_names(&mut self, names: Vec<String>) {
+        self.device_names = names;
+    }
+
+    pub fn device_path(&self) -> Option<&[u8]> {
+        return self.device_path.as_deref();
+    }
+
+    pub fn set_device_path(&mut self, device_path: Option<Vec<u8>>) {
+        self.device_path = device_path;
+    }
+
+    pub fn fs_type(&self) -> &'static str {
+        return self.backend.fs_type();
+    }
+
+    pub fn uuid(&self) -> Uuid {
+        return self.backend.uuid();
+    }
+
+    pub fn label(&self) -> Option<String> {
+        return self.backend.label();
+    }
+
+    pub fn backend(&mut self) -> &mut dyn FilesystemBackend {
+        return self.backend.as_mut();
+    }
+
+    /// Resolve paths inside a subvolume instead of the filesystem root, `None` goes back to the
+    /// root.
+    pub fn set_subvolume(&mut self, subvol: Option<&str>) -> Result<(), RrubError> {
+        self.root = match subvol {
+            Some(name) => self.backend.subvolume(name)?,
+            None => self.backend.root(),
+        };
+        return Ok(());
+    }
+
+    pub fn subvolumes(&mut self, parent: &str) -> Result<Vec<String>, RrubError> {
+        return self.backend.subvolumes(parent);
+    }
+
+    /// Walk an absolute path, handling "." and ".." and following symlinks including the last
+    /// component.
+    pub fn resolve(&mut self, path: &str) -> Result<NodeId, RrubError> {
+        if !path.starts_with('/') {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let mut stack: Vec<NodeId> = Vec::new();
+        let mut pending: Vec<String> = path.rsplit('/').map(ToString::to_string).collect();
+        let mut links = 0;
+        let mut current = self.root;
+
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    current = stack.pop().unwrap_or(self.root);
+                    continue;
+                }
+                _ => {}
+            }
+
+            if self.backend.metadata(current)?.file_type != FileType::Directory {
+                return Err(FilesystemError::NotADirectory.into());
+            }
+
+            let node = self.backend.lookup(current, &component)?;
+
+            if self.backend.metadata(node)?.file_type == FileType::Symlink {
+                links += 1;
+                if links > MAX_SYMLINK_DEPTH {
+                    return Err(FilesystemError::TooManySymlinks.into());
+                }
+
+                let target = self.backend.read_link(node)?;
+                if target.starts_with('/') {
+                    stack.clear();
+                    current = self.root;
+                }
+                pending.extend(target.rsplit('/').map(ToString::to_string));
+                continue;
+            }
+
+            stack.push(current);
+            current = node;
+        }
+
+        return Ok(current);
+    }
+
+    pub fn metadata(&mut self, path: &str) -> Result<Metadata, RrubError> {
+        let node = self.resolve(path)?;
+        return self.backend.metadata(node);
+    }
+
+    pub fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, RrubError> {
+        let node = self.resolve(path)?;
+        if self.backend.metadata(node)?.file_type != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+        return self.backend.read_dir(node);
+    }
+
+    pub fn read_at(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        return self.backend.read(node, offset, buffer);
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, RrubError> {
+        let node = self.resolve(path)?;
+        let metadata = self.backend.metadata(node)?;
+        if metadata.file_type == FileType::Directory {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+
+        let size = usize::try_from(metadata.size).map_err(|_| RrubError::Overflow)?;
+        let mut data = alloc::vec![0u8; size];
+        let read = self.backend.read(node, 0, &mut data)?;
+        data.truncate(read);
+
+        return Ok(data);
+    }
+
+    /// Replace the contents of a file, creating it if it does not exist. New files are written
+    /// under a temporary name first, so that one cut short by a crash doesn't show up as the file.
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), RrubError> {
+        let (parent, name) = split_path(path)?;
+        let dir = self.resolve(parent)?;
+
+        match self.backend.lookup(dir, name) {
+            Ok(node) => return self.write_node(node, data),
+            Err(RrubError::FilesystemError(FilesystemError::NotFound)) => {}
+            Err(e) => return Err(e),
+        }
+
+        // Left behind by an earlier write that didn't finish.
+        let temporary = format!("{}{}", name, TEMPORARY_SUFFIX);
+        let node = match self.backend.lookup(dir, &temporary) {
+            Ok(node) => node,
+            Err(RrubError::FilesystemError(FilesystemError::NotFound)) => {
+                self.backend.create(dir, &temporary)?
+            }
+            Err(e) => return Err(e),
+        };
+        self.write_node(node, data)?;
+
+        return self.rename(&format!("{}{}", path, TEMPORARY_SUFFIX), path);
+    }
+
+    fn write_node(&mut self, node: NodeId, data: &[u8]) -> Result<(), RrubError> {
+        self.backend.write(node, 0, data)?;
+        self.backend.set_len(node, data.len() as u64)?;
+
+        return Ok(());
+    }
+
+    /// Rename a file, both paths must be in the same directory.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), RrubError> {
+        let (from_parent, from_name) = split_path(from)?;
+        let (to_parent, to_name) = split_path(to)?;
+
+        let dir = self.resolve(from_parent)?;
+        if self.resolve(to_parent)? != dir {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        self.backend.rename(dir, from_name, to_name)?;
+
+        return Ok(());
+    }
+
+    /// Flush writes to the device, firmware drivers can be kept off it until then.
+    pub fn sync(&mut self) -> Result<(), RrubError> {
+        return self.backend.sync();
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct FilesystemsList {
     filesystems: Vec<(Uuid, Filesystem)>,
 }
+
+impl FilesystemsList {
+    pub fn new() -> FilesystemsList {
+        return FilesystemsList {
+            filesystems: Vec::new(),
+        };
+    }
+
+    pub fn push(&mut self, filesystem: Filesystem) {
+        self.filesystems.push((filesystem.uuid(), filesystem));
+    }
+
+    pub fn get_mut(&mut self, uuid: &Uuid) -> Option<&mut Filesystem> {
+        return self
+            .filesystems
+            .iter_mut()
+            .find(|(id, _)| id == uuid)
+            .map(|(_, fs)| fs);
+    }
+
+    /// Take a filesystem off the list, e.g. to mount an image stored on it.
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<Filesystem> {
+        let index = self.filesystems.iter().position(|(id, _)| id == uuid)?;
+        return Some(self.filesystems.remove(index).1);
+    }
+
+    /// Find a filesystem by the name of the device it is on, see `Filesystem::device_names`.
+    pub fn get_by_device_mut(&mut self, name: &str) -> Option<&mut Filesystem> {
+        return self
+            .filesystems
+            .iter_mut()
+            .find(|(_, fs)| fs.device_names.iter().any(|device| device == name))
+            .map(|(_, fs)| fs);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Uuid, Filesystem)> {
+        return self.filesystems.iter();
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut (Uuid, Filesystem)> {
+        return self.filesystems.iter_mut();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.filesystems.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.filesystems.is_empty();
+    }
+}
@@ -16,6 +16,17 @@ pub enum PixelFormat {
     NotSupported,
 }
 
+/// A linear framebuffer left set up for the next stage, 32 bits per pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct FramebufferInfo {
+    pub address: u64,
+    pub width: usize,
+    pub height: usize,
+    /// Pixels per line.
+    pub stride: usize,
+    pub format: PixelFormat,
+}
+
 pub trait FrameBuffer: Sized {
     fn init_fb_backend(width: usize, height: usize) -> Result<Self, RrubError>;
     fn width(&self) -> usize;
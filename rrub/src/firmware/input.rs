@@ -1,9 +1,17 @@
-use crate::error::RrubError;
+use core::hint::spin_loop;
+
+use crate::{
+    crypto::{Secret, zeroize},
+    error::RrubError,
+};
 
 /*
  * https://en.wikipedia.org/wiki/C0_and_C1_control_codes
 */
 
+/// Longest passphrase `read_passphrase` takes, further characters are dropped.
+const MAX_PASSPHRASE_LENGTH: usize = 512;
+
 #[non_exhaustive]
 pub enum ControlChar {
     Backspace,    // \b
@@ -68,3 +76,65 @@ pub trait InputBackend: Sized {
 pub struct InputHandle<B: InputBackend> {
     backend: B,
 }
+
+impl<B: InputBackend> InputHandle<B> {
+    pub fn new(backend: B) -> Self {
+        return InputHandle { backend };
+    }
+
+    pub fn read_key(&self) -> Option<Key> {
+        return self.backend.read_key();
+    }
+
+    pub fn wait_key(&self) -> Key {
+        loop {
+            if let Some(key) = self.read_key() {
+                return key;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Wait for a line of input without echoing it, `None` if Escape cancels it.
+    pub fn read_passphrase(&self) -> Option<Secret<[u8]>> {
+        let mut buffer = Secret::<[u8]>::bytes(MAX_PASSPHRASE_LENGTH);
+        let mut length = 0;
+
+        loop {
+            let Some(key) = self.backend.read_key() else {
+                spin_loop();
+                continue;
+            };
+
+            match key {
+                Key::Printable(c) => {
+                    let mut encoded = [0u8; 4];
+                    let bytes = c.encode_utf8(&mut encoded).len();
+                    if length + bytes <= MAX_PASSPHRASE_LENGTH {
+                        buffer[length..length + bytes].copy_from_slice(&encoded[..bytes]);
+                        length += bytes;
+                    }
+                    zeroize(&mut encoded);
+                }
+                // Remove a whole UTF-8 sequence.
+                Key::ControlChar(ControlChar::Backspace) => {
+                    while length > 0 {
+                        length -= 1;
+                        let continuation = buffer[length] & 0xC0 == 0x80;
+                        buffer[length] = 0;
+                        if !continuation {
+                            break;
+                        }
+                    }
+                }
+                Key::ControlChar(ControlChar::Return | ControlChar::LineFeed) => break,
+                Key::SpecialKey(SpecialKey::Escape) => return None,
+                _ => continue,
+            }
+        }
+
+        let mut passphrase = Secret::<[u8]>::bytes(length);
+        passphrase.copy_from_slice(&buffer[..length]);
+        return Some(passphrase);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod e820;
+
 use alloc::vec::Vec;
 use core::{
     marker::PhantomData,
@@ -15,6 +17,8 @@ pub const PAGE_SIZE: usize = 4096;
 pub enum AllocationType {
     AnyPages,
     Address(u64),
+    /// Anywhere ending at or below the address.
+    MaxAddress(u64),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,6 +39,14 @@ pub struct MemoryRegion {
 
 pub type MemoryMap = Vec<MemoryRegion>;
 
+/// The UEFI memory map as the firmware returned it, for kernels that read it themselves.
+#[derive(Debug, Clone)]
+pub struct EfiMemoryMap {
+    pub descriptor_size: u32,
+    pub descriptor_version: u32,
+    pub descriptors: Vec<u8>,
+}
+
 bitflags! {
     #[derive(Debug)]
     pub struct MemAttr: u8 {
@@ -0,0 +1,72 @@
+/*
+ * The BIOS memory map format, which x86 kernels are still handed
+ * https://uefi.org/specs/ACPI/6.5/15_System_Address_Map_Interfaces.html
+*/
+
+use alloc::vec::Vec;
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, U32, U64, Unaligned};
+
+use crate::firmware::memory::{MemoryMap, MemoryRegion, MemoryType};
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E820Type {
+    Ram = 1,
+    Reserved = 2,
+    Acpi = 3,
+    Nvs = 4,
+    Unusable = 5,
+}
+
+impl From<MemoryType> for E820Type {
+    fn from(value: MemoryType) -> Self {
+        return match value {
+            MemoryType::Available => E820Type::Ram,
+            MemoryType::Reserved => E820Type::Reserved,
+            MemoryType::Acpi => E820Type::Acpi,
+            MemoryType::Nvs => E820Type::Nvs,
+            MemoryType::Unusable => E820Type::Unusable,
+        };
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct E820Entry {
+    pub addr: U64<LittleEndian>,
+    pub size: U64<LittleEndian>,
+    pub entry_type: U32<LittleEndian>,
+}
+
+impl From<&MemoryRegion> for E820Entry {
+    fn from(value: &MemoryRegion) -> Self {
+        return E820Entry {
+            addr: U64::new(value.start),
+            size: U64::new(value.size),
+            entry_type: U32::new(E820Type::from(value.region_type) as u32),
+        };
+    }
+}
+
+/// Sorted entries with touching regions of the same type merged, firmware maps being split far
+/// finer than the few entries a zeropage holds.
+pub fn table(map: &MemoryMap) -> Vec<E820Entry> {
+    let mut regions: Vec<&MemoryRegion> = map.iter().filter(|region| region.size != 0).collect();
+    regions.sort_by_key(|region| region.start);
+
+    let mut table: Vec<E820Entry> = Vec::new();
+    for region in regions {
+        let entry = E820Entry::from(region);
+        if let Some(last) = table.last_mut()
+            && last.entry_type == entry.entry_type
+            && last.addr.get() + last.size.get() == entry.addr.get()
+        {
+            last.size = U64::new(last.size.get() + entry.size.get());
+            continue;
+        }
+        table.push(entry);
+    }
+
+    return table;
+}
@@ -1,34 +1,44 @@
+use alloc::{boxed::Box, vec::Vec};
 use core::{
     ptr::NonNull,
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use log::LevelFilter;
+use log::{LevelFilter, debug, warn};
 use simple_alloc::AllocInit;
 use uefi::{
     Status,
     boot::{
         AllocateType as UefiAllocateType, AllocateType, MemoryType, allocate_pages,
-        exit_boot_services, free_pages,
+        exit_boot_services, find_handles, free_pages, get_handle_for_protocol, image_handle,
+        memory_map, open_protocol_exclusive,
     },
     mem::memory_map::MemoryMap as UefiMemoryMap,
+    print,
+    proto::{media::block::BlockIO, rng::Rng},
     runtime::{ResetType, reset},
+    system::with_config_table,
+    table::{cfg::ConfigTableEntry, system_table_raw},
 };
 
 use crate::{
-    ALLOCATOR, HEAP_START, NUM_HEAP_PAGES, RrubError,
+    ALLOCATOR, HEAP_START, NUM_HEAP_PAGES, RrubError, crypto,
     firmware::{
         Firmware,
-        filesystem::FilesystemsList,
-        framebuffer::{FrameBuffer, GraphicalDisplay},
-        input::InputHandle,
+        block::BlockDevice,
+        filesystem::{Filesystem, FilesystemsList},
+        framebuffer::{FrameBuffer, FramebufferInfo, GraphicalDisplay},
+        input::{InputBackend, InputHandle},
         logger::init_logger,
-        memory::{AllocationType, MemoryMap, MemoryRegion, PAGE_SIZE},
-        u_efi::{gop::UefiDisplay, input::UefiInput, logger::UefiLogger},
+        memory::{AllocationType, EfiMemoryMap, MemoryMap, MemoryRegion, PAGE_SIZE},
+        u_efi::{block::UefiBlockDevice, gop::UefiDisplay, input::UefiInput, logger::UefiLogger},
     },
+    fs::{self, luks::LuksVolume},
 };
 
+mod block;
 mod gop;
+mod image;
 mod input;
 mod logger;
 mod mem;
@@ -68,7 +78,7 @@ impl Firmware for UefiFirmware {
     }
 
     fn init_input(&self) -> Result<InputHandle<Self::Input>, RrubError> {
-        todo!()
+        return Ok(InputHandle::new(Self::Input::init_input_backend()?));
     }
 
     fn init_tty(&self, columns: usize, rows: usize) {
@@ -85,12 +95,46 @@ impl Firmware for UefiFirmware {
         return Ok(GraphicalDisplay::new(backend));
     }
 
+    fn framebuffer(&self) -> Option<FramebufferInfo> {
+        return gop::framebuffer_info().ok().flatten();
+    }
+
+    fn print(&self, text: &str) {
+        print!("{}", text);
+    }
+
     fn get_memory_map(&self) -> MemoryMap {
-        BOOT_SERVICES_EXITED.store(true, Ordering::SeqCst);
+        return match memory_map(MemoryType::LOADER_DATA) {
+            Ok(uefi_map) => uefi_map.entries().map(MemoryRegion::from).collect(),
+            Err(e) => {
+                warn!("Failed to get the memory map: {:?}", e);
+                MemoryMap::new()
+            }
+        };
+    }
 
-        let uefi_map = unsafe { exit_boot_services(None) };
+    fn acpi_rsdp(&self) -> Option<u64> {
+        return with_config_table(|tables| {
+            let find = |guid| {
+                tables
+                    .iter()
+                    .find(|table| table.guid == guid)
+                    .map(|table| table.address as u64)
+            };
+            find(ConfigTableEntry::ACPI2_GUID).or_else(|| find(ConfigTableEntry::ACPI_GUID))
+        });
+    }
+
+    fn efi_system(&self) -> Option<(u64, u64)> {
+        let system_table = system_table_raw()?;
+        return Some((image_handle().as_ptr() as u64, system_table.as_ptr() as u64));
+    }
 
-        return uefi_map.entries().map(MemoryRegion::from).collect();
+    fn random(&self, buffer: &mut [u8]) -> Result<(), RrubError> {
+        let handle = get_handle_for_protocol::<Rng>()?;
+        let mut rng = open_protocol_exclusive::<Rng>(handle)?;
+        rng.get_rng(None, buffer)?;
+        return Ok(());
     }
 
     fn allocate_pages(
@@ -101,6 +145,7 @@ impl Firmware for UefiFirmware {
         let uefi_alloc = match allocation_type {
             AllocationType::AnyPages => UefiAllocateType::AnyPages,
             AllocationType::Address(addr) => UefiAllocateType::Address(addr),
+            AllocationType::MaxAddress(addr) => UefiAllocateType::MaxAddress(addr),
         };
 
         let ptr = allocate_pages(uefi_alloc, MemoryType::LOADER_DATA, count)?;
@@ -114,12 +159,84 @@ impl Firmware for UefiFirmware {
         return Ok(());
     }
 
-    fn get_filesystems(&self) -> Result<FilesystemsList, RrubError> {
-        todo!()
+    fn get_block_devices(&self) -> Result<Vec<Box<dyn BlockDevice>>, RrubError> {
+        let mut devices = Vec::new();
+
+        for handle in find_handles::<BlockIO>()? {
+            match UefiBlockDevice::open(handle) {
+                Ok(Some(device)) => devices.push(device),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to open a block device: {:?}", e),
+            }
+        }
+
+        // Disks the firmware found partitions on would have their volumes probed twice, through
+        // the disk and through the partition's own handle.
+        let partitioned: Vec<bool> = devices
+            .iter()
+            .map(|disk| devices.iter().any(|other| other.is_partition_of(disk)))
+            .collect();
+
+        return Ok(devices
+            .into_iter()
+            .zip(partitioned)
+            .filter(|(_, partitioned)| !partitioned)
+            .map(|(device, _)| Box::new(device) as Box<dyn BlockDevice>)
+            .collect());
     }
 
-    fn handover(self) -> ! {
-        todo!()
+    fn get_filesystems(&mut self) -> Result<FilesystemsList, RrubError> {
+        let mut filesystems = FilesystemsList::new();
+
+        let devices = self.get_block_devices()?;
+        let input = self.init_input()?;
+        let mut unlock = |volume: LuksVolume| {
+            volume.unlock_interactive(self, &input, &mut |prompt| print!("{}", prompt))
+        };
+        for result in fs::mount_all(devices, &mut unlock) {
+            match result {
+                Ok(filesystem) => filesystems.push(filesystem),
+                Err(e) => debug!("Skipping block device: {:?}", e),
+            }
+        }
+
+        return Ok(filesystems);
+    }
+
+    fn start_image(
+        &mut self,
+        image: &[u8],
+        file: Option<(&Filesystem, &str)>,
+        options: &str,
+        initrds: &[&[u8]],
+        wipe_keys: bool,
+    ) -> Result<(), RrubError> {
+        let file_path =
+            file.map(|(filesystem, path)| image::file_device_path(filesystem.device_path(), path));
+        return image::start(image, file_path.as_deref(), options, initrds, wipe_keys);
+    }
+
+    fn handover(self) -> MemoryMap {
+        return self.handover_efi().0;
+    }
+
+    fn handover_efi(self) -> (MemoryMap, Option<EfiMemoryMap>) {
+        // Nothing may be left for the kernel to find of LUKS passphrases and keys.
+        crypto::wipe_keys();
+
+        BOOT_SERVICES_EXITED.store(true, Ordering::SeqCst);
+        let uefi_map = unsafe { exit_boot_services(None) };
+
+        let meta = uefi_map.meta();
+        let efi_map = EfiMemoryMap {
+            descriptor_size: meta.desc_size as u32,
+            descriptor_version: meta.desc_version,
+            descriptors: uefi_map.buffer()[..meta.map_size].to_vec(),
+        };
+        return (
+            uefi_map.entries().map(MemoryRegion::from).collect(),
+            Some(efi_map),
+        );
     }
 
     fn reboot(self) -> ! {
@@ -0,0 +1,198 @@
+use alloc::{vec, vec::Vec};
+use core::cmp::max;
+
+use uefi::{
+    Handle,
+    boot::{
+        OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, connect_controller,
+        image_handle, open_protocol, open_protocol_exclusive,
+    },
+    proto::{device_path::DevicePath, media::block::BlockIO},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::block::{BlockDevice, check_range},
+};
+
+/// Unaligned transfers go through a bounce buffer of about this size, a block at least.
+const BOUNCE_SIZE: usize = 64 * 1024;
+/// Every device path ends with a node of this size.
+const END_NODE_SIZE: usize = 4;
+
+pub struct UefiBlockDevice {
+    handle: Handle,
+    block_io: ScopedProtocol<BlockIO>,
+    /// Held from the first write until the next flush. Opening exclusively disconnects the
+    /// firmware's drivers from the device, its FAT driver caches the volume and writing under
+    /// it would corrupt it.
+    exclusive: Option<ScopedProtocol<BlockIO>>,
+    media_id: u32,
+    block_size: usize,
+    block_count: u64,
+    io_align: usize,
+    read_only: bool,
+    /// A partition the firmware found, with a handle of its own next to its disk's.
+    partition: bool,
+    device_path: Option<Vec<u8>>,
+    bounce: Vec<u8>,
+}
+
+impl UefiBlockDevice {
+    pub fn open(handle: Handle) -> Result<Option<UefiBlockDevice>, RrubError> {
+        // Opened non exclusively so firmware filesystem drivers stay connected.
+        let block_io = unsafe {
+            open_protocol::<BlockIO>(
+                OpenProtocolParams {
+                    handle,
+                    agent: image_handle(),
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )?
+        };
+
+        let media = block_io.media();
+        if !media.is_media_present() {
+            return Ok(None);
+        }
+
+        let media_id = media.media_id();
+        let block_size = media.block_size() as usize;
+        let block_count = media.last_block() + 1;
+        // 0 and 1 both mean any alignment.
+        let io_align = max(media.io_align() as usize, 1);
+        let read_only = media.is_read_only();
+        let partition = media.is_logical_partition();
+
+        let device_path = unsafe {
+            open_protocol::<DevicePath>(
+                OpenProtocolParams {
+                    handle,
+                    agent: image_handle(),
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )
+        }
+        .ok()
+        .map(|device_path| device_path.as_bytes().to_vec());
+
+        return Ok(Some(UefiBlockDevice {
+            handle,
+            block_io,
+            exclusive: None,
+            media_id,
+            block_size,
+            block_count,
+            io_align,
+            read_only,
+            partition,
+            device_path,
+            bounce: Vec::new(),
+        }));
+    }
+
+    /// Whether this is a partition the firmware found on `disk`, its device path extending the
+    /// disk's.
+    pub fn is_partition_of(&self, disk: &UefiBlockDevice) -> bool {
+        let (Some(path), Some(disk_path)) = (&self.device_path, &disk.device_path) else {
+            return false;
+        };
+        let prefix = &disk_path[..disk_path.len().saturating_sub(END_NODE_SIZE)];
+        return self.partition && path.len() > disk_path.len() && path.starts_with(prefix);
+    }
+
+    /// Offset and length of the aligned part of the bounce buffer, allocated on first use and kept
+    /// since the heap never frees.
+    fn bounce_range(&mut self) -> (usize, usize) {
+        let size = max(BOUNCE_SIZE / self.block_size, 1) * self.block_size;
+        if self.bounce.is_empty() {
+            self.bounce = vec![0u8; size + self.io_align];
+        }
+        return (self.bounce.as_ptr().align_offset(self.io_align), size);
+    }
+}
+
+impl BlockDevice for UefiBlockDevice {
+    fn block_size(&self) -> usize {
+        return self.block_size;
+    }
+
+    fn block_count(&self) -> u64 {
+        return self.block_count;
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+
+        if (buffer.as_ptr() as usize).is_multiple_of(self.io_align) {
+            let block_io = self.exclusive.as_mut().unwrap_or(&mut self.block_io);
+            block_io.read_blocks(self.media_id, lba, buffer)?;
+            return Ok(());
+        }
+
+        let (start, size) = self.bounce_range();
+        let mut lba = lba;
+        for chunk in buffer.chunks_mut(size) {
+            let bounce = &mut self.bounce[start..start + chunk.len()];
+            let block_io = self.exclusive.as_mut().unwrap_or(&mut self.block_io);
+            block_io.read_blocks(self.media_id, lba, bounce)?;
+            chunk.copy_from_slice(bounce);
+            lba += (chunk.len() / self.block_size) as u64;
+        }
+        return Ok(());
+    }
+
+    fn is_read_only(&self) -> bool {
+        return self.read_only;
+    }
+
+    fn device_path(&self) -> Option<&[u8]> {
+        return self.device_path.as_deref();
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), RrubError> {
+        if self.read_only {
+            return Err(RrubError::ReadOnlyDevice);
+        }
+        check_range(self, lba, buffer.len())?;
+
+        if self.exclusive.is_none() {
+            self.exclusive = Some(open_protocol_exclusive::<BlockIO>(self.handle)?);
+        }
+
+        if (buffer.as_ptr() as usize).is_multiple_of(self.io_align) {
+            if let Some(block_io) = &mut self.exclusive {
+                block_io.write_blocks(self.media_id, lba, buffer)?;
+            }
+            return Ok(());
+        }
+
+        let (start, size) = self.bounce_range();
+        let mut lba = lba;
+        for chunk in buffer.chunks(size) {
+            let bounce = &mut self.bounce[start..start + chunk.len()];
+            bounce.copy_from_slice(chunk);
+            if let Some(block_io) = &mut self.exclusive {
+                block_io.write_blocks(self.media_id, lba, bounce)?;
+            }
+            lba += (chunk.len() / self.block_size) as u64;
+        }
+        return Ok(());
+    }
+
+    /// Flushes and gives the device back to the firmware's drivers, for images started later to
+    /// find their files.
+    fn flush(&mut self) -> Result<(), RrubError> {
+        let Some(mut block_io) = self.exclusive.take() else {
+            self.block_io.flush_blocks()?;
+            return Ok(());
+        };
+
+        block_io.flush_blocks()?;
+        drop(block_io);
+        connect_controller(self.handle, None, None, true)?;
+        return Ok(());
+    }
+}
@@ -8,7 +8,7 @@ use uefi::{
 
 use crate::{
     error::RrubError,
-    firmware::framebuffer::{FrameBuffer, PixelFormat},
+    firmware::framebuffer::{FrameBuffer, FramebufferInfo, PixelFormat},
 };
 
 impl From<UefiPixelFormat> for PixelFormat {
@@ -21,6 +21,49 @@ impl From<UefiPixelFormat> for PixelFormat {
     }
 }
 
+/// Width and height of the current display mode.
+pub fn resolution() -> Result<(usize, usize), RrubError> {
+    let gop_handle = get_handle_for_protocol::<GraphicsOutput>()?;
+    let gop = open_protocol_exclusive::<GraphicsOutput>(gop_handle)?;
+    return Ok(gop.current_mode_info().resolution());
+}
+
+/// Switch to the display mode of `resolution`, staying in the current one if there's none.
+pub fn set_resolution(resolution: (usize, usize)) -> Result<(), RrubError> {
+    let gop_handle = get_handle_for_protocol::<GraphicsOutput>()?;
+    let mut gop = open_protocol_exclusive::<GraphicsOutput>(gop_handle)?;
+
+    if gop.current_mode_info().resolution() != resolution {
+        let target_mode = gop
+            .modes()
+            .find(|mode| mode.info().resolution() == resolution);
+        if let Some(mode) = target_mode {
+            gop.set_mode(&mode)?;
+        }
+    }
+    return Ok(());
+}
+
+/// The current display mode, when it has a framebuffer in a format kernels can draw to.
+pub fn framebuffer_info() -> Result<Option<FramebufferInfo>, RrubError> {
+    let gop_handle = get_handle_for_protocol::<GraphicsOutput>()?;
+    let mut gop = open_protocol_exclusive::<GraphicsOutput>(gop_handle)?;
+
+    let mode = gop.current_mode_info();
+    let format = PixelFormat::from(mode.pixel_format());
+    if format == PixelFormat::NotSupported {
+        return Ok(None);
+    }
+    let (width, height) = mode.resolution();
+    return Ok(Some(FramebufferInfo {
+        address: gop.frame_buffer().as_mut_ptr() as u64,
+        width,
+        height,
+        stride: mode.stride(),
+        format,
+    }));
+}
+
 pub struct UefiDisplay {
     fb_ptr: *mut u8,
     fb_size: usize,
@@ -32,19 +75,10 @@ pub struct UefiDisplay {
 
 impl FrameBuffer for UefiDisplay {
     fn init_fb_backend(width: usize, height: usize) -> Result<Self, RrubError> {
+        set_resolution((width, height))?;
+
         let gop_handle = get_handle_for_protocol::<GraphicsOutput>()?;
         let mut gop = open_protocol_exclusive::<GraphicsOutput>(gop_handle)?;
-
-        let cur_res = gop.current_mode_info().resolution();
-        if cur_res != (width, height) {
-            let target_mode = gop
-                .modes()
-                .find(|mode| mode.info().resolution() == (width, height));
-            if let Some(mode) = target_mode {
-                gop.set_mode(&mode)?;
-            }
-        }
-
         let new_mode = gop.current_mode_info();
         let mut fb = gop.frame_buffer();
         let fb_size = fb.size();
@@ -0,0 +1,260 @@
+/*
+ * Starting PE images through the firmware, with initrds served to Linux EFI stubs
+ * https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#image-services
+ * https://docs.kernel.org/admin-guide/efi-stub.html
+*/
+
+use alloc::{format, vec::Vec};
+use core::{ffi::c_void, slice};
+
+use log::debug;
+use uefi::{
+    Guid, Handle, Status,
+    boot::{
+        LoadImageSource, image_handle, install_protocol_interface, load_image,
+        open_protocol_exclusive, start_image, uninstall_protocol_interface, unload_image,
+    },
+    guid,
+    proto::{device_path::DevicePath, loaded_image::LoadedImage},
+    system::with_stdin,
+};
+
+use crate::{
+    crypto,
+    error::RrubError,
+    firmware::u_efi::gop,
+    loaders::linux::{concatenate_initrds, initrds_size},
+};
+
+const DEVICE_PATH_GUID: Guid = guid!("09576e91-6d3f-11d2-8e39-00a0c969723b");
+const LOAD_FILE2_GUID: Guid = guid!("4006c0c1-fcb3-403e-996d-4a6c8724e06d");
+/// Vendor media device path EFI stubs look for a LoadFile2 protocol on to load their initrd.
+const LINUX_EFI_INITRD_MEDIA_GUID: Guid = guid!("5568e427-68fc-4f3d-ac74-ca555231cc68");
+
+const MEDIA_DEVICE_PATH: u8 = 4;
+const MEDIA_VENDOR_DP: u8 = 3;
+const MEDIA_FILEPATH_DP: u8 = 4;
+const END_DEVICE_PATH: u8 = 0x7F;
+const END_ENTIRE_DEVICE_PATH: u8 = 0xFF;
+/// Type, subtype and length of each node.
+const NODE_HEADER_SIZE: usize = 4;
+const END_NODE: [u8; NODE_HEADER_SIZE] = [END_DEVICE_PATH, END_ENTIRE_DEVICE_PATH, 4, 0];
+
+#[repr(C, packed)]
+struct InitrdDevicePath {
+    node_type: u8,
+    subtype: u8,
+    length: [u8; 2],
+    vendor: [u8; 16],
+    end_type: u8,
+    end_subtype: u8,
+    end_length: [u8; 2],
+}
+
+static INITRD_DEVICE_PATH: InitrdDevicePath = InitrdDevicePath {
+    node_type: MEDIA_DEVICE_PATH,
+    subtype: MEDIA_VENDOR_DP,
+    length: [20, 0],
+    vendor: LINUX_EFI_INITRD_MEDIA_GUID.to_bytes(),
+    end_type: END_DEVICE_PATH,
+    end_subtype: END_ENTIRE_DEVICE_PATH,
+    end_length: [4, 0],
+};
+
+/// `EFI_LOAD_FILE2_PROTOCOL`, followed by the initrds it serves.
+#[repr(C)]
+struct InitrdLoader<'a> {
+    load_file: unsafe extern "efiapi" fn(
+        this: *mut InitrdLoader<'a>,
+        file_path: *const c_void,
+        boot_policy: u8,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    initrds: &'a [&'a [u8]],
+}
+
+unsafe extern "efiapi" fn load_initrd(
+    this: *mut InitrdLoader,
+    file_path: *const c_void,
+    boot_policy: u8,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    if this.is_null() || file_path.is_null() || buffer_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    // LoadFile2 is never used to load boot options.
+    if boot_policy != 0 {
+        return Status::UNSUPPORTED;
+    }
+
+    let loader = unsafe { &*this };
+    let size = initrds_size(loader.initrds);
+    unsafe {
+        if buffer.is_null() || *buffer_size < size {
+            *buffer_size = size;
+            return Status::BUFFER_TOO_SMALL;
+        }
+
+        concatenate_initrds(
+            loader.initrds,
+            slice::from_raw_parts_mut(buffer as *mut u8, size),
+        );
+        *buffer_size = size;
+    }
+
+    return Status::SUCCESS;
+}
+
+/// Device path of the file at `path` on the device `device_path` leads to, or of the file alone
+/// when the firmware doesn't know the device.
+pub fn file_device_path(device_path: Option<&[u8]>, path: &str) -> Vec<u8> {
+    let mut file_path = match device_path {
+        Some(device_path) => {
+            device_path[..device_path.len().saturating_sub(NODE_HEADER_SIZE)].to_vec()
+        }
+        None => Vec::new(),
+    };
+
+    // UCS-2 and terminated, with backslashes between components.
+    let name: Vec<u16> = format!("\\{}", path.trim_start_matches('/').replace('/', "\\"))
+        .encode_utf16()
+        .chain([0])
+        .collect();
+    let length = (NODE_HEADER_SIZE + size_of_val(name.as_slice())) as u16;
+    file_path.extend_from_slice(&[MEDIA_DEVICE_PATH, MEDIA_FILEPATH_DP]);
+    file_path.extend_from_slice(&length.to_le_bytes());
+    file_path.extend(name.iter().flat_map(|character| character.to_le_bytes()));
+
+    file_path.extend_from_slice(&END_NODE);
+    return file_path;
+}
+
+/// Load a PE image from memory and start it with `options` as its command line, returning once
+/// it exits. `file_path` is the device path it's told it was loaded from. Initrds are served
+/// concatenated for Linux EFI stubs while it runs. Keys are wiped once it's loaded if
+/// `wipe_keys` is set.
+pub fn start(
+    image: &[u8],
+    file_path: Option<&[u8]>,
+    options: &str,
+    initrds: &[&[u8]],
+    wipe_keys: bool,
+) -> Result<(), RrubError> {
+    let loader = InitrdLoader {
+        load_file: load_initrd,
+        initrds,
+    };
+    let handle = match initrds.is_empty() {
+        true => None,
+        false => Some(install_initrd(&loader)?),
+    };
+    let resolution = gop::resolution().ok();
+
+    let result = run(image, file_path, options, wipe_keys);
+
+    if let Some(handle) = handle {
+        uninstall_initrd(handle, &loader);
+    }
+    restore_console(resolution);
+    return result;
+}
+
+fn run(
+    image: &[u8],
+    file_path: Option<&[u8]>,
+    options: &str,
+    wipe_keys: bool,
+) -> Result<(), RrubError> {
+    let file_path = match file_path {
+        Some(file_path) => {
+            Some(<&DevicePath>::try_from(file_path).map_err(|_| Status::INVALID_PARAMETER)?)
+        }
+        None => None,
+    };
+    let handle = load_image(
+        image_handle(),
+        LoadImageSource::FromBuffer {
+            buffer: image,
+            file_path,
+        },
+    )?;
+
+    // UCS-2 and terminated, as the Linux EFI stub and most applications read it.
+    let options: Vec<u16> = options.encode_utf16().chain([0]).collect();
+    match open_protocol_exclusive::<LoadedImage>(handle) {
+        Ok(mut loaded_image) => unsafe {
+            loaded_image.set_load_options(
+                options.as_ptr() as *const u8,
+                size_of_val(options.as_slice()) as u32,
+            );
+        },
+        Err(e) => {
+            let _ = unload_image(handle);
+            return Err(e.into());
+        }
+    }
+
+    // The image can read all of memory, keys included.
+    if wipe_keys {
+        crypto::wipe_keys();
+    }
+
+    if let Err(e) = start_image(handle) {
+        let _ = unload_image(handle);
+        return Err(e.into());
+    }
+    return Ok(());
+}
+
+/// Applications that return, like the UEFI shell or vendor tools, can leave the display in
+/// another mode and keys they didn't read waiting.
+fn restore_console(resolution: Option<(usize, usize)>) {
+    if let Some(resolution) = resolution
+        && let Err(e) = gop::set_resolution(resolution)
+    {
+        debug!("Failed to restore display mode: {:?}", e);
+    }
+    if let Err(e) = with_stdin(|stdin| stdin.reset(false)) {
+        debug!("Failed to reset input: {:?}", e);
+    }
+}
+
+fn install_initrd(loader: &InitrdLoader) -> Result<Handle, RrubError> {
+    unsafe {
+        let handle = install_protocol_interface(
+            None,
+            &DEVICE_PATH_GUID,
+            (&raw const INITRD_DEVICE_PATH).cast(),
+        )?;
+        if let Err(e) = install_protocol_interface(
+            Some(handle),
+            &LOAD_FILE2_GUID,
+            (loader as *const InitrdLoader).cast(),
+        ) {
+            let _ = uninstall_protocol_interface(
+                handle,
+                &DEVICE_PATH_GUID,
+                (&raw const INITRD_DEVICE_PATH).cast(),
+            );
+            return Err(e.into());
+        }
+        return Ok(handle);
+    }
+}
+
+fn uninstall_initrd(handle: Handle, loader: &InitrdLoader) {
+    unsafe {
+        let _ = uninstall_protocol_interface(
+            handle,
+            &LOAD_FILE2_GUID,
+            (loader as *const InitrdLoader).cast(),
+        );
+        let _ = uninstall_protocol_interface(
+            handle,
+            &DEVICE_PATH_GUID,
+            (&raw const INITRD_DEVICE_PATH).cast(),
+        );
+    }
+}
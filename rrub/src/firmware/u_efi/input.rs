@@ -1,4 +1,9 @@
-use crate::firmware::input::InputBackend;
+use uefi::{
+    proto::console::text::{Key as UefiKey, ScanCode},
+    system::with_stdin,
+};
+
+use crate::firmware::input::{ControlChar, InputBackend, Key, SpecialKey};
 
 pub struct UefiInput {}
 
@@ -8,6 +13,33 @@ impl InputBackend for UefiInput {
     }
 
     fn read_key(&self) -> Option<crate::firmware::input::Key> {
-        return None;
+        let key = with_stdin(|stdin| stdin.read_key()).ok()??;
+
+        return match key {
+            UefiKey::Printable(c) => match char::from(c) {
+                '\u{8}' => Some(Key::ControlChar(ControlChar::Backspace)),
+                '\t' => Some(Key::ControlChar(ControlChar::Tab)),
+                '\n' => Some(Key::ControlChar(ControlChar::LineFeed)),
+                '\r' => Some(Key::ControlChar(ControlChar::Return)),
+                c => Some(Key::Printable(c)),
+            },
+            UefiKey::Special(scan_code) => {
+                let special = match scan_code {
+                    ScanCode::UP => SpecialKey::Up,
+                    ScanCode::DOWN => SpecialKey::Down,
+                    ScanCode::RIGHT => SpecialKey::Right,
+                    ScanCode::LEFT => SpecialKey::Left,
+                    ScanCode::HOME => SpecialKey::Home,
+                    ScanCode::END => SpecialKey::End,
+                    ScanCode::INSERT => SpecialKey::Insert,
+                    ScanCode::DELETE => SpecialKey::Delete,
+                    ScanCode::PAGE_UP => SpecialKey::PageUp,
+                    ScanCode::PAGE_DOWN => SpecialKey::PageDown,
+                    ScanCode::ESCAPE => SpecialKey::Escape,
+                    _ => return None,
+                };
+                Some(Key::SpecialKey(special))
+            }
+        };
     }
 }
@@ -5,7 +5,13 @@ use crate::firmware::memory::{MemoryRegion, MemoryType, PAGE_SIZE};
 impl From<&MemoryDescriptor> for MemoryRegion {
     fn from(value: &MemoryDescriptor) -> Self {
         let mem_type = match value.ty {
-            UefiMemoryType::CONVENTIONAL => MemoryType::Available,
+            // Boot services memory is free once they are exited, what was loaded into it is
+            // reserved by the next stage itself.
+            UefiMemoryType::CONVENTIONAL
+            | UefiMemoryType::LOADER_CODE
+            | UefiMemoryType::LOADER_DATA
+            | UefiMemoryType::BOOT_SERVICES_CODE
+            | UefiMemoryType::BOOT_SERVICES_DATA => MemoryType::Available,
             UefiMemoryType::ACPI_RECLAIM => MemoryType::Acpi,
             UefiMemoryType::ACPI_NON_VOLATILE => MemoryType::Nvs,
             UefiMemoryType::UNUSABLE => MemoryType::Unusable,
@@ -0,0 +1,239 @@
+pub mod btrfs;
+pub mod erofs;
+pub mod exfat;
+pub mod ext4;
+pub mod fat;
+pub mod iso9660;
+pub mod loopback;
+pub mod luks;
+pub mod lvm;
+pub mod md;
+pub mod ntfs;
+pub mod partition;
+pub mod probe;
+pub mod squashfs;
+pub mod xfs;
+pub mod zfs;
+
+use alloc::{boxed::Box, collections::BTreeMap, rc::Rc, vec, vec::Vec};
+use core::cell::RefCell;
+
+use log::debug;
+
+use crate::{
+    crypto::CryptoError,
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{Filesystem, FilesystemBackend, FilesystemError},
+    },
+    fs::{
+        btrfs::BtrfsFilesystem,
+        erofs::ErofsFilesystem,
+        exfat::ExfatFilesystem,
+        ext4::Ext4Filesystem,
+        fat::FatFilesystem,
+        iso9660::{BootMedia, BootPlatform, Iso9660Filesystem},
+        loopback::LoopDevice,
+        luks::{LuksDevice, LuksVolume},
+        ntfs::NtfsFilesystem,
+        partition::{PartitionDevice, read_partitions},
+        probe::{Usage, VolumeInfo},
+        squashfs::SquashFilesystem,
+        xfs::XfsFilesystem,
+        zfs::ZfsFilesystem,
+    },
+};
+
+/// Mount a block device with the driver of the filesystem `probe::probe` finds on it.
+pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<Filesystem, RrubError> {
+    let info = probe::probe(device.as_mut()).ok_or(FilesystemError::UnknownFilesystem)?;
+    return mount_as(device, &info);
+}
+
+fn mount_as(device: Box<dyn BlockDevice>, info: &VolumeInfo) -> Result<Filesystem, RrubError> {
+    let backend: Box<dyn FilesystemBackend> = match info.fs_type {
+        "btrfs" => Box::new(BtrfsFilesystem::mount(vec![device])?),
+        "ext2" | "ext3" | "ext4" => Box::new(Ext4Filesystem::mount(device)?),
+        "xfs" => Box::new(XfsFilesystem::mount(device)?),
+        "squashfs" => Box::new(SquashFilesystem::mount(device)?),
+        "erofs" => Box::new(ErofsFilesystem::mount(device)?),
+        "iso9660" => Box::new(Iso9660Filesystem::mount(device)?),
+        "ntfs" => Box::new(NtfsFilesystem::mount(device)?),
+        "exfat" => Box::new(ExfatFilesystem::mount(device)?),
+        "vfat" => Box::new(FatFilesystem::mount(device)?),
+        "zfs_member" => Box::new(ZfsFilesystem::mount(vec![device])?),
+        // Encrypted containers and RAID or LVM members hold no filesystem of their own.
+        _ => return Err(FilesystemError::Unsupported.into()),
+    };
+
+    return Ok(Filesystem::new(backend));
+}
+
+/// Mount every filesystem found on a set of block devices. Filesystems spanning several devices,
+/// such as multi device btrfs and ZFS pools, are mounted once from all of their members, and those
+/// on md arrays and LVM logical volumes once the arrays and volume groups are assembled. LUKS
+/// containers are handed to `unlock` and their cleartext probed like any other device.
+pub fn mount_all(
+    devices: Vec<Box<dyn BlockDevice>>,
+    unlock: &mut dyn FnMut(LuksVolume) -> Result<LuksDevice, RrubError>,
+) -> Vec<Result<Filesystem, RrubError>> {
+    let mut results = Vec::new();
+    let mut btrfs: BTreeMap<[u8; 16], Vec<Box<dyn BlockDevice>>> = BTreeMap::new();
+    let mut zfs: BTreeMap<u64, Vec<Box<dyn BlockDevice>>> = BTreeMap::new();
+    let mut lvm_members = Vec::new();
+
+    // Assembled md arrays, unlocked containers and logical volumes are probed like any other
+    // device, as they may hold LVM PVs or LUKS containers. LVs are only assembled once nothing
+    // else is left, so that VGs get every PV.
+    let mut pending = devices;
+    while !pending.is_empty() {
+        let mut md_members = Vec::new();
+        let mut unlocked: Vec<Box<dyn BlockDevice>> = Vec::new();
+        for mut device in pending {
+            let Some(info) = probe::probe(device.as_mut()) else {
+                results.push(Err(FilesystemError::UnknownFilesystem.into()));
+                continue;
+            };
+            match info.fs_type {
+                "linux_raid_member" => {
+                    md_members.push(device);
+                    continue;
+                }
+                "LVM2_member" => {
+                    lvm_members.push(device);
+                    continue;
+                }
+                "crypto_LUKS" => {
+                    match LuksVolume::open(device).and_then(&mut *unlock) {
+                        Ok(cleartext) => unlocked.push(Box::new(cleartext)),
+                        Err(e) => results.push(Err(e)),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if info.usage != Usage::Filesystem {
+                debug!("Skipping {} member {:?}", info.fs_type, info.uuid);
+                continue;
+            }
+
+            if info.fs_type == "btrfs" {
+                match BtrfsFilesystem::read_superblock(device.as_mut()) {
+                    Ok(superblock) => btrfs.entry(superblock.fsid).or_default().push(device),
+                    Err(e) => results.push(Err(e)),
+                }
+                continue;
+            }
+            if info.fs_type == "zfs_member" {
+                match ZfsFilesystem::pool_guid(device.as_mut()) {
+                    Ok(guid) => zfs.entry(guid).or_default().push(device),
+                    Err(e) => results.push(Err(e)),
+                }
+                continue;
+            }
+            let device_path = device.device_path().map(<[u8]>::to_vec);
+            let device_names = device.device_names();
+            results.push(mount_as(device, &info).map(|mut filesystem| {
+                filesystem.set_device_path(device_path);
+                filesystem.set_device_names(device_names);
+                filesystem
+            }));
+        }
+
+        pending = md::assemble(md_members)
+            .into_iter()
+            .map(|array| Box::new(array) as Box<dyn BlockDevice>)
+            .chain(unlocked)
+            .collect();
+        if pending.is_empty() {
+            pending = lvm::assemble(core::mem::take(&mut lvm_members))
+                .into_iter()
+                .map(|volume| Box::new(volume) as Box<dyn BlockDevice>)
+                .collect();
+        }
+    }
+
+    for members in btrfs.into_values() {
+        results.push(
+            BtrfsFilesystem::mount(members).map(|filesystem| Filesystem::new(Box::new(filesystem))),
+        );
+    }
+
+    for members in zfs.into_values() {
+        results.push(
+            ZfsFilesystem::mount(members).map(|filesystem| Filesystem::new(Box::new(filesystem))),
+        );
+    }
+
+    return results;
+}
+
+/// Mount the filesystems of a disk or ISO image stored on `filesystem`, found on the image as a
+/// whole and on each of its partitions.
+pub fn mount_image(
+    filesystem: Rc<RefCell<Filesystem>>,
+    path: &str,
+) -> Result<Vec<Filesystem>, RrubError> {
+    let image: Box<dyn BlockDevice> = Box::new(LoopDevice::open(filesystem, path)?);
+    let image = Rc::new(RefCell::new(image));
+    let partitions = read_partitions(image.borrow_mut().as_mut()).unwrap_or_else(|e| {
+        debug!("Ignoring partition table of {}: {:?}", path, e);
+        Vec::new()
+    });
+
+    let mut devices: Vec<Box<dyn BlockDevice>> =
+        vec![Box::new(PartitionDevice::whole(image.clone()))];
+
+    // Install media that aren't hybrid only have their ESP image in the El Torito catalog.
+    let (block_size, block_count) = {
+        let image = image.borrow();
+        (image.block_size() as u64, image.block_count())
+    };
+    let catalog = Iso9660Filesystem::boot_catalog(image.borrow_mut().as_mut()).unwrap_or_default();
+    for entry in catalog.iter().filter(|entry| {
+        entry.bootable
+            && entry.platform == BootPlatform::Efi
+            && entry.media == BootMedia::NoEmulation
+    }) {
+        let first_block = entry.offset() / block_size;
+        if entry.offset() % block_size != 0
+            || first_block >= block_count
+            || partitions
+                .iter()
+                .any(|partition| partition.first_block == first_block)
+        {
+            continue;
+        }
+        // Images too big for the 16 bit count record one sector, their filesystem knows its size.
+        let length = match entry.sector_count > 1 {
+            true => entry.length().div_ceil(block_size),
+            false => block_count - first_block,
+        };
+        devices.push(Box::new(PartitionDevice::range(
+            image.clone(),
+            first_block,
+            length.min(block_count - first_block),
+        )));
+    }
+
+    for partition in partitions {
+        // Hybrid ISOs point their first partition at the ISO9660 volume that is already mounted
+        // from the whole image.
+        if partition.first_block == 0 {
+            continue;
+        }
+        devices.push(Box::new(PartitionDevice::new(image.clone(), &partition)));
+    }
+
+    // Nothing prompts for passphrases while an entry is booting, containers in images stay locked.
+    let mut filesystems = Vec::new();
+    for result in mount_all(devices, &mut |_| Err(CryptoError::Unsupported.into())) {
+        match result {
+            Ok(filesystem) => filesystems.push(filesystem),
+            Err(e) => debug!("Skipping filesystem in {}: {:?}", path, e),
+        }
+    }
+
+    return Ok(filesystems);
+}
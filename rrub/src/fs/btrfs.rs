@@ -0,0 +1,1178 @@
+/*
+ * https://btrfs.readthedocs.io/en/latest/dev/On-disk-format.html
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use bitflags::bitflags;
+use log::warn;
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    checksum::{crc32c, crc32c_update, xxh64},
+    decompress::{DecompressError, inflate::zlib_decompress, lzo, zstd},
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo, label_from_bytes},
+};
+
+const SUPERBLOCK_OFFSET: u64 = 0x10000;
+const SUPERBLOCK_MAGIC: [u8; 8] = *b"_BHRfS_M";
+const CSUM_SIZE: usize = 32;
+
+const CSUM_TYPE_CRC32C: u16 = 0;
+const CSUM_TYPE_XXHASH64: u16 = 1;
+
+const FS_TREE_OBJECTID: u64 = 5;
+const FIRST_CHUNK_TREE_OBJECTID: u64 = 256;
+
+const INODE_ITEM_KEY: u8 = 1;
+const DIR_ITEM_KEY: u8 = 84;
+const DIR_INDEX_KEY: u8 = 96;
+const EXTENT_DATA_KEY: u8 = 108;
+const ROOT_ITEM_KEY: u8 = 132;
+const CHUNK_ITEM_KEY: u8 = 228;
+
+const FT_REG_FILE: u8 = 1;
+const FT_DIR: u8 = 2;
+const FT_SYMLINK: u8 = 7;
+
+const FILE_EXTENT_INLINE: u8 = 0;
+const FILE_EXTENT_REG: u8 = 1;
+const FILE_EXTENT_PREALLOC: u8 = 2;
+
+const COMPRESS_NONE: u8 = 0;
+const COMPRESS_ZLIB: u8 = 1;
+const COMPRESS_LZO: u8 = 2;
+const COMPRESS_ZSTD: u8 = 3;
+
+const S_IFMT: u32 = 0xF000;
+const S_IFDIR: u32 = 0x4000;
+const S_IFREG: u32 = 0x8000;
+const S_IFLNK: u32 = 0xA000;
+
+/// Tree depth limit, btrfs itself never builds trees deeper than 8 levels.
+const MAX_LEVEL: u8 = 8;
+const BLOCK_CACHE_ENTRIES: usize = 64;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct IncompatFeatures: u64 {
+        const MixedBackref = 0x1;
+        const DefaultSubvol = 0x2;
+        const MixedGroups = 0x4;
+        const CompressLzo = 0x8;
+        const CompressZstd = 0x10;
+        const BigMetadata = 0x20;
+        const ExtendedIref = 0x40;
+        const Raid56 = 0x80;
+        const SkinnyMetadata = 0x100;
+        const NoHoles = 0x200;
+        const MetadataUuid = 0x400;
+        const Raid1c34 = 0x800;
+        const Zoned = 0x1000;
+        const ExtentTreeV2 = 0x2000;
+        const RaidStripeTree = 0x4000;
+        const SimpleQuota = 0x10000;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct ChunkType: u64 {
+        const Data = 0x1;
+        const System = 0x2;
+        const Metadata = 0x4;
+        const Raid0 = 0x8;
+        const Raid1 = 0x10;
+        const Dup = 0x20;
+        const Raid10 = 0x40;
+        const Raid5 = 0x80;
+        const Raid6 = 0x100;
+        const Raid1c3 = 0x200;
+        const Raid1c4 = 0x400;
+        const _ = !0;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DevItem {
+    pub devid: U64<LittleEndian>,
+    pub total_bytes: U64<LittleEndian>,
+    pub bytes_used: U64<LittleEndian>,
+    pub io_align: U32<LittleEndian>,
+    pub io_width: U32<LittleEndian>,
+    pub sector_size: U32<LittleEndian>,
+    pub dev_type: U64<LittleEndian>,
+    pub generation: U64<LittleEndian>,
+    pub start_offset: U64<LittleEndian>,
+    pub dev_group: U32<LittleEndian>,
+    pub seek_speed: u8,
+    pub bandwidth: u8,
+    pub uuid: [u8; 16],
+    pub fsid: [u8; 16],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Superblock {
+    pub csum: [u8; CSUM_SIZE],
+    pub fsid: [u8; 16],
+    pub bytenr: U64<LittleEndian>,
+    pub flags: U64<LittleEndian>,
+    pub magic: [u8; 8],
+    pub generation: U64<LittleEndian>,
+    pub root: U64<LittleEndian>,
+    pub chunk_root: U64<LittleEndian>,
+    pub log_root: U64<LittleEndian>,
+    pub log_root_transid: U64<LittleEndian>,
+    pub total_bytes: U64<LittleEndian>,
+    pub bytes_used: U64<LittleEndian>,
+    pub root_dir_objectid: U64<LittleEndian>,
+    pub num_devices: U64<LittleEndian>,
+    pub sectorsize: U32<LittleEndian>,
+    pub nodesize: U32<LittleEndian>,
+    pub leafsize: U32<LittleEndian>,
+    pub stripesize: U32<LittleEndian>,
+    pub sys_chunk_array_size: U32<LittleEndian>,
+    pub chunk_root_generation: U64<LittleEndian>,
+    pub compat_flags: U64<LittleEndian>,
+    pub compat_ro_flags: U64<LittleEndian>,
+    pub incompat_flags: U64<LittleEndian>,
+    pub csum_type: U16<LittleEndian>,
+    pub root_level: u8,
+    pub chunk_root_level: u8,
+    pub log_root_level: u8,
+    pub dev_item: DevItem,
+    pub label: [u8; 256],
+    pub cache_generation: U64<LittleEndian>,
+    pub uuid_tree_generation: U64<LittleEndian>,
+    pub metadata_uuid: [u8; 16],
+    pub nr_global_roots: U64<LittleEndian>,
+    pub _reserved: [U64<LittleEndian>; 27],
+    pub sys_chunk_array: [u8; 2048],
+    pub _super_roots: [u8; 672],
+    pub _padding: [u8; 565],
+}
+
+const _: () = assert!(size_of::<Superblock>() == 4096);
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Key {
+    pub objectid: U64<LittleEndian>,
+    pub item_type: u8,
+    pub offset: U64<LittleEndian>,
+}
+
+impl Key {
+    fn tuple(&self) -> (u64, u8, u64) {
+        return (self.objectid.get(), self.item_type, self.offset.get());
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Header {
+    pub csum: [u8; CSUM_SIZE],
+    pub fsid: [u8; 16],
+    pub bytenr: U64<LittleEndian>,
+    pub flags: U64<LittleEndian>,
+    pub chunk_tree_uuid: [u8; 16],
+    pub generation: U64<LittleEndian>,
+    pub owner: U64<LittleEndian>,
+    pub nritems: U32<LittleEndian>,
+    pub level: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Item {
+    pub key: Key,
+    pub offset: U32<LittleEndian>,
+    pub size: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct KeyPtr {
+    pub key: Key,
+    pub blockptr: U64<LittleEndian>,
+    pub generation: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ChunkItem {
+    pub length: U64<LittleEndian>,
+    pub owner: U64<LittleEndian>,
+    pub stripe_len: U64<LittleEndian>,
+    pub chunk_type: U64<LittleEndian>,
+    pub io_align: U32<LittleEndian>,
+    pub io_width: U32<LittleEndian>,
+    pub sector_size: U32<LittleEndian>,
+    pub num_stripes: U16<LittleEndian>,
+    pub sub_stripes: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Stripe {
+    pub devid: U64<LittleEndian>,
+    pub offset: U64<LittleEndian>,
+    pub dev_uuid: [u8; 16],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Timespec {
+    pub sec: U64<LittleEndian>,
+    pub nsec: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct InodeItem {
+    pub generation: U64<LittleEndian>,
+    pub transid: U64<LittleEndian>,
+    pub size: U64<LittleEndian>,
+    pub nbytes: U64<LittleEndian>,
+    pub block_group: U64<LittleEndian>,
+    pub nlink: U32<LittleEndian>,
+    pub uid: U32<LittleEndian>,
+    pub gid: U32<LittleEndian>,
+    pub mode: U32<LittleEndian>,
+    pub rdev: U64<LittleEndian>,
+    pub flags: U64<LittleEndian>,
+    pub sequence: U64<LittleEndian>,
+    pub _reserved: [U64<LittleEndian>; 4],
+    pub atime: Timespec,
+    pub ctime: Timespec,
+    pub mtime: Timespec,
+    pub otime: Timespec,
+}
+
+impl InodeItem {
+    fn file_type(&self) -> FileType {
+        return match self.mode.get() & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFREG => FileType::Regular,
+            S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        };
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct RootItem {
+    pub inode: InodeItem,
+    pub generation: U64<LittleEndian>,
+    pub root_dirid: U64<LittleEndian>,
+    pub bytenr: U64<LittleEndian>,
+    pub byte_limit: U64<LittleEndian>,
+    pub bytes_used: U64<LittleEndian>,
+    pub last_snapshot: U64<LittleEndian>,
+    pub flags: U64<LittleEndian>,
+    pub refs: U32<LittleEndian>,
+    pub drop_progress: Key,
+    pub drop_level: u8,
+    pub level: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirItem {
+    pub location: Key,
+    pub transid: U64<LittleEndian>,
+    pub data_len: U16<LittleEndian>,
+    pub name_len: U16<LittleEndian>,
+    pub item_type: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FileExtentItem {
+    pub generation: U64<LittleEndian>,
+    pub ram_bytes: U64<LittleEndian>,
+    pub compression: u8,
+    pub encryption: u8,
+    pub other_encoding: U16<LittleEndian>,
+    pub extent_type: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct RegularExtent {
+    pub disk_bytenr: U64<LittleEndian>,
+    pub disk_num_bytes: U64<LittleEndian>,
+    pub offset: U64<LittleEndian>,
+    pub num_bytes: U64<LittleEndian>,
+}
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    length: u64,
+    chunk_type: ChunkType,
+    /// (devid, physical offset) of every copy.
+    stripes: Vec<(u64, u64)>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Tree {
+    bytenr: u64,
+    level: u8,
+    root_dirid: u64,
+}
+
+#[derive(Debug, Clone)]
+enum ExtentData {
+    Inline(Vec<u8>),
+    Hole,
+    Plain(u64),
+    Compressed {
+        disk_bytenr: u64,
+        disk_num_bytes: u64,
+        compression: u8,
+        ram_bytes: u64,
+        offset: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct FileExtent {
+    offset: u64,
+    length: u64,
+    data: ExtentData,
+}
+
+pub struct BtrfsFilesystem {
+    devices: BTreeMap<u64, Box<dyn BlockDevice>>,
+    superblock: Superblock,
+    chunks: BTreeMap<u64, Chunk>,
+    trees: BTreeMap<u64, Tree>,
+    /// Node ids index this list of (tree, objectid) pairs.
+    nodes: Vec<(u64, u64)>,
+    node_ids: BTreeMap<(u64, u64), NodeId>,
+    inodes: BTreeMap<NodeId, InodeItem>,
+    extents: BTreeMap<NodeId, Vec<FileExtent>>,
+    root: NodeId,
+    block_cache: BTreeMap<u64, Vec<u8>>,
+    /// Last decompressed extent, file reads usually walk one extent in several calls.
+    extent_cache: Option<(u64, Vec<u8>)>,
+}
+
+fn item_range(objectid: u64, item_type: u8) -> ((u64, u8, u64), (u64, u8, u64)) {
+    return ((objectid, item_type, 0), (objectid, item_type, u64::MAX));
+}
+
+/// Name hash used as the key offset of DIR_ITEM entries.
+fn name_hash(name: &[u8]) -> u64 {
+    return crc32c_update(!1, name) as u64;
+}
+
+/// Each compressed extent starts with its total length, followed by LZO segments prefixed with
+/// their length. Segment headers never straddle a sector, a header that would is moved to the
+/// next one.
+fn lzo_decompress(
+    data: &[u8],
+    sector_size: usize,
+    limit: usize,
+) -> Result<Vec<u8>, DecompressError> {
+    let read_length = |offset: usize| -> Result<usize, DecompressError> {
+        let bytes = data
+            .get(offset..offset + 4)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        return Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+    };
+
+    let total = read_length(0)?;
+    if total > data.len() {
+        return Err(DecompressError::UnexpectedEnd);
+    }
+
+    let mut output = Vec::new();
+    let mut offset = 4;
+    while offset < total && output.len() < limit {
+        if sector_size - offset % sector_size < 4 {
+            offset = offset.next_multiple_of(sector_size);
+            if offset >= total {
+                break;
+            }
+        }
+
+        let length = read_length(offset)?;
+        offset += 4;
+        let segment = data
+            .get(offset..offset + length)
+            .ok_or(DecompressError::UnexpectedEnd)?;
+        lzo::decompress(segment, &mut output, limit)?;
+        offset += length;
+    }
+
+    return Ok(output);
+}
+
+impl BtrfsFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut magic = [0u8; 8];
+        return device
+            .read_bytes(SUPERBLOCK_OFFSET + 0x40, &mut magic)
+            .is_ok()
+            && magic == SUPERBLOCK_MAGIC;
+    }
+
+    /// Read and verify the primary superblock, used to group the devices of a filesystem before
+    /// mounting.
+    pub fn read_superblock(device: &mut dyn BlockDevice) -> Result<Superblock, RrubError> {
+        let mut raw = vec![0u8; size_of::<Superblock>()];
+        device.read_bytes(SUPERBLOCK_OFFSET, &mut raw)?;
+        let superblock =
+            Superblock::read_from_bytes(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if superblock.magic != SUPERBLOCK_MAGIC {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+        if !verify_csum(superblock.csum_type.get(), &raw) {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+
+        return Ok(superblock);
+    }
+
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let superblock = Self::read_superblock(device).ok()?;
+        return Some(VolumeInfo {
+            fs_type: "btrfs",
+            usage: Usage::Filesystem,
+            label: label_from_bytes(&superblock.label),
+            uuid: Some(Uuid::RealUuid(RealUuid::from_bytes(superblock.fsid))),
+            size: superblock.total_bytes.get(),
+        });
+    }
+
+    /// Mount a filesystem from all of its member devices, mirrored chunks stay readable with
+    /// devices missing.
+    pub fn mount(devices: Vec<Box<dyn BlockDevice>>) -> Result<BtrfsFilesystem, RrubError> {
+        let mut members = BTreeMap::new();
+        let mut superblock: Option<Superblock> = None;
+
+        for mut device in devices {
+            let current = Self::read_superblock(device.as_mut())?;
+            if let Some(existing) = &superblock
+                && existing.fsid != current.fsid
+            {
+                return Err(FilesystemError::Corrupted.into());
+            }
+
+            if superblock
+                .as_ref()
+                .is_none_or(|existing| current.generation.get() > existing.generation.get())
+            {
+                superblock = Some(current);
+            }
+            members
+                .entry(current.dev_item.devid.get())
+                .or_insert(device);
+        }
+
+        let superblock = superblock.ok_or(FilesystemError::UnknownFilesystem)?;
+
+        let incompat = IncompatFeatures::from_bits(superblock.incompat_flags.get())
+            .ok_or(FilesystemError::Unsupported)?;
+        if incompat.intersects(IncompatFeatures::ExtentTreeV2 | IncompatFeatures::RaidStripeTree) {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let nodesize = superblock.nodesize.get();
+        let sectorsize = superblock.sectorsize.get();
+        if !nodesize.is_power_of_two()
+            || !sectorsize.is_power_of_two()
+            || nodesize < sectorsize
+            || nodesize > 0x10000
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        if (members.len() as u64) < superblock.num_devices.get() {
+            warn!(
+                "btrfs filesystem has {} of {} devices",
+                members.len(),
+                superblock.num_devices.get()
+            );
+        }
+
+        let mut filesystem = BtrfsFilesystem {
+            devices: members,
+            superblock,
+            chunks: BTreeMap::new(),
+            trees: BTreeMap::new(),
+            nodes: Vec::new(),
+            node_ids: BTreeMap::new(),
+            inodes: BTreeMap::new(),
+            extents: BTreeMap::new(),
+            root: NodeId(0),
+            block_cache: BTreeMap::new(),
+            extent_cache: None,
+        };
+
+        filesystem.load_sys_chunks()?;
+        filesystem.load_chunk_tree()?;
+        filesystem.root = filesystem.subvolume_root(FS_TREE_OBJECTID)?;
+
+        return Ok(filesystem);
+    }
+
+    fn add_chunk(&mut self, logical: u64, data: &[u8]) -> Result<usize, RrubError> {
+        let (chunk, mut rest) =
+            ChunkItem::read_from_prefix(data).map_err(|_| FilesystemError::Corrupted)?;
+
+        let mut stripes = Vec::new();
+        for _ in 0..chunk.num_stripes.get() {
+            let (stripe, next) =
+                Stripe::read_from_prefix(rest).map_err(|_| FilesystemError::Corrupted)?;
+            stripes.push((stripe.devid.get(), stripe.offset.get()));
+            rest = next;
+        }
+        if stripes.is_empty() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        self.chunks.insert(
+            logical,
+            Chunk {
+                length: chunk.length.get(),
+                chunk_type: ChunkType::from_bits_retain(chunk.chunk_type.get()),
+                stripes,
+            },
+        );
+
+        return Ok(size_of::<ChunkItem>() + chunk.num_stripes.get() as usize * size_of::<Stripe>());
+    }
+
+    /// The superblock carries the system chunks needed to read the chunk tree itself.
+    fn load_sys_chunks(&mut self) -> Result<(), RrubError> {
+        let array = self.superblock.sys_chunk_array;
+        let size = (self.superblock.sys_chunk_array_size.get() as usize).min(array.len());
+
+        let mut offset = 0;
+        while offset < size {
+            let (key, _) = Key::read_from_prefix(&array[offset..size])
+                .map_err(|_| FilesystemError::Corrupted)?;
+            offset += size_of::<Key>();
+            if key.item_type != CHUNK_ITEM_KEY {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            offset += self.add_chunk(key.offset.get(), &array[offset..size])?;
+        }
+
+        return Ok(());
+    }
+
+    fn load_chunk_tree(&mut self) -> Result<(), RrubError> {
+        let chunk_root = self.superblock.chunk_root.get();
+        let level = self.superblock.chunk_root_level;
+
+        let items = self.items(
+            chunk_root,
+            level,
+            (FIRST_CHUNK_TREE_OBJECTID, CHUNK_ITEM_KEY, 0),
+            (FIRST_CHUNK_TREE_OBJECTID, CHUNK_ITEM_KEY, u64::MAX),
+        )?;
+        for (key, data) in items {
+            self.add_chunk(key.offset.get(), &data)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Read `buffer.len()` bytes at a logical address from the given copy.
+    fn read_logical(
+        &mut self,
+        logical: u64,
+        buffer: &mut [u8],
+        mirror: usize,
+    ) -> Result<(), RrubError> {
+        let mut done = 0;
+
+        while done < buffer.len() {
+            let address = logical + done as u64;
+            let (&start, chunk) = self
+                .chunks
+                .range(..=address)
+                .next_back()
+                .filter(|(start, chunk)| address < *start + chunk.length)
+                .ok_or(FilesystemError::Corrupted)?;
+
+            if chunk.chunk_type.intersects(
+                ChunkType::Raid0 | ChunkType::Raid10 | ChunkType::Raid5 | ChunkType::Raid6,
+            ) {
+                return Err(FilesystemError::Unsupported.into());
+            }
+
+            let offset = address - start;
+            let count = ((chunk.length - offset) as usize).min(buffer.len() - done);
+
+            // Single, DUP and RAID1 variants keep a full copy of the chunk in each stripe.
+            let &(devid, physical) = chunk.stripes.get(mirror).ok_or(FilesystemError::NotFound)?;
+            let device = self
+                .devices
+                .get_mut(&devid)
+                .ok_or(FilesystemError::NotFound)?;
+            device.read_bytes(physical + offset, &mut buffer[done..done + count])?;
+
+            done += count;
+        }
+
+        return Ok(());
+    }
+
+    fn mirrors(&self, logical: u64) -> usize {
+        return self
+            .chunks
+            .range(..=logical)
+            .next_back()
+            .map_or(1, |(_, chunk)| chunk.stripes.len());
+    }
+
+    /// Read data from the first copy that can be read.
+    fn read_data(&mut self, logical: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        let mut result = Ok(());
+        for mirror in 0..self.mirrors(logical) {
+            result = self.read_logical(logical, buffer, mirror);
+            if result.is_ok() {
+                break;
+            }
+        }
+        return result;
+    }
+
+    /// Read a tree block, falling back to other copies when one fails verification.
+    fn read_block(&mut self, logical: u64, level: u8) -> Result<Vec<u8>, RrubError> {
+        if let Some(block) = self.block_cache.get(&logical) {
+            return Ok(block.clone());
+        }
+
+        let nodesize = self.superblock.nodesize.get() as usize;
+        let fsid = match IncompatFeatures::from_bits_retain(self.superblock.incompat_flags.get())
+            .contains(IncompatFeatures::MetadataUuid)
+        {
+            true => self.superblock.metadata_uuid,
+            false => self.superblock.fsid,
+        };
+
+        let mut result = Err(FilesystemError::Corrupted.into());
+        for mirror in 0..self.mirrors(logical) {
+            let mut block = vec![0u8; nodesize];
+            if let Err(e) = self.read_logical(logical, &mut block, mirror) {
+                result = Err(e);
+                continue;
+            }
+
+            let (header, _) =
+                Header::read_from_prefix(&block).map_err(|_| FilesystemError::Corrupted)?;
+            if !verify_csum(self.superblock.csum_type.get(), &block) {
+                warn!(
+                    "btrfs tree block {:#x} copy {} has a bad checksum",
+                    logical, mirror
+                );
+                result = Err(FilesystemError::ChecksumMismatch.into());
+                continue;
+            }
+            if header.bytenr.get() != logical || header.fsid != fsid || header.level != level {
+                result = Err(FilesystemError::Corrupted.into());
+                continue;
+            }
+
+            if self.block_cache.len() >= BLOCK_CACHE_ENTRIES {
+                self.block_cache.clear();
+            }
+            self.block_cache.insert(logical, block.clone());
+            return Ok(block);
+        }
+
+        return result;
+    }
+
+    /// Collect every item with a key in `min..=max` from the tree rooted at `root`.
+    fn items(
+        &mut self,
+        root: u64,
+        level: u8,
+        min: (u64, u8, u64),
+        max: (u64, u8, u64),
+    ) -> Result<Vec<(Key, Vec<u8>)>, RrubError> {
+        let mut items = Vec::new();
+        self.collect_items(root, level, min, max, &mut items)?;
+        return Ok(items);
+    }
+
+    fn collect_items(
+        &mut self,
+        block: u64,
+        level: u8,
+        min: (u64, u8, u64),
+        max: (u64, u8, u64),
+        items: &mut Vec<(Key, Vec<u8>)>,
+    ) -> Result<(), RrubError> {
+        if level > MAX_LEVEL {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let data = self.read_block(block, level)?;
+        let (header, body) =
+            Header::read_from_prefix(&data).map_err(|_| FilesystemError::Corrupted)?;
+        let count = header.nritems.get() as usize;
+
+        if level == 0 {
+            let (entries, _) = <[Item]>::ref_from_prefix_with_elems(body, count)
+                .map_err(|_| FilesystemError::Corrupted)?;
+
+            for item in entries {
+                let key = item.key.tuple();
+                if key < min {
+                    continue;
+                }
+                if key > max {
+                    break;
+                }
+
+                let start = item.offset.get() as usize;
+                let value = body
+                    .get(start..start + item.size.get() as usize)
+                    .ok_or(FilesystemError::Corrupted)?;
+                items.push((item.key, value.to_vec()));
+            }
+            return Ok(());
+        }
+
+        let (pointers, _) = <[KeyPtr]>::ref_from_prefix_with_elems(body, count)
+            .map_err(|_| FilesystemError::Corrupted)?;
+        let pointers = pointers.to_vec();
+
+        for (i, pointer) in pointers.iter().enumerate() {
+            if pointer.key.tuple() > max {
+                break;
+            }
+            // Children only hold keys below the next pointer's key.
+            if pointers
+                .get(i + 1)
+                .is_some_and(|next| next.key.tuple() <= min)
+            {
+                continue;
+            }
+            self.collect_items(pointer.blockptr.get(), level - 1, min, max, items)?;
+        }
+
+        return Ok(());
+    }
+
+    fn tree(&mut self, id: u64) -> Result<Tree, RrubError> {
+        if let Some(&tree) = self.trees.get(&id) {
+            return Ok(tree);
+        }
+
+        let root = self.superblock.root.get();
+        let level = self.superblock.root_level;
+        let (min, max) = item_range(id, ROOT_ITEM_KEY);
+
+        // Snapshots carry their creation transid as key offset, the last item is the current one.
+        let (_, data) = self
+            .items(root, level, min, max)?
+            .pop()
+            .ok_or(FilesystemError::NotFound)?;
+        let (item, _) =
+            RootItem::read_from_prefix(&data).map_err(|_| FilesystemError::Corrupted)?;
+
+        let tree = Tree {
+            bytenr: item.bytenr.get(),
+            level: item.level,
+            root_dirid: item.root_dirid.get(),
+        };
+        self.trees.insert(id, tree);
+        return Ok(tree);
+    }
+
+    fn tree_items(
+        &mut self,
+        tree: u64,
+        min: (u64, u8, u64),
+        max: (u64, u8, u64),
+    ) -> Result<Vec<(Key, Vec<u8>)>, RrubError> {
+        let root = self.tree(tree)?;
+        return self.items(root.bytenr, root.level, min, max);
+    }
+
+    fn node_id(&mut self, tree: u64, objectid: u64) -> NodeId {
+        if let Some(&node) = self.node_ids.get(&(tree, objectid)) {
+            return node;
+        }
+
+        let node = NodeId(self.nodes.len() as u64);
+        self.nodes.push((tree, objectid));
+        self.node_ids.insert((tree, objectid), node);
+        return node;
+    }
+
+    fn node_key(&self, node: NodeId) -> Result<(u64, u64), RrubError> {
+        return self
+            .nodes
+            .get(node.0 as usize)
+            .copied()
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    /// Node of the top directory of a subvolume.
+    fn subvolume_root(&mut self, tree: u64) -> Result<NodeId, RrubError> {
+        let root_dirid = self.tree(tree)?.root_dirid;
+        return Ok(self.node_id(tree, root_dirid));
+    }
+
+    fn inode(&mut self, node: NodeId) -> Result<InodeItem, RrubError> {
+        if let Some(&inode) = self.inodes.get(&node) {
+            return Ok(inode);
+        }
+
+        let (tree, objectid) = self.node_key(node)?;
+        let (min, max) = item_range(objectid, INODE_ITEM_KEY);
+        let (_, data) = self
+            .tree_items(tree, min, max)?
+            .pop()
+            .ok_or(FilesystemError::NotFound)?;
+        let (inode, _) =
+            InodeItem::read_from_prefix(&data).map_err(|_| FilesystemError::Corrupted)?;
+
+        self.inodes.insert(node, inode);
+        return Ok(inode);
+    }
+
+    fn decompress(&self, compression: u8, data: &[u8], limit: usize) -> Result<Vec<u8>, RrubError> {
+        let mut output = match compression {
+            COMPRESS_ZLIB => zlib_decompress(data, limit)?,
+            COMPRESS_LZO => lzo_decompress(data, self.superblock.sectorsize.get() as usize, limit)?,
+            COMPRESS_ZSTD => zstd::decompress(data, limit)?,
+            _ => return Err(FilesystemError::Unsupported.into()),
+        };
+        // Trailing zeros may be left out of the compressed stream.
+        output.resize(limit, 0);
+        return Ok(output);
+    }
+
+    fn file_extents(&mut self, node: NodeId) -> Result<&[FileExtent], RrubError> {
+        if !self.extents.contains_key(&node) {
+            let (tree, objectid) = self.node_key(node)?;
+            let (min, max) = item_range(objectid, EXTENT_DATA_KEY);
+
+            let mut extents = Vec::new();
+            for (key, data) in self.tree_items(tree, min, max)? {
+                let (item, rest) = FileExtentItem::read_from_prefix(&data)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                if item.encryption != 0 || item.other_encoding.get() != 0 {
+                    return Err(FilesystemError::Unsupported.into());
+                }
+
+                let extent = match item.extent_type {
+                    FILE_EXTENT_INLINE => {
+                        let data = match item.compression {
+                            COMPRESS_NONE => rest.to_vec(),
+                            compression => {
+                                self.decompress(compression, rest, item.ram_bytes.get() as usize)?
+                            }
+                        };
+                        FileExtent {
+                            offset: key.offset.get(),
+                            length: data.len() as u64,
+                            data: ExtentData::Inline(data),
+                        }
+                    }
+                    FILE_EXTENT_REG | FILE_EXTENT_PREALLOC => {
+                        let (regular, _) = RegularExtent::read_from_prefix(rest)
+                            .map_err(|_| FilesystemError::Corrupted)?;
+                        let disk_bytenr = regular.disk_bytenr.get();
+
+                        let data = match (item.extent_type, disk_bytenr, item.compression) {
+                            (FILE_EXTENT_PREALLOC, _, _) | (_, 0, _) => ExtentData::Hole,
+                            (_, _, COMPRESS_NONE) => {
+                                ExtentData::Plain(disk_bytenr + regular.offset.get())
+                            }
+                            (_, _, compression) => ExtentData::Compressed {
+                                disk_bytenr,
+                                disk_num_bytes: regular.disk_num_bytes.get(),
+                                compression,
+                                ram_bytes: item.ram_bytes.get(),
+                                offset: regular.offset.get(),
+                            },
+                        };
+                        FileExtent {
+                            offset: key.offset.get(),
+                            length: regular.num_bytes.get(),
+                            data,
+                        }
+                    }
+                    _ => return Err(FilesystemError::Corrupted.into()),
+                };
+                extents.push(extent);
+            }
+
+            self.extents.insert(node, extents);
+        }
+
+        return Ok(&self.extents[&node]);
+    }
+
+    fn read_extent(
+        &mut self,
+        extent: &FileExtent,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), RrubError> {
+        match &extent.data {
+            ExtentData::Inline(data) => {
+                let start = offset as usize;
+                let available = data
+                    .get(start..start + buffer.len())
+                    .ok_or(FilesystemError::Corrupted)?;
+                buffer.copy_from_slice(available);
+            }
+            ExtentData::Hole => buffer.fill(0),
+            ExtentData::Plain(address) => self.read_data(address + offset, buffer)?,
+            &ExtentData::Compressed {
+                disk_bytenr,
+                disk_num_bytes,
+                compression,
+                ram_bytes,
+                offset: extent_offset,
+            } => {
+                if self
+                    .extent_cache
+                    .as_ref()
+                    .is_none_or(|(address, _)| *address != disk_bytenr)
+                {
+                    let mut compressed = vec![0u8; disk_num_bytes as usize];
+                    self.read_data(disk_bytenr, &mut compressed)?;
+                    let data = self.decompress(compression, &compressed, ram_bytes as usize)?;
+                    self.extent_cache = Some((disk_bytenr, data));
+                }
+
+                let (_, data) = self.extent_cache.as_ref().unwrap();
+                let start = (extent_offset + offset) as usize;
+                let available = data
+                    .get(start..start + buffer.len())
+                    .ok_or(FilesystemError::Corrupted)?;
+                buffer.copy_from_slice(available);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn read_node(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let size = self.inode(node)?.size.get();
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length = buffer.len().min((size - offset) as usize);
+        let extents = self.file_extents(node)?.to_vec();
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let remaining = length - done;
+
+            let index = extents.partition_point(|extent| extent.offset + extent.length <= position);
+            let count = match extents.get(index) {
+                Some(extent) if extent.offset <= position => {
+                    let count =
+                        ((extent.offset + extent.length - position) as usize).min(remaining);
+                    self.read_extent(
+                        extent,
+                        position - extent.offset,
+                        &mut buffer[done..done + count],
+                    )?;
+                    count
+                }
+                // Holes are left out entirely with the no-holes feature.
+                next => {
+                    let end = next.map_or(u64::MAX, |extent| extent.offset);
+                    let count = (end - position).min(remaining as u64) as usize;
+                    buffer[done..done + count].fill(0);
+                    count
+                }
+            };
+            done += count;
+        }
+
+        return Ok(length);
+    }
+
+    /// Turn a directory item into an entry, entries pointing at a subvolume lead to its top
+    /// directory.
+    fn dir_entry(&mut self, tree: u64, item: &DirItem, name: &[u8]) -> Result<DirEntry, RrubError> {
+        let location = item.location;
+        let node = match location.item_type {
+            ROOT_ITEM_KEY => self.subvolume_root(location.objectid.get())?,
+            _ => self.node_id(tree, location.objectid.get()),
+        };
+
+        let file_type = match item.item_type {
+            FT_REG_FILE => FileType::Regular,
+            FT_DIR => FileType::Directory,
+            FT_SYMLINK => FileType::Symlink,
+            _ => FileType::Other,
+        };
+
+        return Ok(DirEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            node,
+            file_type,
+        });
+    }
+
+    /// Split the possibly several directory items packed into one item.
+    fn dir_items(data: &[u8]) -> Result<Vec<(DirItem, &[u8])>, RrubError> {
+        let mut items = Vec::new();
+        let mut rest = data;
+
+        while !rest.is_empty() {
+            let (item, tail) =
+                DirItem::read_from_prefix(rest).map_err(|_| FilesystemError::Corrupted)?;
+            let name_len = item.name_len.get() as usize;
+            let total = name_len + item.data_len.get() as usize;
+            let name = tail.get(..name_len).ok_or(FilesystemError::Corrupted)?;
+            items.push((item, name));
+            rest = tail.get(total..).ok_or(FilesystemError::Corrupted)?;
+        }
+
+        return Ok(items);
+    }
+}
+
+/// Verify the checksum stored at the start of a superblock or tree block.
+fn verify_csum(csum_type: u16, block: &[u8]) -> bool {
+    let data = &block[CSUM_SIZE..];
+    return match csum_type {
+        CSUM_TYPE_CRC32C => block[..4] == crc32c(data).to_le_bytes(),
+        CSUM_TYPE_XXHASH64 => block[..8] == xxh64(data, 0).to_le_bytes(),
+        // SHA-256 and BLAKE2b volumes are read without verification.
+        _ => true,
+    };
+}
+
+impl FilesystemBackend for BtrfsFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "btrfs";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::RealUuid(RealUuid::from_bytes(self.superblock.fsid));
+    }
+
+    fn label(&self) -> Option<String> {
+        return label_from_bytes(&self.superblock.label);
+    }
+
+    /// The top level subvolume, other subvolumes appear as directories inside it.
+    fn root(&self) -> NodeId {
+        return self.root;
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let inode = self.inode(node)?;
+        return Ok(Metadata {
+            file_type: inode.file_type(),
+            size: inode.size.get(),
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        if self.inode(dir)?.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        let (tree, objectid) = self.node_key(dir)?;
+        let (min, max) = item_range(objectid, DIR_INDEX_KEY);
+
+        let mut entries = Vec::new();
+        for (_, data) in self.tree_items(tree, min, max)? {
+            for (item, name) in Self::dir_items(&data)? {
+                entries.push(self.dir_entry(tree, &item, name)?);
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        if self.inode(dir)?.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        let (tree, objectid) = self.node_key(dir)?;
+        let hash = name_hash(name.as_bytes());
+        let key = (objectid, DIR_ITEM_KEY, hash);
+
+        for (_, data) in self.tree_items(tree, key, key)? {
+            for (item, entry) in Self::dir_items(&data)? {
+                if entry == name.as_bytes() {
+                    return Ok(self.dir_entry(tree, &item, entry)?.node);
+                }
+            }
+        }
+
+        return Err(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        if self.inode(node)?.file_type() == FileType::Directory {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+        return self.read_node(node, offset, buffer);
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        let inode = self.inode(node)?;
+        if inode.file_type() != FileType::Symlink {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let mut target = vec![0u8; inode.size.get() as usize];
+        let read = self.read_node(node, 0, &mut target)?;
+        target.truncate(read);
+
+        return String::from_utf8(target).map_err(|_| FilesystemError::Corrupted.into());
+    }
+
+    /// Subvolumes are named by their path from the top level subvolume, e.g. "@" or
+    /// "@/.snapshots/1/snapshot", or by their numeric ID.
+    fn subvolume(&mut self, name: &str) -> Result<NodeId, RrubError> {
+        if let Ok(id) = name.parse::<u64>() {
+            return self.subvolume_root(id);
+        }
+
+        let mut current = self.root;
+        for component in name.split('/').filter(|component| !component.is_empty()) {
+            current = self.lookup(current, component)?;
+        }
+
+        if self.inode(current)?.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+        return Ok(current);
+    }
+}
@@ -0,0 +1,1009 @@
+/*
+ * https://docs.kernel.org/filesystems/erofs.html
+ * https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/fs/erofs/erofs_fs.h
+ * Compressed indexes are decoded like fs/erofs/zmap.c.
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    checksum::crc32c_update,
+    decompress::{inflate::inflate, lz4, lzma::microlzma_decompress, zstd},
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo, label_from_bytes},
+};
+
+const EROFS_MAGIC: u32 = 0xE0F5_E1E2;
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_CRC_OFFSET: usize = 4;
+
+const FEATURE_COMPAT_SB_CHKSUM: u32 = 0x1;
+
+const FEATURE_INCOMPAT_ZERO_PADDING: u32 = 0x1;
+const FEATURE_INCOMPAT_BIG_PCLUSTER: u32 = 0x2;
+const FEATURE_INCOMPAT_CHUNKED_FILE: u32 = 0x4;
+const FEATURE_INCOMPAT_DEVICE_TABLE: u32 = 0x8;
+const FEATURE_INCOMPAT_ZTAILPACKING: u32 = 0x10;
+const FEATURE_INCOMPAT_FRAGMENTS: u32 = 0x20;
+const FEATURE_INCOMPAT_XATTR_PREFIXES: u32 = 0x40;
+/// Features that only change how the parts of the image this driver reads are found, or that
+/// are refused per inode when they are actually used.
+const FEATURE_INCOMPAT_SUPPORTED: u32 = FEATURE_INCOMPAT_ZERO_PADDING
+    | FEATURE_INCOMPAT_BIG_PCLUSTER
+    | FEATURE_INCOMPAT_CHUNKED_FILE
+    | FEATURE_INCOMPAT_DEVICE_TABLE
+    | FEATURE_INCOMPAT_ZTAILPACKING
+    | FEATURE_INCOMPAT_FRAGMENTS
+    | FEATURE_INCOMPAT_XATTR_PREFIXES;
+
+const MIN_BLOCK_BITS: u8 = 9;
+const MAX_BLOCK_BITS: u8 = 16;
+
+/// Inodes are addressed in slots of 32 bytes from the start of the metadata area.
+const INODE_SLOT_SIZE: u64 = 32;
+const INODE_EXTENDED: u16 = 0x1;
+const INODE_LAYOUT_SHIFT: u16 = 1;
+const INODE_LAYOUT_MASK: u16 = 0x7;
+const INODE_FORMAT_KNOWN: u16 = 0x1F;
+
+const LAYOUT_FLAT_PLAIN: u16 = 0;
+const LAYOUT_COMPRESSED_FULL: u16 = 1;
+const LAYOUT_FLAT_INLINE: u16 = 2;
+const LAYOUT_COMPRESSED_COMPACT: u16 = 3;
+const LAYOUT_CHUNK_BASED: u16 = 4;
+
+const NULL_ADDRESS: u32 = 0xFFFF_FFFF;
+
+const CHUNK_FORMAT_BLOCK_BITS_MASK: u16 = 0x1F;
+const CHUNK_FORMAT_INDEXES: u16 = 0x20;
+
+const XATTR_HEADER_SIZE: usize = 12;
+const XATTR_ENTRY_SIZE: usize = 4;
+
+const FT_REG_FILE: u8 = 1;
+const FT_DIR: u8 = 2;
+const FT_SYMLINK: u8 = 7;
+
+const S_IFMT: u16 = 0xF000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xA000;
+
+const ADVISE_COMPACTED_2B: u16 = 0x1;
+const ADVISE_BIG_PCLUSTER_1: u16 = 0x2;
+const ADVISE_BIG_PCLUSTER_2: u16 = 0x4;
+const ADVISE_INLINE_PCLUSTER: u16 = 0x8;
+const ADVISE_INTERLACED_PCLUSTER: u16 = 0x10;
+const ADVISE_FRAGMENT_PCLUSTER: u16 = 0x20;
+const ADVISE_KNOWN: u16 = 0x3F;
+const CLUSTER_BITS_MASK: u8 = 0x7;
+const CLUSTER_FRAGMENT_INODE: u8 = 0x80;
+
+const LCLUSTER_TYPE_PLAIN: u8 = 0;
+const LCLUSTER_TYPE_HEAD1: u8 = 1;
+const LCLUSTER_TYPE_NONHEAD: u8 = 2;
+const LCLUSTER_TYPE_HEAD2: u8 = 3;
+const LCLUSTER_TYPE_MASK: u16 = 0x3;
+const LCLUSTER_PARTIAL_REF: u16 = 1 << 15;
+/// Set in the first lookback distance of the lcluster after a head to hold the number of blocks
+/// of the head's pcluster instead.
+const LCLUSTER_D0_CBLKCNT: u16 = 1 << 11;
+
+/// Full indexes start after the map header and 8 legacy bytes.
+const FULL_INDEX_OFFSET: u64 = 16;
+
+const ALGORITHM_LZ4: u8 = 0;
+const ALGORITHM_LZMA: u8 = 1;
+const ALGORITHM_DEFLATE: u8 = 2;
+const ALGORITHM_ZSTD: u8 = 3;
+
+/// Output allowed for pclusters that are only partially referenced, whose full decompressed size
+/// is not recorded anywhere.
+const MAX_PARTIAL_OUTPUT: usize = 1 << 20;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Superblock {
+    pub magic: U32<LittleEndian>,
+    pub checksum: U32<LittleEndian>,
+    pub feature_compat: U32<LittleEndian>,
+    pub block_bits: u8,
+    pub extension_slots: u8,
+    pub root_nid: U16<LittleEndian>,
+    pub inode_count: U64<LittleEndian>,
+    pub build_time: U64<LittleEndian>,
+    pub build_time_nsec: U32<LittleEndian>,
+    pub blocks: U32<LittleEndian>,
+    pub meta_block_address: U32<LittleEndian>,
+    pub xattr_block_address: U32<LittleEndian>,
+    pub uuid: [u8; 16],
+    pub volume_name: [u8; 16],
+    pub feature_incompat: U32<LittleEndian>,
+    pub compression_algorithms: U16<LittleEndian>,
+    pub extra_devices: U16<LittleEndian>,
+    pub device_slot_offset: U16<LittleEndian>,
+    pub dir_block_bits: u8,
+    pub xattr_prefix_count: u8,
+    pub xattr_prefix_start: U32<LittleEndian>,
+    pub packed_nid: U64<LittleEndian>,
+    pub xattr_filter_reserved: u8,
+    pub reserved: [u8; 23],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct CompactInode {
+    pub format: U16<LittleEndian>,
+    pub xattr_count: U16<LittleEndian>,
+    pub mode: U16<LittleEndian>,
+    pub link_count: U16<LittleEndian>,
+    pub size: U32<LittleEndian>,
+    pub modification_time: U32<LittleEndian>,
+    pub data: U32<LittleEndian>,
+    pub number: U32<LittleEndian>,
+    pub uid: U16<LittleEndian>,
+    pub gid: U16<LittleEndian>,
+    pub reserved: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtendedInode {
+    pub format: U16<LittleEndian>,
+    pub xattr_count: U16<LittleEndian>,
+    pub mode: U16<LittleEndian>,
+    pub reserved: U16<LittleEndian>,
+    pub size: U64<LittleEndian>,
+    pub data: U32<LittleEndian>,
+    pub number: U32<LittleEndian>,
+    pub uid: U32<LittleEndian>,
+    pub gid: U32<LittleEndian>,
+    pub modification_time: U64<LittleEndian>,
+    pub modification_time_nsec: U32<LittleEndian>,
+    pub link_count: U32<LittleEndian>,
+    pub reserved2: [u8; 16],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct RawDirent {
+    pub nid: U64<LittleEndian>,
+    pub name_offset: U16<LittleEndian>,
+    pub file_type: u8,
+    pub reserved: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ChunkIndex {
+    pub start_block_high: U16<LittleEndian>,
+    pub device_id: U16<LittleEndian>,
+    pub start_block: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MapHeader {
+    pub fragment_offset: U32<LittleEndian>,
+    pub advise: U16<LittleEndian>,
+    pub algorithm_type: u8,
+    pub cluster_bits: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct LclusterIndex {
+    pub advise: U16<LittleEndian>,
+    pub cluster_offset: U16<LittleEndian>,
+    /// Block address for heads, the lookback and lookahead distances otherwise.
+    pub data: U32<LittleEndian>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Inode {
+    /// Byte position of the inode.
+    position: u64,
+    /// Size of the inode and its inline extended attributes, inline data follows.
+    header_size: u64,
+    mode: u16,
+    layout: u16,
+    size: u64,
+    data: u32,
+}
+
+impl Inode {
+    fn file_type(&self) -> FileType {
+        return match self.mode & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFREG => FileType::Regular,
+            S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        };
+    }
+}
+
+/// Logical cluster as described by its index.
+#[derive(Debug, Copy, Clone)]
+enum Lcluster {
+    Head {
+        kind: u8,
+        cluster_offset: u64,
+        block: u32,
+        partial: bool,
+    },
+    NonHead {
+        /// Block count of the preceding head's pcluster, if this lcluster records it.
+        block_count: Option<u32>,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ExtentKind {
+    /// Stored uncompressed at the start of the pcluster.
+    Shifted,
+    /// Stored uncompressed, rotated by the extent's offset in its block.
+    Interlaced,
+    Compressed(u8),
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Extent {
+    logical: u64,
+    length: u64,
+    /// Byte position and size of the pcluster.
+    physical: u64,
+    stored: u64,
+    kind: ExtentKind,
+    partial: bool,
+}
+
+pub struct ErofsFilesystem {
+    device: Box<dyn BlockDevice>,
+    superblock: Superblock,
+    block_size: u64,
+    inodes: BTreeMap<NodeId, Inode>,
+    extents: BTreeMap<NodeId, Vec<Extent>>,
+    /// Last decompressed extent by inode and logical offset, file reads usually walk one extent
+    /// in several calls.
+    extent_cache: Option<((NodeId, u64), Vec<u8>)>,
+}
+
+fn file_type_from_dirent(file_type: u8) -> FileType {
+    return match file_type {
+        FT_REG_FILE => FileType::Regular,
+        FT_DIR => FileType::Directory,
+        FT_SYMLINK => FileType::Symlink,
+        _ => FileType::Other,
+    };
+}
+
+/// Read a lookback distance or cluster offset and the lcluster type packed at bit `position`.
+fn decode_compacted_bits(pack: &[u8], low_bits: u32, position: usize) -> (u32, u8) {
+    let mut raw = [0u8; 4];
+    let available = pack.len().saturating_sub(position / 8).min(4);
+    raw[..available].copy_from_slice(&pack[position / 8..position / 8 + available]);
+
+    let value = u32::from_le_bytes(raw) >> (position % 8);
+    return (
+        value & ((1 << low_bits) - 1),
+        ((value >> low_bits) & 3) as u8,
+    );
+}
+
+/// Number of compact indexes stored in 4 bytes up to the first 32 byte boundary, followed by the
+/// number stored in 2 bytes. Any remaining indexes are stored in 4 bytes again.
+fn compact_counts(base: u64, total: usize, advise: u16) -> (usize, usize) {
+    let initial_4b = (((32 - base % 32) / 4) & 7) as usize;
+    let compacted_2b = match advise & ADVISE_COMPACTED_2B != 0 && initial_4b < total {
+        true => (total - initial_4b) / 16 * 16,
+        false => 0,
+    };
+    return (initial_4b, compacted_2b);
+}
+
+impl ErofsFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut magic = [0u8; 4];
+        return device.read_bytes(SUPERBLOCK_OFFSET, &mut magic).is_ok()
+            && u32::from_le_bytes(magic) == EROFS_MAGIC;
+    }
+
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let mut raw = [0u8; size_of::<Superblock>()];
+        device.read_bytes(SUPERBLOCK_OFFSET, &mut raw).ok()?;
+        let superblock = Superblock::read_from_bytes(&raw).ok()?;
+        if superblock.magic.get() != EROFS_MAGIC
+            || !(MIN_BLOCK_BITS..=MAX_BLOCK_BITS).contains(&superblock.block_bits)
+        {
+            return None;
+        }
+
+        return Some(VolumeInfo {
+            fs_type: "erofs",
+            usage: Usage::Filesystem,
+            label: label_from_bytes(&superblock.volume_name),
+            uuid: Some(Uuid::RealUuid(RealUuid::from_bytes(superblock.uuid))),
+            size: (superblock.blocks.get() as u64) << superblock.block_bits,
+        });
+    }
+
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<ErofsFilesystem, RrubError> {
+        let mut raw = [0u8; size_of::<Superblock>()];
+        device.read_bytes(SUPERBLOCK_OFFSET, &mut raw)?;
+        let superblock =
+            Superblock::read_from_bytes(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if superblock.magic.get() != EROFS_MAGIC {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+        if !(MIN_BLOCK_BITS..=MAX_BLOCK_BITS).contains(&superblock.block_bits) {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let block_size = 1u64 << superblock.block_bits;
+
+        if superblock.feature_incompat.get() & !FEATURE_INCOMPAT_SUPPORTED != 0
+            || superblock.dir_block_bits != 0
+        {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        // The checksum covers the rest of the block holding the superblock.
+        if superblock.feature_compat.get() & FEATURE_COMPAT_SB_CHKSUM != 0 {
+            if block_size <= SUPERBLOCK_OFFSET {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            let mut block = vec![0u8; (block_size - SUPERBLOCK_OFFSET) as usize];
+            device.read_bytes(SUPERBLOCK_OFFSET, &mut block)?;
+            block[SUPERBLOCK_CRC_OFFSET..SUPERBLOCK_CRC_OFFSET + 4].fill(0);
+            if crc32c_update(!0, &block) != superblock.checksum.get() {
+                return Err(FilesystemError::ChecksumMismatch.into());
+            }
+        }
+
+        return Ok(ErofsFilesystem {
+            device,
+            superblock,
+            block_size,
+            inodes: BTreeMap::new(),
+            extents: BTreeMap::new(),
+            extent_cache: None,
+        });
+    }
+
+    fn has_feature(&self, feature: u32) -> bool {
+        return self.superblock.feature_incompat.get() & feature != 0;
+    }
+
+    fn inode(&mut self, node: NodeId) -> Result<Inode, RrubError> {
+        if let Some(inode) = self.inodes.get(&node) {
+            return Ok(*inode);
+        }
+
+        let position = node
+            .0
+            .checked_mul(INODE_SLOT_SIZE)
+            .and_then(|offset| {
+                offset
+                    .checked_add(self.superblock.meta_block_address.get() as u64 * self.block_size)
+            })
+            .ok_or(FilesystemError::Corrupted)?;
+        let mut raw = [0u8; size_of::<ExtendedInode>()];
+        self.device
+            .read_bytes(position, &mut raw[..size_of::<CompactInode>()])?;
+
+        let format = u16::from_le_bytes([raw[0], raw[1]]);
+        if format & !INODE_FORMAT_KNOWN != 0 {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let (inode_size, xattr_count, mode, size, data) = match format & INODE_EXTENDED != 0 {
+            true => {
+                self.device.read_bytes(
+                    position + size_of::<CompactInode>() as u64,
+                    &mut raw[size_of::<CompactInode>()..],
+                )?;
+                let inode =
+                    ExtendedInode::read_from_bytes(&raw).map_err(|_| FilesystemError::Corrupted)?;
+                (
+                    size_of::<ExtendedInode>(),
+                    inode.xattr_count.get(),
+                    inode.mode.get(),
+                    inode.size.get(),
+                    inode.data.get(),
+                )
+            }
+            false => {
+                let (inode, _) =
+                    CompactInode::read_from_prefix(&raw).map_err(|_| FilesystemError::Corrupted)?;
+                (
+                    size_of::<CompactInode>(),
+                    inode.xattr_count.get(),
+                    inode.mode.get(),
+                    inode.size.get() as u64,
+                    inode.data.get(),
+                )
+            }
+        };
+
+        let xattr_size = match xattr_count {
+            0 => 0,
+            count => XATTR_HEADER_SIZE + (count as usize - 1) * XATTR_ENTRY_SIZE,
+        };
+
+        let inode = Inode {
+            position,
+            header_size: (inode_size + xattr_size) as u64,
+            mode,
+            layout: (format >> INODE_LAYOUT_SHIFT) & INODE_LAYOUT_MASK,
+            size,
+            data,
+        };
+        self.inodes.insert(node, inode);
+        return Ok(inode);
+    }
+
+    /// Read from an uncompressed inode, whose last block may be stored inline after the inode.
+    fn read_flat(
+        &mut self,
+        inode: &Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), RrubError> {
+        let tail_start = match inode.layout {
+            LAYOUT_FLAT_INLINE => (inode.size.div_ceil(self.block_size) - 1) * self.block_size,
+            _ => inode.size,
+        };
+
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let (address, count) = match position >= tail_start {
+                true => (
+                    inode.position + inode.header_size + position - tail_start,
+                    buffer.len() - done,
+                ),
+                false => {
+                    if inode.data == NULL_ADDRESS {
+                        return Err(FilesystemError::Corrupted.into());
+                    }
+                    let count = ((tail_start - position) as usize).min(buffer.len() - done);
+                    (inode.data as u64 * self.block_size + position, count)
+                }
+            };
+            self.device
+                .read_bytes(address, &mut buffer[done..done + count])?;
+            done += count;
+        }
+
+        return Ok(());
+    }
+
+    /// Read from a file split into equally sized chunks, each mapped on its own.
+    fn read_chunked(
+        &mut self,
+        inode: &Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), RrubError> {
+        let format = inode.data as u16;
+        if format & !(CHUNK_FORMAT_BLOCK_BITS_MASK | CHUNK_FORMAT_INDEXES) != 0 {
+            return Err(FilesystemError::Unsupported.into());
+        }
+        let chunk_bits =
+            self.superblock.block_bits as u32 + (format & CHUNK_FORMAT_BLOCK_BITS_MASK) as u32;
+        if chunk_bits >= 48 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let chunk_size = 1u64 << chunk_bits;
+
+        let entry_size = match format & CHUNK_FORMAT_INDEXES != 0 {
+            true => size_of::<ChunkIndex>() as u64,
+            false => 4,
+        };
+        let table = (inode.position + inode.header_size).next_multiple_of(entry_size);
+
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let chunk = position >> chunk_bits;
+            let within = position & (chunk_size - 1);
+            let count = ((chunk_size - within) as usize).min(buffer.len() - done);
+
+            let mut raw = [0u8; size_of::<ChunkIndex>()];
+            let raw = &mut raw[..entry_size as usize];
+            self.device.read_bytes(table + chunk * entry_size, raw)?;
+            let block = match format & CHUNK_FORMAT_INDEXES != 0 {
+                true => {
+                    let index =
+                        ChunkIndex::read_from_bytes(raw).map_err(|_| FilesystemError::Corrupted)?;
+                    // Chunks on extra devices are not reachable through this device.
+                    if index.device_id.get() != 0 {
+                        return Err(FilesystemError::Unsupported.into());
+                    }
+                    index.start_block.get()
+                }
+                false => u32::from_le_bytes(raw.try_into().unwrap()),
+            };
+
+            match block {
+                NULL_ADDRESS => buffer[done..done + count].fill(0),
+                block => self.device.read_bytes(
+                    block as u64 * self.block_size + within,
+                    &mut buffer[done..done + count],
+                )?,
+            }
+            done += count;
+        }
+
+        return Ok(());
+    }
+
+    /// Decode lcluster `lcn` from full indexes of 8 bytes each.
+    fn full_lcluster(&self, indexes: &[u8], lcn: usize) -> Result<Lcluster, RrubError> {
+        let raw = indexes
+            .get(lcn * size_of::<LclusterIndex>()..)
+            .ok_or(FilesystemError::Corrupted)?;
+        let (index, _) =
+            LclusterIndex::read_from_prefix(raw).map_err(|_| FilesystemError::Corrupted)?;
+        let advise = index.advise.get();
+        let kind = (advise & LCLUSTER_TYPE_MASK) as u8;
+
+        if kind == LCLUSTER_TYPE_NONHEAD {
+            let distance = index.data.get() as u16;
+            let block_count = match distance & LCLUSTER_D0_CBLKCNT != 0 {
+                true => Some((distance & !LCLUSTER_D0_CBLKCNT) as u32),
+                false => None,
+            };
+            return Ok(Lcluster::NonHead { block_count });
+        }
+
+        return Ok(Lcluster::Head {
+            kind,
+            cluster_offset: index.cluster_offset.get() as u64,
+            block: index.data.get(),
+            partial: advise & LCLUSTER_PARTIAL_REF != 0,
+        });
+    }
+
+    /// Decode lcluster `lcn` from compact indexes, packs of 2 entries in 8 bytes or 16 entries in
+    /// 32 bytes that end with the block address the heads in them count from.
+    fn compact_lcluster(
+        &self,
+        indexes: &[u8],
+        base: u64,
+        total: usize,
+        advise: u16,
+        lcn: usize,
+    ) -> Result<Lcluster, RrubError> {
+        let cluster_bits = self.superblock.block_bits as u32;
+        let big_pcluster = advise & ADVISE_BIG_PCLUSTER_1 != 0;
+
+        let (initial_4b, compacted_2b) = compact_counts(base, total, advise);
+
+        let mut position = 0;
+        let mut index = lcn;
+        let mut entry_size = 4;
+        if index >= initial_4b {
+            position += initial_4b * 4;
+            index -= initial_4b;
+            match index < compacted_2b {
+                true => entry_size = 2,
+                false => {
+                    position += compacted_2b * 2;
+                    index -= compacted_2b;
+                }
+            }
+        }
+        position += index * entry_size;
+
+        let count = match entry_size {
+            4 if cluster_bits <= 14 => 2,
+            2 if cluster_bits <= 12 => 16,
+            _ => return Err(FilesystemError::Unsupported.into()),
+        };
+        let pack_size = count * entry_size;
+        let low_bits = cluster_bits.max(LCLUSTER_D0_CBLKCNT.ilog2() + 1);
+        let encode_bits = (pack_size - 4) * 8 / count;
+
+        let pack_start = position - (base as usize + position) % pack_size;
+        let pack = indexes
+            .get(pack_start..pack_start + pack_size)
+            .ok_or(FilesystemError::Corrupted)?;
+        let i = (position - pack_start) / entry_size;
+        let decode = |i: usize| decode_compacted_bits(pack, low_bits, encode_bits * i);
+
+        let (low, kind) = decode(i);
+        if kind == LCLUSTER_TYPE_NONHEAD {
+            let block_count = match low & LCLUSTER_D0_CBLKCNT as u32 != 0 {
+                true => Some(low & !(LCLUSTER_D0_CBLKCNT as u32)),
+                false => None,
+            };
+            return Ok(Lcluster::NonHead { block_count });
+        }
+
+        // Heads count their block from the pack's address, one block for every head before them
+        // in the pack, or the recorded block counts of big pclusters.
+        let mut blocks: i64 = match big_pcluster {
+            true => 0,
+            false => 1,
+        };
+        let mut cursor = i as i64;
+        while cursor > 0 {
+            cursor -= 1;
+            let (distance, kind) = decode(cursor as usize);
+            match (big_pcluster, kind == LCLUSTER_TYPE_NONHEAD) {
+                (false, true) => {
+                    cursor -= distance as i64;
+                    if cursor >= 0 {
+                        blocks += 1;
+                    }
+                }
+                (false, false) => blocks += 1,
+                (true, true) if distance & LCLUSTER_D0_CBLKCNT as u32 != 0 => {
+                    cursor -= 1;
+                    blocks += (distance & !(LCLUSTER_D0_CBLKCNT as u32)) as i64;
+                }
+                (true, true) => {
+                    // A lookback of one is always a block count with big pclusters.
+                    if distance <= 1 {
+                        return Err(FilesystemError::Corrupted.into());
+                    }
+                    cursor -= distance as i64 - 2;
+                }
+                (true, false) => blocks += 1,
+            }
+        }
+        let address = u32::from_le_bytes(pack[pack_size - 4..].try_into().unwrap());
+
+        return Ok(Lcluster::Head {
+            kind,
+            cluster_offset: low as u64,
+            block: (address as i64 + blocks) as u32,
+            partial: false,
+        });
+    }
+
+    /// Map every extent of a compressed inode from its lcluster indexes.
+    fn extents(&mut self, node: NodeId, inode: &Inode) -> Result<&[Extent], RrubError> {
+        if !self.extents.contains_key(&node) {
+            let extents = self.load_extents(inode)?;
+            self.extents.insert(node, extents);
+        }
+        return Ok(&self.extents[&node]);
+    }
+
+    fn load_extents(&mut self, inode: &Inode) -> Result<Vec<Extent>, RrubError> {
+        if !self.has_feature(FEATURE_INCOMPAT_ZERO_PADDING) {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let header_position = (inode.position + inode.header_size).next_multiple_of(8);
+        let mut raw = [0u8; size_of::<MapHeader>()];
+        self.device.read_bytes(header_position, &mut raw)?;
+        let header = MapHeader::read_from_bytes(&raw).map_err(|_| FilesystemError::Corrupted)?;
+
+        let advise = header.advise.get();
+        if advise & !ADVISE_KNOWN != 0
+            || advise & (ADVISE_INLINE_PCLUSTER | ADVISE_FRAGMENT_PCLUSTER) != 0
+            || header.cluster_bits & CLUSTER_FRAGMENT_INODE != 0
+            || header.cluster_bits & CLUSTER_BITS_MASK != 0
+        {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let cluster_size = self.block_size;
+        let total = inode.size.div_ceil(cluster_size) as usize;
+
+        let compact = inode.layout == LAYOUT_COMPRESSED_COMPACT;
+        let (base, length) = match compact {
+            true => {
+                let base = header_position + size_of::<MapHeader>() as u64;
+                let (initial_4b, compacted_2b) = compact_counts(base, total, advise);
+                let length = match total <= initial_4b {
+                    true => total.next_multiple_of(2) * 4,
+                    false => {
+                        initial_4b * 4
+                            + compacted_2b * 2
+                            + (total - initial_4b - compacted_2b).next_multiple_of(2) * 4
+                    }
+                };
+                (base, length)
+            }
+            false => (
+                header_position + FULL_INDEX_OFFSET,
+                total * size_of::<LclusterIndex>(),
+            ),
+        };
+        if length as u64 > self.device.block_count() * self.device.block_size() as u64 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let mut indexes = vec![0u8; length];
+        self.device.read_bytes(base, &mut indexes)?;
+
+        let lcluster = |filesystem: &Self, lcn: usize| match compact {
+            true => filesystem.compact_lcluster(&indexes, base, total, advise, lcn),
+            false => filesystem.full_lcluster(&indexes, lcn),
+        };
+
+        let mut extents: Vec<Extent> = Vec::new();
+        for lcn in 0..total {
+            let Lcluster::Head {
+                kind,
+                cluster_offset,
+                block,
+                partial,
+            } = lcluster(self, lcn)?
+            else {
+                continue;
+            };
+
+            let logical = lcn as u64 * cluster_size + cluster_offset;
+            if cluster_offset >= cluster_size
+                || (extents.is_empty() && logical != 0)
+                || logical >= inode.size
+            {
+                return Err(FilesystemError::Corrupted.into());
+            }
+
+            let big = match kind {
+                LCLUSTER_TYPE_HEAD1 => advise & ADVISE_BIG_PCLUSTER_1 != 0,
+                _ => advise & ADVISE_BIG_PCLUSTER_2 != 0,
+            };
+            let blocks = match big && ((lcn + 1) as u64 * cluster_size) < inode.size {
+                true => match lcluster(self, lcn + 1)? {
+                    Lcluster::Head { .. } => 1,
+                    Lcluster::NonHead {
+                        block_count: Some(count),
+                    } if count != 0 => count,
+                    Lcluster::NonHead { .. } => return Err(FilesystemError::Corrupted.into()),
+                },
+                false => 1,
+            };
+
+            let kind = match kind {
+                LCLUSTER_TYPE_PLAIN => match advise & ADVISE_INTERLACED_PCLUSTER != 0 {
+                    true => ExtentKind::Interlaced,
+                    false => ExtentKind::Shifted,
+                },
+                LCLUSTER_TYPE_HEAD1 => ExtentKind::Compressed(header.algorithm_type & 0xF),
+                _ => ExtentKind::Compressed(header.algorithm_type >> 4),
+            };
+
+            if let Some(previous) = extents.last_mut() {
+                previous.length = logical - previous.logical;
+            }
+            extents.push(Extent {
+                logical,
+                length: inode.size - logical,
+                physical: block as u64 * self.block_size,
+                stored: blocks as u64 * self.block_size,
+                kind,
+                partial,
+            });
+        }
+
+        return Ok(extents);
+    }
+
+    fn decompress_extent(&mut self, extent: &Extent) -> Result<Vec<u8>, RrubError> {
+        let length = extent.length as usize;
+        let mut stored = vec![0u8; extent.stored as usize];
+        self.device.read_bytes(extent.physical, &mut stored)?;
+
+        let algorithm = match extent.kind {
+            ExtentKind::Shifted => {
+                stored.truncate(length);
+                return match stored.len() == length {
+                    true => Ok(stored),
+                    false => Err(FilesystemError::Corrupted.into()),
+                };
+            }
+            ExtentKind::Interlaced => {
+                if stored.len() < length {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                stored.rotate_left((extent.logical % self.block_size) as usize);
+                stored.truncate(length);
+                return Ok(stored);
+            }
+            ExtentKind::Compressed(algorithm) => algorithm,
+        };
+
+        // Compressed data is aligned to the end of the pcluster.
+        let start = stored
+            .iter()
+            .position(|&byte| byte != 0)
+            .ok_or(FilesystemError::Corrupted)?;
+        let data = &stored[start..];
+        let limit = match extent.partial {
+            true => length.max(MAX_PARTIAL_OUTPUT),
+            false => length,
+        };
+
+        let mut output = Vec::new();
+        match algorithm {
+            ALGORITHM_LZ4 => lz4::decompress_block(data, &mut output, limit)?,
+            ALGORITHM_LZMA => output = microlzma_decompress(data, length)?,
+            ALGORITHM_DEFLATE => {
+                inflate(data, &mut output, limit)?;
+            }
+            ALGORITHM_ZSTD => output = zstd::decompress(data, limit)?,
+            _ => return Err(FilesystemError::Unsupported.into()),
+        }
+
+        if output.len() < length {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        output.truncate(length);
+        return Ok(output);
+    }
+
+    fn read_compressed(
+        &mut self,
+        node: NodeId,
+        inode: &Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), RrubError> {
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let extents = self.extents(node, inode)?;
+            let index =
+                extents.partition_point(|extent| extent.logical + extent.length <= position);
+            let extent = *extents.get(index).ok_or(FilesystemError::Corrupted)?;
+
+            let key = (node, extent.logical);
+            if self
+                .extent_cache
+                .as_ref()
+                .is_none_or(|(cached, _)| *cached != key)
+            {
+                let data = self.decompress_extent(&extent)?;
+                self.extent_cache = Some((key, data));
+            }
+
+            let (_, data) = self.extent_cache.as_ref().unwrap();
+            let start = (position - extent.logical) as usize;
+            let count = (data.len() - start).min(buffer.len() - done);
+            buffer[done..done + count].copy_from_slice(&data[start..start + count]);
+            done += count;
+        }
+
+        return Ok(());
+    }
+
+    fn read_data(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let inode = self.inode(node)?;
+        if offset >= inode.size {
+            return Ok(0);
+        }
+
+        let length = buffer.len().min((inode.size - offset) as usize);
+        let buffer = &mut buffer[..length];
+        match inode.layout {
+            LAYOUT_FLAT_PLAIN | LAYOUT_FLAT_INLINE => self.read_flat(&inode, offset, buffer)?,
+            LAYOUT_CHUNK_BASED => self.read_chunked(&inode, offset, buffer)?,
+            LAYOUT_COMPRESSED_FULL | LAYOUT_COMPRESSED_COMPACT => {
+                self.read_compressed(node, &inode, offset, buffer)?
+            }
+            _ => return Err(FilesystemError::Unsupported.into()),
+        }
+
+        return Ok(length);
+    }
+
+    fn read_all(&mut self, node: NodeId) -> Result<Vec<u8>, RrubError> {
+        let size = self.inode(node)?.size;
+        if size > self.device.block_count() * self.device.block_size() as u64 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut data = vec![0u8; size as usize];
+        self.read_data(node, 0, &mut data)?;
+        return Ok(data);
+    }
+}
+
+impl FilesystemBackend for ErofsFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "erofs";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::RealUuid(RealUuid::from_bytes(self.superblock.uuid));
+    }
+
+    fn label(&self) -> Option<String> {
+        return label_from_bytes(&self.superblock.volume_name);
+    }
+
+    fn root(&self) -> NodeId {
+        return NodeId(self.superblock.root_nid.get() as u64);
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let inode = self.inode(node)?;
+        return Ok(Metadata {
+            file_type: inode.file_type(),
+            size: inode.size,
+        });
+    }
+
+    /// Directory blocks start with their entries, whose names are packed after them. The first
+    /// name offset gives the number of entries in the block.
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        if self.inode(dir)?.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+        let data = self.read_all(dir)?;
+
+        let mut entries = Vec::new();
+        for block in data.chunks(self.block_size as usize) {
+            let (first, _) =
+                RawDirent::read_from_prefix(block).map_err(|_| FilesystemError::Corrupted)?;
+            let first_name = first.name_offset.get() as usize;
+            let count = first_name / size_of::<RawDirent>();
+            if count == 0 || first_name > block.len() {
+                return Err(FilesystemError::Corrupted.into());
+            }
+
+            let dirents = <[RawDirent]>::ref_from_prefix_with_elems(block, count)
+                .map_err(|_| FilesystemError::Corrupted)?
+                .0;
+            for (i, dirent) in dirents.iter().enumerate() {
+                let start = dirent.name_offset.get() as usize;
+                let end = match dirents.get(i + 1) {
+                    Some(next) => next.name_offset.get() as usize,
+                    None => block.len(),
+                };
+                let name = block.get(start..end).ok_or(FilesystemError::Corrupted)?;
+                // The last name of a block is padded with zeros.
+                let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+                if name == b"." || name == b".." {
+                    continue;
+                }
+
+                entries.push(DirEntry {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    node: NodeId(dirent.nid.get()),
+                    file_type: file_type_from_dirent(dirent.file_type),
+                });
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        if self.inode(node)?.file_type() == FileType::Directory {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+        return self.read_data(node, offset, buffer);
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        if self.inode(node)?.file_type() != FileType::Symlink {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+        let target = self.read_all(node)?;
+        return String::from_utf8(target).map_err(|_| FilesystemError::Corrupted.into());
+    }
+}
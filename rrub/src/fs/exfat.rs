@@ -0,0 +1,736 @@
+/*
+ * https://learn.microsoft.com/en-us/windows/win32/fileio/exfat-specification
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+            VolumeId32,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo},
+};
+
+const FILE_SYSTEM_NAME: &[u8; 8] = b"EXFAT   ";
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+/// The boot checksum covers the main boot sector, the extended boot sectors, the OEM parameters
+/// and the reserved sector, and is repeated throughout the sector after them.
+const BOOT_CHECKSUM_SECTOR: usize = 11;
+
+const VOLUME_FLAG_ACTIVE_FAT: u16 = 0x1;
+
+const FIRST_CLUSTER: u32 = 2;
+const END_OF_CHAIN: u32 = 0xFFFF_FFFF;
+const BAD_CLUSTER: u32 = 0xFFFF_FFF7;
+
+const ENTRY_SIZE: usize = 32;
+const ENTRY_END: u8 = 0x00;
+const ENTRY_UPCASE: u8 = 0x82;
+const ENTRY_VOLUME_LABEL: u8 = 0x83;
+const ENTRY_FILE: u8 = 0x85;
+const ENTRY_STREAM: u8 = 0xC0;
+const ENTRY_FILE_NAME: u8 = 0xC1;
+
+const ATTRIBUTE_DIRECTORY: u16 = 0x10;
+const STREAM_NO_FAT_CHAIN: u8 = 0x2;
+
+const NAME_CHARS_PER_ENTRY: usize = 15;
+const MAX_LABEL_CHARS: usize = 11;
+const UPCASE_ENTRIES: usize = 0x10000;
+const MAX_DIRECTORY_SIZE: u64 = 256 * 1024 * 1024;
+
+const ROOT_NODE: NodeId = NodeId(0);
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BootSector {
+    pub jump: [u8; 3],
+    pub file_system_name: [u8; 8],
+    pub must_be_zero: [u8; 53],
+    pub partition_offset: U64<LittleEndian>,
+    pub volume_length: U64<LittleEndian>,
+    pub fat_offset: U32<LittleEndian>,
+    pub fat_length: U32<LittleEndian>,
+    pub cluster_heap_offset: U32<LittleEndian>,
+    pub cluster_count: U32<LittleEndian>,
+    pub root_cluster: U32<LittleEndian>,
+    pub volume_serial: U32<LittleEndian>,
+    pub revision: U16<LittleEndian>,
+    pub volume_flags: U16<LittleEndian>,
+    pub bytes_per_sector_shift: u8,
+    pub sectors_per_cluster_shift: u8,
+    pub fat_count: u8,
+    pub drive_select: u8,
+    pub percent_in_use: u8,
+    pub _reserved: [u8; 7],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct VolumeLabelEntry {
+    pub entry_type: u8,
+    pub char_count: u8,
+    pub label: [U16<LittleEndian>; MAX_LABEL_CHARS],
+    pub _reserved: [u8; 8],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct UpcaseEntry {
+    pub entry_type: u8,
+    pub _reserved: [u8; 3],
+    pub checksum: U32<LittleEndian>,
+    pub _reserved1: [u8; 12],
+    pub first_cluster: U32<LittleEndian>,
+    pub data_length: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FileEntry {
+    pub entry_type: u8,
+    pub secondary_count: u8,
+    pub set_checksum: U16<LittleEndian>,
+    pub attributes: U16<LittleEndian>,
+    pub _reserved: U16<LittleEndian>,
+    pub create_time: U32<LittleEndian>,
+    pub modify_time: U32<LittleEndian>,
+    pub access_time: U32<LittleEndian>,
+    pub create_10ms: u8,
+    pub modify_10ms: u8,
+    pub create_utc_offset: u8,
+    pub modify_utc_offset: u8,
+    pub access_utc_offset: u8,
+    pub _reserved1: [u8; 7],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct StreamEntry {
+    pub entry_type: u8,
+    pub flags: u8,
+    pub _reserved: u8,
+    pub name_length: u8,
+    pub name_hash: U16<LittleEndian>,
+    pub _reserved1: U16<LittleEndian>,
+    pub valid_data_length: U64<LittleEndian>,
+    pub _reserved2: U32<LittleEndian>,
+    pub first_cluster: U32<LittleEndian>,
+    pub data_length: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FileNameEntry {
+    pub entry_type: u8,
+    pub flags: u8,
+    pub name: [U16<LittleEndian>; NAME_CHARS_PER_ENTRY],
+}
+
+fn table_checksum(bytes: &[u8]) -> u32 {
+    return bytes
+        .iter()
+        .fold(0u32, |sum, &b| sum.rotate_right(1).wrapping_add(b as u32));
+}
+
+/// Checksum of a directory entry set, which skips its own field in the file entry.
+fn set_checksum(set: &[u8]) -> u16 {
+    return set
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != 2 && index != 3)
+        .fold(0u16, |sum, (_, &b)| {
+            sum.rotate_right(1).wrapping_add(b as u16)
+        });
+}
+
+/// The table on disk compresses runs of characters that map to themselves into 0xFFFF followed by
+/// the length of the run.
+fn expand_upcase(raw: &[u8]) -> Vec<u16> {
+    let units: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+
+    let mut table = Vec::with_capacity(UPCASE_ENTRIES);
+    let mut index = 0;
+    while index < units.len() && table.len() < UPCASE_ENTRIES {
+        match (units[index], units.get(index + 1)) {
+            (0xFFFF, Some(&skip)) => {
+                let end = (table.len() + skip as usize).min(UPCASE_ENTRIES);
+                table.extend((table.len()..end).map(|c| c as u16));
+                index += 2;
+            }
+            (unit, _) => {
+                table.push(unit);
+                index += 1;
+            }
+        }
+    }
+
+    return table;
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ExfatNode {
+    first_cluster: u32,
+    size: u64,
+    valid_size: u64,
+    /// The clusters are consecutive and the FAT is not used for them.
+    contiguous: bool,
+    is_dir: bool,
+}
+
+pub struct ExfatFilesystem {
+    device: Box<dyn BlockDevice>,
+    sector_size: u64,
+    cluster_size: u64,
+    cluster_count: u32,
+    /// Byte offset of the FAT used for reads.
+    fat_start: u64,
+    /// Byte offset of cluster 2.
+    heap_start: u64,
+    volume_serial: u32,
+    label: Option<String>,
+    /// Empty when the volume has no usable upcase table.
+    upcase: Vec<u16>,
+    nodes: BTreeMap<NodeId, ExfatNode>,
+    /// Runs of consecutive clusters, keyed by the first cluster of a FAT chain.
+    chains: BTreeMap<u32, Vec<(u32, u32)>>,
+    fat_cache: Option<(u64, Vec<u8>)>,
+}
+
+fn volume_label(entry: &VolumeLabelEntry) -> Option<String> {
+    let units: Vec<u16> = entry.label[..(entry.char_count as usize).min(MAX_LABEL_CHARS)]
+        .iter()
+        .map(|unit| unit.get())
+        .collect();
+    return char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+        .filter(|label| !label.is_empty());
+}
+
+impl ExfatFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut boot_sector = [0u8; 512];
+        return device.read_bytes(0, &mut boot_sector).is_ok()
+            && boot_sector[510..] == BOOT_SIGNATURE
+            && &boot_sector[3..11] == FILE_SYSTEM_NAME;
+    }
+
+    /// Only the first cluster of the root directory is searched for the label, formatters put it
+    /// right at the start.
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        if !Self::probe(device) {
+            return None;
+        }
+
+        let mut raw = [0u8; size_of::<BootSector>()];
+        device.read_bytes(0, &mut raw).ok()?;
+        let boot_sector = BootSector::read_from_bytes(&raw).ok()?;
+        let sector_shift = boot_sector.bytes_per_sector_shift as u32;
+        let cluster_shift = sector_shift + boot_sector.sectors_per_cluster_shift as u32;
+        if !(9..=12).contains(&sector_shift) || cluster_shift > 25 {
+            return None;
+        }
+
+        let mut root = vec![0u8; 1 << cluster_shift];
+        let offset = (boot_sector.root_cluster.get() as u64)
+            .checked_sub(FIRST_CLUSTER as u64)
+            .map(|index| {
+                ((boot_sector.cluster_heap_offset.get() as u64) << sector_shift)
+                    + (index << cluster_shift)
+            });
+        let label = match offset {
+            Some(offset) if device.read_bytes(offset, &mut root).is_ok() => root
+                .chunks_exact(ENTRY_SIZE)
+                .take_while(|raw| raw[0] != ENTRY_END)
+                .find(|raw| raw[0] == ENTRY_VOLUME_LABEL)
+                .and_then(|raw| VolumeLabelEntry::read_from_prefix(raw).ok())
+                .and_then(|(entry, _)| volume_label(&entry)),
+            _ => None,
+        };
+
+        return Some(VolumeInfo {
+            fs_type: "exfat",
+            usage: Usage::Filesystem,
+            label,
+            uuid: Some(Uuid::VolumeId32(VolumeId32::from_u32_le(
+                boot_sector.volume_serial.get(),
+            ))),
+            size: boot_sector
+                .volume_length
+                .get()
+                .saturating_mul(1 << sector_shift),
+        });
+    }
+
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<ExfatFilesystem, RrubError> {
+        let mut raw = [0u8; 512];
+        device.read_bytes(0, &mut raw)?;
+        let (boot_sector, _) =
+            BootSector::read_from_prefix(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if &boot_sector.file_system_name != FILE_SYSTEM_NAME
+            || boot_sector.must_be_zero.iter().any(|&b| b != 0)
+        {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        let sector_shift = boot_sector.bytes_per_sector_shift as u32;
+        let cluster_shift = sector_shift + boot_sector.sectors_per_cluster_shift as u32;
+        if !(9..=12).contains(&sector_shift)
+            || cluster_shift > 25
+            || !matches!(boot_sector.fat_count, 1 | 2)
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let sector_size = 1u64 << sector_shift;
+
+        let mut region = vec![0u8; (BOOT_CHECKSUM_SECTOR + 1) * sector_size as usize];
+        device.read_bytes(0, &mut region)?;
+        let (covered, stored) = region.split_at(BOOT_CHECKSUM_SECTOR * sector_size as usize);
+        let checksum = covered
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| !matches!(index, 106 | 107 | 112))
+            .fold(0u32, |sum, (_, &b)| {
+                sum.rotate_right(1).wrapping_add(b as u32)
+            });
+        if stored
+            .chunks_exact(4)
+            .any(|value| u32::from_le_bytes(value.try_into().unwrap()) != checksum)
+        {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+
+        let cluster_count = boot_sector.cluster_count.get();
+        let fat_length = boot_sector.fat_length.get() as u64 * sector_size;
+        if cluster_count == 0
+            || cluster_count > 0xFFFF_FFF5
+            || fat_length < (cluster_count as u64 + 2) * 4
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        // TexFAT volumes have a second FAT that may be the active one.
+        let active_fat = match boot_sector.fat_count == 2 {
+            true => (boot_sector.volume_flags.get() & VOLUME_FLAG_ACTIVE_FAT) as u64,
+            false => 0,
+        };
+
+        let mut filesystem = ExfatFilesystem {
+            device,
+            sector_size,
+            cluster_size: 1 << cluster_shift,
+            cluster_count,
+            fat_start: boot_sector.fat_offset.get() as u64 * sector_size + active_fat * fat_length,
+            heap_start: boot_sector.cluster_heap_offset.get() as u64 * sector_size,
+            volume_serial: boot_sector.volume_serial.get(),
+            label: None,
+            upcase: Vec::new(),
+            nodes: BTreeMap::new(),
+            chains: BTreeMap::new(),
+            fat_cache: None,
+        };
+
+        let root_cluster = boot_sector.root_cluster.get();
+        let root_clusters = filesystem
+            .chain(root_cluster)?
+            .iter()
+            .map(|run| run.1)
+            .sum::<u32>();
+        let root_size = root_clusters as u64 * filesystem.cluster_size;
+        filesystem.nodes.insert(
+            ROOT_NODE,
+            ExfatNode {
+                first_cluster: root_cluster,
+                size: root_size,
+                valid_size: root_size,
+                contiguous: false,
+                is_dir: true,
+            },
+        );
+
+        let root = filesystem.read_directory(&filesystem.node(ROOT_NODE)?)?;
+        for position in (0..root.len()).step_by(ENTRY_SIZE) {
+            let raw = &root[position..position + ENTRY_SIZE];
+            match raw[0] {
+                ENTRY_END => break,
+                ENTRY_VOLUME_LABEL => {
+                    let (entry, _) = VolumeLabelEntry::read_from_prefix(raw)
+                        .map_err(|_| FilesystemError::Corrupted)?;
+                    filesystem.label = volume_label(&entry);
+                }
+                ENTRY_UPCASE => {
+                    let (entry, _) = UpcaseEntry::read_from_prefix(raw)
+                        .map_err(|_| FilesystemError::Corrupted)?;
+                    filesystem.upcase = filesystem.read_upcase(&entry)?;
+                }
+                _ => {}
+            }
+        }
+
+        return Ok(filesystem);
+    }
+
+    /// A table that fails its checksum is ignored and names are compared with the Unicode case
+    /// mapping instead.
+    fn read_upcase(&mut self, entry: &UpcaseEntry) -> Result<Vec<u16>, RrubError> {
+        let length = entry.data_length.get();
+        if length > UPCASE_ENTRIES as u64 * 2 * 2 {
+            return Ok(Vec::new());
+        }
+
+        let node = ExfatNode {
+            first_cluster: entry.first_cluster.get(),
+            size: length,
+            valid_size: length,
+            contiguous: false,
+            is_dir: false,
+        };
+        let mut raw = vec![0u8; length as usize];
+        self.read_node(&node, 0, &mut raw)?;
+
+        return Ok(match table_checksum(&raw) == entry.checksum.get() {
+            true => expand_upcase(&raw),
+            false => Vec::new(),
+        });
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        return self.heap_start + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size;
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        return (FIRST_CLUSTER..self.cluster_count + FIRST_CLUSTER).contains(&cluster);
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, RrubError> {
+        let offset = cluster as u64 * 4;
+        let sector = offset / self.sector_size;
+        let index = (offset % self.sector_size) as usize;
+
+        if !matches!(&self.fat_cache, Some((cached, _)) if *cached == sector) {
+            let mut data = vec![0u8; self.sector_size as usize];
+            self.device
+                .read_bytes(self.fat_start + sector * self.sector_size, &mut data)?;
+            self.fat_cache = Some((sector, data));
+        }
+
+        let (_, data) = self.fat_cache.as_ref().unwrap();
+        return Ok(u32::from_le_bytes(
+            data[index..index + 4].try_into().unwrap(),
+        ));
+    }
+
+    /// Follow and cache the cluster chain starting at `first` as runs of consecutive clusters.
+    fn chain(&mut self, first: u32) -> Result<&[(u32, u32)], RrubError> {
+        if !self.chains.contains_key(&first) {
+            let mut runs: Vec<(u32, u32)> = Vec::new();
+            let mut length = 0u32;
+            let mut cluster = first;
+
+            loop {
+                if !self.is_valid_cluster(cluster) {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                if length >= self.cluster_count {
+                    // Loop in the FAT
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                length += 1;
+
+                match runs.last_mut() {
+                    Some((start, count)) if *start + *count == cluster => *count += 1,
+                    _ => runs.push((cluster, 1)),
+                }
+
+                match self.fat_entry(cluster)? {
+                    END_OF_CHAIN => break,
+                    BAD_CLUSTER => return Err(FilesystemError::Corrupted.into()),
+                    next => cluster = next,
+                }
+            }
+
+            self.chains.insert(first, runs);
+        }
+
+        return Ok(&self.chains[&first]);
+    }
+
+    /// Runs of consecutive clusters holding the data of a node.
+    fn runs(&mut self, node: &ExfatNode) -> Result<Vec<(u32, u32)>, RrubError> {
+        if node.size == 0 {
+            return Ok(Vec::new());
+        }
+
+        if node.contiguous {
+            let count = node.size.div_ceil(self.cluster_size);
+            let last = node.first_cluster as u64 + count - 1;
+            if !self.is_valid_cluster(node.first_cluster) || last >= self.cluster_count as u64 + 2 {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            return Ok(vec![(node.first_cluster, count as u32)]);
+        }
+
+        return Ok(self.chain(node.first_cluster)?.to_vec());
+    }
+
+    /// Read the data of a node, bytes past its valid data length read as zeros.
+    fn read_node(
+        &mut self,
+        node: &ExfatNode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        if offset >= node.size {
+            return Ok(0);
+        }
+        let length = buffer.len().min((node.size - offset) as usize);
+        let valid = match offset < node.valid_size {
+            true => length.min((node.valid_size - offset) as usize),
+            false => 0,
+        };
+        buffer[valid..length].fill(0);
+
+        let mut done = 0;
+        let mut run_start = 0u64;
+        for (cluster, count) in self.runs(node)? {
+            if done == valid {
+                break;
+            }
+
+            let run_length = count as u64 * self.cluster_size;
+            let position = offset + done as u64;
+            if position < run_start + run_length {
+                let skip = position - run_start;
+                let count = ((run_length - skip) as usize).min(valid - done);
+                self.device.read_bytes(
+                    self.cluster_offset(cluster) + skip,
+                    &mut buffer[done..done + count],
+                )?;
+                done += count;
+            }
+            run_start += run_length;
+        }
+
+        if done != valid {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        return Ok(length);
+    }
+
+    fn node(&self, id: NodeId) -> Result<ExfatNode, RrubError> {
+        return self
+            .nodes
+            .get(&id)
+            .copied()
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read_directory(&mut self, node: &ExfatNode) -> Result<Vec<u8>, RrubError> {
+        if !node.is_dir {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+        if node.size > MAX_DIRECTORY_SIZE {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut data = vec![0u8; node.size as usize];
+        self.read_node(node, 0, &mut data)?;
+
+        return Ok(data);
+    }
+
+    fn read_entries(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let node = self.node(dir)?;
+        let data = self.read_directory(&node)?;
+
+        // Entry sets may continue in the next cluster, which need not follow on the volume.
+        let cluster_size = self.cluster_size;
+        let regions: Vec<(u64, u64)> = self
+            .runs(&node)?
+            .into_iter()
+            .map(|(cluster, count)| (self.cluster_offset(cluster), count as u64 * cluster_size))
+            .collect();
+        let volume_offset = |mut position: u64| -> u64 {
+            for &(start, length) in &regions {
+                if position < length {
+                    return start + position;
+                }
+                position -= length;
+            }
+            unreachable!("Directory entry past the end of its clusters");
+        };
+
+        let mut entries = Vec::new();
+        let mut position = 0;
+        while position + ENTRY_SIZE <= data.len() {
+            match data[position] {
+                ENTRY_END => break,
+                ENTRY_FILE => {}
+                _ => {
+                    position += ENTRY_SIZE;
+                    continue;
+                }
+            }
+
+            let (file, _) = FileEntry::read_from_prefix(&data[position..])
+                .map_err(|_| FilesystemError::Corrupted)?;
+            let set_length = (file.secondary_count as usize + 1) * ENTRY_SIZE;
+            let Some(set) = data.get(position..position + set_length) else {
+                break;
+            };
+            let id = NodeId(volume_offset(position as u64));
+
+            // Damaged or incomplete sets are skipped, the entries in them are seen again below as
+            // stray secondary entries.
+            position += ENTRY_SIZE;
+            if file.secondary_count < 2 || set_checksum(set) != file.set_checksum.get() {
+                continue;
+            }
+
+            let (stream, _) = StreamEntry::read_from_prefix(&set[ENTRY_SIZE..])
+                .map_err(|_| FilesystemError::Corrupted)?;
+            let name_length = stream.name_length as usize;
+            let name_entries = name_length.div_ceil(NAME_CHARS_PER_ENTRY);
+            if stream.entry_type != ENTRY_STREAM
+                || name_length == 0
+                || name_entries > file.secondary_count as usize - 1
+            {
+                continue;
+            }
+
+            let mut units = Vec::with_capacity(name_entries * NAME_CHARS_PER_ENTRY);
+            for raw in set[2 * ENTRY_SIZE..]
+                .chunks_exact(ENTRY_SIZE)
+                .take(name_entries)
+            {
+                let (name, _) =
+                    FileNameEntry::read_from_prefix(raw).map_err(|_| FilesystemError::Corrupted)?;
+                if name.entry_type != ENTRY_FILE_NAME {
+                    break;
+                }
+                units.extend(name.name.iter().map(|unit| unit.get()));
+            }
+            if units.len() < name_length {
+                continue;
+            }
+            let Ok(name) = char::decode_utf16(units[..name_length].iter().copied())
+                .collect::<Result<String, _>>()
+            else {
+                continue;
+            };
+            position += set_length - ENTRY_SIZE;
+
+            let is_dir = file.attributes.get() & ATTRIBUTE_DIRECTORY != 0;
+            let size = stream.data_length.get();
+            self.nodes.insert(
+                id,
+                ExfatNode {
+                    first_cluster: stream.first_cluster.get(),
+                    size,
+                    valid_size: match is_dir {
+                        true => size,
+                        false => stream.valid_data_length.get().min(size),
+                    },
+                    contiguous: stream.flags & STREAM_NO_FAT_CHAIN != 0,
+                    is_dir,
+                },
+            );
+
+            entries.push(DirEntry {
+                name,
+                node: id,
+                file_type: match is_dir {
+                    true => FileType::Directory,
+                    false => FileType::Regular,
+                },
+            });
+        }
+
+        return Ok(entries);
+    }
+
+    /// exFAT names are compared case insensitively through the volume's upcase table.
+    fn names_equal(&self, a: &str, b: &str) -> bool {
+        if self.upcase.is_empty() {
+            return a
+                .chars()
+                .flat_map(char::to_uppercase)
+                .eq(b.chars().flat_map(char::to_uppercase));
+        }
+
+        let upcase = |c: u16| self.upcase.get(c as usize).copied().unwrap_or(c);
+        return a
+            .encode_utf16()
+            .map(upcase)
+            .eq(b.encode_utf16().map(upcase));
+    }
+}
+
+impl FilesystemBackend for ExfatFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "exfat";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::VolumeId32(VolumeId32::from_u32_le(self.volume_serial));
+    }
+
+    fn label(&self) -> Option<String> {
+        return self.label.clone();
+    }
+
+    fn root(&self) -> NodeId {
+        return ROOT_NODE;
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let node = self.node(node)?;
+        return Ok(match node.is_dir {
+            true => Metadata {
+                file_type: FileType::Directory,
+                size: 0,
+            },
+            false => Metadata {
+                file_type: FileType::Regular,
+                size: node.size,
+            },
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        return self.read_entries(dir);
+    }
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        return self
+            .read_entries(dir)?
+            .into_iter()
+            .find(|entry| self.names_equal(&entry.name, name))
+            .map(|entry| entry.node)
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        let node = self.node(node)?;
+        if node.is_dir {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+        return self.read_node(&node, offset, buffer);
+    }
+}
@@ -0,0 +1,1392 @@
+/*
+ * https://docs.kernel.org/filesystems/ext4/index.html
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use bitflags::bitflags;
+use log::warn;
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    checksum::crc32c_update,
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo, label_from_bytes},
+};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT4_MAGIC: u16 = 0xEF53;
+const EXTENT_MAGIC: u16 = 0xF30A;
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+const CHECKSUM_TYPE_CRC32C: u8 = 1;
+const COMPAT_HAS_JOURNAL: u32 = 0x4;
+
+const ROOT_INODE: u32 = 2;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+const INODE_BLOCK_SIZE: usize = 60;
+const DIRECT_BLOCKS: usize = 12;
+const MAX_EXTENT_DEPTH: u16 = 5;
+const INITIALIZED_EXTENT_MAX: u16 = 32768;
+
+const S_IFMT: u16 = 0xF000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xA000;
+
+const INODE_INDEX_FL: u32 = 0x1000;
+const INODE_EXTENTS_FL: u32 = 0x80000;
+const INODE_INLINE_DATA_FL: u32 = 0x1000_0000;
+
+const DIR_ENTRY_TAIL_SIZE: usize = 12;
+const DIR_ENTRY_TAIL_TYPE: u8 = 0xDE;
+const XATTR_INDEX_SYSTEM: u8 = 7;
+
+const FLAGS_UNSIGNED_HASH: u32 = 0x2;
+const DX_HASH_LEGACY: u8 = 0;
+const DX_HASH_HALF_MD4: u8 = 1;
+const DX_HASH_TEA: u8 = 2;
+const DX_HASH_LEGACY_UNSIGNED: u8 = 3;
+const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+const DX_HASH_TEA_UNSIGNED: u8 = 5;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct IncompatFeatures: u32 {
+        const Compression = 0x1;
+        const Filetype = 0x2;
+        const Recover = 0x4;
+        const JournalDev = 0x8;
+        const MetaBg = 0x10;
+        const Extents = 0x40;
+        const Bit64 = 0x80;
+        const Mmp = 0x100;
+        const FlexBg = 0x200;
+        const EaInode = 0x400;
+        const DirData = 0x1000;
+        const CsumSeed = 0x2000;
+        const LargeDir = 0x4000;
+        const InlineData = 0x8000;
+        const Encrypt = 0x10000;
+        const Casefold = 0x20000;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct RoCompatFeatures: u32 {
+        const SparseSuper = 0x1;
+        const LargeFile = 0x2;
+        const BtreeDir = 0x4;
+        const HugeFile = 0x8;
+        const GdtCsum = 0x10;
+        const DirNlink = 0x20;
+        const ExtraIsize = 0x40;
+        const Quota = 0x100;
+        const Bigalloc = 0x200;
+        const MetadataCsum = 0x400;
+        const Readonly = 0x1000;
+        const Project = 0x2000;
+        const Verity = 0x8000;
+        const OrphanPresent = 0x10000;
+        const _ = !0;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Superblock {
+    pub inodes_count: U32<LittleEndian>,
+    pub blocks_count_lo: U32<LittleEndian>,
+    pub r_blocks_count_lo: U32<LittleEndian>,
+    pub free_blocks_count_lo: U32<LittleEndian>,
+    pub free_inodes_count: U32<LittleEndian>,
+    pub first_data_block: U32<LittleEndian>,
+    pub log_block_size: U32<LittleEndian>,
+    pub log_cluster_size: U32<LittleEndian>,
+    pub blocks_per_group: U32<LittleEndian>,
+    pub clusters_per_group: U32<LittleEndian>,
+    pub inodes_per_group: U32<LittleEndian>,
+    pub mtime: U32<LittleEndian>,
+    pub wtime: U32<LittleEndian>,
+    pub mnt_count: U16<LittleEndian>,
+    pub max_mnt_count: U16<LittleEndian>,
+    pub magic: U16<LittleEndian>,
+    pub state: U16<LittleEndian>,
+    pub errors: U16<LittleEndian>,
+    pub minor_rev_level: U16<LittleEndian>,
+    pub lastcheck: U32<LittleEndian>,
+    pub checkinterval: U32<LittleEndian>,
+    pub creator_os: U32<LittleEndian>,
+    pub rev_level: U32<LittleEndian>,
+    pub def_resuid: U16<LittleEndian>,
+    pub def_resgid: U16<LittleEndian>,
+    pub first_ino: U32<LittleEndian>,
+    pub inode_size: U16<LittleEndian>,
+    pub block_group_nr: U16<LittleEndian>,
+    pub feature_compat: U32<LittleEndian>,
+    pub feature_incompat: U32<LittleEndian>,
+    pub feature_ro_compat: U32<LittleEndian>,
+    pub uuid: [u8; 16],
+    pub volume_name: [u8; 16],
+    pub last_mounted: [u8; 64],
+    pub algorithm_usage_bitmap: U32<LittleEndian>,
+    pub prealloc_blocks: u8,
+    pub prealloc_dir_blocks: u8,
+    pub reserved_gdt_blocks: U16<LittleEndian>,
+    pub journal_uuid: [u8; 16],
+    pub journal_inum: U32<LittleEndian>,
+    pub journal_dev: U32<LittleEndian>,
+    pub last_orphan: U32<LittleEndian>,
+    pub hash_seed: [U32<LittleEndian>; 4],
+    pub def_hash_version: u8,
+    pub jnl_backup_type: u8,
+    pub desc_size: U16<LittleEndian>,
+    pub default_mount_opts: U32<LittleEndian>,
+    pub first_meta_bg: U32<LittleEndian>,
+    pub mkfs_time: U32<LittleEndian>,
+    pub jnl_blocks: [U32<LittleEndian>; 17],
+    pub blocks_count_hi: U32<LittleEndian>,
+    pub r_blocks_count_hi: U32<LittleEndian>,
+    pub free_blocks_count_hi: U32<LittleEndian>,
+    pub min_extra_isize: U16<LittleEndian>,
+    pub want_extra_isize: U16<LittleEndian>,
+    pub flags: U32<LittleEndian>,
+    pub raid_stride: U16<LittleEndian>,
+    pub mmp_interval: U16<LittleEndian>,
+    pub mmp_block: U64<LittleEndian>,
+    pub raid_stripe_width: U32<LittleEndian>,
+    pub log_groups_per_flex: u8,
+    pub checksum_type: u8,
+    pub _reserved_pad: U16<LittleEndian>,
+    pub _reserved: [u8; 0xF8],
+    pub checksum_seed: U32<LittleEndian>,
+    pub _reserved2: [u8; 0x188],
+    pub checksum: U32<LittleEndian>,
+}
+
+const _: () = assert!(size_of::<Superblock>() == 1024);
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct GroupDescriptor {
+    pub block_bitmap_lo: U32<LittleEndian>,
+    pub inode_bitmap_lo: U32<LittleEndian>,
+    pub inode_table_lo: U32<LittleEndian>,
+    pub free_blocks_count_lo: U16<LittleEndian>,
+    pub free_inodes_count_lo: U16<LittleEndian>,
+    pub used_dirs_count_lo: U16<LittleEndian>,
+    pub flags: U16<LittleEndian>,
+    pub exclude_bitmap_lo: U32<LittleEndian>,
+    pub block_bitmap_csum_lo: U16<LittleEndian>,
+    pub inode_bitmap_csum_lo: U16<LittleEndian>,
+    pub itable_unused_lo: U16<LittleEndian>,
+    pub checksum: U16<LittleEndian>,
+    pub block_bitmap_hi: U32<LittleEndian>,
+    pub inode_bitmap_hi: U32<LittleEndian>,
+    pub inode_table_hi: U32<LittleEndian>,
+    pub free_blocks_count_hi: U16<LittleEndian>,
+    pub free_inodes_count_hi: U16<LittleEndian>,
+    pub used_dirs_count_hi: U16<LittleEndian>,
+    pub itable_unused_hi: U16<LittleEndian>,
+    pub exclude_bitmap_hi: U32<LittleEndian>,
+    pub block_bitmap_csum_hi: U16<LittleEndian>,
+    pub inode_bitmap_csum_hi: U16<LittleEndian>,
+    pub _reserved: U32<LittleEndian>,
+}
+
+const GROUP_DESC_CHECKSUM_OFFSET: usize = 0x1E;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct RawInode {
+    pub mode: U16<LittleEndian>,
+    pub uid: U16<LittleEndian>,
+    pub size_lo: U32<LittleEndian>,
+    pub atime: U32<LittleEndian>,
+    pub ctime: U32<LittleEndian>,
+    pub mtime: U32<LittleEndian>,
+    pub dtime: U32<LittleEndian>,
+    pub gid: U16<LittleEndian>,
+    pub links_count: U16<LittleEndian>,
+    pub blocks_lo: U32<LittleEndian>,
+    pub flags: U32<LittleEndian>,
+    pub osd1: U32<LittleEndian>,
+    pub block: [u8; INODE_BLOCK_SIZE],
+    pub generation: U32<LittleEndian>,
+    pub file_acl_lo: U32<LittleEndian>,
+    pub size_high: U32<LittleEndian>,
+    pub obso_faddr: U32<LittleEndian>,
+    pub blocks_high: U16<LittleEndian>,
+    pub file_acl_high: U16<LittleEndian>,
+    pub uid_high: U16<LittleEndian>,
+    pub gid_high: U16<LittleEndian>,
+    pub checksum_lo: U16<LittleEndian>,
+    pub _reserved: U16<LittleEndian>,
+    pub extra_isize: U16<LittleEndian>,
+    pub checksum_hi: U16<LittleEndian>,
+}
+
+const INODE_CHECKSUM_LO_OFFSET: usize = 0x7C;
+const INODE_CHECKSUM_HI_OFFSET: usize = 0x82;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtentHeader {
+    pub magic: U16<LittleEndian>,
+    pub entries: U16<LittleEndian>,
+    pub max: U16<LittleEndian>,
+    pub depth: U16<LittleEndian>,
+    pub generation: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtentIndex {
+    pub block: U32<LittleEndian>,
+    pub leaf_lo: U32<LittleEndian>,
+    pub leaf_hi: U16<LittleEndian>,
+    pub _unused: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtentLeaf {
+    pub block: U32<LittleEndian>,
+    pub len: U16<LittleEndian>,
+    pub start_hi: U16<LittleEndian>,
+    pub start_lo: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirEntryHeader {
+    pub inode: U32<LittleEndian>,
+    pub rec_len: U16<LittleEndian>,
+    pub name_len: u8,
+    pub file_type: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct XattrEntry {
+    pub name_len: u8,
+    pub name_index: u8,
+    pub value_offs: U16<LittleEndian>,
+    pub value_inum: U32<LittleEndian>,
+    pub value_size: U32<LittleEndian>,
+    pub hash: U32<LittleEndian>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Extent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    /// Allocated but never written, reads back as zeros.
+    uninitialized: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    number: u32,
+    raw: Vec<u8>,
+    fields: RawInode,
+}
+
+impl Inode {
+    fn mode(&self) -> u16 {
+        return self.fields.mode.get();
+    }
+
+    fn flags(&self) -> u32 {
+        return self.fields.flags.get();
+    }
+
+    fn size(&self) -> u64 {
+        return self.fields.size_lo.get() as u64 | (self.fields.size_high.get() as u64) << 32;
+    }
+
+    fn file_type(&self) -> FileType {
+        return match self.mode() & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFREG => FileType::Regular,
+            S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        };
+    }
+
+    fn has_inline_data(&self) -> bool {
+        return self.flags() & INODE_INLINE_DATA_FL != 0;
+    }
+
+    /// Space after the extra inode fields, holding in-inode extended attributes.
+    fn extra_space(&self) -> Option<&[u8]> {
+        if self.raw.len() <= GOOD_OLD_INODE_SIZE {
+            return None;
+        }
+        let start = GOOD_OLD_INODE_SIZE + self.fields.extra_isize.get() as usize;
+        return self.raw.get(start..);
+    }
+
+    /// Value of the "system.data" attribute holding inline data past the first 60 bytes.
+    fn inline_xattr(&self) -> Result<&[u8], RrubError> {
+        let Some(space) = self.extra_space() else {
+            return Ok(&[]);
+        };
+        if space.len() < 4 || u32::from_le_bytes(space[..4].try_into().unwrap()) != XATTR_MAGIC {
+            return Ok(&[]);
+        }
+
+        let entries = &space[4..];
+        let mut offset = 0;
+        while offset + size_of::<XattrEntry>() <= entries.len() {
+            if entries[offset..offset + 4] == [0u8; 4] {
+                break;
+            }
+
+            let (entry, rest) = XattrEntry::read_from_prefix(&entries[offset..])
+                .map_err(|_| FilesystemError::Corrupted)?;
+            let name = rest
+                .get(..entry.name_len as usize)
+                .ok_or(FilesystemError::Corrupted)?;
+
+            if entry.name_index == XATTR_INDEX_SYSTEM && name == b"data" {
+                let start = entry.value_offs.get() as usize;
+                return entries
+                    .get(start..start + entry.value_size.get() as usize)
+                    .ok_or(FilesystemError::Corrupted.into());
+            }
+
+            offset += (size_of::<XattrEntry>() + entry.name_len as usize).next_multiple_of(4);
+        }
+
+        return Ok(&[]);
+    }
+}
+
+pub struct Ext4Filesystem {
+    device: Box<dyn BlockDevice>,
+    superblock: Superblock,
+    incompat: IncompatFeatures,
+    ro_compat: RoCompatFeatures,
+    block_size: u64,
+    inode_size: usize,
+    desc_size: usize,
+    group_count: u32,
+    /// Seed for metadata checksums, present when metadata_csum is enabled.
+    csum_seed: Option<u32>,
+    inode_tables: BTreeMap<u32, u64>,
+    inodes: BTreeMap<u32, Inode>,
+    extents: BTreeMap<u32, Vec<Extent>>,
+}
+
+impl Ext4Filesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut magic = [0u8; 2];
+        return device
+            .read_bytes(SUPERBLOCK_OFFSET + 0x38, &mut magic)
+            .is_ok()
+            && u16::from_le_bytes(magic) == EXT4_MAGIC;
+    }
+
+    /// Named like blkid does, ext3 has a journal and ext4 uses any feature ext3 does not know.
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let mut raw = [0u8; 1024];
+        device.read_bytes(SUPERBLOCK_OFFSET, &mut raw).ok()?;
+        let superblock = Superblock::read_from_bytes(&raw).ok()?;
+
+        let incompat = IncompatFeatures::from_bits_retain(superblock.feature_incompat.get());
+        let ro_compat = RoCompatFeatures::from_bits_retain(superblock.feature_ro_compat.get());
+        if superblock.magic.get() != EXT4_MAGIC || incompat.contains(IncompatFeatures::JournalDev) {
+            return None;
+        }
+
+        let ext3_incompat =
+            IncompatFeatures::Filetype | IncompatFeatures::Recover | IncompatFeatures::MetaBg;
+        let ext3_ro_compat = RoCompatFeatures::SparseSuper
+            | RoCompatFeatures::LargeFile
+            | RoCompatFeatures::BtreeDir;
+        let fs_type = match (
+            !ext3_incompat.contains(incompat) || !ext3_ro_compat.contains(ro_compat),
+            superblock.feature_compat.get() & COMPAT_HAS_JOURNAL != 0,
+        ) {
+            (true, _) => "ext4",
+            (false, true) => "ext3",
+            (false, false) => "ext2",
+        };
+
+        let mut blocks = superblock.blocks_count_lo.get() as u64;
+        if incompat.contains(IncompatFeatures::Bit64) {
+            blocks |= (superblock.blocks_count_hi.get() as u64) << 32;
+        }
+        let block_size = 1024u64 << superblock.log_block_size.get().min(6);
+
+        return Some(VolumeInfo {
+            fs_type,
+            usage: Usage::Filesystem,
+            label: label_from_bytes(&superblock.volume_name),
+            uuid: Some(Uuid::RealUuid(RealUuid::from_bytes(superblock.uuid))),
+            size: blocks * block_size,
+        });
+    }
+
+    /// The journal is not replayed, a volume that was not cleanly unmounted may show stale data.
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<Ext4Filesystem, RrubError> {
+        let mut raw = [0u8; 1024];
+        device.read_bytes(SUPERBLOCK_OFFSET, &mut raw)?;
+        let superblock =
+            Superblock::read_from_bytes(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if superblock.magic.get() != EXT4_MAGIC {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        let incompat = IncompatFeatures::from_bits(superblock.feature_incompat.get())
+            .ok_or(FilesystemError::Unsupported)?;
+        let ro_compat = RoCompatFeatures::from_bits_retain(superblock.feature_ro_compat.get());
+
+        if incompat.contains(IncompatFeatures::JournalDev) {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+        if incompat.intersects(IncompatFeatures::Compression | IncompatFeatures::DirData) {
+            return Err(FilesystemError::Unsupported.into());
+        }
+        if incompat.contains(IncompatFeatures::Recover) {
+            warn!("ext4 journal needs recovery, reading without replaying it");
+        }
+
+        let csum_seed = match ro_compat.contains(RoCompatFeatures::MetadataCsum) {
+            true => {
+                if superblock.checksum_type != CHECKSUM_TYPE_CRC32C
+                    || crc32c_update(!0, &raw[..1020]) != superblock.checksum.get()
+                {
+                    return Err(FilesystemError::ChecksumMismatch.into());
+                }
+
+                match incompat.contains(IncompatFeatures::CsumSeed) {
+                    true => Some(superblock.checksum_seed.get()),
+                    false => Some(crc32c_update(!0, &superblock.uuid)),
+                }
+            }
+            false => None,
+        };
+
+        let log_block_size = superblock.log_block_size.get();
+        if log_block_size > 6 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let block_size = 1024u64 << log_block_size;
+
+        let inode_size = match superblock.rev_level.get() {
+            0 => GOOD_OLD_INODE_SIZE,
+            _ => superblock.inode_size.get() as usize,
+        };
+        let desc_size = match incompat.contains(IncompatFeatures::Bit64) {
+            true => superblock.desc_size.get() as usize,
+            false => 32,
+        };
+        if inode_size < GOOD_OLD_INODE_SIZE
+            || !inode_size.is_power_of_two()
+            || inode_size as u64 > block_size
+            || !(32..=size_of::<GroupDescriptor>()).contains(&desc_size)
+            || superblock.blocks_per_group.get() == 0
+            || superblock.inodes_per_group.get() == 0
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut blocks_count = superblock.blocks_count_lo.get() as u64;
+        if incompat.contains(IncompatFeatures::Bit64) {
+            blocks_count |= (superblock.blocks_count_hi.get() as u64) << 32;
+        }
+        let group_count = (blocks_count - superblock.first_data_block.get() as u64)
+            .div_ceil(superblock.blocks_per_group.get() as u64);
+
+        return Ok(Ext4Filesystem {
+            device,
+            superblock,
+            incompat,
+            ro_compat,
+            block_size,
+            inode_size,
+            desc_size,
+            group_count: u32::try_from(group_count).map_err(|_| FilesystemError::Corrupted)?,
+            csum_seed,
+            inode_tables: BTreeMap::new(),
+            inodes: BTreeMap::new(),
+            extents: BTreeMap::new(),
+        });
+    }
+
+    fn read_block(&mut self, block: u64) -> Result<Vec<u8>, RrubError> {
+        let mut data = vec![0u8; self.block_size as usize];
+        let offset = block
+            .checked_mul(self.block_size)
+            .ok_or(FilesystemError::Corrupted)?;
+        self.device.read_bytes(offset, &mut data)?;
+        return Ok(data);
+    }
+
+    fn has_super(&self, group: u32) -> bool {
+        if !self.ro_compat.contains(RoCompatFeatures::SparseSuper) || group <= 1 {
+            return true;
+        }
+
+        for base in [3u32, 5, 7] {
+            let mut power = base;
+            while power < group {
+                power = match power.checked_mul(base) {
+                    Some(power) => power,
+                    None => break,
+                };
+            }
+            if power == group {
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    fn descriptor_offset(&self, group: u32) -> u64 {
+        let per_block = (self.block_size as usize / self.desc_size) as u32;
+        let first_data_block = self.superblock.first_data_block.get() as u64;
+        let desc_block = group / per_block;
+        let index = (group % per_block) as u64 * self.desc_size as u64;
+
+        let block = match self.incompat.contains(IncompatFeatures::MetaBg)
+            && desc_block >= self.superblock.first_meta_bg.get()
+        {
+            // Meta block groups keep their descriptors in the first group of each meta group.
+            true => {
+                let first_group = desc_block * per_block;
+                let mut has_super = self.has_super(first_group) as u64;
+                if self.block_size == 1024 && desc_block == 0 && first_data_block == 0 {
+                    has_super += 1;
+                }
+                first_data_block
+                    + first_group as u64 * self.superblock.blocks_per_group.get() as u64
+                    + has_super
+            }
+            // Right after the superblock, which is in block 1 for 1 KiB blocks even with bigalloc.
+            false => SUPERBLOCK_OFFSET / self.block_size + 1 + desc_block as u64,
+        };
+
+        return block * self.block_size + index;
+    }
+
+    fn inode_table(&mut self, group: u32) -> Result<u64, RrubError> {
+        if let Some(&table) = self.inode_tables.get(&group) {
+            return Ok(table);
+        }
+        if group >= self.group_count {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut raw = [0u8; size_of::<GroupDescriptor>()];
+        let offset = self.descriptor_offset(group);
+        self.device.read_bytes(offset, &mut raw[..self.desc_size])?;
+
+        if let Some(seed) = self.csum_seed {
+            let mut crc = crc32c_update(seed, &group.to_le_bytes());
+            crc = crc32c_update(crc, &raw[..GROUP_DESC_CHECKSUM_OFFSET]);
+            crc = crc32c_update(crc, &[0, 0]);
+            crc = crc32c_update(crc, &raw[GROUP_DESC_CHECKSUM_OFFSET + 2..self.desc_size]);
+
+            if crc as u16 != u16::from_le_bytes([raw[0x1E], raw[0x1F]]) {
+                return Err(FilesystemError::ChecksumMismatch.into());
+            }
+        }
+
+        let desc =
+            GroupDescriptor::read_from_bytes(&raw).map_err(|_| FilesystemError::Corrupted)?;
+        let mut table = desc.inode_table_lo.get() as u64;
+        if self.desc_size >= 64 {
+            table |= (desc.inode_table_hi.get() as u64) << 32;
+        }
+
+        self.inode_tables.insert(group, table);
+        return Ok(table);
+    }
+
+    fn inode(&mut self, number: u32) -> Result<&Inode, RrubError> {
+        if !self.inodes.contains_key(&number) {
+            let inode = self.load_inode(number)?;
+            self.inodes.insert(number, inode);
+        }
+        return Ok(&self.inodes[&number]);
+    }
+
+    fn load_inode(&mut self, number: u32) -> Result<Inode, RrubError> {
+        if number == 0 || number > self.superblock.inodes_count.get() {
+            return Err(FilesystemError::NotFound.into());
+        }
+
+        let per_group = self.superblock.inodes_per_group.get();
+        let group = (number - 1) / per_group;
+        let index = ((number - 1) % per_group) as u64;
+        let table = self.inode_table(group)?;
+
+        let mut raw = vec![0u8; self.inode_size];
+        self.device.read_bytes(
+            table * self.block_size + index * self.inode_size as u64,
+            &mut raw,
+        )?;
+
+        // Inodes without the large inode fields are read as if those are zero.
+        let mut padded = [0u8; size_of::<RawInode>()];
+        let len = raw.len().min(padded.len());
+        padded[..len].copy_from_slice(&raw[..len]);
+        let fields = RawInode::read_from_bytes(&padded).map_err(|_| FilesystemError::Corrupted)?;
+
+        let inode = Inode {
+            number,
+            raw,
+            fields,
+        };
+
+        if self.csum_seed.is_some() {
+            self.verify_inode(&inode)?;
+        }
+
+        return Ok(inode);
+    }
+
+    fn inode_seed(&self, inode: &Inode) -> u32 {
+        let seed = self.csum_seed.unwrap_or(0);
+        let crc = crc32c_update(seed, &inode.number.to_le_bytes());
+        return crc32c_update(crc, &inode.fields.generation.get().to_le_bytes());
+    }
+
+    fn verify_inode(&self, inode: &Inode) -> Result<(), RrubError> {
+        let raw = &inode.raw;
+        let has_hi = raw.len() > GOOD_OLD_INODE_SIZE
+            && GOOD_OLD_INODE_SIZE + inode.fields.extra_isize.get() as usize
+                >= INODE_CHECKSUM_HI_OFFSET + 2;
+
+        let mut crc = crc32c_update(self.inode_seed(inode), &raw[..INODE_CHECKSUM_LO_OFFSET]);
+        crc = crc32c_update(crc, &[0, 0]);
+        crc = crc32c_update(crc, &raw[INODE_CHECKSUM_LO_OFFSET + 2..GOOD_OLD_INODE_SIZE]);
+        if raw.len() > GOOD_OLD_INODE_SIZE {
+            match has_hi {
+                true => {
+                    crc = crc32c_update(crc, &raw[GOOD_OLD_INODE_SIZE..INODE_CHECKSUM_HI_OFFSET]);
+                    crc = crc32c_update(crc, &[0, 0]);
+                    crc = crc32c_update(crc, &raw[INODE_CHECKSUM_HI_OFFSET + 2..]);
+                }
+                false => crc = crc32c_update(crc, &raw[GOOD_OLD_INODE_SIZE..]),
+            }
+        }
+
+        let mut stored = inode.fields.checksum_lo.get() as u32;
+        match has_hi {
+            true => stored |= (inode.fields.checksum_hi.get() as u32) << 16,
+            false => crc &= 0xFFFF,
+        }
+
+        if crc != stored {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        return Ok(());
+    }
+
+    /// Check the checksum stored after the last possible entry of an extent tree block.
+    fn verify_extent_block(&self, inode: &Inode, block: &[u8], max: u16) -> Result<(), RrubError> {
+        let Some(_) = self.csum_seed else {
+            return Ok(());
+        };
+
+        let tail = size_of::<ExtentHeader>() + max as usize * size_of::<ExtentLeaf>();
+        let stored = block
+            .get(tail..tail + 4)
+            .ok_or(FilesystemError::Corrupted)?;
+
+        if crc32c_update(self.inode_seed(inode), &block[..tail]).to_le_bytes() != stored {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        return Ok(());
+    }
+
+    fn collect_extents(
+        &mut self,
+        inode: &Inode,
+        node: &[u8],
+        depth: u16,
+        extents: &mut Vec<Extent>,
+    ) -> Result<(), RrubError> {
+        let (header, mut rest) =
+            ExtentHeader::read_from_prefix(node).map_err(|_| FilesystemError::Corrupted)?;
+
+        if header.magic.get() != EXTENT_MAGIC
+            || header.depth.get() != depth
+            || header.entries.get() > header.max.get()
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        for _ in 0..header.entries.get() {
+            if depth == 0 {
+                let (leaf, next) =
+                    ExtentLeaf::read_from_prefix(rest).map_err(|_| FilesystemError::Corrupted)?;
+                rest = next;
+
+                let (length, uninitialized) = match leaf.len.get() {
+                    len if len > INITIALIZED_EXTENT_MAX => (len - INITIALIZED_EXTENT_MAX, true),
+                    len => (len, false),
+                };
+                extents.push(Extent {
+                    logical: leaf.block.get() as u64,
+                    physical: leaf.start_lo.get() as u64 | (leaf.start_hi.get() as u64) << 32,
+                    length: length as u64,
+                    uninitialized,
+                });
+            } else {
+                let (index, next) =
+                    ExtentIndex::read_from_prefix(rest).map_err(|_| FilesystemError::Corrupted)?;
+                rest = next;
+
+                let child = self
+                    .read_block(index.leaf_lo.get() as u64 | (index.leaf_hi.get() as u64) << 32)?;
+                let (child_header, _) = ExtentHeader::read_from_prefix(&child)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                self.verify_extent_block(inode, &child, child_header.max.get())?;
+                self.collect_extents(inode, &child, depth - 1, extents)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Walk one level of the indirect block map of ext2/3 style inodes.
+    fn collect_indirect(
+        &mut self,
+        block: u64,
+        level: u32,
+        logical: &mut u64,
+        end: u64,
+        extents: &mut Vec<Extent>,
+    ) -> Result<(), RrubError> {
+        let per_block = self.block_size / 4;
+        let span = per_block.pow(level);
+
+        if block == 0 {
+            *logical += span * per_block;
+            return Ok(());
+        }
+
+        let data = self.read_block(block)?;
+        for pointer in data.chunks_exact(4) {
+            if *logical >= end {
+                break;
+            }
+
+            let pointer = u32::from_le_bytes(pointer.try_into().unwrap()) as u64;
+            match level {
+                0 => {
+                    push_extent(extents, *logical, pointer);
+                    *logical += 1;
+                }
+                _ => self.collect_indirect(pointer, level - 1, logical, end, extents)?,
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn extents(&mut self, number: u32) -> Result<&[Extent], RrubError> {
+        if !self.extents.contains_key(&number) {
+            let inode = self.inode(number)?.clone();
+            let mut extents = Vec::new();
+
+            if inode.flags() & INODE_EXTENTS_FL != 0 {
+                let (header, _) = ExtentHeader::read_from_prefix(&inode.fields.block)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                if header.depth.get() > MAX_EXTENT_DEPTH {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                self.collect_extents(
+                    &inode,
+                    &inode.fields.block,
+                    header.depth.get(),
+                    &mut extents,
+                )?;
+                extents.sort_by_key(|extent| extent.logical);
+            } else {
+                let end = inode.size().div_ceil(self.block_size);
+                let pointers: Vec<u64> = inode
+                    .fields
+                    .block
+                    .chunks_exact(4)
+                    .map(|pointer| u32::from_le_bytes(pointer.try_into().unwrap()) as u64)
+                    .collect();
+
+                let mut logical = 0;
+                for &pointer in &pointers[..DIRECT_BLOCKS] {
+                    if logical < end {
+                        push_extent(&mut extents, logical, pointer);
+                    }
+                    logical += 1;
+                }
+                for (level, &pointer) in pointers[DIRECT_BLOCKS..].iter().enumerate() {
+                    if logical < end {
+                        self.collect_indirect(
+                            pointer,
+                            level as u32,
+                            &mut logical,
+                            end,
+                            &mut extents,
+                        )?;
+                    }
+                }
+            }
+
+            self.extents.insert(number, extents);
+        }
+
+        return Ok(&self.extents[&number]);
+    }
+
+    fn read_inline(
+        &mut self,
+        number: u32,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let inode = self.inode(number)?;
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let mut data = inode.fields.block.to_vec();
+        data.extend_from_slice(inode.inline_xattr()?);
+
+        let end = (offset + buffer.len() as u64).min(size) as usize;
+        let available = data
+            .get(offset as usize..end)
+            .ok_or(FilesystemError::Corrupted)?;
+        buffer[..available.len()].copy_from_slice(available);
+
+        return Ok(available.len());
+    }
+
+    fn read_data(
+        &mut self,
+        number: u32,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let inode = self.inode(number)?;
+        let size = inode.size();
+
+        if inode.has_inline_data() {
+            return self.read_inline(number, offset, buffer);
+        }
+        if inode.file_type() == FileType::Symlink
+            && inode.flags() & INODE_EXTENTS_FL == 0
+            && size < INODE_BLOCK_SIZE as u64
+        {
+            // Fast symlinks store the target in place of the block map.
+            return self.read_inline(number, offset, buffer);
+        }
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length = buffer.len().min((size - offset) as usize);
+        let block_size = self.block_size;
+        let extents = self.extents(number)?;
+
+        let mut reads = Vec::new();
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let logical = position / block_size;
+            let remaining = length - done;
+
+            let index = extents.partition_point(|extent| extent.logical + extent.length <= logical);
+            let count = match extents.get(index) {
+                Some(extent) if extent.logical <= logical => {
+                    let end = (extent.logical + extent.length) * block_size;
+                    let count = ((end - position) as usize).min(remaining);
+                    if !extent.uninitialized {
+                        let physical = (extent.physical + logical - extent.logical) * block_size
+                            + position % block_size;
+                        reads.push((physical, done, count));
+                    } else {
+                        buffer[done..done + count].fill(0);
+                    }
+                    count
+                }
+                // Holes read as zeros.
+                next => {
+                    let end = next.map_or(u64::MAX, |extent| extent.logical * block_size);
+                    let count = (end - position).min(remaining as u64) as usize;
+                    buffer[done..done + count].fill(0);
+                    count
+                }
+            };
+            done += count;
+        }
+
+        for (physical, start, count) in reads {
+            self.device
+                .read_bytes(physical, &mut buffer[start..start + count])?;
+        }
+
+        return Ok(length);
+    }
+
+    /// Verify the checksum tail of a linear directory block, blocks without one are htree nodes.
+    fn verify_dir_block(&self, inode: &Inode, block: &[u8]) -> Result<(), RrubError> {
+        if self.csum_seed.is_none() || block.len() < DIR_ENTRY_TAIL_SIZE {
+            return Ok(());
+        }
+
+        let tail = &block[block.len() - DIR_ENTRY_TAIL_SIZE..];
+        let is_tail = tail[..4] == [0u8; 4]
+            && u16::from_le_bytes([tail[4], tail[5]]) as usize == DIR_ENTRY_TAIL_SIZE
+            && tail[6] == 0
+            && tail[7] == DIR_ENTRY_TAIL_TYPE;
+        if !is_tail {
+            return Ok(());
+        }
+
+        let crc = crc32c_update(
+            self.inode_seed(inode),
+            &block[..block.len() - DIR_ENTRY_TAIL_SIZE],
+        );
+        if crc.to_le_bytes() != tail[8..12] {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        return Ok(());
+    }
+
+    fn parse_dir_entries(
+        &self,
+        data: &[u8],
+        entries: &mut Vec<(Vec<u8>, u32, u8)>,
+    ) -> Result<(), RrubError> {
+        let mut offset = 0;
+
+        while offset + size_of::<DirEntryHeader>() <= data.len() {
+            let (header, rest) = DirEntryHeader::read_from_prefix(&data[offset..])
+                .map_err(|_| FilesystemError::Corrupted)?;
+
+            let rec_len = match header.rec_len.get() {
+                // 64KiB blocks can't store their length in 16 bits.
+                0 | 0xFFFF if self.block_size == 65536 => 65536,
+                len => len as usize,
+            };
+            if rec_len < size_of::<DirEntryHeader>() || offset + rec_len > data.len() {
+                return Err(FilesystemError::Corrupted.into());
+            }
+
+            let name = rest
+                .get(..header.name_len as usize)
+                .ok_or(FilesystemError::Corrupted)?;
+            if header.inode.get() != 0 && name != b"." && name != b".." {
+                entries.push((name.to_vec(), header.inode.get(), header.file_type));
+            }
+
+            offset += rec_len;
+        }
+
+        return Ok(());
+    }
+
+    fn dir_entries(&mut self, number: u32) -> Result<Vec<(Vec<u8>, u32, u8)>, RrubError> {
+        let inode = self.inode(number)?.clone();
+        if inode.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        let mut entries = Vec::new();
+
+        if inode.has_inline_data() {
+            // The first four bytes of inline directories hold the parent inode.
+            self.parse_dir_entries(&inode.fields.block[4..], &mut entries)?;
+            self.parse_dir_entries(inode.inline_xattr()?, &mut entries)?;
+            return Ok(entries);
+        }
+
+        let mut block = vec![0u8; self.block_size as usize];
+        for index in 0..inode.size().div_ceil(self.block_size) {
+            self.read_data(number, index * self.block_size, &mut block)?;
+            self.verify_dir_block(&inode, &block)?;
+            self.parse_dir_entries(&block, &mut entries)?;
+        }
+
+        return Ok(entries);
+    }
+
+    /// Find the leaf block that would hold `name` through the htree index.
+    fn htree_leaf(&mut self, inode: &Inode, name: &[u8]) -> Result<Option<u64>, RrubError> {
+        let mut block = vec![0u8; self.block_size as usize];
+        self.read_data(inode.number, 0, &mut block)?;
+
+        // dx_root: "." and ".." entries, then the root info
+        let mut hash_version = block[0x1C];
+        let info_length = block[0x1D] as usize;
+        let levels = block[0x1E];
+        if hash_version <= DX_HASH_TEA && self.superblock.flags.get() & FLAGS_UNSIGNED_HASH != 0 {
+            hash_version += 3;
+        }
+
+        let seed = self.superblock.hash_seed.map(|word| word.get());
+        let Some(hash) = dx_hash(name, hash_version, &seed) else {
+            return Ok(None);
+        };
+
+        let mut count_offset = 0x18 + info_length;
+        for level in 0..=levels {
+            let limit = u16::from_le_bytes([block[count_offset], block[count_offset + 1]]) as usize;
+            let count =
+                u16::from_le_bytes([block[count_offset + 2], block[count_offset + 3]]) as usize;
+            if count == 0 || count > limit || count_offset + limit * 8 > block.len() {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            self.verify_dx_block(inode, &block, count_offset, count, limit)?;
+
+            let entry = |i: usize| -> (u32, u32) {
+                let at = count_offset + i * 8;
+                let hash = u32::from_le_bytes(block[at..at + 4].try_into().unwrap());
+                let child = u32::from_le_bytes(block[at + 4..at + 8].try_into().unwrap());
+                return (hash, child);
+            };
+
+            // First entry has no hash and covers everything below the second.
+            let index = (1..count).rev().find(|&i| entry(i).0 <= hash).unwrap_or(0);
+            let child = entry(index).1 as u64;
+
+            // A following block starting with the same hash means the name may continue there.
+            if let Some(next) = (index + 1 < count).then(|| entry(index + 1).0)
+                && next & !1 == hash
+            {
+                return Ok(None);
+            }
+            if index + 1 == count && level < levels {
+                return Ok(None);
+            }
+
+            self.read_data(inode.number, child * self.block_size, &mut block)?;
+            if level == levels {
+                return Ok(Some(child));
+            }
+            // dx_node: a fake empty entry covering the block
+            count_offset = 8;
+        }
+
+        return Ok(None);
+    }
+
+    fn verify_dx_block(
+        &self,
+        inode: &Inode,
+        block: &[u8],
+        count_offset: usize,
+        count: usize,
+        limit: usize,
+    ) -> Result<(), RrubError> {
+        let tail = count_offset + limit * 8;
+        if self.csum_seed.is_none() || tail + 8 > block.len() {
+            return Ok(());
+        }
+
+        let mut crc = crc32c_update(self.inode_seed(inode), &block[..count_offset + count * 8]);
+        crc = crc32c_update(crc, &block[tail..tail + 4]);
+        crc = crc32c_update(crc, &[0; 4]);
+        if crc.to_le_bytes() != block[tail + 4..tail + 8] {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        return Ok(());
+    }
+}
+
+fn push_extent(extents: &mut Vec<Extent>, logical: u64, physical: u64) {
+    if physical == 0 {
+        return;
+    }
+
+    if let Some(last) = extents.last_mut()
+        && last.logical + last.length == logical
+        && last.physical + last.length == physical
+    {
+        last.length += 1;
+        return;
+    }
+
+    extents.push(Extent {
+        logical,
+        physical,
+        length: 1,
+        uninitialized: false,
+    });
+}
+
+/*
+ * Directory index hashes, ported from fs/ext4/hash.c
+*/
+
+const TEA_DELTA: u32 = 0x9E37_79B9;
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32]) {
+    let (mut b0, mut b1) = (buf[0], buf[1]);
+    let (a, b, c, d) = (input[0], input[1], input[2], input[3]);
+    let mut sum = 0u32;
+
+    for _ in 0..16 {
+        sum = sum.wrapping_add(TEA_DELTA);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32]) {
+    const K2: u32 = 0o13240474631;
+    const K3: u32 = 0o15666365641;
+
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let [mut a, mut b, mut c, mut d] = *buf;
+    macro_rules! round {
+        ($f:expr, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a
+                .wrapping_add($f($b, $c, $d))
+                .wrapping_add($x)
+                .rotate_left($s)
+        };
+    }
+
+    round!(f, a, b, c, d, input[0], 3);
+    round!(f, d, a, b, c, input[1], 7);
+    round!(f, c, d, a, b, input[2], 11);
+    round!(f, b, c, d, a, input[3], 19);
+    round!(f, a, b, c, d, input[4], 3);
+    round!(f, d, a, b, c, input[5], 7);
+    round!(f, c, d, a, b, input[6], 11);
+    round!(f, b, c, d, a, input[7], 19);
+
+    round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+/// Sign or zero extend a name byte depending on the hash variant.
+fn hash_char(c: u8, unsigned: bool) -> u32 {
+    return match unsigned {
+        true => c as u32,
+        false => c as i8 as i32 as u32,
+    };
+}
+
+fn legacy_hash(name: &[u8], unsigned: bool) -> u32 {
+    let (mut hash0, mut hash1) = (0x12A3_FE2Du32, 0x37AB_E8F9u32);
+
+    for &c in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ hash_char(c, unsigned).wrapping_mul(7_152_373));
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7FFF_FFFF);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+
+    return hash0 << 1;
+}
+
+fn str_to_hash_buf(name: &[u8], words: usize, unsigned: bool) -> [u32; 8] {
+    let len = name.len() as u32;
+    let mut pad = len | (len << 8);
+    pad |= pad << 16;
+
+    let mut buf = [pad; 8];
+    let mut value = pad;
+    let mut word = 0;
+
+    for (i, &c) in name.iter().take(words * 4).enumerate() {
+        value = hash_char(c, unsigned).wrapping_add(value << 8);
+        if i % 4 == 3 {
+            buf[word] = value;
+            value = pad;
+            word += 1;
+        }
+    }
+    if word < words {
+        buf[word] = value;
+    }
+
+    return buf;
+}
+
+fn dx_hash(name: &[u8], version: u8, seed: &[u32; 4]) -> Option<u32> {
+    let mut buf = match seed.iter().any(|&word| word != 0) {
+        true => *seed,
+        false => [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476],
+    };
+
+    let hash = match version {
+        DX_HASH_LEGACY | DX_HASH_LEGACY_UNSIGNED => {
+            legacy_hash(name, version == DX_HASH_LEGACY_UNSIGNED)
+        }
+        DX_HASH_HALF_MD4 | DX_HASH_HALF_MD4_UNSIGNED => {
+            for chunk in name.chunks(32) {
+                let input = str_to_hash_buf(chunk, 8, version == DX_HASH_HALF_MD4_UNSIGNED);
+                half_md4_transform(&mut buf, &input);
+            }
+            buf[1]
+        }
+        DX_HASH_TEA | DX_HASH_TEA_UNSIGNED => {
+            for chunk in name.chunks(16) {
+                let input = str_to_hash_buf(chunk, 4, version == DX_HASH_TEA_UNSIGNED);
+                tea_transform(&mut buf, &input);
+            }
+            buf[0]
+        }
+        _ => return None,
+    };
+
+    let hash = hash & !1;
+    return match hash == 0x7FFF_FFFF << 1 {
+        true => Some((0x7FFF_FFFF - 1) << 1),
+        false => Some(hash),
+    };
+}
+
+impl FilesystemBackend for Ext4Filesystem {
+    fn fs_type(&self) -> &'static str {
+        return "ext4";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::RealUuid(RealUuid::from_bytes(self.superblock.uuid));
+    }
+
+    fn label(&self) -> Option<String> {
+        return label_from_bytes(&self.superblock.volume_name);
+    }
+
+    fn root(&self) -> NodeId {
+        return NodeId(ROOT_INODE as u64);
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let inode = self.inode(node_number(node)?)?;
+        return Ok(Metadata {
+            file_type: inode.file_type(),
+            size: inode.size(),
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let entries = self.dir_entries(node_number(dir)?)?;
+        let mut result = Vec::with_capacity(entries.len());
+
+        for (name, number, _) in entries {
+            let file_type = self.inode(number)?.file_type();
+            result.push(DirEntry {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                node: NodeId(number as u64),
+                file_type,
+            });
+        }
+
+        return Ok(result);
+    }
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        let number = node_number(dir)?;
+        let inode = self.inode(number)?.clone();
+        if inode.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        if inode.flags() & INODE_INDEX_FL != 0
+            && !inode.has_inline_data()
+            && let Some(leaf) = self.htree_leaf(&inode, name.as_bytes())?
+        {
+            let mut block = vec![0u8; self.block_size as usize];
+            self.read_data(number, leaf * self.block_size, &mut block)?;
+            self.verify_dir_block(&inode, &block)?;
+
+            let mut entries = Vec::new();
+            self.parse_dir_entries(&block, &mut entries)?;
+            return entries
+                .into_iter()
+                .find(|(entry, _, _)| entry == name.as_bytes())
+                .map(|(_, number, _)| NodeId(number as u64))
+                .ok_or(FilesystemError::NotFound.into());
+        }
+
+        return self
+            .dir_entries(number)?
+            .into_iter()
+            .find(|(entry, _, _)| entry == name.as_bytes())
+            .map(|(_, number, _)| NodeId(number as u64))
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        let number = node_number(node)?;
+        if self.inode(number)?.file_type() == FileType::Directory {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+        return self.read_data(number, offset, buffer);
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        let number = node_number(node)?;
+        let inode = self.inode(number)?;
+        if inode.file_type() != FileType::Symlink {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let mut target = vec![0u8; inode.size() as usize];
+        let read = self.read_data(number, 0, &mut target)?;
+        target.truncate(read);
+
+        return String::from_utf8(target).map_err(|_| FilesystemError::Corrupted.into());
+    }
+}
+
+fn node_number(node: NodeId) -> Result<u32, RrubError> {
+    return u32::try_from(node.0).map_err(|_| FilesystemError::NotFound.into());
+}
@@ -0,0 +1,1398 @@
+/*
+ * https://academy.cba.mit.edu/classes/networking_communications/SD/FAT.pdf
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+            VolumeId32,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo},
+};
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+const LAST_LONG_ENTRY: u8 = 0x40;
+const DELETED_ENTRY: u8 = 0xE5;
+const NT_LOWERCASE_BASE: u8 = 0x08;
+const NT_LOWERCASE_EXT: u8 = 0x10;
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ROOT_NODE: NodeId = NodeId(0);
+
+/// 1980-01-01, the FAT epoch. rrub has no clock to stamp files with.
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_FREE_COUNT: u64 = 488;
+const FS_INFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+const MAX_LONG_NAME: usize = 255;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BiosParameterBlock {
+    pub jump: [u8; 3],
+    pub oem_name: [u8; 8],
+    pub bytes_per_sector: U16<LittleEndian>,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: U16<LittleEndian>,
+    pub fat_count: u8,
+    pub root_entry_count: U16<LittleEndian>,
+    pub total_sectors_16: U16<LittleEndian>,
+    pub media: u8,
+    pub sectors_per_fat_16: U16<LittleEndian>,
+    pub sectors_per_track: U16<LittleEndian>,
+    pub head_count: U16<LittleEndian>,
+    pub hidden_sectors: U32<LittleEndian>,
+    pub total_sectors_32: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtendedBpb16 {
+    pub drive_number: u8,
+    pub _reserved: u8,
+    pub boot_signature: u8,
+    pub volume_id: U32<LittleEndian>,
+    pub volume_label: [u8; 11],
+    pub fs_type: [u8; 8],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtendedBpb32 {
+    pub sectors_per_fat_32: U32<LittleEndian>,
+    pub ext_flags: U16<LittleEndian>,
+    pub fs_version: U16<LittleEndian>,
+    pub root_cluster: U32<LittleEndian>,
+    pub fs_info_sector: U16<LittleEndian>,
+    pub backup_boot_sector: U16<LittleEndian>,
+    pub _reserved: [u8; 12],
+    pub drive_number: u8,
+    pub _reserved1: u8,
+    pub boot_signature: u8,
+    pub volume_id: U32<LittleEndian>,
+    pub volume_label: [u8; 11],
+    pub fs_type: [u8; 8],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirectoryEntry {
+    pub name: [u8; 11],
+    pub attr: u8,
+    pub nt_reserved: u8,
+    pub create_time_tenth: u8,
+    pub create_time: U16<LittleEndian>,
+    pub create_date: U16<LittleEndian>,
+    pub access_date: U16<LittleEndian>,
+    pub cluster_high: U16<LittleEndian>,
+    pub write_time: U16<LittleEndian>,
+    pub write_date: U16<LittleEndian>,
+    pub cluster_low: U16<LittleEndian>,
+    pub size: U32<LittleEndian>,
+}
+
+impl DirectoryEntry {
+    pub fn first_cluster(&self) -> u32 {
+        return ((self.cluster_high.get() as u32) << 16) | self.cluster_low.get() as u32;
+    }
+
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        self.cluster_high = U16::new((cluster >> 16) as u16);
+        self.cluster_low = U16::new(cluster as u16);
+    }
+
+    pub fn is_directory(&self) -> bool {
+        return self.attr & ATTR_DIRECTORY != 0;
+    }
+
+    /// Checksum of the 8.3 name stored in every long name entry belonging to it.
+    pub fn name_checksum(&self) -> u8 {
+        return self
+            .name
+            .iter()
+            .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c));
+    }
+
+    pub fn short_name(&self) -> String {
+        let mut base = self.name[..8].to_vec();
+        if base[0] == 0x05 {
+            base[0] = DELETED_ENTRY;
+        }
+
+        let mut name = decode_oem(&base, self.nt_reserved & NT_LOWERCASE_BASE != 0);
+        let ext = decode_oem(&self.name[8..], self.nt_reserved & NT_LOWERCASE_EXT != 0);
+        if !ext.is_empty() {
+            name.push('.');
+            name.push_str(&ext);
+        }
+
+        return name;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct LongNameEntry {
+    pub order: u8,
+    pub name1: [U16<LittleEndian>; 5],
+    pub attr: u8,
+    pub entry_type: u8,
+    pub checksum: u8,
+    pub name2: [U16<LittleEndian>; 6],
+    pub cluster_low: U16<LittleEndian>,
+    pub name3: [U16<LittleEndian>; 2],
+}
+
+impl LongNameEntry {
+    pub fn new(order: u8, checksum: u8, chars: &[u16; 13]) -> LongNameEntry {
+        let mut entry = LongNameEntry {
+            order,
+            name1: [U16::ZERO; 5],
+            attr: ATTR_LONG_NAME,
+            entry_type: 0,
+            checksum,
+            name2: [U16::ZERO; 6],
+            cluster_low: U16::ZERO,
+            name3: [U16::ZERO; 2],
+        };
+
+        let all = entry
+            .name1
+            .iter_mut()
+            .chain(&mut entry.name2)
+            .chain(&mut entry.name3);
+        for (dst, &src) in all.zip(chars) {
+            *dst = U16::new(src);
+        }
+
+        return entry;
+    }
+
+    pub fn chars(&self) -> [u16; 13] {
+        let mut chars = [0u16; 13];
+        let all = self.name1.iter().chain(&self.name2).chain(&self.name3);
+        for (dst, src) in chars.iter_mut().zip(all) {
+            *dst = src.get();
+        }
+        return chars;
+    }
+}
+
+/// Bytes above 0x7F are OEM code page specific, they are mapped as Latin-1 as a best effort.
+fn decode_oem(bytes: &[u8], lowercase: bool) -> String {
+    let trimmed = match bytes.iter().rposition(|&b| b != b' ') {
+        Some(end) => &bytes[..=end],
+        None => &[],
+    };
+
+    return trimmed
+        .iter()
+        .map(|&b| match lowercase {
+            true => b.to_ascii_lowercase() as char,
+            false => b as char,
+        })
+        .collect();
+}
+
+fn is_short_name_char(c: u8) -> bool {
+    return c.is_ascii_uppercase() || c.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&c);
+}
+
+fn is_valid_long_name(name: &str) -> bool {
+    return !matches!(name, "" | "." | "..")
+        && name.encode_utf16().count() <= MAX_LONG_NAME
+        && !name.ends_with(['.', ' '])
+        && !name
+            .chars()
+            .any(|c| c < ' ' || matches!(c, '"' | '*' | '/' | ':' | '<' | '>' | '?' | '\\' | '|'));
+}
+
+/// Use `name` directly as an 8.3 name if it is one, in which case no long name is needed.
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(is_short_name_char)
+    {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+
+    return Some(short);
+}
+
+/// Generate a unique "BASE~N.EXT" name for a long name.
+fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> Result<[u8; 11], RrubError> {
+    let convert = |part: &str| -> Vec<u8> {
+        return part
+            .chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| match c.to_ascii_uppercase() {
+                c if c.is_ascii() && is_short_name_char(c as u8) => c as u8,
+                _ => b'_',
+            })
+            .collect();
+    };
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = trimmed.rsplit_once('.').unwrap_or((trimmed, ""));
+    let base = convert(base);
+    let ext = convert(ext);
+
+    for number in 1..1_000_000u32 {
+        let tail = alloc::format!("~{}", number);
+        let keep = base.len().min(8 - tail.len());
+
+        let mut short = [b' '; 11];
+        short[..keep].copy_from_slice(&base[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        let ext_len = ext.len().min(3);
+        short[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+
+        if !existing.contains(&short) {
+            return Ok(short);
+        }
+    }
+
+    return Err(FilesystemError::NoSpace.into());
+}
+
+/// Build the long name entries followed by the short entry for a directory entry set.
+fn entry_set(name: &str, short: &DirectoryEntry, long_name: bool) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut set = Vec::new();
+
+    if long_name {
+        let mut units: Vec<u16> = name.encode_utf16().collect();
+        if !units.len().is_multiple_of(13) {
+            units.push(0x0000);
+        }
+        while !units.len().is_multiple_of(13) {
+            units.push(0xFFFF);
+        }
+
+        let checksum = short.name_checksum();
+        let parts: Vec<&[u16]> = units.chunks(13).collect();
+        for (index, part) in parts.iter().enumerate().rev() {
+            let mut order = index as u8 + 1;
+            if index == parts.len() - 1 {
+                order |= LAST_LONG_ENTRY;
+            }
+
+            let chars: &[u16; 13] = (*part)
+                .try_into()
+                .expect("Long name part not 13 characters");
+            let mut raw = [0u8; DIR_ENTRY_SIZE];
+            raw.copy_from_slice(LongNameEntry::new(order, checksum, chars).as_bytes());
+            set.push(raw);
+        }
+    }
+
+    let mut raw = [0u8; DIR_ENTRY_SIZE];
+    raw.copy_from_slice(short.as_bytes());
+    set.push(raw);
+
+    return set;
+}
+
+/// FAT names are compared case insensitively.
+fn names_equal(a: &str, b: &str) -> bool {
+    return a
+        .chars()
+        .flat_map(char::to_uppercase)
+        .eq(b.chars().flat_map(char::to_uppercase));
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// Sizes of the regions of a volume, in sectors.
+struct Layout {
+    fat_type: FatType,
+    total_sectors: u64,
+    fat_sectors: u64,
+    root_dir_sectors: u64,
+    /// Everything before the first cluster: reserved sectors, FATs and the FAT12/16 root
+    /// directory.
+    meta_sectors: u64,
+    cluster_count: u32,
+}
+
+impl Layout {
+    /// The FAT type follows from the cluster count alone, as the specification has it.
+    fn new(bpb: &BiosParameterBlock, ext32: &ExtendedBpb32) -> Result<Layout, RrubError> {
+        if bpb.bytes_per_sector.get() == 0 || bpb.sectors_per_cluster == 0 {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        let total_sectors = match bpb.total_sectors_16.get() {
+            0 => bpb.total_sectors_32.get() as u64,
+            sectors => sectors as u64,
+        };
+        let fat_sectors = match bpb.sectors_per_fat_16.get() {
+            0 => ext32.sectors_per_fat_32.get() as u64,
+            sectors => sectors as u64,
+        };
+        let root_dir_sectors = (bpb.root_entry_count.get() as u64 * DIR_ENTRY_SIZE as u64)
+            .div_ceil(bpb.bytes_per_sector.get() as u64);
+
+        let meta_sectors = bpb.reserved_sectors.get() as u64
+            + bpb.fat_count as u64 * fat_sectors
+            + root_dir_sectors;
+        let data_sectors = total_sectors
+            .checked_sub(meta_sectors)
+            .ok_or(FilesystemError::Corrupted)?;
+        let cluster_count = u32::try_from(data_sectors / bpb.sectors_per_cluster as u64)
+            .map_err(|_| FilesystemError::Corrupted)?;
+
+        let fat_type = match cluster_count {
+            0..4085 => FatType::Fat12,
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+
+        return Ok(Layout {
+            fat_type,
+            total_sectors,
+            fat_sectors,
+            root_dir_sectors,
+            meta_sectors,
+            cluster_count,
+        });
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct FatNode {
+    first_cluster: u32,
+    size: u32,
+    is_dir: bool,
+}
+
+struct RawDirEntry {
+    entry: DirEntry,
+    short_name: String,
+    short: DirectoryEntry,
+    /// Volume offsets of every slot used by the entry, long name entries first.
+    slots: Vec<u64>,
+}
+
+/// Long name entries collected while scanning a directory, in the order found on disk.
+struct LongName {
+    checksum: u8,
+    remaining: u8,
+    parts: Vec<[u16; 13]>,
+    slots: Vec<u64>,
+}
+
+pub struct FatFilesystem {
+    device: Box<dyn BlockDevice>,
+    fat_type: FatType,
+    sector_size: u64,
+    cluster_size: u64,
+    cluster_count: u32,
+    /// Byte offset of the FAT used for reads.
+    fat_start: u64,
+    /// Byte offsets of every FAT updated on writes, all of them unless FAT32 mirroring is off.
+    fat_copies: Vec<u64>,
+    fs_info: Option<u64>,
+    fs_info_dirty: bool,
+    next_free: u32,
+    writable: bool,
+    /// Byte offset and length of the fixed root directory of FAT12/16.
+    root_dir: (u64, u64),
+    root_cluster: u32,
+    /// Byte offset of cluster 2.
+    data_start: u64,
+    volume_id: VolumeId32,
+    label: Option<String>,
+    nodes: BTreeMap<NodeId, FatNode>,
+    chains: BTreeMap<u32, Vec<u32>>,
+    fat_cache: Option<(u64, Vec<u8>)>,
+}
+
+impl FatFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut boot_sector = [0u8; 512];
+        return device.read_bytes(0, &mut boot_sector).is_ok()
+            && boot_sector[510..] == [0x55, 0xAA]
+            && matches!(boot_sector[0], 0xEB | 0xE9)
+            && BiosParameterBlock::read_from_prefix(&boot_sector)
+                .is_ok_and(|(bpb, _)| bpb.bytes_per_sector.get() != 0 && bpb.fat_count != 0);
+    }
+
+    /// The label is searched for in the first root directory cluster only.
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        if !Self::probe(device) {
+            return None;
+        }
+
+        let mut boot_sector = [0u8; 512];
+        device.read_bytes(0, &mut boot_sector).ok()?;
+        let (bpb, ext) = BiosParameterBlock::read_from_prefix(&boot_sector).ok()?;
+        let (ext16, _) = ExtendedBpb16::read_from_prefix(ext).ok()?;
+        let (ext32, _) = ExtendedBpb32::read_from_prefix(ext).ok()?;
+
+        let layout = Layout::new(&bpb, &ext32).ok()?;
+        let sector_size = bpb.bytes_per_sector.get() as u64;
+        let fats_end = (bpb.reserved_sectors.get() as u64
+            + bpb.fat_count as u64 * layout.fat_sectors)
+            * sector_size;
+
+        let (volume_id, bpb_label, root_region) = match layout.fat_type {
+            FatType::Fat32 => {
+                let cluster_size = bpb.sectors_per_cluster as u64 * sector_size;
+                let root = (ext32.root_cluster.get() as u64)
+                    .checked_sub(2)
+                    .map(|index| layout.meta_sectors * sector_size + index * cluster_size);
+                (
+                    ext32.volume_id,
+                    ext32.volume_label,
+                    root.map(|start| (start, cluster_size)),
+                )
+            }
+            FatType::Fat12 | FatType::Fat16 => (
+                ext16.volume_id,
+                ext16.volume_label,
+                Some((fats_end, layout.root_dir_sectors * sector_size)),
+            ),
+        };
+
+        let mut root = vec![0u8; root_region.map_or(0, |(_, length)| length.min(1 << 20)) as usize];
+        let label = match root_region {
+            Some((start, _)) if device.read_bytes(start, &mut root).is_ok() => root
+                .chunks_exact(DIR_ENTRY_SIZE)
+                .filter_map(|raw| DirectoryEntry::read_from_bytes(raw).ok())
+                .take_while(|entry| entry.name[0] != 0x00)
+                .find(|entry| {
+                    entry.name[0] != DELETED_ENTRY && entry.attr & ATTR_LONG_NAME == ATTR_VOLUME_ID
+                })
+                .map(|entry| decode_oem(&entry.name, false)),
+            _ => None,
+        }
+        .or_else(|| Some(decode_oem(&bpb_label, false)))
+        .filter(|label| !label.is_empty() && label != "NO NAME");
+
+        return Some(VolumeInfo {
+            fs_type: "vfat",
+            usage: Usage::Filesystem,
+            label,
+            uuid: Some(Uuid::VolumeId32(VolumeId32::from_u32_le(volume_id.get()))),
+            size: layout.total_sectors * sector_size,
+        });
+    }
+
+    /// Writes go straight to the block device, which has to keep the firmware's own FAT driver off
+    /// the volume until they're flushed by `sync`.
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<FatFilesystem, RrubError> {
+        let mut boot_sector = [0u8; 512];
+        device.read_bytes(0, &mut boot_sector)?;
+
+        if boot_sector[510..] != [0x55, 0xAA] || !matches!(boot_sector[0], 0xEB | 0xE9) {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        let (bpb, ext) = BiosParameterBlock::read_from_prefix(&boot_sector)
+            .map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        let sector_size = bpb.bytes_per_sector.get() as u64;
+        let sectors_per_cluster = bpb.sectors_per_cluster as u64;
+        if !matches!(sector_size, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || bpb.reserved_sectors.get() == 0
+            || bpb.fat_count == 0
+        {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        let (ext16, _) =
+            ExtendedBpb16::read_from_prefix(ext).map_err(|_| FilesystemError::UnknownFilesystem)?;
+        let (ext32, _) =
+            ExtendedBpb32::read_from_prefix(ext).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        let Layout {
+            fat_type,
+            fat_sectors,
+            root_dir_sectors,
+            meta_sectors,
+            cluster_count,
+            ..
+        } = Layout::new(&bpb, &ext32)?;
+        let reserved = bpb.reserved_sectors.get() as u64;
+
+        // FAT32 may disable mirroring and pick any FAT as the active one.
+        let mirrored = fat_type != FatType::Fat32 || ext32.ext_flags.get() & 0x80 == 0;
+        let active_fat = match mirrored {
+            true => 0,
+            false => (ext32.ext_flags.get() & 0x0F) as u64,
+        };
+        let fat_copies = (0..bpb.fat_count as u64)
+            .filter(|&fat| mirrored || fat == active_fat)
+            .map(|fat| (reserved + fat * fat_sectors) * sector_size)
+            .collect();
+
+        let fs_info = match fat_type {
+            FatType::Fat32 => Some(ext32.fs_info_sector.get() as u64 * sector_size)
+                .filter(|&offset| offset != 0 && offset < reserved * sector_size),
+            _ => None,
+        };
+
+        let (volume_id, bpb_label) = match fat_type {
+            FatType::Fat32 => (ext32.volume_id.get(), ext32.volume_label),
+            _ => (ext16.volume_id.get(), ext16.volume_label),
+        };
+
+        let mut fs = FatFilesystem {
+            device,
+            fat_type,
+            sector_size,
+            cluster_size: sector_size * sectors_per_cluster,
+            cluster_count,
+            fat_start: (reserved + active_fat * fat_sectors) * sector_size,
+            fat_copies,
+            fs_info: None,
+            fs_info_dirty: false,
+            next_free: 2,
+            writable: false,
+            root_dir: (
+                (reserved + bpb.fat_count as u64 * fat_sectors) * sector_size,
+                root_dir_sectors * sector_size,
+            ),
+            root_cluster: ext32.root_cluster.get(),
+            data_start: meta_sectors * sector_size,
+            volume_id: VolumeId32::from_u32_le(volume_id),
+            label: None,
+            nodes: BTreeMap::new(),
+            chains: BTreeMap::new(),
+            fat_cache: None,
+        };
+        fs.writable = !fs.device.is_read_only();
+
+        let root = FatNode {
+            first_cluster: match fat_type {
+                FatType::Fat32 => fs.root_cluster,
+                _ => 0,
+            },
+            size: 0,
+            is_dir: true,
+        };
+        fs.nodes.insert(ROOT_NODE, root);
+
+        fs.label = match fs.find_volume_label()? {
+            Some(label) => Some(label),
+            None => Some(decode_oem(&bpb_label, false)),
+        }
+        .filter(|label| !label.is_empty() && label != "NO NAME");
+
+        if let Some(offset) = fs_info {
+            let mut raw = [0u8; 512];
+            fs.device.read_bytes(offset, &mut raw)?;
+
+            let field = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+            if field(0) == FS_INFO_LEAD_SIGNATURE && field(484) == FS_INFO_STRUCT_SIGNATURE {
+                fs.fs_info = Some(offset);
+
+                let hint = field(492);
+                if (2..cluster_count + 2).contains(&hint) {
+                    fs.next_free = hint;
+                }
+            }
+        }
+
+        return Ok(fs);
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        return self.fat_type;
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        return self.data_start + (cluster as u64 - 2) * self.cluster_size;
+    }
+
+    fn is_end_of_chain(&self, entry: u32) -> bool {
+        return match self.fat_type {
+            FatType::Fat12 => entry >= 0xFF8,
+            FatType::Fat16 => entry >= 0xFFF8,
+            FatType::Fat32 => entry >= 0x0FFF_FFF8,
+        };
+    }
+
+    fn read_fat_byte(&mut self, offset: u64) -> Result<u8, RrubError> {
+        let sector = offset / self.sector_size;
+        let index = (offset % self.sector_size) as usize;
+
+        if let Some((cached, data)) = &self.fat_cache
+            && *cached == sector
+        {
+            return Ok(data[index]);
+        }
+
+        let mut data = vec![0u8; self.sector_size as usize];
+        self.device
+            .read_bytes(self.fat_start + sector * self.sector_size, &mut data)?;
+        let byte = data[index];
+        self.fat_cache = Some((sector, data));
+
+        return Ok(byte);
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, RrubError> {
+        let (offset, width) = match self.fat_type {
+            FatType::Fat12 => (cluster as u64 + cluster as u64 / 2, 2),
+            FatType::Fat16 => (cluster as u64 * 2, 2),
+            FatType::Fat32 => (cluster as u64 * 4, 4),
+        };
+
+        let mut value = 0u32;
+        for i in 0..width {
+            value |= (self.read_fat_byte(offset + i)? as u32) << (i * 8);
+        }
+
+        return Ok(match self.fat_type {
+            FatType::Fat12 if cluster & 1 == 1 => value >> 4,
+            FatType::Fat12 => value & 0xFFF,
+            FatType::Fat16 => value,
+            FatType::Fat32 => value & 0x0FFF_FFFF,
+        });
+    }
+
+    /// Follow and cache the cluster chain starting at `first`.
+    fn chain(&mut self, first: u32) -> Result<&[u32], RrubError> {
+        if !self.chains.contains_key(&first) {
+            let mut chain = Vec::new();
+            let mut cluster = first;
+
+            while cluster != 0 {
+                if cluster < 2 || cluster >= self.cluster_count + 2 {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                if chain.len() > self.cluster_count as usize {
+                    // Loop in the FAT
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                chain.push(cluster);
+
+                let next = self.fat_entry(cluster)?;
+                if self.is_end_of_chain(next) {
+                    break;
+                }
+                cluster = next;
+            }
+
+            self.chains.insert(first, chain);
+        }
+
+        return Ok(&self.chains[&first]);
+    }
+
+    /// Byte ranges on the volume holding the contents of a directory.
+    fn dir_regions(&mut self, node: &FatNode) -> Result<Vec<(u64, u64)>, RrubError> {
+        if node.first_cluster == 0 {
+            return Ok(vec![self.root_dir]);
+        }
+
+        let cluster_size = self.cluster_size;
+        let chain = self.chain(node.first_cluster)?.to_vec();
+
+        return Ok(chain
+            .into_iter()
+            .map(|cluster| (self.cluster_offset(cluster), cluster_size))
+            .collect());
+    }
+
+    fn node(&mut self, id: NodeId) -> Result<FatNode, RrubError> {
+        if let Some(node) = self.nodes.get(&id) {
+            return Ok(*node);
+        }
+
+        // Node IDs are the volume offsets of the short directory entries.
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.device.read_bytes(id.0, &mut raw)?;
+        let entry =
+            DirectoryEntry::read_from_bytes(&raw).map_err(|_| FilesystemError::Corrupted)?;
+
+        if matches!(entry.name[0], 0x00 | DELETED_ENTRY) || entry.attr & ATTR_VOLUME_ID != 0 {
+            return Err(FilesystemError::NotFound.into());
+        }
+
+        let node = FatNode {
+            first_cluster: entry.first_cluster(),
+            size: entry.size.get(),
+            is_dir: entry.is_directory(),
+        };
+        self.nodes.insert(id, node);
+
+        return Ok(node);
+    }
+
+    fn read_entries(&mut self, dir: NodeId) -> Result<Vec<RawDirEntry>, RrubError> {
+        let node = self.node(dir)?;
+        if !node.is_dir {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        let mut entries = Vec::new();
+        let mut long_name: Option<LongName> = None;
+
+        'regions: for (start, length) in self.dir_regions(&node)? {
+            let mut data = vec![0u8; length as usize];
+            self.device.read_bytes(start, &mut data)?;
+
+            for (index, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                let entry =
+                    DirectoryEntry::read_from_bytes(raw).map_err(|_| FilesystemError::Corrupted)?;
+
+                let slot = start + (index * DIR_ENTRY_SIZE) as u64;
+
+                match entry.name[0] {
+                    0x00 => break 'regions,
+                    DELETED_ENTRY => {
+                        long_name = None;
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                if entry.attr & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                    let lfn = LongNameEntry::read_from_bytes(raw)
+                        .map_err(|_| FilesystemError::Corrupted)?;
+                    long_name = collect_long_name(long_name.take(), &lfn, slot);
+                    continue;
+                }
+
+                if entry.attr & ATTR_VOLUME_ID != 0 || entry.name[0] == b'.' {
+                    long_name = None;
+                    continue;
+                }
+
+                let short_name = entry.short_name();
+                let (name, mut slots) = match long_name.take() {
+                    Some(lfn) if lfn.remaining == 0 && lfn.checksum == entry.name_checksum() => {
+                        (decode_long_name(&lfn.parts), lfn.slots)
+                    }
+                    _ => (None, Vec::new()),
+                };
+                let name = name.unwrap_or(short_name.clone());
+                slots.push(slot);
+
+                let id = NodeId(slot);
+                let fat_node = FatNode {
+                    first_cluster: entry.first_cluster(),
+                    size: entry.size.get(),
+                    is_dir: entry.is_directory(),
+                };
+                self.nodes.insert(id, fat_node);
+
+                entries.push(RawDirEntry {
+                    entry: DirEntry {
+                        name,
+                        node: id,
+                        file_type: match fat_node.is_dir {
+                            true => FileType::Directory,
+                            false => FileType::Regular,
+                        },
+                    },
+                    short_name,
+                    short: entry,
+                    slots,
+                });
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    fn find_volume_label(&mut self) -> Result<Option<String>, RrubError> {
+        let node = self.node(ROOT_NODE)?;
+
+        for (start, length) in self.dir_regions(&node)? {
+            let mut data = vec![0u8; length as usize];
+            self.device.read_bytes(start, &mut data)?;
+
+            for raw in data.chunks_exact(DIR_ENTRY_SIZE) {
+                let entry =
+                    DirectoryEntry::read_from_bytes(raw).map_err(|_| FilesystemError::Corrupted)?;
+
+                if entry.name[0] == 0x00 {
+                    return Ok(None);
+                }
+                if entry.name[0] != DELETED_ENTRY && entry.attr & ATTR_LONG_NAME == ATTR_VOLUME_ID {
+                    return Ok(Some(decode_oem(&entry.name, false)));
+                }
+            }
+        }
+
+        return Ok(None);
+    }
+
+    fn check_writable(&self) -> Result<(), RrubError> {
+        if !self.writable {
+            return Err(FilesystemError::ReadOnly.into());
+        }
+        return Ok(());
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        return match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        };
+    }
+
+    /// Update an entry in every mirrored FAT.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), RrubError> {
+        for fat in self.fat_copies.clone() {
+            match self.fat_type {
+                FatType::Fat12 => {
+                    let offset = fat + cluster as u64 + cluster as u64 / 2;
+                    let mut raw = [0u8; 2];
+                    self.device.read_bytes(offset, &mut raw)?;
+
+                    let old = u16::from_le_bytes(raw);
+                    let new = match cluster & 1 {
+                        1 => (old & 0x000F) | ((value as u16) << 4),
+                        _ => (old & 0xF000) | (value as u16 & 0x0FFF),
+                    };
+                    self.device.write_bytes(offset, &new.to_le_bytes())?;
+                }
+                FatType::Fat16 => {
+                    self.device
+                        .write_bytes(fat + cluster as u64 * 2, &(value as u16).to_le_bytes())?;
+                }
+                FatType::Fat32 => {
+                    // The top four bits are reserved and must be preserved.
+                    let offset = fat + cluster as u64 * 4;
+                    let mut raw = [0u8; 4];
+                    self.device.read_bytes(offset, &mut raw)?;
+
+                    let new = (u32::from_le_bytes(raw) & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                    self.device.write_bytes(offset, &new.to_le_bytes())?;
+                }
+            }
+        }
+
+        self.fat_cache = None;
+        self.fs_info_dirty = true;
+
+        return Ok(());
+    }
+
+    fn allocate_cluster(&mut self) -> Result<u32, RrubError> {
+        let count = self.cluster_count;
+
+        for i in 0..count {
+            let cluster = 2 + (self.next_free - 2 + i) % count;
+            if self.fat_entry(cluster)? == 0 {
+                self.set_fat_entry(cluster, self.end_of_chain())?;
+                self.next_free = 2 + (cluster - 1) % count;
+                return Ok(cluster);
+            }
+        }
+
+        return Err(FilesystemError::NoSpace.into());
+    }
+
+    /// Read, modify and write back the short directory entry of a node.
+    fn update_entry(
+        &mut self,
+        id: NodeId,
+        update: impl FnOnce(&mut DirectoryEntry),
+    ) -> Result<(), RrubError> {
+        if id == ROOT_NODE {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.device.read_bytes(id.0, &mut raw)?;
+        let mut entry =
+            DirectoryEntry::read_from_bytes(&raw).map_err(|_| FilesystemError::Corrupted)?;
+
+        update(&mut entry);
+        self.device.write_bytes(id.0, entry.as_bytes())?;
+
+        self.nodes.insert(
+            id,
+            FatNode {
+                first_cluster: entry.first_cluster(),
+                size: entry.size.get(),
+                is_dir: entry.is_directory(),
+            },
+        );
+
+        return Ok(());
+    }
+
+    /// Grow or shrink the cluster chain of a node to `clusters` clusters.
+    fn resize_chain(&mut self, id: NodeId, clusters: usize) -> Result<(), RrubError> {
+        let node = self.node(id)?;
+        let mut chain = match node.first_cluster {
+            0 => Vec::new(),
+            first => self.chain(first)?.to_vec(),
+        };
+
+        // Clusters allocated before running out of space are still linked in and recorded.
+        let mut error = None;
+        while chain.len() < clusters {
+            match self.allocate_cluster() {
+                Ok(cluster) => {
+                    if let Some(&last) = chain.last() {
+                        self.set_fat_entry(last, cluster)?;
+                    }
+                    chain.push(cluster);
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if chain.len() > clusters {
+            for cluster in chain.split_off(clusters) {
+                self.set_fat_entry(cluster, 0)?;
+            }
+            if let Some(&last) = chain.last() {
+                self.set_fat_entry(last, self.end_of_chain())?;
+            }
+        }
+
+        self.chains.remove(&node.first_cluster);
+        let first = chain.first().copied().unwrap_or(0);
+        if first != 0 {
+            self.chains.insert(first, chain);
+        }
+        if first != node.first_cluster {
+            self.update_entry(id, |entry| entry.set_first_cluster(first))?;
+        }
+
+        return match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+    }
+
+    /// Find `count` consecutive free slots in a directory, growing it if needed.
+    fn free_slots(&mut self, dir: NodeId, count: usize) -> Result<Vec<u64>, RrubError> {
+        loop {
+            let node = self.node(dir)?;
+            let mut run = Vec::new();
+
+            for (start, length) in self.dir_regions(&node)? {
+                let mut data = vec![0u8; length as usize];
+                self.device.read_bytes(start, &mut data)?;
+
+                for (index, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                    if !matches!(raw[0], 0x00 | DELETED_ENTRY) {
+                        run.clear();
+                        continue;
+                    }
+
+                    run.push(start + (index * DIR_ENTRY_SIZE) as u64);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                }
+            }
+
+            // The FAT12/16 root directory has a fixed size.
+            if node.first_cluster == 0 {
+                return Err(FilesystemError::NoSpace.into());
+            }
+
+            let clusters = self.chain(node.first_cluster)?.len();
+            self.resize_chain(dir, clusters + 1)?;
+
+            let last = *self
+                .chain(node.first_cluster)?
+                .last()
+                .ok_or(FilesystemError::Corrupted)?;
+            let zero = vec![0u8; self.cluster_size as usize];
+            self.device.write_bytes(self.cluster_offset(last), &zero)?;
+        }
+    }
+
+    /// Write a new entry set for `name` into `dir`, returning the node of its short entry.
+    fn insert_entry(
+        &mut self,
+        dir: NodeId,
+        name: &str,
+        mut short: DirectoryEntry,
+        existing: &[RawDirEntry],
+    ) -> Result<NodeId, RrubError> {
+        let (short_name, long_name) = match exact_short_name(name) {
+            Some(short_name) => (short_name, false),
+            None => {
+                let taken: Vec<[u8; 11]> = existing.iter().map(|raw| raw.short.name).collect();
+                (generate_short_name(name, &taken)?, true)
+            }
+        };
+        short.name = short_name;
+        short.nt_reserved = 0;
+
+        let set = entry_set(name, &short, long_name);
+        let slots = self.free_slots(dir, set.len())?;
+
+        for (slot, raw) in slots.iter().zip(&set) {
+            self.device.write_bytes(*slot, raw)?;
+        }
+
+        let id = NodeId(*slots.last().ok_or(FilesystemError::Corrupted)?);
+        self.nodes.insert(
+            id,
+            FatNode {
+                first_cluster: short.first_cluster(),
+                size: short.size.get(),
+                is_dir: short.is_directory(),
+            },
+        );
+
+        return Ok(id);
+    }
+}
+
+fn collect_long_name(
+    current: Option<LongName>,
+    lfn: &LongNameEntry,
+    slot: u64,
+) -> Option<LongName> {
+    let sequence = lfn.order & 0x1F;
+
+    if lfn.order & LAST_LONG_ENTRY != 0 {
+        if sequence == 0 {
+            return None;
+        }
+        return Some(LongName {
+            checksum: lfn.checksum,
+            remaining: sequence - 1,
+            parts: vec![lfn.chars()],
+            slots: vec![slot],
+        });
+    }
+
+    match current {
+        Some(mut name) if name.remaining == sequence && name.checksum == lfn.checksum => {
+            name.remaining -= 1;
+            name.parts.push(lfn.chars());
+            name.slots.push(slot);
+            return Some(name);
+        }
+        _ => return None,
+    }
+}
+
+/// Long name parts are stored last part first.
+fn decode_long_name(parts: &[[u16; 13]]) -> Option<String> {
+    let units = parts
+        .iter()
+        .rev()
+        .flatten()
+        .copied()
+        .take_while(|&c| c != 0x0000);
+
+    return char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+        .filter(|name| !name.is_empty());
+}
+
+impl FilesystemBackend for FatFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "vfat";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::VolumeId32(self.volume_id);
+    }
+
+    fn label(&self) -> Option<String> {
+        return self.label.clone();
+    }
+
+    fn root(&self) -> NodeId {
+        return ROOT_NODE;
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let node = self.node(node)?;
+
+        return Ok(match node.is_dir {
+            true => Metadata {
+                file_type: FileType::Directory,
+                size: 0,
+            },
+            false => Metadata {
+                file_type: FileType::Regular,
+                size: node.size as u64,
+            },
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        return Ok(self
+            .read_entries(dir)?
+            .into_iter()
+            .map(|raw| raw.entry)
+            .collect());
+    }
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        return self
+            .read_entries(dir)?
+            .into_iter()
+            .find(|raw| names_equal(&raw.entry.name, name) || names_equal(&raw.short_name, name))
+            .map(|raw| raw.entry.node)
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        let node = self.node(node)?;
+        if node.is_dir {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+
+        let size = node.size as u64;
+        if offset >= size || node.first_cluster == 0 {
+            return Ok(0);
+        }
+
+        let length = buffer.len().min((size - offset) as usize);
+        let cluster_size = self.cluster_size;
+        let data_start = self.data_start;
+        let chain = self.chain(node.first_cluster)?;
+
+        let mut reads = Vec::new();
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let index = (position / cluster_size) as usize;
+            let skip = position % cluster_size;
+            let cluster = *chain.get(index).ok_or(FilesystemError::Corrupted)?;
+
+            // Merge physically contiguous clusters into a single read.
+            let mut run = 1;
+            while index + run < chain.len() && chain[index + run] == cluster + run as u32 {
+                run += 1;
+            }
+
+            let count = ((run as u64 * cluster_size - skip) as usize).min(length - done);
+            reads.push((
+                data_start + (cluster as u64 - 2) * cluster_size + skip,
+                done,
+                count,
+            ));
+            done += count;
+        }
+
+        for (position, start, count) in reads {
+            self.device
+                .read_bytes(position, &mut buffer[start..start + count])?;
+        }
+
+        return Ok(length);
+    }
+
+    fn write(&mut self, id: NodeId, offset: u64, data: &[u8]) -> Result<(), RrubError> {
+        self.check_writable()?;
+
+        let node = self.node(id)?;
+        if node.is_dir {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+
+        let end = offset
+            .checked_add(data.len() as u64)
+            .filter(|&end| end <= u32::MAX as u64)
+            .ok_or(FilesystemError::NoSpace)?;
+
+        // Zero any gap between the current end of the file and the write.
+        let size = node.size as u64;
+        if offset > size {
+            self.write(id, size, &vec![0u8; (offset - size) as usize])?;
+        }
+
+        let cluster_size = self.cluster_size;
+        let clusters = end.div_ceil(cluster_size) as usize;
+        let node = self.node(id)?;
+        let allocated = match node.first_cluster {
+            0 => 0,
+            first => self.chain(first)?.len(),
+        };
+        if clusters > allocated {
+            self.resize_chain(id, clusters)?;
+        }
+
+        let first = self.node(id)?.first_cluster;
+        let data_start = self.data_start;
+        let chain = self.chain(first)?;
+
+        let mut writes = Vec::new();
+        let mut done = 0;
+        while done < data.len() {
+            let position = offset + done as u64;
+            let index = (position / cluster_size) as usize;
+            let skip = position % cluster_size;
+            let cluster = *chain.get(index).ok_or(FilesystemError::Corrupted)?;
+
+            let mut run = 1;
+            while index + run < chain.len() && chain[index + run] == cluster + run as u32 {
+                run += 1;
+            }
+
+            let count = ((run as u64 * cluster_size - skip) as usize).min(data.len() - done);
+            writes.push((
+                data_start + (cluster as u64 - 2) * cluster_size + skip,
+                done,
+                count,
+            ));
+            done += count;
+        }
+
+        for (position, start, count) in writes {
+            self.device
+                .write_bytes(position, &data[start..start + count])?;
+        }
+
+        if end > self.node(id)?.size as u64 {
+            self.update_entry(id, |entry| entry.size = U32::new(end as u32))?;
+        }
+
+        return Ok(());
+    }
+
+    fn set_len(&mut self, id: NodeId, size: u64) -> Result<(), RrubError> {
+        self.check_writable()?;
+
+        let node = self.node(id)?;
+        if node.is_dir {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+
+        let current = node.size as u64;
+        if size > current {
+            return self.write(id, current, &vec![0u8; (size - current) as usize]);
+        }
+
+        self.resize_chain(id, size.div_ceil(self.cluster_size) as usize)?;
+        self.update_entry(id, |entry| entry.size = U32::new(size as u32))?;
+
+        return Ok(());
+    }
+
+    fn create(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        self.check_writable()?;
+
+        if !is_valid_long_name(name) {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let existing = self.read_entries(dir)?;
+        if existing
+            .iter()
+            .any(|raw| names_equal(&raw.entry.name, name) || names_equal(&raw.short_name, name))
+        {
+            return Err(FilesystemError::AlreadyExists.into());
+        }
+
+        let short = DirectoryEntry {
+            name: [b' '; 11],
+            attr: ATTR_ARCHIVE,
+            nt_reserved: 0,
+            create_time_tenth: 0,
+            create_time: U16::ZERO,
+            create_date: U16::new(DEFAULT_DATE),
+            access_date: U16::new(DEFAULT_DATE),
+            cluster_high: U16::ZERO,
+            write_time: U16::ZERO,
+            write_date: U16::new(DEFAULT_DATE),
+            cluster_low: U16::ZERO,
+            size: U32::ZERO,
+        };
+
+        return self.insert_entry(dir, name, short, &existing);
+    }
+
+    fn rename(&mut self, dir: NodeId, from: &str, to: &str) -> Result<NodeId, RrubError> {
+        self.check_writable()?;
+
+        if !is_valid_long_name(to) {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let mut existing = self.read_entries(dir)?;
+        let index = existing
+            .iter()
+            .position(|raw| {
+                names_equal(&raw.entry.name, from) || names_equal(&raw.short_name, from)
+            })
+            .ok_or(FilesystemError::NotFound)?;
+        let source = existing.remove(index);
+
+        if existing
+            .iter()
+            .any(|raw| names_equal(&raw.entry.name, to) || names_equal(&raw.short_name, to))
+        {
+            return Err(FilesystemError::AlreadyExists.into());
+        }
+
+        // The new entry is written before the old one is removed so a crash in between leaves
+        // the file reachable.
+        let id = self.insert_entry(dir, to, source.short, &existing)?;
+        for slot in source.slots {
+            self.device.write_bytes(slot, &[DELETED_ENTRY])?;
+        }
+        self.nodes.remove(&source.entry.node);
+
+        return Ok(id);
+    }
+
+    fn sync(&mut self) -> Result<(), RrubError> {
+        if !self.writable {
+            return Ok(());
+        }
+
+        // Free count is marked unknown rather than tracked, it is only a hint.
+        if self.fs_info_dirty
+            && let Some(offset) = self.fs_info
+        {
+            let mut hint = [0u8; 8];
+            hint[..4].copy_from_slice(&FS_INFO_UNKNOWN.to_le_bytes());
+            hint[4..].copy_from_slice(&self.next_free.to_le_bytes());
+            self.device
+                .write_bytes(offset + FS_INFO_FREE_COUNT, &hint)?;
+        }
+        self.fs_info_dirty = false;
+
+        return self.device.flush();
+    }
+}
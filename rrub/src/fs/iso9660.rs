@@ -0,0 +1,952 @@
+/*
+ * https://ecma-international.org/publications-and-standards/standards/ecma-119/
+ * https://pismotec.com/cfs/jolspec.html
+ * https://web.archive.org/web/20170404043745/http://www.ymi.com/ymi/sites/default/files/pdf/Rockridge.pdf
+ * https://pdos.csail.mit.edu/6.828/2014/readings/boot-cdrom.pdf
+*/
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use bitflags::bitflags;
+use zerocopy::{
+    BigEndian, FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+            VolumeId64,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo},
+};
+
+/// Volume descriptors and the El Torito boot catalog always use 2048 byte sectors, regardless of
+/// the logical block size of the volume.
+const SECTOR_SIZE: u64 = 2048;
+const FIRST_DESCRIPTOR_SECTOR: u64 = 16;
+const MAX_DESCRIPTORS: u64 = 64;
+const STANDARD_IDENTIFIER: &[u8; 5] = b"CD001";
+
+const DESCRIPTOR_BOOT_RECORD: u8 = 0;
+const DESCRIPTOR_PRIMARY: u8 = 1;
+const DESCRIPTOR_SUPPLEMENTARY: u8 = 2;
+const DESCRIPTOR_TERMINATOR: u8 = 255;
+
+const EL_TORITO_IDENTIFIER: &[u8] = b"EL TORITO SPECIFICATION";
+/// El Torito images are measured in emulated 512 byte sectors.
+const VIRTUAL_SECTOR_SIZE: u64 = 512;
+const CATALOG_ENTRY_SIZE: usize = 32;
+const CATALOG_VALIDATION: u8 = 0x01;
+const CATALOG_BOOTABLE: u8 = 0x88;
+const CATALOG_SECTION: u8 = 0x90;
+const CATALOG_FINAL_SECTION: u8 = 0x91;
+const CATALOG_EXTENSION: u8 = 0x44;
+const CATALOG_KEY: [u8; 2] = [0x55, 0xAA];
+/// Set in the media type of a section entry, and in the flags of an extension, when an extension
+/// entry follows.
+const CATALOG_CONTINUED: u8 = 0x20;
+const MAX_CATALOG_ENTRIES: usize = 512;
+
+const RECORD_HEADER_SIZE: usize = 33;
+const ROOT_RECORD_OFFSET: u64 = 156;
+const CURRENT_DIRECTORY: &[u8] = &[0];
+const PARENT_DIRECTORY: &[u8] = &[1];
+
+const SUSP_ENTRY_HEADER_SIZE: usize = 4;
+const SUSP_CHECK: [u8; 2] = [0xBE, 0xEF];
+const MAX_CONTINUATIONS: usize = 32;
+
+const NM_CONTINUE: u8 = 0x1;
+const NM_CURRENT: u8 = 0x2;
+const NM_PARENT: u8 = 0x4;
+
+const SL_CONTINUE: u8 = 0x1;
+const SL_COMPONENT_CONTINUE: u8 = 0x1;
+const SL_COMPONENT_CURRENT: u8 = 0x2;
+const SL_COMPONENT_PARENT: u8 = 0x4;
+const SL_COMPONENT_ROOT: u8 = 0x8;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct FileFlags: u8 {
+        const Hidden = 0x01;
+        const Directory = 0x02;
+        const Associated = 0x04;
+        const Record = 0x08;
+        const Protection = 0x10;
+        const MultiExtent = 0x80;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct VolumeDescriptor {
+    pub kind: u8,
+    pub identifier: [u8; 5],
+    pub version: u8,
+    /// Volume flags in supplementary descriptors, unused in the primary one.
+    pub flags: u8,
+    pub system_id: [u8; 32],
+    pub volume_id: [u8; 32],
+    pub unused: [u8; 8],
+    pub space_size: U32<LittleEndian>,
+    pub space_size_be: U32<BigEndian>,
+    pub escape_sequences: [u8; 32],
+    pub set_size: U16<LittleEndian>,
+    pub set_size_be: U16<BigEndian>,
+    pub sequence_number: U16<LittleEndian>,
+    pub sequence_number_be: U16<BigEndian>,
+    pub logical_block_size: U16<LittleEndian>,
+    pub logical_block_size_be: U16<BigEndian>,
+    pub path_table_size: U32<LittleEndian>,
+    pub path_table_size_be: U32<BigEndian>,
+    pub path_table_le: U32<LittleEndian>,
+    pub optional_path_table_le: U32<LittleEndian>,
+    pub path_table_be: U32<BigEndian>,
+    pub optional_path_table_be: U32<BigEndian>,
+    pub root_record: [u8; 34],
+    pub volume_set_id: [u8; 128],
+    pub publisher_id: [u8; 128],
+    pub preparer_id: [u8; 128],
+    pub application_id: [u8; 128],
+    pub copyright_file: [u8; 37],
+    pub abstract_file: [u8; 37],
+    pub bibliographic_file: [u8; 37],
+    pub created: [u8; 17],
+    pub modified: [u8; 17],
+    pub expires: [u8; 17],
+    pub effective: [u8; 17],
+    pub file_structure_version: u8,
+    pub reserved: u8,
+    pub application_use: [u8; 512],
+    pub reserved2: [u8; 653],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BootRecord {
+    pub kind: u8,
+    pub identifier: [u8; 5],
+    pub version: u8,
+    pub boot_system_id: [u8; 32],
+    pub boot_id: [u8; 32],
+    /// Sector of the boot catalog.
+    pub catalog: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirectoryRecord {
+    pub length: u8,
+    pub ext_attr_length: u8,
+    pub extent: U32<LittleEndian>,
+    pub extent_be: U32<BigEndian>,
+    pub data_length: U32<LittleEndian>,
+    pub data_length_be: U32<BigEndian>,
+    pub recorded: [u8; 7],
+    pub flags: u8,
+    pub unit_size: u8,
+    pub interleave_gap: u8,
+    pub sequence_number: U16<LittleEndian>,
+    pub sequence_number_be: U16<BigEndian>,
+    pub name_length: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct CatalogValidation {
+    pub header_id: u8,
+    pub platform: u8,
+    pub reserved: U16<LittleEndian>,
+    pub id: [u8; 24],
+    pub checksum: U16<LittleEndian>,
+    pub key: [u8; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct CatalogSectionHeader {
+    pub indicator: u8,
+    pub platform: u8,
+    pub entries: U16<LittleEndian>,
+    pub id: [u8; 28],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct CatalogEntry {
+    pub indicator: u8,
+    pub media: u8,
+    pub load_segment: U16<LittleEndian>,
+    pub system_type: u8,
+    pub unused: u8,
+    pub sector_count: U16<LittleEndian>,
+    pub load_rba: U32<LittleEndian>,
+    pub selection_criteria: u8,
+    pub selection: [u8; 19],
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BootPlatform {
+    X86,
+    PowerPc,
+    Mac,
+    Efi,
+    Other(u8),
+}
+
+impl From<u8> for BootPlatform {
+    fn from(value: u8) -> Self {
+        return match value {
+            0x00 => BootPlatform::X86,
+            0x01 => BootPlatform::PowerPc,
+            0x02 => BootPlatform::Mac,
+            0xEF => BootPlatform::Efi,
+            other => BootPlatform::Other(other),
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BootMedia {
+    NoEmulation,
+    Floppy1_2M,
+    Floppy1_44M,
+    Floppy2_88M,
+    HardDisk,
+    Unknown(u8),
+}
+
+impl From<u8> for BootMedia {
+    fn from(value: u8) -> Self {
+        return match value & 0xF {
+            0 => BootMedia::NoEmulation,
+            1 => BootMedia::Floppy1_2M,
+            2 => BootMedia::Floppy1_44M,
+            3 => BootMedia::Floppy2_88M,
+            4 => BootMedia::HardDisk,
+            other => BootMedia::Unknown(other),
+        };
+    }
+}
+
+/// Boot image listed in the El Torito boot catalog.
+#[derive(Debug, Copy, Clone)]
+pub struct BootEntry {
+    pub platform: BootPlatform,
+    pub bootable: bool,
+    pub media: BootMedia,
+    /// Number of emulated 512 byte sectors loaded by BIOS, EFI images often leave it at 0 or 1 and
+    /// have to be sized from their own headers.
+    pub sector_count: u16,
+    /// First 2048 byte sector of the image.
+    pub load_rba: u32,
+}
+
+impl BootEntry {
+    fn new(platform: BootPlatform, entry: &CatalogEntry) -> BootEntry {
+        return BootEntry {
+            platform,
+            bootable: entry.indicator == CATALOG_BOOTABLE,
+            media: BootMedia::from(entry.media),
+            sector_count: entry.sector_count.get(),
+            load_rba: entry.load_rba.get(),
+        };
+    }
+
+    /// Byte offset of the image from the start of the volume.
+    pub fn offset(&self) -> u64 {
+        return self.load_rba as u64 * SECTOR_SIZE;
+    }
+
+    pub fn length(&self) -> u64 {
+        return self.sector_count as u64 * VIRTUAL_SECTOR_SIZE;
+    }
+}
+
+/// How file names are read from directory records.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Names {
+    /// Plain ISO9660 names, shown in lower case without the version suffix.
+    Iso9660,
+    /// UCS-2 names from a supplementary volume descriptor.
+    Joliet,
+    /// POSIX names from the system use area, after `skip` bytes.
+    RockRidge { skip: usize },
+}
+
+/// Rock Ridge fields of a single directory record.
+#[derive(Debug, Default)]
+struct RockRidge {
+    name: Option<Vec<u8>>,
+    mode: Option<u32>,
+    link: Option<String>,
+    /// Location of a directory that was relocated to keep the tree within 8 levels.
+    child: Option<u32>,
+    /// The record is the relocated directory itself and is listed through its placeholder.
+    relocated: bool,
+    /// The file is zisofs compressed.
+    compressed: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    file_type: FileType,
+    size: u64,
+    /// Byte offset and length of every extent, only multi-extent files have more than one.
+    extents: Vec<(u64, u64)>,
+    link: Option<String>,
+    /// Interleaved and zisofs compressed files can be listed but not read.
+    unsupported: bool,
+}
+
+pub struct Iso9660Filesystem {
+    device: Box<dyn BlockDevice>,
+    primary: VolumeDescriptor,
+    block_size: u64,
+    names: Names,
+    root: NodeId,
+    /// Nodes are identified by the position of their directory record and become known when their
+    /// parent directory is listed.
+    nodes: BTreeMap<NodeId, Node>,
+}
+
+/// Scan the volume descriptor set, calling `visit` for each descriptor until it returns true.
+fn scan_descriptors(
+    device: &mut dyn BlockDevice,
+    mut visit: impl FnMut(&[u8; SECTOR_SIZE as usize]) -> bool,
+) -> Result<(), RrubError> {
+    let mut raw = [0u8; SECTOR_SIZE as usize];
+
+    for sector in FIRST_DESCRIPTOR_SECTOR..FIRST_DESCRIPTOR_SECTOR + MAX_DESCRIPTORS {
+        device.read_bytes(sector * SECTOR_SIZE, &mut raw)?;
+        if &raw[1..6] != STANDARD_IDENTIFIER {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        if raw[0] == DESCRIPTOR_TERMINATOR || visit(&raw) {
+            return Ok(());
+        }
+    }
+
+    return Err(FilesystemError::Corrupted.into());
+}
+
+/// Joliet descriptors are supplementary descriptors announcing UCS-2 level 1, 2 or 3.
+fn is_joliet(descriptor: &VolumeDescriptor) -> bool {
+    return descriptor.flags & 1 == 0
+        && matches!(
+            descriptor.escape_sequences[..3],
+            [0x25, 0x2F, 0x40 | 0x43 | 0x45]
+        );
+}
+
+/// Trim the version suffix that ISO9660 and Joliet add to file names.
+fn strip_version(name: &[u8]) -> &[u8] {
+    return match name.iter().rposition(|&c| c == b';') {
+        Some(end) => &name[..end],
+        None => name,
+    };
+}
+
+fn decode_name(names: Names, raw: &[u8]) -> String {
+    return match names {
+        Names::Iso9660 => {
+            let name = strip_version(raw);
+            let name = name.strip_suffix(b".").unwrap_or(name);
+            String::from_utf8_lossy(name).to_lowercase()
+        }
+        Names::Joliet => {
+            let units = raw
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect::<Vec<_>>();
+            let name = char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect::<String>();
+            match name.rsplit_once(';') {
+                Some((name, _)) => name.to_string(),
+                None => name,
+            }
+        }
+        Names::RockRidge { .. } => String::from_utf8_lossy(raw).into_owned(),
+    };
+}
+
+/// Encode the 16 digit volume timestamp as a BCD number, which is displayed as the same digits
+/// blkid shows as the UUID of an ISO.
+fn timestamp_id(timestamp: &[u8; 17]) -> Option<VolumeId64> {
+    let digits = &timestamp[..16];
+    if !digits.iter().all(u8::is_ascii_digit) || digits.iter().all(|&c| c == b'0') {
+        return None;
+    }
+
+    let mut id = [0u8; 8];
+    for (byte, pair) in id.iter_mut().zip(digits.chunks_exact(2)) {
+        *byte = ((pair[0] - b'0') << 4) | (pair[1] - b'0');
+    }
+
+    return Some(VolumeId64::from_u64_le(u64::from_be_bytes(id)));
+}
+
+fn volume_uuid(primary: &VolumeDescriptor) -> Uuid {
+    let id = timestamp_id(&primary.modified)
+        .or_else(|| timestamp_id(&primary.created))
+        .unwrap_or(VolumeId64::nil());
+    return Uuid::VolumeId64(id);
+}
+
+fn volume_label(primary: &VolumeDescriptor) -> Option<String> {
+    let label = String::from_utf8_lossy(&primary.volume_id);
+    let label = label.trim_end_matches([' ', '\0']);
+    return match label.is_empty() {
+        true => None,
+        false => Some(label.to_string()),
+    };
+}
+
+/// Parse the directory record at the start of `raw`, returning it with its name and system use
+/// area.
+fn parse_record(raw: &[u8]) -> Result<(DirectoryRecord, &[u8], &[u8]), RrubError> {
+    let (record, _) =
+        DirectoryRecord::read_from_prefix(raw).map_err(|_| FilesystemError::Corrupted)?;
+    let length = record.length as usize;
+    let name_end = RECORD_HEADER_SIZE + record.name_length as usize;
+    if length > raw.len() || name_end > length {
+        return Err(FilesystemError::Corrupted.into());
+    }
+
+    // Names of even length are followed by a padding byte.
+    let system_use = (name_end + name_end % 2).min(length);
+
+    return Ok((
+        record,
+        &raw[RECORD_HEADER_SIZE..name_end],
+        &raw[system_use..length],
+    ));
+}
+
+impl Iso9660Filesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut header = [0u8; 6];
+        return device
+            .read_bytes(FIRST_DESCRIPTOR_SECTOR * SECTOR_SIZE, &mut header)
+            .is_ok()
+            && &header[1..] == STANDARD_IDENTIFIER;
+    }
+
+    /// Read the El Torito boot catalog, returning the initial entry followed by the entries of
+    /// every section. Volumes without a boot record have an empty catalog.
+    pub fn boot_catalog(device: &mut dyn BlockDevice) -> Result<Vec<BootEntry>, RrubError> {
+        let mut catalog = None;
+        scan_descriptors(device, |raw| {
+            if raw[0] != DESCRIPTOR_BOOT_RECORD {
+                return false;
+            }
+            let Ok((record, _)) = BootRecord::read_from_prefix(raw) else {
+                return false;
+            };
+            if record.boot_system_id.starts_with(EL_TORITO_IDENTIFIER) {
+                catalog = Some(record.catalog.get());
+                return true;
+            }
+            return false;
+        })?;
+
+        let Some(catalog) = catalog else {
+            return Ok(Vec::new());
+        };
+
+        let mut raw = vec![0u8; SECTOR_SIZE as usize];
+        device.read_bytes(catalog as u64 * SECTOR_SIZE, &mut raw)?;
+
+        let (validation, _) =
+            CatalogValidation::read_from_prefix(&raw).map_err(|_| FilesystemError::Corrupted)?;
+        let sum = raw[..CATALOG_ENTRY_SIZE]
+            .chunks_exact(2)
+            .fold(0u16, |sum, word| {
+                sum.wrapping_add(u16::from_le_bytes([word[0], word[1]]))
+            });
+        if validation.header_id != CATALOG_VALIDATION || validation.key != CATALOG_KEY || sum != 0 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut entries = Vec::new();
+        let mut platform = BootPlatform::from(validation.platform);
+        let (initial, _) = CatalogEntry::read_from_prefix(&raw[CATALOG_ENTRY_SIZE..])
+            .map_err(|_| FilesystemError::Corrupted)?;
+        entries.push(BootEntry::new(platform, &initial));
+
+        let mut index = 2;
+        let mut remaining = 0;
+        let mut last_section = false;
+        while index < MAX_CATALOG_ENTRIES {
+            let offset = index * CATALOG_ENTRY_SIZE;
+            if offset >= raw.len() {
+                // The catalog may span several sectors.
+                raw.resize(raw.len() + SECTOR_SIZE as usize, 0);
+                device.read_bytes(
+                    catalog as u64 * SECTOR_SIZE + offset as u64,
+                    &mut raw[offset..],
+                )?;
+            }
+            let entry = &raw[offset..offset + CATALOG_ENTRY_SIZE];
+            index += 1;
+
+            if remaining == 0 {
+                if last_section || !matches!(entry[0], CATALOG_SECTION | CATALOG_FINAL_SECTION) {
+                    break;
+                }
+                let (header, _) = CatalogSectionHeader::read_from_prefix(entry)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                platform = BootPlatform::from(header.platform);
+                remaining = header.entries.get();
+                last_section = header.indicator == CATALOG_FINAL_SECTION;
+                continue;
+            }
+
+            let (section_entry, _) =
+                CatalogEntry::read_from_prefix(entry).map_err(|_| FilesystemError::Corrupted)?;
+            entries.push(BootEntry::new(platform, &section_entry));
+            remaining -= 1;
+
+            // Skip the selection criteria extensions of the entry.
+            let mut continued = section_entry.media & CATALOG_CONTINUED != 0;
+            while continued && index < MAX_CATALOG_ENTRIES {
+                let offset = index * CATALOG_ENTRY_SIZE;
+                if raw.get(offset) != Some(&CATALOG_EXTENSION) {
+                    break;
+                }
+                continued = raw[offset + 1] & CATALOG_CONTINUED != 0;
+                index += 1;
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        if !Self::probe(device) {
+            return None;
+        }
+
+        let mut primary = None;
+        scan_descriptors(device, |raw| {
+            if raw[0] != DESCRIPTOR_PRIMARY {
+                return false;
+            }
+            primary = VolumeDescriptor::read_from_prefix(raw)
+                .ok()
+                .map(|(descriptor, _)| descriptor);
+            return true;
+        })
+        .ok()?;
+        let primary = primary?;
+
+        return Some(VolumeInfo {
+            fs_type: "iso9660",
+            usage: Usage::Filesystem,
+            label: volume_label(&primary),
+            uuid: Some(volume_uuid(&primary)),
+            size: primary.space_size.get() as u64 * primary.logical_block_size.get() as u64,
+        });
+    }
+
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<Iso9660Filesystem, RrubError> {
+        let mut primary = None;
+        let mut joliet = None;
+        scan_descriptors(device.as_mut(), |raw| {
+            let Ok((descriptor, _)) = VolumeDescriptor::read_from_prefix(raw) else {
+                return false;
+            };
+            match descriptor.kind {
+                DESCRIPTOR_PRIMARY if primary.is_none() => primary = Some(descriptor),
+                DESCRIPTOR_SUPPLEMENTARY if joliet.is_none() && is_joliet(&descriptor) => {
+                    joliet = Some(descriptor)
+                }
+                _ => {}
+            }
+            return false;
+        })?;
+
+        let primary = primary.ok_or(FilesystemError::UnknownFilesystem)?;
+        let block_size = primary.logical_block_size.get() as u64;
+        if !block_size.is_power_of_two() || !(512..=SECTOR_SIZE).contains(&block_size) {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut filesystem = Iso9660Filesystem {
+            device,
+            primary,
+            block_size,
+            names: Names::Iso9660,
+            root: NodeId(0),
+            nodes: BTreeMap::new(),
+        };
+
+        // Rock Ridge is announced by a SUSP "SP" entry in the first record of the root directory.
+        let (root, _, _) = parse_record(&primary.root_record)?;
+        let mut first = vec![0u8; block_size as usize];
+        filesystem
+            .device
+            .read_bytes(root.extent.get() as u64 * block_size, &mut first)?;
+        let (_, name, system_use) = parse_record(&first)?;
+        let rock_ridge = name == CURRENT_DIRECTORY
+            && system_use.len() >= 7
+            && &system_use[..2] == b"SP"
+            && system_use[4..6] == SUSP_CHECK;
+
+        let descriptor = match (rock_ridge, joliet) {
+            (true, _) => {
+                filesystem.names = Names::RockRidge {
+                    skip: system_use[6] as usize,
+                };
+                primary
+            }
+            (false, Some(joliet)) => {
+                filesystem.names = Names::Joliet;
+                joliet
+            }
+            (false, None) => primary,
+        };
+
+        // The root is identified by the position of its record in the primary descriptor, which
+        // cannot collide with a record in a directory.
+        let root = NodeId(FIRST_DESCRIPTOR_SECTOR * SECTOR_SIZE + ROOT_RECORD_OFFSET);
+        let (record, _, _) = parse_record(&descriptor.root_record)?;
+        let node = filesystem.node_from_records(&[record], RockRidge::default())?;
+        if node.file_type != FileType::Directory {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        filesystem.root = root;
+        filesystem.nodes.insert(root, node);
+
+        return Ok(filesystem);
+    }
+
+    fn node(&self, node: NodeId) -> Result<&Node, RrubError> {
+        return self
+            .nodes
+            .get(&node)
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    /// Collect the Rock Ridge fields of a system use area, following continuation areas.
+    fn rock_ridge(&mut self, system_use: &[u8]) -> Result<RockRidge, RrubError> {
+        let Names::RockRidge { skip } = self.names else {
+            return Ok(RockRidge::default());
+        };
+
+        let mut result = RockRidge::default();
+        let mut name = Vec::new();
+        let mut link = String::new();
+        let mut has_link = false;
+        let mut area = system_use.get(skip..).unwrap_or_default().to_vec();
+        let mut continuations = 0;
+
+        loop {
+            let mut next = None;
+            let mut position = 0;
+
+            while position + SUSP_ENTRY_HEADER_SIZE <= area.len() {
+                let signature = [area[position], area[position + 1]];
+                let length = area[position + 2] as usize;
+                if length < SUSP_ENTRY_HEADER_SIZE || position + length > area.len() {
+                    break;
+                }
+                let entry = &area[position..position + length];
+                position += length;
+
+                match &signature {
+                    b"CE" if length >= 28 => {
+                        let block = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                        let offset = u32::from_le_bytes(entry[12..16].try_into().unwrap());
+                        let length = u32::from_le_bytes(entry[20..24].try_into().unwrap());
+                        next = Some((block as u64, offset as u64, length as u64));
+                    }
+                    b"NM" if length >= 5 && entry[4] & (NM_CURRENT | NM_PARENT) == 0 => {
+                        name.extend_from_slice(&entry[5..]);
+                        if entry[4] & NM_CONTINUE == 0 {
+                            result.name = Some(core::mem::take(&mut name));
+                        }
+                    }
+                    b"PX" if length >= 12 => {
+                        result.mode = Some(u32::from_le_bytes(entry[4..8].try_into().unwrap()));
+                    }
+                    b"SL" if length >= 5 => {
+                        has_link = true;
+                        let mut components = &entry[5..];
+                        while let [flags, size, rest @ ..] = components {
+                            let size = (*size as usize).min(rest.len());
+                            match flags & !SL_COMPONENT_CONTINUE {
+                                SL_COMPONENT_CURRENT => link.push('.'),
+                                SL_COMPONENT_PARENT => link.push_str(".."),
+                                SL_COMPONENT_ROOT => link.push('/'),
+                                _ => link.push_str(&String::from_utf8_lossy(&rest[..size])),
+                            }
+                            // Complete components are separated by a slash, the root already
+                            // ends with one.
+                            if flags & (SL_COMPONENT_CONTINUE | SL_COMPONENT_ROOT) == 0 {
+                                link.push('/');
+                            }
+                            components = &rest[size..];
+                        }
+                        if entry[4] & SL_CONTINUE == 0 && link.len() > 1 && link.ends_with('/') {
+                            link.pop();
+                        }
+                    }
+                    b"CL" if length >= 12 => {
+                        result.child = Some(u32::from_le_bytes(entry[4..8].try_into().unwrap()));
+                    }
+                    b"RE" => result.relocated = true,
+                    b"ZF" => result.compressed = true,
+                    b"ST" => break,
+                    _ => {}
+                }
+            }
+
+            let Some((block, offset, length)) = next else {
+                break;
+            };
+            // A continuation area is confined to a single logical block.
+            continuations += 1;
+            if continuations > MAX_CONTINUATIONS || offset + length > self.block_size {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            area = vec![0u8; length as usize];
+            self.device
+                .read_bytes(block * self.block_size + offset, &mut area)?;
+        }
+
+        if has_link {
+            result.link = Some(link);
+        }
+
+        return Ok(result);
+    }
+
+    /// Build a node from the records of a file, several for multi-extent files.
+    fn node_from_records(
+        &mut self,
+        records: &[DirectoryRecord],
+        rock_ridge: RockRidge,
+    ) -> Result<Node, RrubError> {
+        let block_size = self.block_size;
+
+        if let Some(child) = rock_ridge.child {
+            // The placeholder of a relocated directory points at its "." record.
+            let mut raw = vec![0u8; block_size as usize];
+            self.device
+                .read_bytes(child as u64 * block_size, &mut raw)?;
+            let (record, name, _) = parse_record(&raw)?;
+            if name != CURRENT_DIRECTORY {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            return self.node_from_records(&[record], RockRidge::default());
+        }
+
+        let first = records.first().ok_or(FilesystemError::Corrupted)?;
+        let flags = FileFlags::from_bits_retain(first.flags);
+        let file_type = match rock_ridge.mode.map(|mode| mode & S_IFMT) {
+            _ if rock_ridge.link.is_some() => FileType::Symlink,
+            _ if flags.contains(FileFlags::Directory) => FileType::Directory,
+            None | Some(S_IFREG) => FileType::Regular,
+            Some(_) => FileType::Other,
+        };
+
+        let extents = records
+            .iter()
+            .map(|record| {
+                let start =
+                    (record.extent.get() as u64 + record.ext_attr_length as u64) * block_size;
+                (start, record.data_length.get() as u64)
+            })
+            .collect::<Vec<_>>();
+
+        let size = match &rock_ridge.link {
+            Some(link) => link.len() as u64,
+            None => extents.iter().map(|(_, length)| length).sum(),
+        };
+
+        return Ok(Node {
+            file_type,
+            size,
+            extents,
+            link: rock_ridge.link,
+            unsupported: rock_ridge.compressed
+                || records.iter().any(|record| record.unit_size != 0),
+        });
+    }
+
+    fn read_extents(
+        &mut self,
+        node: &Node,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        if offset >= node.size {
+            return Ok(0);
+        }
+        let length = buffer.len().min((node.size - offset) as usize);
+
+        let mut done = 0;
+        let mut start = 0;
+        for &(physical, extent_length) in &node.extents {
+            let end = start + extent_length;
+            let position = offset + done as u64;
+            if done < length && position < end {
+                let count = ((end - position) as usize).min(length - done);
+                self.device
+                    .read_bytes(physical + position - start, &mut buffer[done..done + count])?;
+                done += count;
+            }
+            start = end;
+        }
+
+        return Ok(done);
+    }
+}
+
+impl FilesystemBackend for Iso9660Filesystem {
+    fn fs_type(&self) -> &'static str {
+        return "iso9660";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return volume_uuid(&self.primary);
+    }
+
+    fn label(&self) -> Option<String> {
+        return volume_label(&self.primary);
+    }
+
+    fn root(&self) -> NodeId {
+        return self.root;
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let node = self.node(node)?;
+        return Ok(Metadata {
+            file_type: node.file_type,
+            size: node.size,
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let node = self.node(dir)?.clone();
+        if node.file_type != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        let block_size = self.block_size as usize;
+        let base = node.extents.first().map_or(0, |&(physical, _)| physical);
+
+        let mut entries = Vec::new();
+        let mut records = Vec::new();
+        let mut rock_ridge = RockRidge::default();
+        let mut name = String::new();
+        let mut first_position = 0;
+        let mut block = vec![0u8; block_size];
+
+        for block_start in (0..node.size).step_by(block_size) {
+            let length = self.read_extents(&node, block_start, &mut block)?;
+            let mut position = 0;
+
+            // Records never cross a logical block, the rest of a block is padded with zeros.
+            while position < length && block[position] != 0 {
+                let (record, raw_name, system_use) = parse_record(&block[position..length])?;
+                let record_position = base + block_start + position as u64;
+                position += record.length as usize;
+
+                if raw_name == CURRENT_DIRECTORY || raw_name == PARENT_DIRECTORY {
+                    continue;
+                }
+
+                // Only the first record of a multi-extent file carries the name that is shown.
+                if records.is_empty() {
+                    first_position = record_position;
+                    rock_ridge = self.rock_ridge(system_use)?;
+                    name = match rock_ridge.name.take() {
+                        Some(name) => decode_name(self.names, &name),
+                        None => decode_name(self.names, raw_name),
+                    };
+                }
+                records.push(record);
+
+                if FileFlags::from_bits_retain(record.flags).contains(FileFlags::MultiExtent) {
+                    continue;
+                }
+
+                if rock_ridge.relocated {
+                    records.clear();
+                    continue;
+                }
+                let node = self.node_from_records(&records, core::mem::take(&mut rock_ridge))?;
+                records.clear();
+
+                entries.push(DirEntry {
+                    name: core::mem::take(&mut name),
+                    node: NodeId(first_position),
+                    file_type: node.file_type,
+                });
+                self.nodes.insert(NodeId(first_position), node);
+            }
+        }
+
+        if !records.is_empty() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        return Ok(entries);
+    }
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        // Plain ISO9660 names are upper case on disk, accept them in any case.
+        let ignore_case = self.names == Names::Iso9660;
+        return self
+            .read_dir(dir)?
+            .into_iter()
+            .find(|entry| match ignore_case {
+                true => entry.name.eq_ignore_ascii_case(name),
+                false => entry.name == name,
+            })
+            .map(|entry| entry.node)
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        let node = self.node(node)?.clone();
+        match (node.file_type, &node.link) {
+            (FileType::Directory, _) => return Err(FilesystemError::IsADirectory.into()),
+            (_, Some(link)) => {
+                let target = link.as_bytes().get(offset as usize..).unwrap_or_default();
+                let count = target.len().min(buffer.len());
+                buffer[..count].copy_from_slice(&target[..count]);
+                return Ok(count);
+            }
+            _ if node.unsupported => return Err(FilesystemError::Unsupported.into()),
+            _ => return self.read_extents(&node, offset, buffer),
+        }
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        return self
+            .node(node)?
+            .link
+            .clone()
+            .ok_or(FilesystemError::NotFound.into());
+    }
+}
@@ -0,0 +1,71 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::{BlockDevice, check_range},
+        filesystem::{FileType, Filesystem, FilesystemError, NodeId},
+    },
+};
+
+/// Image files are exposed with the sector size partition tables inside them expect.
+const LOOP_BLOCK_SIZE: usize = 512;
+
+/// Read-only block device backed by a disk or ISO image stored on another filesystem. The
+/// filesystem is shared, to be had back once everything mounted from the image is dropped.
+pub struct LoopDevice {
+    filesystem: Rc<RefCell<Filesystem>>,
+    node: NodeId,
+    size: u64,
+}
+
+impl LoopDevice {
+    pub fn open(filesystem: Rc<RefCell<Filesystem>>, path: &str) -> Result<LoopDevice, RrubError> {
+        let (node, metadata) = {
+            let mut filesystem = filesystem.borrow_mut();
+            let node = filesystem.resolve(path)?;
+            (node, filesystem.backend().metadata(node)?)
+        };
+        if metadata.file_type != FileType::Regular {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+
+        return Ok(LoopDevice {
+            filesystem,
+            node,
+            size: metadata.size,
+        });
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn block_size(&self) -> usize {
+        return LOOP_BLOCK_SIZE;
+    }
+
+    fn block_count(&self) -> u64 {
+        return self.size.div_ceil(LOOP_BLOCK_SIZE as u64);
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+
+        let mut done = 0;
+        while done < buffer.len() {
+            let offset = lba * LOOP_BLOCK_SIZE as u64 + done as u64;
+            let read =
+                self.filesystem
+                    .borrow_mut()
+                    .read_at(self.node, offset, &mut buffer[done..])?;
+            if read == 0 {
+                // The last block of an image whose size is not a multiple of the block size.
+                buffer[done..].fill(0);
+                break;
+            }
+            done += read;
+        }
+
+        return Ok(());
+    }
+}
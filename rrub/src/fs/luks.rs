@@ -0,0 +1,1032 @@
+/*
+ * https://gitlab.com/cryptsetup/cryptsetup/-/wikis/LUKS-standard/on-disk-format.pdf
+ * https://gitlab.com/cryptsetup/cryptsetup/-/blob/main/docs/on-disk-format-luks2.pdf
+*/
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::slice::from_raw_parts_mut;
+
+use log::debug;
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    BigEndian, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    crypto::{
+        CryptoError, HashAlgorithm, Secret,
+        aes::Xts,
+        argon2::{self, Block, Variant},
+        keys_wiped, zeroize,
+    },
+    error::RrubError,
+    firmware::{
+        Firmware,
+        block::{BlockDevice, check_range},
+        filesystem::{FilesystemError, Uuid},
+        input::InputHandle,
+        memory::{AllocationType, PAGE_SIZE},
+    },
+    fs::probe::{Usage, VolumeInfo, label_from_bytes},
+};
+
+const LUKS_MAGIC: &[u8; 6] = b"LUKS\xBA\xBE";
+const LUKS2_SECONDARY_MAGIC: &[u8; 6] = b"SKUL\xBA\xBE";
+/// Key material and LUKS1 offsets are always in 512 byte sectors, whatever the data sector size.
+const LUKS_SECTOR_SIZE: u64 = 512;
+
+const LUKS1_KEYSLOT_ACTIVE: u32 = 0x00AC_71F3;
+
+const LUKS2_BINARY_HEADER_SIZE: usize = 4096;
+/// The secondary header follows the primary, whose size is one of these.
+const LUKS2_SECONDARY_OFFSETS: [u64; 9] = [
+    0x4000, 0x8000, 0x1_0000, 0x2_0000, 0x4_0000, 0x8_0000, 0x10_0000, 0x20_0000, 0x40_0000,
+];
+/// Nesting allowed in the JSON area, which is a few levels deep in practice.
+const MAX_JSON_DEPTH: usize = 32;
+
+/// cryptsetup refuses anything else, and it bounds the key material read for a keyslot.
+const LUKS_STRIPES: usize = 4000;
+/// Two AES-256 keys for XTS.
+const MAX_KEY_SIZE: usize = 64;
+
+const MAX_PASSPHRASE_ATTEMPTS: usize = 3;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Luks1Keyslot {
+    pub active: U32<BigEndian>,
+    pub iterations: U32<BigEndian>,
+    pub salt: [u8; 32],
+    /// In 512 byte sectors.
+    pub key_material_offset: U32<BigEndian>,
+    pub stripes: U32<BigEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Luks1Header {
+    pub magic: [u8; 6],
+    pub version: U16<BigEndian>,
+    pub cipher_name: [u8; 32],
+    pub cipher_mode: [u8; 32],
+    pub hash_spec: [u8; 32],
+    /// In 512 byte sectors.
+    pub payload_offset: U32<BigEndian>,
+    pub key_bytes: U32<BigEndian>,
+    pub mk_digest: [u8; 20],
+    pub mk_digest_salt: [u8; 32],
+    pub mk_digest_iterations: U32<BigEndian>,
+    pub uuid: [u8; 40],
+    pub keyslots: [Luks1Keyslot; 8],
+}
+
+/// The binary part of a LUKS2 header, followed by the JSON metadata.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Luks2Header {
+    pub magic: [u8; 6],
+    pub version: U16<BigEndian>,
+    /// Binary header and JSON area together.
+    pub header_size: U64<BigEndian>,
+    pub sequence_id: U64<BigEndian>,
+    pub label: [u8; 48],
+    pub checksum_algorithm: [u8; 32],
+    pub salt: [u8; 64],
+    pub uuid: [u8; 40],
+    pub subsystem: [u8; 48],
+    pub header_offset: U64<BigEndian>,
+    pub _padding: [u8; 184],
+    pub checksum: [u8; 64],
+    pub _padding_4096: [u8; 3584],
+}
+
+/// How the sector number becomes the XTS tweak.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum IvMode {
+    /// Truncated to 32 bits.
+    Plain,
+    Plain64,
+}
+
+impl IvMode {
+    /// Only AES-XTS is supported, which is what cryptsetup has defaulted to for years.
+    fn from_cipher(cipher: &str, mode: &str) -> Result<IvMode, CryptoError> {
+        return match (cipher, mode) {
+            ("aes", "xts-plain64") => Ok(IvMode::Plain64),
+            ("aes", "xts-plain") => Ok(IvMode::Plain),
+            _ => Err(CryptoError::Unsupported),
+        };
+    }
+
+    /// Parse a LUKS2 style specification such as "aes-xts-plain64".
+    fn from_encryption(encryption: &str) -> Result<IvMode, CryptoError> {
+        let (cipher, mode) = encryption.split_once('-').ok_or(CryptoError::Unsupported)?;
+        return IvMode::from_cipher(cipher, mode);
+    }
+
+    fn tweak(self, sector: u64) -> u64 {
+        return match self {
+            IvMode::Plain => sector & 0xFFFF_FFFF,
+            IvMode::Plain64 => sector,
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Kdf {
+    Pbkdf2 {
+        hash: HashAlgorithm,
+        iterations: u32,
+        salt: Vec<u8>,
+    },
+    Argon2 {
+        variant: Variant,
+        passes: u32,
+        /// In KiB.
+        memory: u32,
+        lanes: u32,
+        salt: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Keyslot {
+    /// Higher priority slots are tried first.
+    priority: u8,
+    /// Size of the volume key.
+    key_size: usize,
+    kdf: Kdf,
+    /// Byte offset of the encrypted, anti-forensic split key material.
+    area_offset: u64,
+    area_iv: IvMode,
+    area_key_size: usize,
+    stripes: usize,
+    af_hash: HashAlgorithm,
+}
+
+/// PBKDF2 digest of the volume key, to recognise the right key.
+#[derive(Debug, Clone)]
+struct Digest {
+    hash: HashAlgorithm,
+    iterations: u32,
+    salt: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Digest {
+    fn matches(&self, key: &[u8]) -> bool {
+        let mut computed = vec![0u8; self.value.len()];
+        self.hash
+            .pbkdf2(key, &self.salt, self.iterations, &mut computed);
+        let difference = computed
+            .iter()
+            .zip(&self.value)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        zeroize(&mut computed);
+        return difference == 0;
+    }
+}
+
+/// The encrypted data area.
+#[derive(Debug, Clone)]
+struct Segment {
+    offset: u64,
+    /// `None` when the segment extends to the end of the device.
+    size: Option<u64>,
+    /// In 512 byte sectors, added to the sector number.
+    iv_tweak: u64,
+    sector_size: usize,
+    iv: IvMode,
+}
+
+/// A LUKS1 or LUKS2 container whose header has been parsed, but which hasn't been unlocked yet.
+pub struct LuksVolume {
+    device: Box<dyn BlockDevice>,
+    uuid: Option<RealUuid>,
+    label: Option<String>,
+    keyslots: Vec<Keyslot>,
+    digest: Digest,
+    segment: Segment,
+}
+
+/// Block device of the decrypted contents of a LUKS container, which reads fail on once the keys
+/// have been wiped.
+pub struct LuksDevice {
+    device: Box<dyn BlockDevice>,
+    xts: Secret<Xts>,
+    segment: Segment,
+    block_count: u64,
+}
+
+fn c_string(raw: &[u8]) -> &str {
+    let end = raw.iter().position(|&c| c == 0).unwrap_or(raw.len());
+    return core::str::from_utf8(&raw[..end]).unwrap_or("");
+}
+
+fn hash_from_name(name: &str) -> Result<HashAlgorithm, CryptoError> {
+    return HashAlgorithm::from_name(name).ok_or(CryptoError::Unsupported);
+}
+
+/// Undo the anti-forensic split of LUKS, which stores the key as `stripes` blocks diffused with
+/// the hash.
+fn af_merge(material: &[u8], stripes: usize, hash: HashAlgorithm, key: &mut [u8]) {
+    let mut digest = [0u8; 64];
+    key.fill(0);
+
+    for (index, stripe) in material.chunks_exact(key.len()).take(stripes).enumerate() {
+        for (byte, other) in key.iter_mut().zip(stripe) {
+            *byte ^= other;
+        }
+        if index == stripes - 1 {
+            break;
+        }
+
+        // Each hash sized chunk, the last one possibly shorter, is replaced by the hash of its
+        // index and contents.
+        for (chunk_index, chunk) in key.chunks_mut(hash.output_size()).enumerate() {
+            hash.digest(&[&(chunk_index as u32).to_be_bytes(), chunk], &mut digest);
+            chunk.copy_from_slice(&digest[..chunk.len()]);
+        }
+    }
+    zeroize(&mut digest);
+}
+
+/// Run Argon2 with its memory in pages from the firmware, as the heap never frees anything.
+#[allow(clippy::too_many_arguments)]
+fn argon2_with_pages<F: Firmware>(
+    firmware: &mut F,
+    variant: Variant,
+    passphrase: &[u8],
+    salt: &[u8],
+    passes: u32,
+    memory: u32,
+    lanes: u32,
+    output: &mut [u8],
+) -> Result<(), RrubError> {
+    let blocks = argon2::memory_blocks(memory, lanes);
+    let pages = (blocks * argon2::BLOCK_SIZE).div_ceil(PAGE_SIZE);
+    let start = firmware.allocate_pages(AllocationType::AnyPages, pages)?;
+
+    let area = unsafe { from_raw_parts_mut(start.as_ptr() as *mut Block, blocks) };
+    let result = argon2::argon2(
+        variant, passphrase, salt, passes, memory, lanes, area, output,
+    );
+    zeroize(area.as_mut_bytes());
+
+    unsafe { firmware.deallocate_pages(start, pages)? };
+    result?;
+    return Ok(());
+}
+
+impl LuksVolume {
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let mut raw = [0u8; size_of::<Luks1Header>()];
+        device.read_bytes(0, &mut raw).ok()?;
+        let header = Luks1Header::read_from_bytes(&raw).ok()?;
+        if &header.magic != LUKS_MAGIC {
+            return None;
+        }
+
+        // Both versions keep the UUID at the same offset, only LUKS2 has a label.
+        let label = match header.version.get() {
+            1 => None,
+            2 => {
+                let mut raw = [0u8; size_of::<Luks2Header>()];
+                device.read_bytes(0, &mut raw).ok()?;
+                label_from_bytes(&Luks2Header::read_from_bytes(&raw).ok()?.label)
+            }
+            _ => return None,
+        };
+
+        let uuid = RealUuid::try_parse(c_string(&header.uuid)).ok();
+        return Some(VolumeInfo {
+            fs_type: "crypto_LUKS",
+            usage: Usage::Crypto,
+            label,
+            uuid: uuid.map(Uuid::RealUuid),
+            size: device.size(),
+        });
+    }
+
+    pub fn open(mut device: Box<dyn BlockDevice>) -> Result<LuksVolume, RrubError> {
+        let mut raw = [0u8; size_of::<Luks1Header>()];
+        device.read_bytes(0, &mut raw)?;
+        let header =
+            Luks1Header::read_from_bytes(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+        if &header.magic != LUKS_MAGIC {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        return match header.version.get() {
+            1 => LuksVolume::open_luks1(device, &header),
+            2 => LuksVolume::open_luks2(device),
+            _ => Err(FilesystemError::Unsupported.into()),
+        };
+    }
+
+    fn open_luks1(
+        device: Box<dyn BlockDevice>,
+        header: &Luks1Header,
+    ) -> Result<LuksVolume, RrubError> {
+        let iv = IvMode::from_cipher(c_string(&header.cipher_name), c_string(&header.cipher_mode))?;
+        let hash = hash_from_name(c_string(&header.hash_spec))?;
+        let key_size = header.key_bytes.get() as usize;
+
+        let keyslots = header
+            .keyslots
+            .iter()
+            .filter(|keyslot| keyslot.active.get() == LUKS1_KEYSLOT_ACTIVE)
+            .map(|keyslot| Keyslot {
+                priority: 1,
+                key_size,
+                kdf: Kdf::Pbkdf2 {
+                    hash,
+                    iterations: keyslot.iterations.get(),
+                    salt: keyslot.salt.to_vec(),
+                },
+                area_offset: keyslot.key_material_offset.get() as u64 * LUKS_SECTOR_SIZE,
+                area_iv: iv,
+                area_key_size: key_size,
+                stripes: keyslot.stripes.get() as usize,
+                af_hash: hash,
+            })
+            .collect();
+
+        return Ok(LuksVolume {
+            device,
+            uuid: RealUuid::try_parse(c_string(&header.uuid)).ok(),
+            label: None,
+            keyslots,
+            digest: Digest {
+                hash,
+                iterations: header.mk_digest_iterations.get(),
+                salt: header.mk_digest_salt.to_vec(),
+                value: header.mk_digest.to_vec(),
+            },
+            segment: Segment {
+                offset: header.payload_offset.get() as u64 * LUKS_SECTOR_SIZE,
+                size: None,
+                iv_tweak: 0,
+                sector_size: LUKS_SECTOR_SIZE as usize,
+                iv,
+            },
+        });
+    }
+
+    /// Read one copy of the LUKS2 header and its JSON area, `None` unless the checksum matches.
+    fn read_luks2_header(
+        device: &mut dyn BlockDevice,
+        offset: u64,
+    ) -> Option<(Luks2Header, Vec<u8>)> {
+        let mut raw = [0u8; LUKS2_BINARY_HEADER_SIZE];
+        device.read_bytes(offset, &mut raw).ok()?;
+        let header = Luks2Header::read_from_bytes(&raw).ok()?;
+
+        let magic = match offset {
+            0 => LUKS_MAGIC,
+            _ => LUKS2_SECONDARY_MAGIC,
+        };
+        let header_size = header.header_size.get();
+        if &header.magic != magic
+            || header.version.get() != 2
+            || header.header_offset.get() != offset
+            || !header_size.is_power_of_two()
+            || !LUKS2_SECONDARY_OFFSETS.contains(&header_size)
+        {
+            return None;
+        }
+
+        let mut json = vec![0u8; header_size as usize - LUKS2_BINARY_HEADER_SIZE];
+        device
+            .read_bytes(offset + LUKS2_BINARY_HEADER_SIZE as u64, &mut json)
+            .ok()?;
+
+        // The checksum covers the binary header with the checksum field zeroed and the JSON area.
+        let hash = HashAlgorithm::from_name(c_string(&header.checksum_algorithm))?;
+        let checksum_offset = core::mem::offset_of!(Luks2Header, checksum);
+        raw[checksum_offset..checksum_offset + header.checksum.len()].fill(0);
+        let mut checksum = [0u8; 64];
+        hash.digest(&[&raw, &json], &mut checksum);
+        if checksum[..hash.output_size()] != header.checksum[..hash.output_size()] {
+            debug!("LUKS2 header at {:#X} has a bad checksum", offset);
+            return None;
+        }
+
+        return Some((header, json));
+    }
+
+    fn open_luks2(mut device: Box<dyn BlockDevice>) -> Result<LuksVolume, RrubError> {
+        let primary = LuksVolume::read_luks2_header(device.as_mut(), 0);
+        let secondary_offsets = match &primary {
+            Some((header, _)) => vec![header.header_size.get()],
+            None => LUKS2_SECONDARY_OFFSETS.to_vec(),
+        };
+        let secondary = secondary_offsets
+            .into_iter()
+            .find_map(|offset| LuksVolume::read_luks2_header(device.as_mut(), offset));
+
+        // Both copies are normally identical, otherwise the one written last wins.
+        let (header, json) = [primary, secondary]
+            .into_iter()
+            .flatten()
+            .max_by_key(|(header, _)| header.sequence_id.get())
+            .ok_or(FilesystemError::ChecksumMismatch)?;
+
+        let end = json.iter().position(|&c| c == 0).unwrap_or(json.len());
+        let text = core::str::from_utf8(&json[..end]).map_err(|_| FilesystemError::Corrupted)?;
+        let metadata = Json::parse(text).ok_or(FilesystemError::Corrupted)?;
+
+        let mut volume = LuksVolume::from_metadata(device, &metadata)?;
+        volume.uuid = RealUuid::try_parse(c_string(&header.uuid)).ok();
+        volume.label = label_from_bytes(&header.label);
+        return Ok(volume);
+    }
+
+    fn from_metadata(
+        device: Box<dyn BlockDevice>,
+        metadata: &Json,
+    ) -> Result<LuksVolume, RrubError> {
+        let corrupted = || RrubError::from(FilesystemError::Corrupted);
+
+        // Requirements such as an unfinished reencryption change how the data has to be read.
+        if metadata
+            .get("config")
+            .and_then(|config| config.get("requirements"))
+            .and_then(|requirements| requirements.get("mandatory"))
+            .and_then(Json::as_array)
+            .is_some_and(|mandatory| !mandatory.is_empty())
+        {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        // The data is the crypt segment with the lowest number.
+        let (segment_id, segment) = metadata
+            .get("segments")
+            .and_then(Json::as_object)
+            .ok_or_else(corrupted)?
+            .iter()
+            .filter(|(_, segment)| segment.get_str("type") == Some("crypt"))
+            .min_by_key(|(id, _)| id.parse::<u64>().unwrap_or(u64::MAX))
+            .ok_or_else(corrupted)?;
+        let segment = Segment {
+            offset: segment.get_u64("offset").ok_or_else(corrupted)?,
+            size: match segment.get_str("size") {
+                Some("dynamic") => None,
+                _ => Some(segment.get_u64("size").ok_or_else(corrupted)?),
+            },
+            iv_tweak: segment.get_u64("iv_tweak").unwrap_or(0),
+            sector_size: segment
+                .get_u64("sector_size")
+                .filter(|size| size.is_power_of_two() && (512..=4096).contains(size))
+                .ok_or_else(corrupted)? as usize,
+            iv: IvMode::from_encryption(segment.get_str("encryption").ok_or_else(corrupted)?)?,
+        };
+
+        let (digest_ids, digest) = metadata
+            .get("digests")
+            .and_then(Json::as_object)
+            .ok_or_else(corrupted)?
+            .iter()
+            .map(|(_, digest)| digest)
+            .find(|digest| {
+                digest
+                    .get("segments")
+                    .and_then(Json::as_array)
+                    .is_some_and(|segments| {
+                        segments.iter().any(|id| id.as_str() == Some(segment_id))
+                    })
+            })
+            .map(|digest| -> Result<_, RrubError> {
+                if digest.get_str("type") != Some("pbkdf2") {
+                    return Err(CryptoError::Unsupported.into());
+                }
+                let keyslots: Vec<&str> = digest
+                    .get("keyslots")
+                    .and_then(Json::as_array)
+                    .ok_or_else(corrupted)?
+                    .iter()
+                    .filter_map(Json::as_str)
+                    .collect();
+                let digest = Digest {
+                    hash: hash_from_name(digest.get_str("hash").ok_or_else(corrupted)?)?,
+                    iterations: digest.get_u64("iterations").ok_or_else(corrupted)? as u32,
+                    salt: digest.get_base64("salt").ok_or_else(corrupted)?,
+                    value: digest.get_base64("digest").ok_or_else(corrupted)?,
+                };
+                return Ok((keyslots, digest));
+            })
+            .ok_or_else(corrupted)??;
+
+        let mut keyslots = Vec::new();
+        for (id, keyslot) in metadata
+            .get("keyslots")
+            .and_then(Json::as_object)
+            .ok_or_else(corrupted)?
+        {
+            if !digest_ids.contains(&id.as_str()) {
+                continue;
+            }
+            match LuksVolume::parse_keyslot(keyslot) {
+                Some(Ok(keyslot)) => keyslots.push(keyslot),
+                Some(Err(error)) => debug!("Skipping LUKS2 keyslot {}: {:?}", id, error),
+                // Priority 0 keyslots are only used when asked for explicitly.
+                None => continue,
+            }
+        }
+
+        return Ok(LuksVolume {
+            device,
+            uuid: None,
+            label: None,
+            keyslots,
+            digest,
+            segment,
+        });
+    }
+
+    fn parse_keyslot(keyslot: &Json) -> Option<Result<Keyslot, RrubError>> {
+        let priority = keyslot.get_u64("priority").unwrap_or(1) as u8;
+        if priority == 0 {
+            return None;
+        }
+
+        let parse = || -> Result<Keyslot, RrubError> {
+            let corrupted = || RrubError::from(FilesystemError::Corrupted);
+            let af = keyslot.get("af").ok_or_else(corrupted)?;
+            let area = keyslot.get("area").ok_or_else(corrupted)?;
+            let kdf = keyslot.get("kdf").ok_or_else(corrupted)?;
+            if keyslot.get_str("type") != Some("luks2")
+                || af.get_str("type") != Some("luks1")
+                || area.get_str("type") != Some("raw")
+            {
+                return Err(CryptoError::Unsupported.into());
+            }
+
+            let salt = kdf.get_base64("salt").ok_or_else(corrupted)?;
+            let kdf = match kdf.get_str("type") {
+                Some("pbkdf2") => Kdf::Pbkdf2 {
+                    hash: hash_from_name(kdf.get_str("hash").ok_or_else(corrupted)?)?,
+                    iterations: kdf.get_u64("iterations").ok_or_else(corrupted)? as u32,
+                    salt,
+                },
+                Some(name @ ("argon2i" | "argon2id")) => Kdf::Argon2 {
+                    variant: match name {
+                        "argon2i" => Variant::Argon2i,
+                        _ => Variant::Argon2id,
+                    },
+                    passes: kdf.get_u64("time").ok_or_else(corrupted)? as u32,
+                    memory: kdf.get_u64("memory").ok_or_else(corrupted)? as u32,
+                    lanes: kdf.get_u64("cpus").ok_or_else(corrupted)? as u32,
+                    salt,
+                },
+                _ => return Err(CryptoError::Unsupported.into()),
+            };
+
+            let key_size = keyslot.get_u64("key_size").ok_or_else(corrupted)? as usize;
+            let stripes = af.get_u64("stripes").ok_or_else(corrupted)? as usize;
+            let area_size = area.get_u64("size").ok_or_else(corrupted)?;
+            if key_size
+                .checked_mul(stripes)
+                .is_none_or(|size| size as u64 > area_size)
+            {
+                return Err(corrupted());
+            }
+
+            return Ok(Keyslot {
+                priority,
+                key_size,
+                kdf,
+                area_offset: area.get_u64("offset").ok_or_else(corrupted)?,
+                area_iv: IvMode::from_encryption(
+                    area.get_str("encryption").ok_or_else(corrupted)?,
+                )?,
+                area_key_size: area.get_u64("key_size").ok_or_else(corrupted)? as usize,
+                stripes,
+                af_hash: hash_from_name(af.get_str("hash").ok_or_else(corrupted)?)?,
+            });
+        };
+
+        return Some(parse());
+    }
+
+    /// The volume key if `keyslot` opens with the passphrase, `None` if the passphrase is wrong.
+    fn open_keyslot<F: Firmware>(
+        &mut self,
+        firmware: &mut F,
+        keyslot: &Keyslot,
+        passphrase: &[u8],
+    ) -> Result<Option<Secret<[u8]>>, RrubError> {
+        if keyslot.key_size == 0
+            || keyslot.key_size > MAX_KEY_SIZE
+            || keyslot.stripes != LUKS_STRIPES
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut derived = Secret::<[u8]>::bytes(keyslot.area_key_size);
+        match &keyslot.kdf {
+            Kdf::Pbkdf2 {
+                hash,
+                iterations,
+                salt,
+            } => hash.pbkdf2(passphrase, salt, *iterations, &mut derived),
+            Kdf::Argon2 {
+                variant,
+                passes,
+                memory,
+                lanes,
+                salt,
+            } => argon2_with_pages(
+                firmware,
+                *variant,
+                passphrase,
+                salt,
+                *passes,
+                *memory,
+                *lanes,
+                &mut derived,
+            )?,
+        }
+
+        let mut xts = Secret::<Xts>::zeroed();
+        xts.set_key(&derived)?;
+        drop(derived);
+
+        let mut material = Secret::<[u8]>::bytes(
+            (keyslot.key_size * keyslot.stripes).next_multiple_of(LUKS_SECTOR_SIZE as usize),
+        );
+        self.device.read_bytes(keyslot.area_offset, &mut material)?;
+        for (sector, data) in material
+            .chunks_exact_mut(LUKS_SECTOR_SIZE as usize)
+            .enumerate()
+        {
+            xts.decrypt(keyslot.area_iv.tweak(sector as u64), data);
+        }
+
+        let mut key = Secret::<[u8]>::bytes(keyslot.key_size);
+        af_merge(&material, keyslot.stripes, keyslot.af_hash, &mut key);
+        return match self.digest.matches(&key) {
+            true => Ok(Some(key)),
+            false => Ok(None),
+        };
+    }
+
+    /// Try every keyslot with the passphrase, highest priority first, for the volume key.
+    pub fn volume_key<F: Firmware>(
+        &mut self,
+        firmware: &mut F,
+        passphrase: &[u8],
+    ) -> Result<Secret<[u8]>, RrubError> {
+        if self.keyslots.is_empty() {
+            return Err(CryptoError::Unsupported.into());
+        }
+
+        let mut keyslots = self.keyslots.clone();
+        keyslots.sort_by_key(|keyslot| core::cmp::Reverse(keyslot.priority));
+        for keyslot in &keyslots {
+            if let Some(key) = self.open_keyslot(firmware, keyslot, passphrase)? {
+                return Ok(key);
+            }
+        }
+
+        return Err(CryptoError::WrongPassphrase.into());
+    }
+
+    /// Prompt for the passphrase until the container unlocks, gives up after a few wrong ones or
+    /// when the prompt is cancelled.
+    pub fn unlock_interactive<F: Firmware>(
+        mut self,
+        firmware: &mut F,
+        input: &InputHandle<F::Input>,
+        prompt: &mut dyn FnMut(&str),
+    ) -> Result<LuksDevice, RrubError> {
+        let name = match (&self.label, self.uuid) {
+            (Some(label), _) => label.clone(),
+            (None, Some(uuid)) => uuid.to_string(),
+            (None, None) => "LUKS volume".to_string(),
+        };
+
+        for attempt in 0..MAX_PASSPHRASE_ATTEMPTS {
+            prompt(&match attempt {
+                0 => format!("Enter passphrase for {}: ", name),
+                _ => format!(
+                    "No key available with this passphrase.\nEnter passphrase for {}: ",
+                    name
+                ),
+            });
+            let passphrase = input
+                .read_passphrase()
+                .ok_or(CryptoError::WrongPassphrase)?;
+
+            match self.volume_key(firmware, &passphrase) {
+                Ok(key) => return LuksDevice::new(self, &key),
+                Err(RrubError::CryptoError(CryptoError::WrongPassphrase)) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        return Err(CryptoError::WrongPassphrase.into());
+    }
+}
+
+impl LuksDevice {
+    fn new(volume: LuksVolume, key: &[u8]) -> Result<LuksDevice, RrubError> {
+        let mut xts = Secret::<Xts>::zeroed();
+        xts.set_key(key)?;
+
+        let segment = volume.segment;
+        let available = volume.device.size().saturating_sub(segment.offset);
+        let size = segment.size.unwrap_or(available).min(available);
+        return Ok(LuksDevice {
+            device: volume.device,
+            xts,
+            block_count: size / segment.sector_size as u64,
+            segment,
+        });
+    }
+}
+
+impl BlockDevice for LuksDevice {
+    fn block_size(&self) -> usize {
+        return self.segment.sector_size;
+    }
+
+    fn block_count(&self) -> u64 {
+        return self.block_count;
+    }
+
+    /// The cleartext goes by the names of the container, as with LUKS on an LVM logical volume.
+    fn device_names(&self) -> Vec<String> {
+        return self.device.device_names();
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+        if keys_wiped() {
+            return Err(CryptoError::KeysWiped.into());
+        }
+
+        let sector_size = self.segment.sector_size;
+        self.device
+            .read_bytes(self.segment.offset + lba * sector_size as u64, buffer)?;
+
+        // The tweak counts in data sectors, the IV offset in 512 byte ones.
+        let ratio = sector_size as u64 / LUKS_SECTOR_SIZE;
+        for (index, sector) in buffer.chunks_exact_mut(sector_size).enumerate() {
+            let number = (self.segment.iv_tweak + (lba + index as u64) * ratio) / ratio;
+            self.xts.decrypt(self.segment.iv.tweak(number), sector);
+        }
+
+        return Ok(());
+    }
+}
+
+/// Just enough JSON for LUKS2 metadata. Numbers are kept as text, since LUKS2 writes 64 bit
+/// values as strings and everything else fits.
+#[derive(Debug, Clone)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Json {
+    fn parse(text: &str) -> Option<Json> {
+        let mut parser = JsonParser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        return match parser.position == parser.text.len() {
+            true => Some(value),
+            false => None,
+        };
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        return self
+            .as_object()?
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value);
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        return self.get(key)?.as_str();
+    }
+
+    fn get_u64(&self, key: &str) -> Option<u64> {
+        return self.get(key)?.as_u64();
+    }
+
+    fn get_base64(&self, key: &str) -> Option<Vec<u8>> {
+        return base64_decode(self.get_str(key)?);
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        return match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        };
+    }
+
+    /// Either a number or a string holding one.
+    fn as_u64(&self) -> Option<u64> {
+        return match self {
+            Json::Number(value) | Json::String(value) => value.parse().ok(),
+            _ => None,
+        };
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        return match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        };
+    }
+
+    fn as_object(&self) -> Option<&[(String, Json)]> {
+        return match self {
+            Json::Object(members) => Some(members),
+            _ => None,
+        };
+    }
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| matches!(c, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> Option<()> {
+        if !self.text[self.position..].starts_with(literal) {
+            return None;
+        }
+        self.position += literal.len();
+        return Some(());
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        if depth > MAX_JSON_DEPTH {
+            return None;
+        }
+
+        self.skip_whitespace();
+        return match *self.text.get(self.position)? {
+            b'n' => self.expect(b"null").map(|_| Json::Null),
+            b't' => self.expect(b"true").map(|_| Json::Bool(true)),
+            b'f' => self.expect(b"false").map(|_| Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.expect(b"]").is_some() {
+                    return Some(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match *self.text.get(self.position)? {
+                        b',' => self.position += 1,
+                        b']' => break,
+                        _ => return None,
+                    }
+                }
+                self.position += 1;
+                Some(Json::Array(values))
+            }
+            b'{' => {
+                self.position += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.expect(b"}").is_some() {
+                    return Some(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(b":")?;
+                    members.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match *self.text.get(self.position)? {
+                        b',' => self.position += 1,
+                        b'}' => break,
+                        _ => return None,
+                    }
+                }
+                self.position += 1;
+                Some(Json::Object(members))
+            }
+            b'-' | b'0'..=b'9' => {
+                let start = self.position;
+                while self
+                    .text
+                    .get(self.position)
+                    .is_some_and(|c| matches!(c, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+                {
+                    self.position += 1;
+                }
+                let number = core::str::from_utf8(&self.text[start..self.position]).ok()?;
+                Some(Json::Number(number.to_string()))
+            }
+            _ => None,
+        };
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect(b"\"")?;
+        let mut value = String::new();
+        loop {
+            let c = *self.text.get(self.position)?;
+            self.position += 1;
+            match c {
+                b'"' => return Some(value),
+                b'\\' => {
+                    let escaped = *self.text.get(self.position)?;
+                    self.position += 1;
+                    value.push(match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\x08',
+                        b'f' => '\x0C',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let digits = self.text.get(self.position..self.position + 4)?;
+                            self.position += 4;
+                            let code =
+                                u32::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+                            // Surrogate pairs never occur in LUKS2 metadata.
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return None,
+                    });
+                }
+                _ => {
+                    // Copy a whole UTF-8 sequence, the text is known to be valid.
+                    let start = self.position - 1;
+                    while self
+                        .text
+                        .get(self.position)
+                        .is_some_and(|&c| c & 0xC0 == 0x80)
+                    {
+                        self.position += 1;
+                    }
+                    value.push_str(core::str::from_utf8(&self.text[start..self.position]).ok()?);
+                }
+            }
+        }
+    }
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            output.push((bits >> count) as u8);
+        }
+    }
+
+    return Some(output);
+}
@@ -0,0 +1,634 @@
+/*
+ * https://github.com/lvmteam/lvm2/blob/main/lib/format_text/layout.h
+ * https://github.com/lvmteam/lvm2/blob/main/doc/lvm_fmt.txt
+ * https://man7.org/linux/man-pages/man7/lvmraid.7.html
+*/
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::cell::RefCell;
+
+use log::debug;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U32, U64},
+};
+
+use crate::{
+    checksum::crc32_update,
+    error::RrubError,
+    firmware::{
+        block::{BlockDevice, check_range},
+        filesystem::FilesystemError,
+    },
+    fs::probe::{Usage, VolumeInfo},
+};
+
+const LABEL_ID: &[u8; 8] = b"LABELONE";
+const LABEL_TYPE: &[u8; 8] = b"LVM2 001";
+/// The label may be in any of the first four sectors.
+const LABEL_SECTORS: u64 = 4;
+/// Offsets and sizes in the metadata are in 512 byte sectors, whatever the device block size.
+const SECTOR_SIZE: u64 = 512;
+
+const MDA_MAGIC: &[u8; 16] = b" LVM2 x[5A%r0N*>";
+const MDA_HEADER_SIZE: u64 = 512;
+/// Seed of the CRC32 LVM checksums its metadata with, without the final inversion.
+const CRC_SEED: u32 = 0xF597_A6CF;
+/// Metadata is a few KiB even for large volume groups, the default area is 1 MiB.
+const MAX_METADATA_SIZE: u64 = 16 * 1024 * 1024;
+/// Sections in the metadata text are only a few levels deep.
+const MAX_METADATA_DEPTH: usize = 16;
+
+/// PVs are shared by all the LVs on them.
+type SharedDevice = Rc<RefCell<Box<dyn BlockDevice>>>;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct LabelHeader {
+    pub id: [u8; 8],
+    pub sector: U64<LittleEndian>,
+    pub crc: U32<LittleEndian>,
+    /// Offset of the PV header from the start of the label.
+    pub offset: U32<LittleEndian>,
+    pub kind: [u8; 8],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct PvHeader {
+    pub uuid: [u8; 32],
+    pub device_size: U64<LittleEndian>,
+}
+
+/// Byte range of a data or metadata area, the lists of them end with a zeroed entry.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DiskLocation {
+    pub offset: U64<LittleEndian>,
+    pub size: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MdaHeader {
+    /// Of the rest of the 512 byte header.
+    pub checksum: U32<LittleEndian>,
+    pub magic: [u8; 16],
+    pub version: U32<LittleEndian>,
+    pub start: U64<LittleEndian>,
+    pub size: U64<LittleEndian>,
+}
+
+/// Where the metadata text is in the circular buffer after the MDA header, the first one is the
+/// committed metadata.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct RawLocation {
+    pub offset: U64<LittleEndian>,
+    pub size: U64<LittleEndian>,
+    pub checksum: U32<LittleEndian>,
+    pub flags: U32<LittleEndian>,
+}
+
+/// A device initialised with `pvcreate` and the volume group metadata stored on it.
+pub struct PhysicalVolume {
+    device: SharedDevice,
+    /// Without the dashes LVM shows it with.
+    uuid: String,
+    /// Metadata text, `None` for PVs created with `--metadatacopies 0`.
+    metadata: Option<String>,
+}
+
+/// Part of a logical volume on one or more PVs, striped in `stripe_size` chunks over several.
+struct Segment {
+    /// In sectors of the LV.
+    start: u64,
+    length: u64,
+    stripe_size: u64,
+    /// Devices and the sector each stripe starts at on them.
+    stripes: Vec<(SharedDevice, u64)>,
+}
+
+/// A linear or striped logical volume, read-only.
+pub struct LogicalVolume {
+    /// `vg/lv`, as in `/dev/vg/lv`.
+    name: String,
+    uuid: String,
+    segments: Vec<Segment>,
+    sector_count: u64,
+}
+
+/// The textual metadata format, a tree of sections with `key = value` settings.
+#[derive(Debug, Clone)]
+enum Value {
+    Number(i64),
+    String(String),
+    Array(Vec<Value>),
+    Section(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        return self
+            .members()?
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value);
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        return match self.get(key)? {
+            Value::String(value) => Some(value),
+            _ => None,
+        };
+    }
+
+    fn get_u64(&self, key: &str) -> Option<u64> {
+        return match self.get(key)? {
+            Value::Number(value) => u64::try_from(*value).ok(),
+            _ => None,
+        };
+    }
+
+    fn get_array(&self, key: &str) -> Option<&[Value]> {
+        return match self.get(key)? {
+            Value::Array(values) => Some(values),
+            _ => None,
+        };
+    }
+
+    fn members(&self) -> Option<&[(String, Value)]> {
+        return match self {
+            Value::Section(members) => Some(members),
+            _ => None,
+        };
+    }
+
+    fn parse(text: &str) -> Option<Value> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let members = parser.members(0)?;
+        return match parser.position == parser.text.len() {
+            true => Some(Value::Section(members)),
+            false => None,
+        };
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<u8> {
+        // Skip whitespace and comments.
+        loop {
+            match *self.text.get(self.position)? {
+                b' ' | b'\t' | b'\n' | b'\r' => self.position += 1,
+                b'#' => {
+                    while self.text.get(self.position).is_some_and(|&c| c != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                c => return Some(c),
+            }
+        }
+    }
+
+    fn members(&mut self, depth: usize) -> Option<Vec<(String, Value)>> {
+        if depth > MAX_METADATA_DEPTH {
+            return None;
+        }
+
+        let mut members = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == b'}' {
+                break;
+            }
+
+            let start = self.position;
+            while self.text.get(self.position).is_some_and(|&c| {
+                c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.' | b'-' | b'+')
+            }) {
+                self.position += 1;
+            }
+            if self.position == start {
+                return None;
+            }
+            let name = core::str::from_utf8(&self.text[start..self.position]).ok()?;
+
+            let value = match self.peek()? {
+                b'{' => {
+                    self.position += 1;
+                    let section = self.members(depth + 1)?;
+                    if self.peek()? != b'}' {
+                        return None;
+                    }
+                    self.position += 1;
+                    Value::Section(section)
+                }
+                b'=' => {
+                    self.position += 1;
+                    self.value()?
+                }
+                _ => return None,
+            };
+            members.push((name.to_string(), value));
+        }
+
+        return Some(members);
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        return match self.peek()? {
+            b'"' => {
+                self.position += 1;
+                let mut value = Vec::new();
+                loop {
+                    match *self.text.get(self.position)? {
+                        b'"' => break,
+                        b'\\' => {
+                            self.position += 1;
+                            value.push(*self.text.get(self.position)?);
+                        }
+                        c => value.push(c),
+                    }
+                    self.position += 1;
+                }
+                self.position += 1;
+                Some(Value::String(String::from_utf8(value).ok()?))
+            }
+            b'[' => {
+                self.position += 1;
+                let mut values = Vec::new();
+                loop {
+                    match self.peek()? {
+                        b']' => break,
+                        b',' => self.position += 1,
+                        _ => values.push(self.value()?),
+                    }
+                }
+                self.position += 1;
+                Some(Value::Array(values))
+            }
+            _ => {
+                let start = self.position;
+                while self
+                    .text
+                    .get(self.position)
+                    .is_some_and(|&c| c == b'-' || c == b'.' || c.is_ascii_digit())
+                {
+                    self.position += 1;
+                }
+                let number = core::str::from_utf8(&self.text[start..self.position]).ok()?;
+                // Floats only appear in settings that don't matter here.
+                let integer = number.split('.').next()?;
+                Some(Value::Number(integer.parse().ok()?))
+            }
+        };
+    }
+}
+
+/// LVM UUIDs are 32 characters, shown in groups of 6-4-4-4-4-4-6 separated by dashes.
+fn strip_uuid(uuid: &str) -> String {
+    return uuid.chars().filter(|&c| c != '-').collect();
+}
+
+impl PhysicalVolume {
+    /// LVM UUIDs are 32 random characters rather than a real UUID, so physical volumes have none.
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let (_, sector, label) = PhysicalVolume::find_label(device)?;
+        let size = sector
+            .get(label.offset.get() as usize..)
+            .and_then(|raw| PvHeader::read_from_prefix(raw).ok())
+            .map(|(header, _)| header.device_size.get())
+            .filter(|&size| size != 0)
+            .unwrap_or(device.size());
+
+        return Some(VolumeInfo {
+            fs_type: "LVM2_member",
+            usage: Usage::Raid,
+            label: None,
+            uuid: None,
+            size,
+        });
+    }
+
+    fn find_label(device: &mut dyn BlockDevice) -> Option<(u64, [u8; 512], LabelHeader)> {
+        let mut sector = [0u8; SECTOR_SIZE as usize];
+        for number in 0..LABEL_SECTORS {
+            device.read_bytes(number * SECTOR_SIZE, &mut sector).ok()?;
+            let Ok((label, _)) = LabelHeader::read_from_prefix(&sector) else {
+                continue;
+            };
+            if &label.id == LABEL_ID && &label.kind == LABEL_TYPE {
+                return Some((number, sector, label));
+            }
+        }
+
+        return None;
+    }
+
+    pub fn open(mut device: Box<dyn BlockDevice>) -> Result<PhysicalVolume, RrubError> {
+        let (_, sector, label) = PhysicalVolume::find_label(device.as_mut())
+            .ok_or(FilesystemError::UnknownFilesystem)?;
+        let raw = sector
+            .get(label.offset.get() as usize..)
+            .ok_or(FilesystemError::Corrupted)?;
+        let (header, mut rest) =
+            PvHeader::read_from_prefix(raw).map_err(|_| FilesystemError::Corrupted)?;
+        let uuid =
+            String::from_utf8(header.uuid.to_vec()).map_err(|_| FilesystemError::Corrupted)?;
+
+        // Data areas come first, then the metadata areas, each list ending with a zeroed entry.
+        let mut lists: [Vec<DiskLocation>; 2] = [Vec::new(), Vec::new()];
+        for list in &mut lists {
+            while let Ok((location, next)) = DiskLocation::read_from_prefix(rest) {
+                rest = next;
+                if location.offset.get() == 0 {
+                    break;
+                }
+                list.push(location);
+            }
+        }
+
+        let mut metadata = None;
+        for area in &lists[1] {
+            match PhysicalVolume::read_metadata(device.as_mut(), area.offset.get()) {
+                Ok(text) => {
+                    metadata = Some(text);
+                    break;
+                }
+                Err(e) => debug!("Skipping LVM metadata area of PV {}: {:?}", uuid, e),
+            }
+        }
+
+        return Ok(PhysicalVolume {
+            device: Rc::new(RefCell::new(device)),
+            uuid,
+            metadata,
+        });
+    }
+
+    fn read_metadata(device: &mut dyn BlockDevice, offset: u64) -> Result<String, RrubError> {
+        let mut raw = [0u8; MDA_HEADER_SIZE as usize];
+        device.read_bytes(offset, &mut raw)?;
+        let (header, rest) =
+            MdaHeader::read_from_prefix(&raw).map_err(|_| FilesystemError::Corrupted)?;
+        if &header.magic != MDA_MAGIC || header.version.get() != 1 || header.start.get() != offset {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+        if crc32_update(CRC_SEED, &raw[4..]) != header.checksum.get() {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+
+        let (location, _) =
+            RawLocation::read_from_prefix(rest).map_err(|_| FilesystemError::Corrupted)?;
+        let area_size = header.size.get();
+        let (start, size) = (location.offset.get(), location.size.get());
+        if size == 0
+            || size > MAX_METADATA_SIZE
+            || size > area_size.saturating_sub(MDA_HEADER_SIZE)
+            || !(MDA_HEADER_SIZE..area_size).contains(&start)
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        // The text is in a circular buffer and wraps around to just after the header.
+        let mut text = vec![0u8; size as usize];
+        let first = size.min(area_size - start) as usize;
+        device.read_bytes(offset + start, &mut text[..first])?;
+        device.read_bytes(offset + MDA_HEADER_SIZE, &mut text[first..])?;
+        if crc32_update(CRC_SEED, &text) != location.checksum.get() {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+
+        // The text ends with a NUL that is counted in the size.
+        let end = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+        text.truncate(end);
+        return String::from_utf8(text).map_err(|_| FilesystemError::Corrupted.into());
+    }
+}
+
+impl LogicalVolume {
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    /// In the dashed form `lvs -o lv_uuid` shows.
+    pub fn uuid(&self) -> &str {
+        return &self.uuid;
+    }
+
+    /// Build a logical volume from its section in the metadata, `None` for types other than
+    /// linear and striped or when a PV it uses is missing.
+    fn from_metadata(
+        vg_name: &str,
+        lv_name: &str,
+        lv: &Value,
+        extent_size: u64,
+        pvs: &BTreeMap<&str, (SharedDevice, u64)>,
+    ) -> Option<LogicalVolume> {
+        let mut segments = Vec::new();
+        for (_, segment) in lv.members()?.iter().filter(|(name, value)| {
+            name.starts_with("segment") && matches!(value, Value::Section(_))
+        }) {
+            if segment.get_str("type") != Some("striped") {
+                debug!(
+                    "Skipping LV {}/{} with {:?} segments",
+                    vg_name,
+                    lv_name,
+                    segment.get_str("type")
+                );
+                return None;
+            }
+
+            let stripe_count = segment.get_u64("stripe_count")?;
+            let list = segment.get_array("stripes")?;
+            if stripe_count == 0 || Some(list.len() as u64) != stripe_count.checked_mul(2) {
+                return None;
+            }
+            let mut stripes = Vec::new();
+            for pair in list.chunks_exact(2) {
+                let (Value::String(pv), Value::Number(extent)) = (&pair[0], &pair[1]) else {
+                    return None;
+                };
+                let Some((device, pe_start)) = pvs.get(pv.as_str()) else {
+                    debug!("LV {}/{} is missing PV {}", vg_name, lv_name, pv);
+                    return None;
+                };
+                stripes.push((
+                    device.clone(),
+                    pe_start + u64::try_from(*extent).ok()? * extent_size,
+                ));
+            }
+
+            segments.push(Segment {
+                start: segment.get_u64("start_extent")?.checked_mul(extent_size)?,
+                length: segment.get_u64("extent_count")?.checked_mul(extent_size)?,
+                stripe_size: match stripe_count {
+                    1 => extent_size,
+                    _ => segment.get_u64("stripe_size").filter(|&size| size != 0)?,
+                },
+                stripes,
+            });
+        }
+
+        segments.sort_by_key(|segment| segment.start);
+        let mut sector_count = 0;
+        for segment in &segments {
+            if segment.start != sector_count {
+                return None;
+            }
+            sector_count = sector_count.checked_add(segment.length)?;
+        }
+
+        return Some(LogicalVolume {
+            name: format!("{}/{}", vg_name, lv_name),
+            uuid: lv.get_str("id")?.to_string(),
+            segments,
+            sector_count,
+        });
+    }
+}
+
+impl BlockDevice for LogicalVolume {
+    fn block_size(&self) -> usize {
+        return SECTOR_SIZE as usize;
+    }
+
+    fn block_count(&self) -> u64 {
+        return self.sector_count;
+    }
+
+    fn device_names(&self) -> Vec<String> {
+        return vec![self.name().to_string(), self.uuid().to_string()];
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+
+        let mut done = 0;
+        while done < buffer.len() {
+            let sector = lba + (done as u64 / SECTOR_SIZE);
+            let segment = self
+                .segments
+                .iter()
+                .find(|segment| sector < segment.start + segment.length)
+                .ok_or(RrubError::OutOfBounds)?;
+
+            // Chunks go round robin over the stripes.
+            let relative = sector - segment.start;
+            let chunk = relative / segment.stripe_size;
+            let within = relative % segment.stripe_size;
+            let stripe_count = segment.stripes.len() as u64;
+            let (device, start) = &segment.stripes[(chunk % stripe_count) as usize];
+            let device_sector = start + (chunk / stripe_count) * segment.stripe_size + within;
+
+            let count = ((segment.stripe_size - within) * SECTOR_SIZE)
+                .min((buffer.len() - done) as u64) as usize;
+            device
+                .borrow_mut()
+                .read_bytes(device_sector * SECTOR_SIZE, &mut buffer[done..done + count])?;
+            done += count;
+        }
+
+        return Ok(());
+    }
+}
+
+/// Assemble the logical volumes of every volume group whose PVs are among `devices`. Volume groups
+/// are matched by their UUID, with the metadata that has the highest sequence number.
+pub fn assemble(devices: Vec<Box<dyn BlockDevice>>) -> Vec<LogicalVolume> {
+    let mut pvs = Vec::new();
+    for device in devices {
+        match PhysicalVolume::open(device) {
+            Ok(pv) => pvs.push(pv),
+            Err(e) => debug!("Skipping LVM PV: {:?}", e),
+        }
+    }
+
+    // The VG section is the only one at the top level of the metadata.
+    let mut groups: BTreeMap<String, (u64, String, Value)> = BTreeMap::new();
+    for pv in &pvs {
+        let Some(metadata) = pv.metadata.as_deref().and_then(Value::parse) else {
+            continue;
+        };
+        let Some((name, vg)) = metadata
+            .members()
+            .into_iter()
+            .flatten()
+            .find(|(_, value)| matches!(value, Value::Section(_)))
+        else {
+            continue;
+        };
+        let (Some(id), Some(seqno)) = (vg.get_str("id"), vg.get_u64("seqno")) else {
+            continue;
+        };
+
+        let newer = groups.get(id).is_none_or(|(known, _, _)| seqno > *known);
+        if newer {
+            groups.insert(id.to_string(), (seqno, name.clone(), vg.clone()));
+        }
+    }
+
+    let mut volumes = Vec::new();
+    for (_, vg_name, vg) in groups.into_values() {
+        let Some(extent_size) = vg.get_u64("extent_size").filter(|&size| size != 0) else {
+            continue;
+        };
+
+        // PVs by their name in the metadata, e.g. "pv0", and the sector their extents start at.
+        let mut members = BTreeMap::new();
+        for (name, pv) in vg
+            .get("physical_volumes")
+            .and_then(Value::members)
+            .unwrap_or_default()
+        {
+            let (Some(id), Some(pe_start)) = (pv.get_str("id"), pv.get_u64("pe_start")) else {
+                continue;
+            };
+            let id = strip_uuid(id);
+            match pvs.iter().find(|pv| pv.uuid == id) {
+                Some(found) => {
+                    members.insert(name.as_str(), (found.device.clone(), pe_start));
+                }
+                None => debug!("PV {} of VG {} is missing", id, vg_name),
+            }
+        }
+
+        for (lv_name, lv) in vg
+            .get("logical_volumes")
+            .and_then(Value::members)
+            .unwrap_or_default()
+        {
+            // Hidden LVs are the parts of others, such as RAID images or thin pool data.
+            let visible = lv.get_array("status").is_some_and(|status| {
+                status
+                    .iter()
+                    .any(|flag| matches!(flag, Value::String(flag) if flag == "VISIBLE"))
+            });
+            if !visible {
+                continue;
+            }
+            if let Some(volume) =
+                LogicalVolume::from_metadata(&vg_name, lv_name, lv, extent_size, &members)
+            {
+                volumes.push(volume);
+            }
+        }
+    }
+
+    return volumes;
+}
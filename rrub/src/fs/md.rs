@@ -0,0 +1,780 @@
+/*
+ * https://raid.wiki.kernel.org/index.php/RAID_superblock_formats
+ * https://github.com/torvalds/linux/blob/master/include/uapi/linux/raid/md_p.h
+ * https://github.com/torvalds/linux/blob/master/drivers/md/raid0.c
+ * https://github.com/torvalds/linux/blob/master/drivers/md/raid10.c
+ * https://github.com/torvalds/linux/blob/master/drivers/md/raid5.c
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String, vec, vec::Vec};
+
+use log::{debug, warn};
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{I32, U16, U32, U64},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::{BlockDevice, check_range},
+        filesystem::{FilesystemError, Uuid},
+    },
+    fs::probe::{Usage, VolumeInfo, label_from_bytes},
+};
+
+const MAGIC: u32 = 0xA92B_4EFC;
+const SECTOR_SIZE: u64 = 512;
+/// Version 0.90 superblocks are in the last 64 KiB aligned block of a member.
+const RESERVED_SIZE: u64 = 64 * 1024;
+/// Version 1 superblocks with their role table fit in 4 KiB.
+const MAX_SUPERBLOCK_SIZE: usize = 4096;
+const MAX_RAID_DISKS: usize = (MAX_SUPERBLOCK_SIZE - size_of::<MdSuperblock1>()) / 2;
+
+/// Roles of version 1 members that hold no data of the array.
+const ROLE_JOURNAL: u16 = 0xFFFD;
+/// Set while a member is being rebuilt, only the part below `recovery_offset` is valid.
+const FEATURE_RECOVERY_OFFSET: u32 = 1 << 1;
+const FEATURE_RESHAPE_ACTIVE: u32 = 1 << 2;
+
+const DISK_FAULTY: u32 = 1 << 0;
+const DISK_SYNC: u32 = 1 << 2;
+
+const RAID0_ORIGINAL_LAYOUT: u32 = 1;
+const RAID0_ALTERNATE_LAYOUT: u32 = 2;
+
+/// Members whose superblock is one event behind the newest are still in sync, as the kernel
+/// and mdadm allow.
+const EVENT_MARGIN: u64 = 1;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MdDisk0 {
+    pub number: U32<LittleEndian>,
+    pub major: U32<LittleEndian>,
+    pub minor: U32<LittleEndian>,
+    pub raid_disk: U32<LittleEndian>,
+    pub state: U32<LittleEndian>,
+    pub _reserved: [U32<LittleEndian>; 27],
+}
+
+/// Written in the byte order of the host that created it, only little endian ones are read.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MdSuperblock0 {
+    pub magic: U32<LittleEndian>,
+    pub major_version: U32<LittleEndian>,
+    pub minor_version: U32<LittleEndian>,
+    pub patch_version: U32<LittleEndian>,
+    pub valid_words: U32<LittleEndian>,
+    pub set_uuid0: [u8; 4],
+    pub create_time: U32<LittleEndian>,
+    pub level: I32<LittleEndian>,
+    /// Used size of each member in KiB.
+    pub size: U32<LittleEndian>,
+    pub disk_count: U32<LittleEndian>,
+    pub raid_disks: U32<LittleEndian>,
+    pub md_minor: U32<LittleEndian>,
+    pub not_persistent: U32<LittleEndian>,
+    pub set_uuid1: [u8; 4],
+    pub set_uuid2: [u8; 4],
+    pub set_uuid3: [u8; 4],
+    pub _reserved0: [U32<LittleEndian>; 16],
+    pub update_time: U32<LittleEndian>,
+    pub state: U32<LittleEndian>,
+    pub active_disks: U32<LittleEndian>,
+    pub working_disks: U32<LittleEndian>,
+    pub failed_disks: U32<LittleEndian>,
+    pub spare_disks: U32<LittleEndian>,
+    pub checksum: U32<LittleEndian>,
+    pub events_low: U32<LittleEndian>,
+    pub events_high: U32<LittleEndian>,
+    pub _reserved1: [U32<LittleEndian>; 23],
+    pub layout: U32<LittleEndian>,
+    /// In bytes.
+    pub chunk_size: U32<LittleEndian>,
+    pub _reserved2: [U32<LittleEndian>; 62],
+    pub disks: [MdDisk0; 27],
+    pub this_disk: MdDisk0,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MdSuperblock1 {
+    pub magic: U32<LittleEndian>,
+    pub major_version: U32<LittleEndian>,
+    pub feature_map: U32<LittleEndian>,
+    pub _pad: U32<LittleEndian>,
+    pub set_uuid: [u8; 16],
+    pub set_name: [u8; 32],
+    pub create_time: U64<LittleEndian>,
+    pub level: I32<LittleEndian>,
+    pub layout: U32<LittleEndian>,
+    /// Used size of each member in sectors.
+    pub size: U64<LittleEndian>,
+    /// In sectors.
+    pub chunk_size: U32<LittleEndian>,
+    pub raid_disks: U32<LittleEndian>,
+    pub bitmap_offset: U32<LittleEndian>,
+    pub new_level: U32<LittleEndian>,
+    pub reshape_position: U64<LittleEndian>,
+    pub delta_disks: U32<LittleEndian>,
+    pub new_layout: U32<LittleEndian>,
+    pub new_chunk: U32<LittleEndian>,
+    pub new_offset: U32<LittleEndian>,
+    pub data_offset: U64<LittleEndian>,
+    pub data_size: U64<LittleEndian>,
+    pub super_offset: U64<LittleEndian>,
+    pub recovery_offset: U64<LittleEndian>,
+    pub dev_number: U32<LittleEndian>,
+    pub corrected_reads: U32<LittleEndian>,
+    pub device_uuid: [u8; 16],
+    pub device_flags: u8,
+    pub bad_block_shift: u8,
+    pub bad_block_size: U16<LittleEndian>,
+    pub bad_block_offset: U32<LittleEndian>,
+    pub update_time: U64<LittleEndian>,
+    pub events: U64<LittleEndian>,
+    pub resync_offset: U64<LittleEndian>,
+    pub checksum: U32<LittleEndian>,
+    /// Entries in the role table that follows.
+    pub max_dev: U32<LittleEndian>,
+    pub _pad3: [u8; 32],
+}
+
+/// Sum of the little endian words of a superblock with its checksum field zeroed, folded to 32
+/// bits. A trailing half word is added on its own.
+fn superblock_checksum(raw: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum = 0u64;
+    for (index, word) in raw.chunks(4).enumerate() {
+        sum += match word.len() {
+            4 if index * 4 == checksum_offset => 0,
+            4 => u32::from_le_bytes(word.try_into().unwrap()) as u64,
+            _ => u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u64,
+        };
+    }
+    return (sum as u32).wrapping_add((sum >> 32) as u32);
+}
+
+/// What assembly needs from the superblock of a member, whichever version it has.
+#[derive(Debug, Clone)]
+struct Superblock {
+    uuid: [u8; 16],
+    name: String,
+    level: i32,
+    layout: u32,
+    /// In sectors.
+    chunk_size: u64,
+    raid_disks: u32,
+    events: u64,
+    /// Slot of the member in the array, `None` for spares, faulty and partly rebuilt members.
+    role: Option<u32>,
+    /// Used sectors of each member, the same on all of them.
+    size: u64,
+    data_offset: u64,
+    /// Sectors of data this member holds, which RAID0 uses whole.
+    data_size: u64,
+}
+
+fn read_superblock1(device: &mut dyn BlockDevice) -> Option<Superblock> {
+    // Version 1.1 at the start, 1.2 4 KiB in and 1.0 at least 8 KiB before the end.
+    let end = (device.size() / SECTOR_SIZE)
+        .checked_sub(16)
+        .map(|sector| sector & !7);
+    for sector in [Some(0), Some(8), end].into_iter().flatten() {
+        let mut raw = vec![0u8; size_of::<MdSuperblock1>()];
+        if device.read_bytes(sector * SECTOR_SIZE, &mut raw).is_err() {
+            continue;
+        }
+        let superblock = MdSuperblock1::read_from_bytes(&raw).ok()?;
+        if superblock.magic.get() != MAGIC || superblock.major_version.get() != 1 {
+            continue;
+        }
+
+        let max_dev = superblock.max_dev.get() as usize;
+        let length = size_of::<MdSuperblock1>() + max_dev * 2;
+        let data_end = superblock
+            .data_offset
+            .get()
+            .checked_add(superblock.data_size.get());
+        if max_dev > MAX_RAID_DISKS
+            || superblock.super_offset.get() != sector
+            || data_end.is_none_or(|end| end > device.size() / SECTOR_SIZE)
+        {
+            debug!("md superblock at sector {} is corrupted", sector);
+            continue;
+        }
+        raw.resize(length, 0);
+        if device.read_bytes(sector * SECTOR_SIZE, &mut raw).is_err() {
+            continue;
+        }
+        let checksum_offset = core::mem::offset_of!(MdSuperblock1, checksum);
+        if superblock_checksum(&raw, checksum_offset) != superblock.checksum.get() {
+            debug!("md superblock at sector {} has a bad checksum", sector);
+            continue;
+        }
+        let features = superblock.feature_map.get();
+        if features & FEATURE_RESHAPE_ACTIVE != 0 {
+            debug!("md array is being reshaped");
+            return None;
+        }
+
+        let index = superblock.dev_number.get() as usize;
+        let role = raw[size_of::<MdSuperblock1>()..]
+            .chunks_exact(2)
+            .nth(index)
+            .map(|role| u16::from_le_bytes([role[0], role[1]]))
+            .filter(|&role| role < ROLE_JOURNAL && features & FEATURE_RECOVERY_OFFSET == 0);
+
+        return Some(Superblock {
+            uuid: superblock.set_uuid,
+            name: label_from_bytes(&superblock.set_name).unwrap_or_default(),
+            level: superblock.level.get(),
+            layout: superblock.layout.get(),
+            chunk_size: superblock.chunk_size.get() as u64,
+            raid_disks: superblock.raid_disks.get(),
+            events: superblock.events.get(),
+            role: role.map(u32::from),
+            size: superblock.size.get(),
+            data_offset: superblock.data_offset.get(),
+            data_size: superblock.data_size.get(),
+        });
+    }
+
+    return None;
+}
+
+fn read_superblock0(device: &mut dyn BlockDevice) -> Option<Superblock> {
+    let offset = (device.size() & !(RESERVED_SIZE - 1)).checked_sub(RESERVED_SIZE)?;
+    let mut raw = [0u8; size_of::<MdSuperblock0>()];
+    device.read_bytes(offset, &mut raw).ok()?;
+    let superblock = MdSuperblock0::read_from_bytes(&raw).ok()?;
+    if superblock.magic.get() != MAGIC || superblock.major_version.get() != 0 {
+        return None;
+    }
+    // Minor version 91 marks a reshape in progress.
+    if superblock.minor_version.get() != 90 {
+        debug!(
+            "md 0.{} superblock is not supported",
+            superblock.minor_version.get()
+        );
+        return None;
+    }
+    let checksum_offset = core::mem::offset_of!(MdSuperblock0, checksum);
+    if superblock_checksum(&raw, checksum_offset) != superblock.checksum.get() {
+        debug!("md 0.90 superblock has a bad checksum");
+        return None;
+    }
+
+    let mut uuid = [0u8; 16];
+    for (part, id) in uuid.chunks_exact_mut(4).zip([
+        superblock.set_uuid0,
+        superblock.set_uuid1,
+        superblock.set_uuid2,
+        superblock.set_uuid3,
+    ]) {
+        part.copy_from_slice(&id);
+    }
+
+    // The state of a member is kept in the table of all of them rather than in `this_disk`.
+    let disk = superblock
+        .disks
+        .get(superblock.this_disk.number.get() as usize)?;
+    let state = disk.state.get();
+    let role = (state & DISK_SYNC != 0 && state & DISK_FAULTY == 0).then_some(disk.raid_disk.get());
+
+    return Some(Superblock {
+        uuid,
+        name: format!("md{}", superblock.md_minor.get()),
+        level: superblock.level.get(),
+        layout: superblock.layout.get(),
+        chunk_size: superblock.chunk_size.get() as u64 / SECTOR_SIZE,
+        raid_disks: superblock.raid_disks.get(),
+        events: (superblock.events_high.get() as u64) << 32 | superblock.events_low.get() as u64,
+        role,
+        size: superblock.size.get() as u64 * 2,
+        data_offset: 0,
+        data_size: offset / SECTOR_SIZE,
+    });
+}
+
+fn read_superblock(device: &mut dyn BlockDevice) -> Option<Superblock> {
+    return read_superblock1(device).or_else(|| read_superblock0(device));
+}
+
+/// md metadata 0.90 and 1.0 is at the end of a member, which starts with the filesystem of the
+/// array.
+pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+    let superblock = read_superblock(device)?;
+    return Some(VolumeInfo {
+        fs_type: "linux_raid_member",
+        usage: Usage::Raid,
+        label: Some(superblock.name).filter(|name| !name.is_empty()),
+        uuid: Some(Uuid::RealUuid(RealUuid::from_bytes(superblock.uuid))),
+        size: superblock.size.saturating_mul(SECTOR_SIZE),
+    });
+}
+
+/// A device with the superblock read from it, before it takes its slot in an array.
+type Found = (Superblock, Box<dyn BlockDevice>);
+
+struct Member {
+    device: Box<dyn BlockDevice>,
+    data_offset: u64,
+}
+
+/// Part of a RAID0 array striped over the members that are large enough for it.
+struct Zone {
+    /// Array sector the zone ends at.
+    end: u64,
+    /// Member sector the zone starts at.
+    member_start: u64,
+    members: Vec<usize>,
+}
+
+enum Level {
+    Raid0 {
+        zones: Vec<Zone>,
+        /// Later zones are mapped by the sector within them rather than within the array.
+        alternate: bool,
+    },
+    Raid1,
+    Raid10 {
+        near: u64,
+        far: u64,
+        far_offset: bool,
+        far_set_size: u64,
+        /// Sectors between the far copies of a chunk on a member.
+        stride: u64,
+    },
+    Raid5 {
+        algorithm: u32,
+    },
+}
+
+/// A read-only md array, assembled from the members present and reading around missing or
+/// failing ones where the level has redundancy.
+pub struct MdArray {
+    name: String,
+    uuid: [u8; 16],
+    level: Level,
+    /// In sectors.
+    chunk_size: u64,
+    /// By role, `None` where a member is missing.
+    members: Vec<Option<Member>>,
+    sector_count: u64,
+}
+
+impl MdArray {
+    /// As mdadm shows it, e.g. "hostname:boot" for version 1 or "md0" for 0.90 arrays.
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub fn uuid(&self) -> Uuid {
+        return Uuid::RealUuid(RealUuid::from_bytes(self.uuid));
+    }
+
+    fn new(members: Vec<Found>) -> Result<MdArray, RrubError> {
+        // The newest superblock describes the array, members that missed updates are stale.
+        let newest = members
+            .iter()
+            .map(|(superblock, _)| superblock)
+            .max_by_key(|superblock| superblock.events)
+            .ok_or(FilesystemError::NotFound)?
+            .clone();
+        let raid_disks = newest.raid_disks as usize;
+        if raid_disks == 0 || raid_disks > MAX_RAID_DISKS {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut slots: Vec<Option<Found>> = (0..raid_disks).map(|_| None).collect();
+        for (superblock, device) in members {
+            let Some(role) = superblock.role.filter(|&role| (role as usize) < raid_disks) else {
+                debug!(
+                    "Skipping spare or faulty member of md array {}",
+                    newest.name
+                );
+                continue;
+            };
+            if superblock.events + EVENT_MARGIN < newest.events {
+                warn!("Skipping stale member {} of md array {}", role, newest.name);
+                continue;
+            }
+            let slot = &mut slots[role as usize];
+            if slot
+                .as_ref()
+                .is_none_or(|(other, _)| superblock.events > other.events)
+            {
+                *slot = Some((superblock, device));
+            }
+        }
+
+        let present = slots.iter().filter(|slot| slot.is_some()).count();
+        if present < raid_disks {
+            warn!(
+                "md array {} is degraded, {} of {} members present",
+                newest.name, present, raid_disks
+            );
+        }
+
+        let chunk_size = newest.chunk_size;
+        if newest.level != 1 && chunk_size == 0 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let disks = raid_disks as u64;
+        let (level, sector_count) = match newest.level {
+            0 => {
+                if present < raid_disks {
+                    return Err(FilesystemError::NotFound.into());
+                }
+                let sizes: Vec<u64> = slots
+                    .iter()
+                    .flatten()
+                    .map(|(superblock, _)| superblock.data_size / chunk_size * chunk_size)
+                    .collect();
+                let zones = raid0_zones(&sizes);
+                let alternate = match (zones.len(), newest.layout) {
+                    (1, _) => false,
+                    (_, RAID0_ORIGINAL_LAYOUT) => false,
+                    (_, RAID0_ALTERNATE_LAYOUT) => true,
+                    _ => {
+                        debug!("md RAID0 with zones and layout {}", newest.layout);
+                        return Err(FilesystemError::Unsupported.into());
+                    }
+                };
+                let sector_count = zones.last().map_or(0, |zone| zone.end);
+                (Level::Raid0 { zones, alternate }, sector_count)
+            }
+            1 => (Level::Raid1, newest.size),
+            10 => {
+                let layout = newest.layout;
+                let near = (layout & 0xFF) as u64;
+                let far = ((layout >> 8) & 0xFF) as u64;
+                let far_set_size = match layout >> 17 {
+                    0 => disks,
+                    2 => far * near,
+                    _ => return Err(FilesystemError::Unsupported.into()),
+                };
+                if near == 0 || far == 0 || far_set_size == 0 || near * far > disks {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+
+                // As calc_sectors() in raid10.c.
+                let array_chunks = newest.size / chunk_size / far * disks / near;
+                let member_chunks = (array_chunks * near * far).div_ceil(disks);
+                let far_offset = layout & (1 << 16) != 0;
+                let stride = match far_offset {
+                    true => chunk_size,
+                    false => member_chunks / far * chunk_size,
+                };
+                let level = Level::Raid10 {
+                    near,
+                    far,
+                    far_offset,
+                    far_set_size,
+                    stride,
+                };
+                (level, array_chunks * chunk_size)
+            }
+            5 => {
+                if newest.layout > 3 || disks < 2 {
+                    return Err(FilesystemError::Unsupported.into());
+                }
+                if present + 1 < raid_disks {
+                    return Err(FilesystemError::NotFound.into());
+                }
+                let level = Level::Raid5 {
+                    algorithm: newest.layout,
+                };
+                (level, newest.size / chunk_size * chunk_size * (disks - 1))
+            }
+            level => {
+                debug!("md RAID level {} is not supported", level);
+                return Err(FilesystemError::Unsupported.into());
+            }
+        };
+
+        let array = MdArray {
+            name: newest.name,
+            uuid: newest.uuid,
+            level,
+            chunk_size,
+            members: slots
+                .into_iter()
+                .map(|slot| {
+                    slot.map(|(superblock, device)| Member {
+                        device,
+                        data_offset: superblock.data_offset,
+                    })
+                })
+                .collect(),
+            sector_count,
+        };
+
+        // The RAID10 layout repeats every `raid_disks` chunks, check each chunk of that has a
+        // copy left.
+        if matches!(array.level, Level::Raid10 { .. }) {
+            for chunk in 0..disks {
+                let copies = array.raid10_copies(chunk * chunk_size);
+                if copies
+                    .iter()
+                    .all(|&(member, _)| array.members[member].is_none())
+                {
+                    return Err(FilesystemError::NotFound.into());
+                }
+            }
+        }
+
+        return Ok(array);
+    }
+
+    /// The members and sectors holding the chunk an array sector is in, as raid10_find_phys().
+    fn raid10_copies(&self, sector: u64) -> Vec<(usize, u64)> {
+        let Level::Raid10 {
+            near,
+            far,
+            far_offset,
+            far_set_size,
+            stride,
+        } = self.level
+        else {
+            return Vec::new();
+        };
+        let disks = self.members.len() as u64;
+        let last_far_set_start = (disks / far_set_size - 1) * far_set_size;
+        let last_far_set_size = far_set_size + disks % far_set_size;
+
+        let chunk = sector / self.chunk_size * near;
+        let mut member_sector = sector % self.chunk_size;
+        let mut stripe = chunk / disks;
+        let mut disk = chunk % disks;
+        if far_offset {
+            stripe *= far;
+        }
+        member_sector += stripe * self.chunk_size;
+
+        let mut copies = Vec::new();
+        for _ in 0..near {
+            let mut d = disk;
+            let mut s = member_sector;
+            copies.push((d as usize, s));
+            for _ in 1..far {
+                let set = d / far_set_size;
+                d += near;
+                match !disks.is_multiple_of(far_set_size) && d > last_far_set_start {
+                    true => d = (d - last_far_set_start) % last_far_set_size + last_far_set_start,
+                    false => d = d % far_set_size + far_set_size * set,
+                }
+                s += stride;
+                copies.push((d as usize, s));
+            }
+
+            disk += 1;
+            if disk >= disks {
+                disk = 0;
+                member_sector += self.chunk_size;
+            }
+        }
+
+        return copies;
+    }
+
+    fn read_member(
+        &mut self,
+        index: usize,
+        sector: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), RrubError> {
+        let member = self.members[index]
+            .as_mut()
+            .ok_or(FilesystemError::NotFound)?;
+        return member
+            .device
+            .read_bytes((member.data_offset + sector) * SECTOR_SIZE, buffer);
+    }
+
+    /// Read from the first copy that can be read.
+    fn read_copies(&mut self, copies: &[(usize, u64)], buffer: &mut [u8]) -> Result<(), RrubError> {
+        let mut result = Err(FilesystemError::NotFound.into());
+        for &(index, sector) in copies {
+            if self.members[index].is_none() {
+                continue;
+            }
+            result = self.read_member(index, sector, buffer);
+            match &result {
+                Ok(()) => break,
+                Err(e) => warn!(
+                    "md array {} member {} failed to read sector {}: {:?}",
+                    self.name, index, sector, e
+                ),
+            }
+        }
+
+        return result;
+    }
+
+    /// Read a data chunk of RAID5, or rebuild it from the parity and the other chunks of its
+    /// stripe when its member is missing or fails.
+    fn read_raid5(&mut self, data: usize, sector: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        if self.members[data].is_some() {
+            match self.read_member(data, sector, buffer) {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!(
+                    "md array {} member {} failed to read sector {}: {:?}",
+                    self.name, data, sector, e
+                ),
+            }
+        }
+
+        buffer.fill(0);
+        let mut scratch = vec![0u8; buffer.len()];
+        for index in (0..self.members.len()).filter(|&index| index != data) {
+            self.read_member(index, sector, &mut scratch)?;
+            for (byte, other) in buffer.iter_mut().zip(&scratch) {
+                *byte ^= other;
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+/// Split RAID0 members of different sizes into zones, as create_strip_zones() in raid0.c. Sizes
+/// are in sectors, rounded down to the chunk size.
+fn raid0_zones(sizes: &[u64]) -> Vec<Zone> {
+    let mut zones = Vec::new();
+    let mut member_start = 0;
+    let mut end = 0;
+    loop {
+        let members: Vec<usize> = (0..sizes.len())
+            .filter(|&index| sizes[index] > member_start)
+            .collect();
+        let Some(smallest) = members.iter().map(|&index| sizes[index]).min() else {
+            break;
+        };
+
+        end += (smallest - member_start) * members.len() as u64;
+        zones.push(Zone {
+            end,
+            member_start,
+            members,
+        });
+        member_start = smallest;
+    }
+
+    return zones;
+}
+
+impl BlockDevice for MdArray {
+    fn block_size(&self) -> usize {
+        return SECTOR_SIZE as usize;
+    }
+
+    fn block_count(&self) -> u64 {
+        return self.sector_count;
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+
+        let mut done = 0;
+        while done < buffer.len() {
+            let sector = lba + (done as u64 / SECTOR_SIZE);
+            let remaining = (buffer.len() - done) as u64;
+            // RAID1 members are whole copies, the other levels are read a chunk at a time.
+            let count = match self.level {
+                Level::Raid1 => remaining,
+                _ => ((self.chunk_size - sector % self.chunk_size) * SECTOR_SIZE).min(remaining),
+            } as usize;
+            let piece = &mut buffer[done..done + count];
+            let within = sector % self.chunk_size.max(1);
+
+            match &self.level {
+                Level::Raid0 { zones, alternate } => {
+                    let index = zones
+                        .iter()
+                        .position(|zone| sector < zone.end)
+                        .ok_or(RrubError::OutOfBounds)?;
+                    let zone = &zones[index];
+                    let zone_sector = match index {
+                        0 => sector,
+                        _ => sector - zones[index - 1].end,
+                    };
+                    let width = zone.members.len() as u64;
+                    let chunk = match alternate {
+                        true => zone_sector / self.chunk_size,
+                        false => sector / self.chunk_size,
+                    };
+                    let member = zone.members[(chunk % width) as usize];
+                    let member_sector = zone.member_start
+                        + zone_sector / (self.chunk_size * width) * self.chunk_size
+                        + within;
+                    self.read_member(member, member_sector, piece)?;
+                }
+                Level::Raid1 => {
+                    let copies: Vec<(usize, u64)> = (0..self.members.len())
+                        .map(|index| (index, sector))
+                        .collect();
+                    self.read_copies(&copies, piece)?;
+                }
+                Level::Raid10 { .. } => {
+                    let copies = self.raid10_copies(sector);
+                    self.read_copies(&copies, piece)?;
+                }
+                &Level::Raid5 { algorithm } => {
+                    // As raid5_compute_sector() for the four RAID5 algorithms.
+                    let disks = self.members.len() as u64;
+                    let chunk = sector / self.chunk_size;
+                    let stripe = chunk / (disks - 1);
+                    let mut data = chunk % (disks - 1);
+                    let parity = match algorithm {
+                        0 | 2 => disks - 1 - stripe % disks,
+                        _ => stripe % disks,
+                    };
+                    data = match algorithm {
+                        0 | 1 if data >= parity => data + 1,
+                        0 | 1 => data,
+                        _ => (parity + 1 + data) % disks,
+                    };
+                    let member_sector = stripe * self.chunk_size + within;
+                    self.read_raid5(data as usize, member_sector, piece)?;
+                }
+            }
+            done += count;
+        }
+
+        return Ok(());
+    }
+}
+
+/// Assemble every md array with members among `devices`, degraded ones included as long as
+/// their data can still be read.
+pub fn assemble(devices: Vec<Box<dyn BlockDevice>>) -> Vec<MdArray> {
+    let mut arrays: BTreeMap<[u8; 16], Vec<Found>> = BTreeMap::new();
+    for mut device in devices {
+        match read_superblock(device.as_mut()) {
+            Some(superblock) => arrays
+                .entry(superblock.uuid)
+                .or_default()
+                .push((superblock, device)),
+            None => debug!("Skipping device without an md superblock"),
+        }
+    }
+
+    let mut assembled = Vec::new();
+    for (uuid, members) in arrays {
+        match MdArray::new(members) {
+            Ok(array) => assembled.push(array),
+            Err(e) => warn!(
+                "Cannot assemble md array {}: {:?}",
+                RealUuid::from_bytes(uuid),
+                e
+            ),
+        }
+    }
+
+    return assembled;
+}
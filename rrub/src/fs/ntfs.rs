@@ -0,0 +1,1063 @@
+/*
+ * https://flatcap.github.io/linux-ntfs/ntfs/index.html
+ * https://github.com/tuxera/ntfs-3g/blob/edge/include/ntfs-3g/layout.h
+*/
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+            VolumeId64,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo},
+};
+
+const OEM_ID: &[u8; 8] = b"NTFS    ";
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+const RECORD_MAGIC: &[u8; 4] = b"FILE";
+const INDEX_MAGIC: &[u8; 4] = b"INDX";
+/// Multi sector records are protected by an update sequence at the end of every 512 bytes,
+/// whatever the sector size.
+const FIXUP_STRIDE: usize = 512;
+
+const RECORD_IN_USE: u16 = 0x1;
+const RECORD_IS_DIRECTORY: u16 = 0x2;
+
+const MFT_RECORD: u64 = 0;
+const VOLUME_RECORD: u64 = 3;
+const ROOT_RECORD: u64 = 5;
+const UPCASE_RECORD: u64 = 10;
+/// Records below this hold the metadata files, which are hidden from listings.
+const FIRST_USER_RECORD: u64 = 16;
+const REFERENCE_RECORD_MASK: u64 = 0xFFFF_FFFF_FFFF;
+
+const ATTRIBUTE_LIST: u32 = 0x20;
+const ATTRIBUTE_VOLUME_NAME: u32 = 0x60;
+const ATTRIBUTE_DATA: u32 = 0x80;
+const ATTRIBUTE_INDEX_ROOT: u32 = 0x90;
+const ATTRIBUTE_INDEX_ALLOCATION: u32 = 0xA0;
+const ATTRIBUTE_REPARSE_POINT: u32 = 0xC0;
+const ATTRIBUTE_END: u32 = 0xFFFF_FFFF;
+
+const ATTRIBUTE_COMPRESSION_MASK: u16 = 0x00FF;
+const ATTRIBUTE_ENCRYPTED: u16 = 0x4000;
+
+/// Name of the filename index of directories.
+const INDEX_NAME: &[u16] = &[b'$' as u16, b'I' as u16, b'3' as u16, b'0' as u16];
+const ENTRY_SUBNODE: u16 = 0x1;
+const ENTRY_LAST: u16 = 0x2;
+const MAX_INDEX_DEPTH: usize = 32;
+
+const FILE_NAME_DOS: u8 = 2;
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x1000_0000;
+
+const REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+const REPARSE_TAG_LX_SYMLINK: u32 = 0xA000_001D;
+const SYMLINK_RELATIVE: u32 = 0x1;
+const LX_SYMLINK_VERSION: u32 = 2;
+
+const UPCASE_ENTRIES: usize = 0x10000;
+const MAX_RECORD_SIZE: u64 = 64 * 1024;
+const MAX_ATTRIBUTE_LIST_SIZE: u64 = 256 * 1024;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BootSector {
+    pub jump: [u8; 3],
+    pub oem_id: [u8; 8],
+    pub bytes_per_sector: U16<LittleEndian>,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: U16<LittleEndian>,
+    pub unused: [u8; 5],
+    pub media: u8,
+    pub unused2: U16<LittleEndian>,
+    pub sectors_per_track: U16<LittleEndian>,
+    pub heads: U16<LittleEndian>,
+    pub hidden_sectors: U32<LittleEndian>,
+    pub unused3: [u8; 8],
+    pub total_sectors: U64<LittleEndian>,
+    pub mft_cluster: U64<LittleEndian>,
+    pub mft_mirror_cluster: U64<LittleEndian>,
+    /// Clusters per record if positive, otherwise the negated log2 of the size in bytes.
+    pub clusters_per_record: i8,
+    pub unused4: [u8; 3],
+    pub clusters_per_index: i8,
+    pub unused5: [u8; 3],
+    pub serial: U64<LittleEndian>,
+    pub checksum: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct RecordHeader {
+    pub magic: [u8; 4],
+    pub update_sequence_offset: U16<LittleEndian>,
+    pub update_sequence_count: U16<LittleEndian>,
+    pub log_sequence: U64<LittleEndian>,
+    pub sequence: U16<LittleEndian>,
+    pub link_count: U16<LittleEndian>,
+    pub attributes_offset: U16<LittleEndian>,
+    pub flags: U16<LittleEndian>,
+    pub used_size: U32<LittleEndian>,
+    pub allocated_size: U32<LittleEndian>,
+    pub base_record: U64<LittleEndian>,
+    pub next_attribute_id: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct AttributeHeader {
+    pub kind: U32<LittleEndian>,
+    pub length: U32<LittleEndian>,
+    pub non_resident: u8,
+    pub name_length: u8,
+    pub name_offset: U16<LittleEndian>,
+    pub flags: U16<LittleEndian>,
+    pub instance: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ResidentHeader {
+    pub value_length: U32<LittleEndian>,
+    pub value_offset: U16<LittleEndian>,
+    pub indexed: u8,
+    pub reserved: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct NonResidentHeader {
+    pub lowest_vcn: U64<LittleEndian>,
+    pub highest_vcn: U64<LittleEndian>,
+    pub mapping_pairs_offset: U16<LittleEndian>,
+    pub compression_unit: u8,
+    pub reserved: [u8; 5],
+    pub allocated_size: U64<LittleEndian>,
+    pub data_size: U64<LittleEndian>,
+    pub initialized_size: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct AttributeListEntry {
+    pub kind: U32<LittleEndian>,
+    pub length: U16<LittleEndian>,
+    pub name_length: u8,
+    pub name_offset: u8,
+    pub lowest_vcn: U64<LittleEndian>,
+    pub reference: U64<LittleEndian>,
+    pub instance: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FileName {
+    pub parent: U64<LittleEndian>,
+    pub creation_time: U64<LittleEndian>,
+    pub modification_time: U64<LittleEndian>,
+    pub record_modification_time: U64<LittleEndian>,
+    pub access_time: U64<LittleEndian>,
+    pub allocated_size: U64<LittleEndian>,
+    pub data_size: U64<LittleEndian>,
+    pub flags: U32<LittleEndian>,
+    pub reparse_tag: U32<LittleEndian>,
+    pub name_length: u8,
+    pub namespace: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct IndexRoot {
+    pub attribute_type: U32<LittleEndian>,
+    pub collation: U32<LittleEndian>,
+    pub index_block_size: U32<LittleEndian>,
+    pub clusters_per_index_block: u8,
+    pub reserved: [u8; 3],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct IndexHeader {
+    pub entries_offset: U32<LittleEndian>,
+    pub index_length: U32<LittleEndian>,
+    pub allocated_size: U32<LittleEndian>,
+    pub flags: u8,
+    pub reserved: [u8; 3],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct IndexBlockHeader {
+    pub magic: [u8; 4],
+    pub update_sequence_offset: U16<LittleEndian>,
+    pub update_sequence_count: U16<LittleEndian>,
+    pub log_sequence: U64<LittleEndian>,
+    pub vcn: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct IndexEntryHeader {
+    pub reference: U64<LittleEndian>,
+    pub length: U16<LittleEndian>,
+    pub key_length: U16<LittleEndian>,
+    pub flags: U16<LittleEndian>,
+    pub reserved: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ReparseHeader {
+    pub tag: U32<LittleEndian>,
+    pub data_length: U16<LittleEndian>,
+    pub reserved: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct SymlinkReparse {
+    pub substitute_offset: U16<LittleEndian>,
+    pub substitute_length: U16<LittleEndian>,
+    pub print_offset: U16<LittleEndian>,
+    pub print_length: U16<LittleEndian>,
+    pub flags: U32<LittleEndian>,
+}
+
+/// Clusters `vcn..vcn + length` of an attribute, stored from `lcn` or sparse.
+#[derive(Debug, Copy, Clone)]
+struct Run {
+    vcn: u64,
+    length: u64,
+    lcn: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+enum Stream {
+    Resident(Vec<u8>),
+    NonResident {
+        runs: Vec<Run>,
+        size: u64,
+        initialized: u64,
+        flags: u16,
+    },
+}
+
+impl Stream {
+    fn size(&self) -> u64 {
+        return match self {
+            Stream::Resident(value) => value.len() as u64,
+            Stream::NonResident { size, .. } => *size,
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Attribute {
+    kind: u32,
+    name: Vec<u16>,
+    /// First cluster described by this part of a non-resident attribute.
+    lowest_vcn: u64,
+    stream: Stream,
+}
+
+#[derive(Debug, Clone)]
+struct Index {
+    root: Vec<u8>,
+    block_size: u64,
+    allocation: Option<Stream>,
+}
+
+#[derive(Debug, Clone)]
+struct NtfsNode {
+    file_type: FileType,
+    data: Option<Stream>,
+    index: Option<Index>,
+    link: Option<String>,
+}
+
+pub struct NtfsFilesystem {
+    device: Box<dyn BlockDevice>,
+    boot_sector: BootSector,
+    cluster_size: u64,
+    record_size: u64,
+    mft: Stream,
+    upcase: Vec<u16>,
+    label: Option<String>,
+    nodes: BTreeMap<NodeId, NtfsNode>,
+}
+
+fn decode_utf16(units: &[u16]) -> Option<String> {
+    return char::decode_utf16(units.iter().copied())
+        .collect::<Result<String, _>>()
+        .ok();
+}
+
+fn utf16_units(bytes: &[u8]) -> Vec<u16> {
+    return bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+}
+
+fn cluster_size(boot_sector: &BootSector) -> u64 {
+    // Clusters above 64 KiB are stored as a negated power of two.
+    let sectors_per_cluster = match boot_sector.sectors_per_cluster {
+        count @ 0..=0x80 => count as u64,
+        shift => 1u64 << (256 - shift as u32).min(31),
+    };
+    return boot_sector.bytes_per_sector.get() as u64 * sectors_per_cluster;
+}
+
+/// Size of records and index blocks, given in clusters if positive and as a power of two bytes
+/// otherwise.
+fn unit_size(value: i8, cluster_size: u64) -> Option<u64> {
+    return match value > 0 {
+        true => Some(value as u64 * cluster_size),
+        false => 1u64.checked_shl(value.unsigned_abs() as u32),
+    };
+}
+
+/// Check and undo the update sequence of a multi sector record.
+fn apply_fixups(record: &mut [u8], magic: &[u8; 4]) -> Result<(), RrubError> {
+    if record.get(..4) != Some(magic.as_slice()) {
+        return Err(FilesystemError::Corrupted.into());
+    }
+    let offset = u16::from_le_bytes([record[4], record[5]]) as usize;
+    let count = u16::from_le_bytes([record[6], record[7]]) as usize;
+    if count != record.len() / FIXUP_STRIDE + 1 || offset + count * 2 > record.len() {
+        return Err(FilesystemError::Corrupted.into());
+    }
+
+    let sequence = [record[offset], record[offset + 1]];
+    for i in 1..count {
+        let end = i * FIXUP_STRIDE;
+        if record[end - 2..end] != sequence {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let fixup = offset + i * 2;
+        record.copy_within(fixup..fixup + 2, end - 2);
+    }
+
+    return Ok(());
+}
+
+/// Decode a mapping pairs array into runs, each pair holding a length and a signed offset from
+/// the previous run.
+fn decode_runs(mapping: &[u8], lowest_vcn: u64) -> Result<Vec<Run>, RrubError> {
+    let mut runs = Vec::new();
+    let mut vcn = lowest_vcn;
+    let mut lcn: i64 = 0;
+    let mut position = 0;
+
+    loop {
+        let header = *mapping.get(position).ok_or(FilesystemError::Corrupted)?;
+        if header == 0 {
+            break;
+        }
+        let length_size = (header & 0xF) as usize;
+        let offset_size = (header >> 4) as usize;
+        if length_size == 0 || length_size > 8 || offset_size > 8 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let bytes = mapping
+            .get(position + 1..position + 1 + length_size + offset_size)
+            .ok_or(FilesystemError::Corrupted)?;
+        let mut raw = [0u8; 8];
+        raw[..length_size].copy_from_slice(&bytes[..length_size]);
+        let length = u64::from_le_bytes(raw);
+
+        let start = match offset_size {
+            0 => None,
+            _ => {
+                // Sign extend from the top byte given.
+                let fill = match bytes[length_size + offset_size - 1] & 0x80 != 0 {
+                    true => 0xFF,
+                    false => 0x00,
+                };
+                let mut raw = [fill; 8];
+                raw[..offset_size].copy_from_slice(&bytes[length_size..]);
+                lcn = lcn
+                    .checked_add(i64::from_le_bytes(raw))
+                    .filter(|&lcn| lcn >= 0)
+                    .ok_or(FilesystemError::Corrupted)?;
+                Some(lcn as u64)
+            }
+        };
+
+        if length == 0 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        runs.push(Run {
+            vcn,
+            length,
+            lcn: start,
+        });
+        vcn = vcn.checked_add(length).ok_or(FilesystemError::Corrupted)?;
+        position += 1 + length_size + offset_size;
+    }
+
+    return Ok(runs);
+}
+
+/// Parse the attributes stored in one record.
+fn record_attributes(record: &[u8]) -> Result<Vec<Attribute>, RrubError> {
+    let (header, _) =
+        RecordHeader::read_from_prefix(record).map_err(|_| FilesystemError::Corrupted)?;
+    let used = (header.used_size.get() as usize).min(record.len());
+
+    let mut attributes = Vec::new();
+    let mut position = header.attributes_offset.get() as usize;
+    loop {
+        let raw = record
+            .get(position..used)
+            .ok_or(FilesystemError::Corrupted)?;
+        if raw.len() >= 4 && u32::from_le_bytes(raw[..4].try_into().unwrap()) == ATTRIBUTE_END {
+            break;
+        }
+
+        let (attribute, _) =
+            AttributeHeader::read_from_prefix(raw).map_err(|_| FilesystemError::Corrupted)?;
+        let length = attribute.length.get() as usize;
+        if length < size_of::<AttributeHeader>() || length > raw.len() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let raw = &raw[..length];
+
+        let name_offset = attribute.name_offset.get() as usize;
+        let name = raw
+            .get(name_offset..name_offset + attribute.name_length as usize * 2)
+            .ok_or(FilesystemError::Corrupted)?;
+        let body = &raw[size_of::<AttributeHeader>()..];
+
+        let (lowest_vcn, stream) = match attribute.non_resident != 0 {
+            false => {
+                let (resident, _) = ResidentHeader::read_from_prefix(body)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                let offset = resident.value_offset.get() as usize;
+                let value = raw
+                    .get(offset..offset + resident.value_length.get() as usize)
+                    .ok_or(FilesystemError::Corrupted)?;
+                (0, Stream::Resident(value.to_vec()))
+            }
+            true => {
+                let (non_resident, _) = NonResidentHeader::read_from_prefix(body)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                let mapping = raw
+                    .get(non_resident.mapping_pairs_offset.get() as usize..)
+                    .ok_or(FilesystemError::Corrupted)?;
+                let lowest_vcn = non_resident.lowest_vcn.get();
+                (
+                    lowest_vcn,
+                    Stream::NonResident {
+                        runs: decode_runs(mapping, lowest_vcn)?,
+                        size: non_resident.data_size.get(),
+                        initialized: non_resident.initialized_size.get(),
+                        flags: attribute.flags.get(),
+                    },
+                )
+            }
+        };
+
+        attributes.push(Attribute {
+            kind: attribute.kind.get(),
+            name: utf16_units(name),
+            lowest_vcn,
+            stream,
+        });
+        position += length;
+    }
+
+    return Ok(attributes);
+}
+
+/// Join the parts of non-resident attributes spread over several records. The first part holds
+/// the sizes of the whole attribute.
+fn merge_attributes(mut attributes: Vec<Attribute>) -> Vec<Attribute> {
+    attributes
+        .sort_by(|a, b| (a.kind, &a.name, a.lowest_vcn).cmp(&(b.kind, &b.name, b.lowest_vcn)));
+
+    let mut merged: Vec<Attribute> = Vec::new();
+    for attribute in attributes {
+        if let Some(previous) = merged.last_mut()
+            && previous.kind == attribute.kind
+            && previous.name == attribute.name
+            && attribute.lowest_vcn != 0
+            && let Stream::NonResident { runs, .. } = &mut previous.stream
+            && let Stream::NonResident { runs: more, .. } = attribute.stream
+        {
+            runs.extend(more);
+            continue;
+        }
+        merged.push(attribute);
+    }
+
+    return merged;
+}
+
+impl NtfsFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut boot_sector = [0u8; 512];
+        return device.read_bytes(0, &mut boot_sector).is_ok()
+            && boot_sector[510..] == BOOT_SIGNATURE
+            && &boot_sector[3..11] == OEM_ID;
+    }
+
+    /// The label lives in $Volume, which like all of the first 16 records directly follows the
+    /// start of the MFT, so it is read without loading the MFT's run list.
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        if !Self::probe(device) {
+            return None;
+        }
+
+        let mut raw = [0u8; size_of::<BootSector>()];
+        device.read_bytes(0, &mut raw).ok()?;
+        let boot_sector = BootSector::read_from_bytes(&raw).ok()?;
+        let sector_size = boot_sector.bytes_per_sector.get() as u64;
+        let cluster_size = cluster_size(&boot_sector);
+
+        let label = unit_size(boot_sector.clusters_per_record, cluster_size)
+            .filter(|&size| (FIXUP_STRIDE as u64..=MAX_RECORD_SIZE).contains(&size))
+            .and_then(|record_size| {
+                let mut record = vec![0u8; record_size as usize];
+                let offset = boot_sector
+                    .mft_cluster
+                    .get()
+                    .checked_mul(cluster_size)?
+                    .checked_add(VOLUME_RECORD * record_size)?;
+                device.read_bytes(offset, &mut record).ok()?;
+                apply_fixups(&mut record, RECORD_MAGIC).ok()?;
+                return record_attributes(&record)
+                    .ok()?
+                    .into_iter()
+                    .find_map(|attribute| match attribute {
+                        Attribute {
+                            kind: ATTRIBUTE_VOLUME_NAME,
+                            stream: Stream::Resident(name),
+                            ..
+                        } => decode_utf16(&utf16_units(&name)),
+                        _ => None,
+                    });
+            })
+            .filter(|label| !label.is_empty());
+
+        return Some(VolumeInfo {
+            fs_type: "ntfs",
+            usage: Usage::Filesystem,
+            label,
+            uuid: Some(Uuid::VolumeId64(VolumeId64::from_u64_le(
+                boot_sector.serial.get(),
+            ))),
+            size: boot_sector.total_sectors.get().saturating_mul(sector_size),
+        });
+    }
+
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<NtfsFilesystem, RrubError> {
+        let mut raw = [0u8; size_of::<BootSector>()];
+        device.read_bytes(0, &mut raw)?;
+        let boot_sector =
+            BootSector::read_from_bytes(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if &boot_sector.oem_id != OEM_ID {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+
+        let sector_size = boot_sector.bytes_per_sector.get() as u64;
+        let cluster_size = cluster_size(&boot_sector);
+        let record_size = unit_size(boot_sector.clusters_per_record, cluster_size).unwrap_or(0);
+        if !sector_size.is_power_of_two()
+            || !(256..=4096).contains(&sector_size)
+            || !cluster_size.is_power_of_two()
+            || !record_size.is_power_of_two()
+            || !(FIXUP_STRIDE as u64..=MAX_RECORD_SIZE).contains(&record_size)
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut filesystem = NtfsFilesystem {
+            device,
+            boot_sector,
+            cluster_size,
+            record_size,
+            mft: Stream::Resident(Vec::new()),
+            upcase: Vec::new(),
+            label: None,
+            nodes: BTreeMap::new(),
+        };
+
+        // The first part of the MFT's own data locates the records any further parts are in.
+        let mut record = vec![0u8; record_size as usize];
+        filesystem.device.read_bytes(
+            boot_sector
+                .mft_cluster
+                .get()
+                .checked_mul(cluster_size)
+                .ok_or(FilesystemError::Corrupted)?,
+            &mut record,
+        )?;
+        apply_fixups(&mut record, RECORD_MAGIC)?;
+        filesystem.mft = record_attributes(&record)?
+            .into_iter()
+            .find(|attribute| attribute.kind == ATTRIBUTE_DATA && attribute.name.is_empty())
+            .ok_or(FilesystemError::Corrupted)?
+            .stream;
+        filesystem.mft = filesystem
+            .stream(MFT_RECORD, ATTRIBUTE_DATA, &[])?
+            .ok_or(FilesystemError::Corrupted)?;
+
+        let upcase = filesystem
+            .stream(UPCASE_RECORD, ATTRIBUTE_DATA, &[])?
+            .ok_or(FilesystemError::Corrupted)?;
+        let mut table = vec![0u8; UPCASE_ENTRIES * 2];
+        if filesystem.read_stream(&upcase, 0, &mut table)? != table.len() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        filesystem.upcase = utf16_units(&table);
+
+        filesystem.label = match filesystem.stream(VOLUME_RECORD, ATTRIBUTE_VOLUME_NAME, &[])? {
+            Some(Stream::Resident(name)) => {
+                decode_utf16(&utf16_units(&name)).filter(|label| !label.is_empty())
+            }
+            _ => None,
+        };
+
+        return Ok(filesystem);
+    }
+
+    /// Read a record of the MFT, checking its update sequence.
+    fn record(&mut self, number: u64) -> Result<Vec<u8>, RrubError> {
+        let offset = number
+            .checked_mul(self.record_size)
+            .ok_or(FilesystemError::Corrupted)?;
+        let mut record = vec![0u8; self.record_size as usize];
+        let mft = core::mem::replace(&mut self.mft, Stream::Resident(Vec::new()));
+        let read = self.read_stream(&mft, offset, &mut record);
+        self.mft = mft;
+        if read? != record.len() {
+            return Err(FilesystemError::NotFound.into());
+        }
+
+        apply_fixups(&mut record, RECORD_MAGIC)?;
+        return Ok(record);
+    }
+
+    /// Every attribute of a file, including those moved to extension records by an attribute
+    /// list.
+    fn attributes(&mut self, number: u64) -> Result<(u16, Vec<Attribute>), RrubError> {
+        let record = self.record(number)?;
+        let (header, _) =
+            RecordHeader::read_from_prefix(&record).map_err(|_| FilesystemError::Corrupted)?;
+        if header.flags.get() & RECORD_IN_USE == 0 || header.base_record.get() != 0 {
+            return Err(FilesystemError::NotFound.into());
+        }
+
+        let mut attributes = record_attributes(&record)?;
+        let list = attributes
+            .iter()
+            .find(|attribute| attribute.kind == ATTRIBUTE_LIST)
+            .map(|attribute| attribute.stream.clone());
+
+        if let Some(list) = list {
+            let size = list.size();
+            if size > MAX_ATTRIBUTE_LIST_SIZE {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            let mut raw = vec![0u8; size as usize];
+            self.read_stream(&list, 0, &mut raw)?;
+
+            let mut extensions = BTreeSet::new();
+            let mut position = 0;
+            while position < raw.len() {
+                let (entry, _) = AttributeListEntry::read_from_prefix(&raw[position..])
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                let length = entry.length.get() as usize;
+                if length < size_of::<AttributeListEntry>() {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                let record = entry.reference.get() & REFERENCE_RECORD_MASK;
+                if record != number {
+                    extensions.insert(record);
+                }
+                position += length;
+            }
+
+            for extension in extensions {
+                let record = self.record(extension)?;
+                let (header, _) = RecordHeader::read_from_prefix(&record)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                if header.base_record.get() & REFERENCE_RECORD_MASK != number {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                attributes.extend(record_attributes(&record)?);
+            }
+        }
+
+        return Ok((header.flags.get(), merge_attributes(attributes)));
+    }
+
+    fn stream(
+        &mut self,
+        number: u64,
+        kind: u32,
+        name: &[u16],
+    ) -> Result<Option<Stream>, RrubError> {
+        let (_, attributes) = self.attributes(number)?;
+        return Ok(attributes
+            .into_iter()
+            .find(|attribute| attribute.kind == kind && attribute.name == name)
+            .map(|attribute| attribute.stream));
+    }
+
+    fn read_stream(
+        &mut self,
+        stream: &Stream,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let (runs, size, initialized) = match stream {
+            Stream::Resident(value) => {
+                let start = (offset as usize).min(value.len());
+                let count = buffer.len().min(value.len() - start);
+                buffer[..count].copy_from_slice(&value[start..start + count]);
+                return Ok(count);
+            }
+            Stream::NonResident { flags, .. }
+                if flags & (ATTRIBUTE_COMPRESSION_MASK | ATTRIBUTE_ENCRYPTED) != 0 =>
+            {
+                return Err(FilesystemError::Unsupported.into());
+            }
+            Stream::NonResident {
+                runs,
+                size,
+                initialized,
+                ..
+            } => (runs, *size, *initialized),
+        };
+
+        if offset >= size {
+            return Ok(0);
+        }
+        let length = buffer.len().min((size - offset) as usize);
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let vcn = position / self.cluster_size;
+            let within = position % self.cluster_size;
+
+            // Data past the initialized size reads as zeros.
+            if position >= initialized {
+                buffer[done..length].fill(0);
+                break;
+            }
+            let run = runs
+                .iter()
+                .find(|run| (run.vcn..run.vcn + run.length).contains(&vcn))
+                .ok_or(FilesystemError::Corrupted)?;
+            let available = (run.vcn + run.length - vcn) * self.cluster_size - within;
+            let count = (available.min(initialized - position) as usize).min(length - done);
+
+            match run.lcn {
+                Some(lcn) => {
+                    let address = (lcn + vcn - run.vcn)
+                        .checked_mul(self.cluster_size)
+                        .ok_or(FilesystemError::Corrupted)?;
+                    self.device
+                        .read_bytes(address + within, &mut buffer[done..done + count])?;
+                }
+                None => buffer[done..done + count].fill(0),
+            }
+            done += count;
+        }
+
+        return Ok(length);
+    }
+
+    fn node(&mut self, id: NodeId) -> Result<NtfsNode, RrubError> {
+        if let Some(node) = self.nodes.get(&id) {
+            return Ok(node.clone());
+        }
+
+        let (flags, attributes) = self.attributes(id.0)?;
+        let find = |kind: u32, name: &[u16]| {
+            attributes
+                .iter()
+                .find(|attribute| attribute.kind == kind && attribute.name == name)
+                .map(|attribute| attribute.stream.clone())
+        };
+
+        let index = match find(ATTRIBUTE_INDEX_ROOT, INDEX_NAME) {
+            Some(Stream::Resident(root)) => {
+                let (header, _) =
+                    IndexRoot::read_from_prefix(&root).map_err(|_| FilesystemError::Corrupted)?;
+                let block_size = header.index_block_size.get() as u64;
+                if !block_size.is_power_of_two() || block_size < FIXUP_STRIDE as u64 {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                Some(Index {
+                    root,
+                    block_size,
+                    allocation: find(ATTRIBUTE_INDEX_ALLOCATION, INDEX_NAME),
+                })
+            }
+            Some(_) => return Err(FilesystemError::Corrupted.into()),
+            None => None,
+        };
+
+        let link = match find(ATTRIBUTE_REPARSE_POINT, &[]) {
+            Some(reparse) => {
+                let mut raw = vec![0u8; reparse.size().min(MAX_RECORD_SIZE) as usize];
+                self.read_stream(&reparse, 0, &mut raw)?;
+                parse_reparse(&raw)
+            }
+            None => None,
+        };
+
+        let file_type = match (&link, flags & RECORD_IS_DIRECTORY != 0) {
+            (Some(_), _) => FileType::Symlink,
+            (None, true) => FileType::Directory,
+            (None, false) => FileType::Regular,
+        };
+        if file_type == FileType::Directory && index.is_none() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let node = NtfsNode {
+            file_type,
+            data: find(ATTRIBUTE_DATA, &[]),
+            index,
+            link,
+        };
+        self.nodes.insert(id, node.clone());
+        return Ok(node);
+    }
+
+    /// Collect the entries of a directory index, walking every block of its B+ tree.
+    fn index_entries(
+        &mut self,
+        index: &Index,
+    ) -> Result<Vec<(u64, FileName, Vec<u16>)>, RrubError> {
+        let vcn_size = match index.block_size >= self.cluster_size {
+            true => self.cluster_size,
+            false => FIXUP_STRIDE as u64,
+        };
+
+        let mut entries = Vec::new();
+        let mut pending = vec![(index.root.clone(), size_of::<IndexRoot>(), 0)];
+        let mut visited = BTreeSet::new();
+
+        while let Some((block, header_offset, depth)) = pending.pop() {
+            let (header, _) = IndexHeader::read_from_prefix(
+                block
+                    .get(header_offset..)
+                    .ok_or(FilesystemError::Corrupted)?,
+            )
+            .map_err(|_| FilesystemError::Corrupted)?;
+            let end = (header_offset + header.index_length.get() as usize).min(block.len());
+            let mut position = header_offset + header.entries_offset.get() as usize;
+
+            loop {
+                let raw = block.get(position..end).ok_or(FilesystemError::Corrupted)?;
+                let (entry, _) = IndexEntryHeader::read_from_prefix(raw)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                let length = entry.length.get() as usize;
+                if length < size_of::<IndexEntryHeader>() || length > raw.len() {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                let flags = entry.flags.get();
+
+                if flags & ENTRY_SUBNODE != 0 {
+                    let vcn = u64::from_le_bytes(raw[length - 8..length].try_into().unwrap());
+                    let Some(allocation) = &index.allocation else {
+                        return Err(FilesystemError::Corrupted.into());
+                    };
+                    if depth >= MAX_INDEX_DEPTH || !visited.insert(vcn) {
+                        return Err(FilesystemError::Corrupted.into());
+                    }
+
+                    let mut child = vec![0u8; index.block_size as usize];
+                    let offset = vcn
+                        .checked_mul(vcn_size)
+                        .ok_or(FilesystemError::Corrupted)?;
+                    if self.read_stream(allocation, offset, &mut child)? != child.len() {
+                        return Err(FilesystemError::Corrupted.into());
+                    }
+                    apply_fixups(&mut child, INDEX_MAGIC)?;
+                    pending.push((child, size_of::<IndexBlockHeader>(), depth + 1));
+                }
+
+                if flags & ENTRY_LAST != 0 {
+                    break;
+                }
+
+                let key = &raw[size_of::<IndexEntryHeader>()..length];
+                let (file_name, rest) =
+                    FileName::read_from_prefix(key).map_err(|_| FilesystemError::Corrupted)?;
+                let name = rest
+                    .get(..file_name.name_length as usize * 2)
+                    .ok_or(FilesystemError::Corrupted)?;
+                entries.push((
+                    entry.reference.get() & REFERENCE_RECORD_MASK,
+                    file_name,
+                    utf16_units(name),
+                ));
+                position += length;
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    fn upcase_equal(&self, a: &[u16], b: &[u16]) -> bool {
+        let upcase = |c: u16| self.upcase.get(c as usize).copied().unwrap_or(c);
+        return a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| upcase(x) == upcase(y));
+    }
+}
+
+/// Target of a symlink reparse point, only relative Windows symlinks and WSL symlinks have targets
+/// that make sense on their own.
+fn parse_reparse(raw: &[u8]) -> Option<String> {
+    let (header, data) = ReparseHeader::read_from_prefix(raw).ok()?;
+    let data = data.get(..header.data_length.get() as usize)?;
+
+    return match header.tag.get() {
+        REPARSE_TAG_SYMLINK => {
+            let (symlink, buffer) = SymlinkReparse::read_from_prefix(data).ok()?;
+            if symlink.flags.get() & SYMLINK_RELATIVE == 0 {
+                return None;
+            }
+            let offset = symlink.substitute_offset.get() as usize;
+            let target = buffer.get(offset..offset + symlink.substitute_length.get() as usize)?;
+            let target = decode_utf16(&utf16_units(target))?;
+            Some(target.replace('\\', "/"))
+        }
+        REPARSE_TAG_LX_SYMLINK => {
+            let version = u32::from_le_bytes(data.get(..4)?.try_into().unwrap());
+            match version == LX_SYMLINK_VERSION {
+                true => String::from_utf8(data[4..].to_vec()).ok(),
+                false => None,
+            }
+        }
+        _ => None,
+    };
+}
+
+impl FilesystemBackend for NtfsFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "ntfs";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::VolumeId64(VolumeId64::from_u64_le(self.boot_sector.serial.get()));
+    }
+
+    fn label(&self) -> Option<String> {
+        return self.label.clone();
+    }
+
+    fn root(&self) -> NodeId {
+        return NodeId(ROOT_RECORD);
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let node = self.node(node)?;
+        let size = match (&node.file_type, &node.data) {
+            (FileType::Regular, Some(data)) => data.size(),
+            (FileType::Symlink, _) => node.link.map_or(0, |link| link.len() as u64),
+            _ => 0,
+        };
+
+        return Ok(Metadata {
+            file_type: node.file_type,
+            size,
+        });
+    }
+
+    /// Short DOS names are left out, files with both a long and a short name have separate index
+    /// entries for each.
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let node = self.node(dir)?;
+        let Some(index) = node.index.filter(|_| node.file_type == FileType::Directory) else {
+            return Err(FilesystemError::NotADirectory.into());
+        };
+
+        let mut entries = Vec::new();
+        for (record, file_name, name) in self.index_entries(&index)? {
+            if record < FIRST_USER_RECORD || file_name.namespace == FILE_NAME_DOS {
+                continue;
+            }
+            let Some(name) = decode_utf16(&name) else {
+                continue;
+            };
+
+            let flags = file_name.flags.get();
+            let file_type = match flags & FILE_ATTRIBUTE_REPARSE_POINT != 0 {
+                true => self.node(NodeId(record))?.file_type,
+                false => match flags & FILE_ATTRIBUTE_DIRECTORY != 0 {
+                    true => FileType::Directory,
+                    false => FileType::Regular,
+                },
+            };
+            entries.push(DirEntry {
+                name,
+                node: NodeId(record),
+                file_type,
+            });
+        }
+
+        return Ok(entries);
+    }
+
+    /// Names are matched exactly first, then case insensitively through the volume's `$UpCase`
+    /// table like Windows does.
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        let entries = self.read_dir(dir)?;
+        if let Some(entry) = entries.iter().find(|entry| entry.name == name) {
+            return Ok(entry.node);
+        }
+
+        let wanted: Vec<u16> = name.encode_utf16().collect();
+        return entries
+            .iter()
+            .find(|entry| {
+                self.upcase_equal(&entry.name.encode_utf16().collect::<Vec<u16>>(), &wanted)
+            })
+            .map(|entry| entry.node)
+            .ok_or(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        let node = self.node(node)?;
+        return match (node.file_type, node.data) {
+            (FileType::Directory, _) => Err(FilesystemError::IsADirectory.into()),
+            (_, Some(data)) => self.read_stream(&data, offset, buffer),
+            (_, None) => Ok(0),
+        };
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        return self
+            .node(node)?
+            .link
+            .ok_or(FilesystemError::InvalidPath.into());
+    }
+}
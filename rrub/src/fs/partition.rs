@@ -0,0 +1,324 @@
+/*
+ * https://uefi.org/specs/UEFI/2.10/05_GUID_Partition_Table_Format.html
+ * https://en.wikipedia.org/wiki/Extended_boot_record
+*/
+
+use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+use core::cell::RefCell;
+
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U32, U64},
+};
+
+use crate::{
+    checksum::crc32,
+    error::RrubError,
+    firmware::block::{BlockDevice, check_range},
+};
+
+const MBR_SIZE: usize = 512;
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_STATUS_ACTIVE: u8 = 0x80;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_PROTECTIVE: u8 = 0xEE;
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+const MAX_LOGICAL_PARTITIONS: u32 = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_CRC_OFFSET: usize = 16;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+const MAX_GPT_ENTRIES: usize = 1024;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MbrEntry {
+    pub status: u8,
+    pub first_chs: [u8; 3],
+    pub kind: u8,
+    pub last_chs: [u8; 3],
+    pub first_lba: U32<LittleEndian>,
+    pub sector_count: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct GptHeader {
+    pub signature: [u8; 8],
+    pub revision: U32<LittleEndian>,
+    pub header_size: U32<LittleEndian>,
+    pub header_crc: U32<LittleEndian>,
+    pub reserved: U32<LittleEndian>,
+    pub current_lba: U64<LittleEndian>,
+    pub backup_lba: U64<LittleEndian>,
+    pub first_usable_lba: U64<LittleEndian>,
+    pub last_usable_lba: U64<LittleEndian>,
+    pub disk_guid: [u8; 16],
+    pub entries_lba: U64<LittleEndian>,
+    pub entry_count: U32<LittleEndian>,
+    pub entry_size: U32<LittleEndian>,
+    pub entries_crc: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct GptEntry {
+    pub type_guid: [u8; 16],
+    pub unique_guid: [u8; 16],
+    pub first_lba: U64<LittleEndian>,
+    pub last_lba: U64<LittleEndian>,
+    pub attributes: U64<LittleEndian>,
+    pub name: [u8; 72],
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PartitionType {
+    Mbr(u8),
+    Gpt(RealUuid),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Partition {
+    /// Number as the kernel counts them, logical MBR partitions start at 5.
+    pub number: u32,
+    pub kind: PartitionType,
+    /// GPT unique partition GUID.
+    pub uuid: Option<RealUuid>,
+    pub first_block: u64,
+    pub block_count: u64,
+}
+
+fn read_mbr(device: &mut dyn BlockDevice, lba: u64) -> Result<Option<[MbrEntry; 4]>, RrubError> {
+    let mut raw = [0u8; MBR_SIZE];
+    device.read_bytes(lba * device.block_size() as u64, &mut raw)?;
+    if raw[510..] != MBR_SIGNATURE {
+        return Ok(None);
+    }
+
+    let (entries, _) = <[MbrEntry; 4]>::read_from_prefix(&raw[MBR_TABLE_OFFSET..])
+        .map_err(|_| RrubError::OutOfBounds)?;
+
+    // FAT boot sectors carry the same signature, their boot code rarely passes for status bytes.
+    if entries
+        .iter()
+        .any(|entry| entry.status != 0 && entry.status != MBR_STATUS_ACTIVE)
+    {
+        return Ok(None);
+    }
+
+    return Ok(Some(entries));
+}
+
+fn read_gpt(device: &mut dyn BlockDevice) -> Result<Vec<Partition>, RrubError> {
+    let block_size = device.block_size() as u64;
+    if device.block_count() < 2 {
+        return Ok(Vec::new());
+    }
+
+    let mut raw = vec![0u8; block_size as usize];
+    device.read_blocks(1, &mut raw)?;
+
+    let (header, _) = GptHeader::read_from_prefix(&raw).map_err(|_| RrubError::OutOfBounds)?;
+    let header_size = header.header_size.get() as usize;
+    if &header.signature != GPT_SIGNATURE
+        || !(size_of::<GptHeader>()..=raw.len()).contains(&header_size)
+    {
+        return Ok(Vec::new());
+    }
+
+    let mut check = raw[..header_size].to_vec();
+    check[GPT_HEADER_CRC_OFFSET..GPT_HEADER_CRC_OFFSET + 4].fill(0);
+    if crc32(&check) != header.header_crc.get() {
+        return Ok(Vec::new());
+    }
+
+    let entry_size = header.entry_size.get() as usize;
+    let entry_count = header.entry_count.get() as usize;
+    if entry_size < GPT_MIN_ENTRY_SIZE || entry_count > MAX_GPT_ENTRIES {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = vec![0u8; entry_size * entry_count];
+    device.read_bytes(header.entries_lba.get() * block_size, &mut entries)?;
+    if crc32(&entries) != header.entries_crc.get() {
+        return Ok(Vec::new());
+    }
+
+    let mut partitions = Vec::new();
+    for (index, raw) in entries.chunks_exact(entry_size).enumerate() {
+        let (entry, _) = GptEntry::read_from_prefix(raw).map_err(|_| RrubError::OutOfBounds)?;
+        let (first, last) = (entry.first_lba.get(), entry.last_lba.get());
+        if entry.type_guid == [0u8; 16] || last < first || last >= device.block_count() {
+            continue;
+        }
+
+        partitions.push(Partition {
+            number: index as u32 + 1,
+            kind: PartitionType::Gpt(RealUuid::from_bytes_le(entry.type_guid)),
+            uuid: Some(RealUuid::from_bytes_le(entry.unique_guid)),
+            first_block: first,
+            block_count: last - first + 1,
+        });
+    }
+
+    return Ok(partitions);
+}
+
+/// Partitions of a device with an MBR or GPT partition table. Devices without a partition table
+/// have none.
+pub fn read_partitions(device: &mut dyn BlockDevice) -> Result<Vec<Partition>, RrubError> {
+    let Some(entries) = read_mbr(device, 0)? else {
+        return read_gpt(device);
+    };
+    if entries
+        .iter()
+        .any(|entry| entry.kind == MBR_TYPE_PROTECTIVE)
+    {
+        return read_gpt(device);
+    }
+
+    let block_count = device.block_count();
+    let in_bounds = |start: u64, count: u64| {
+        count != 0
+            && start
+                .checked_add(count)
+                .is_some_and(|end| end <= block_count)
+    };
+
+    let mut partitions = Vec::new();
+    let mut extended = None;
+    for (index, entry) in entries.iter().enumerate() {
+        let (start, count) = (
+            entry.first_lba.get() as u64,
+            entry.sector_count.get() as u64,
+        );
+        if entry.kind == MBR_TYPE_EMPTY || !in_bounds(start, count) {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&entry.kind) {
+            extended.get_or_insert(start);
+            continue;
+        }
+
+        partitions.push(Partition {
+            number: index as u32 + 1,
+            kind: PartitionType::Mbr(entry.kind),
+            uuid: None,
+            first_block: start,
+            block_count: count,
+        });
+    }
+
+    // Logical partitions form a chain of EBRs, each relative to its own EBR while the link to the
+    // next EBR is relative to the start of the extended partition.
+    if let Some(base) = extended {
+        let mut ebr = base;
+        let mut number = 5;
+        for _ in 0..MAX_LOGICAL_PARTITIONS {
+            let Some([logical, next, ..]) = read_mbr(device, ebr)? else {
+                break;
+            };
+
+            let start = ebr + logical.first_lba.get() as u64;
+            let count = logical.sector_count.get() as u64;
+            if logical.kind != MBR_TYPE_EMPTY && in_bounds(start, count) {
+                partitions.push(Partition {
+                    number,
+                    kind: PartitionType::Mbr(logical.kind),
+                    uuid: None,
+                    first_block: start,
+                    block_count: count,
+                });
+                number += 1;
+            }
+
+            let link = base + next.first_lba.get() as u64;
+            if !MBR_TYPES_EXTENDED.contains(&next.kind) || link <= ebr || link >= block_count {
+                break;
+            }
+            ebr = link;
+        }
+    }
+
+    return Ok(partitions);
+}
+
+/// Range of blocks of a device shared with other partitions, for devices the firmware does not
+/// split into partitions itself.
+pub struct PartitionDevice {
+    parent: Rc<RefCell<Box<dyn BlockDevice>>>,
+    block_size: usize,
+    first_block: u64,
+    block_count: u64,
+}
+
+impl PartitionDevice {
+    pub fn new(parent: Rc<RefCell<Box<dyn BlockDevice>>>, partition: &Partition) -> Self {
+        return PartitionDevice::range(parent, partition.first_block, partition.block_count);
+    }
+
+    /// Blocks of the shared device found by other means than a partition table.
+    pub fn range(
+        parent: Rc<RefCell<Box<dyn BlockDevice>>>,
+        first_block: u64,
+        block_count: u64,
+    ) -> Self {
+        let block_size = parent.borrow().block_size();
+        return PartitionDevice {
+            parent,
+            block_size,
+            first_block,
+            block_count,
+        };
+    }
+
+    /// The whole of the shared device.
+    pub fn whole(parent: Rc<RefCell<Box<dyn BlockDevice>>>) -> Self {
+        let (block_size, block_count) = {
+            let device = parent.borrow();
+            (device.block_size(), device.block_count())
+        };
+        return PartitionDevice {
+            parent,
+            block_size,
+            first_block: 0,
+            block_count,
+        };
+    }
+}
+
+impl BlockDevice for PartitionDevice {
+    fn block_size(&self) -> usize {
+        return self.block_size;
+    }
+
+    fn block_count(&self) -> u64 {
+        return self.block_count;
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+        return self
+            .parent
+            .borrow_mut()
+            .read_blocks(self.first_block + lba, buffer);
+    }
+
+    fn is_read_only(&self) -> bool {
+        return self.parent.borrow().is_read_only();
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<(), RrubError> {
+        check_range(self, lba, buffer.len())?;
+        return self
+            .parent
+            .borrow_mut()
+            .write_blocks(self.first_block + lba, buffer);
+    }
+
+    fn flush(&mut self) -> Result<(), RrubError> {
+        return self.parent.borrow_mut().flush();
+    }
+}
@@ -0,0 +1,139 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    firmware::{block::BlockDevice, filesystem::Uuid},
+    fs::{
+        btrfs::BtrfsFilesystem, erofs::ErofsFilesystem, exfat::ExfatFilesystem,
+        ext4::Ext4Filesystem, fat::FatFilesystem, iso9660::Iso9660Filesystem, luks::LuksVolume,
+        lvm::PhysicalVolume, md, ntfs::NtfsFilesystem, squashfs::SquashFilesystem,
+        xfs::XfsFilesystem, zfs::ZfsFilesystem,
+    },
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Usage {
+    Filesystem,
+    /// Encrypted container that has to be unlocked first.
+    Crypto,
+    /// Member of an md array or LVM volume group.
+    Raid,
+}
+
+/// What a block device holds, identified from its superblock alone.
+#[derive(Debug, Clone)]
+pub struct VolumeInfo {
+    /// Type as blkid names it, e.g. "ext4" or "crypto_LUKS".
+    pub fs_type: &'static str,
+    pub usage: Usage,
+    pub label: Option<String>,
+    pub uuid: Option<Uuid>,
+    /// Size of the filesystem in bytes, which may be less than the device. Containers report the
+    /// size of their data area or of the device.
+    pub size: u64,
+}
+
+type Prober = fn(&mut dyn BlockDevice) -> Option<VolumeInfo>;
+
+/// md metadata 0.90 and 1.0 is at the end of a member, which starts with the filesystem of the
+/// array, so md is checked first. Hybrid ISOs also carry an MBR, so ISO9660 comes before FAT.
+const PROBERS: [Prober; 13] = [
+    md::identify,
+    LuksVolume::identify,
+    PhysicalVolume::identify,
+    ZfsFilesystem::identify,
+    BtrfsFilesystem::identify,
+    Ext4Filesystem::identify,
+    XfsFilesystem::identify,
+    SquashFilesystem::identify,
+    ErofsFilesystem::identify,
+    Iso9660Filesystem::identify,
+    NtfsFilesystem::identify,
+    ExfatFilesystem::identify,
+    FatFilesystem::identify,
+];
+
+/// Identify the filesystem or container on a device.
+pub fn probe(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+    return PROBERS.iter().find_map(|prober| prober(device));
+}
+
+/// A fixed size, NUL padded label field, `None` when empty.
+pub fn label_from_bytes(raw: &[u8]) -> Option<String> {
+    let end = raw.iter().position(|&c| c == 0).unwrap_or(raw.len());
+    return String::from_utf8(raw[..end].to_vec())
+        .ok()
+        .filter(|label| !label.is_empty());
+}
+
+/// Sizes with one decimal and a binary unit suffix, like lsblk shows them.
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 7] = ["B", "K", "M", "G", "T", "P", "E"];
+
+    let mut unit = 0;
+    let mut scaled = size;
+    while scaled >= 1024 && unit < UNITS.len() - 1 {
+        scaled /= 1024;
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{}B", size);
+    }
+
+    // Tenths of the unit, rounded to nearest.
+    let divisor = 1u128 << (10 * unit);
+    let tenths = (size as u128 * 10 + divisor / 2) / divisor;
+    return match tenths % 10 {
+        0 => format!("{}{}", tenths / 10, UNITS[unit]),
+        fraction => format!("{}.{}{}", tenths / 10, fraction, UNITS[unit]),
+    };
+}
+
+/// Table of every block device and what it holds, for the `Lsblk` entry and when there's no
+/// configuration to find the boot disk with. Devices are named by their position in `devices`.
+pub fn lsblk(devices: &mut [Box<dyn BlockDevice>]) -> String {
+    let mut rows = Vec::new();
+    rows.push(["NAME", "SIZE", "TYPE", "LABEL", "UUID"].map(ToString::to_string));
+
+    for (index, device) in devices.iter_mut().enumerate() {
+        let info = probe(device.as_mut());
+        rows.push([
+            format!("blk{}", index),
+            format_size(device.size()),
+            info.as_ref()
+                .map_or(String::new(), |info| info.fs_type.to_string()),
+            info.as_ref()
+                .and_then(|info| info.label.clone())
+                .unwrap_or_default(),
+            info.and_then(|info| info.uuid)
+                .map_or(String::new(), |uuid| uuid.to_string()),
+        ]);
+    }
+
+    let mut widths = [0usize; 5];
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (column, (value, width)) in row.iter().zip(widths).enumerate() {
+            match column {
+                // Sizes are right aligned.
+                1 => line.push_str(&format!("{:>width$} ", value)),
+                _ => line.push_str(&format!("{:<width$} ", value)),
+            }
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+
+    return table;
+}
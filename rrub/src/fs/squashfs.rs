@@ -0,0 +1,662 @@
+/*
+ * https://dr-emann.github.io/squashfs/squashfs.html
+ * https://docs.kernel.org/filesystems/squashfs.html
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    decompress::{inflate::zlib_decompress, lz4, lzo, xz, zstd},
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+            VolumeId32,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo},
+};
+
+const SQUASHFS_MAGIC: u32 = 0x7371_7368;
+const VERSION_MAJOR: u16 = 4;
+const VERSION_MINOR: u16 = 0;
+
+const COMPRESSION_GZIP: u16 = 1;
+const COMPRESSION_LZO: u16 = 3;
+const COMPRESSION_XZ: u16 = 4;
+const COMPRESSION_LZ4: u16 = 5;
+const COMPRESSION_ZSTD: u16 = 6;
+
+const MIN_BLOCK_LOG: u16 = 12;
+const MAX_BLOCK_LOG: u16 = 20;
+
+/// Metadata is stored in blocks of at most 8K, each prefixed with its stored size.
+const METADATA_SIZE: usize = 8192;
+const METADATA_UNCOMPRESSED: u16 = 0x8000;
+/// Data block and fragment sizes with this bit set are stored uncompressed.
+const DATA_UNCOMPRESSED: u32 = 1 << 24;
+const NO_FRAGMENT: u32 = 0xFFFF_FFFF;
+/// Number of fragment entries in one metadata block.
+const FRAGMENTS_PER_BLOCK: u32 = (METADATA_SIZE / size_of::<FragmentEntry>()) as u32;
+
+const INODE_DIR: u16 = 1;
+const INODE_FILE: u16 = 2;
+const INODE_SYMLINK: u16 = 3;
+const INODE_EXTENDED_DIR: u16 = 8;
+const INODE_EXTENDED_FILE: u16 = 9;
+const INODE_EXTENDED_SYMLINK: u16 = 10;
+
+/// Directory sizes count the "." and ".." entries which are not stored.
+const DIR_SIZE_OFFSET: u32 = 3;
+const MAX_DIR_HEADER_ENTRIES: u32 = 256;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Superblock {
+    pub magic: U32<LittleEndian>,
+    pub inode_count: U32<LittleEndian>,
+    pub modification_time: U32<LittleEndian>,
+    pub block_size: U32<LittleEndian>,
+    pub fragment_count: U32<LittleEndian>,
+    pub compression: U16<LittleEndian>,
+    pub block_log: U16<LittleEndian>,
+    pub flags: U16<LittleEndian>,
+    pub id_count: U16<LittleEndian>,
+    pub version_major: U16<LittleEndian>,
+    pub version_minor: U16<LittleEndian>,
+    pub root_inode: U64<LittleEndian>,
+    pub bytes_used: U64<LittleEndian>,
+    pub id_table: U64<LittleEndian>,
+    pub xattr_table: U64<LittleEndian>,
+    pub inode_table: U64<LittleEndian>,
+    pub directory_table: U64<LittleEndian>,
+    pub fragment_table: U64<LittleEndian>,
+    pub export_table: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct InodeHeader {
+    pub inode_type: U16<LittleEndian>,
+    pub mode: U16<LittleEndian>,
+    pub uid: U16<LittleEndian>,
+    pub gid: U16<LittleEndian>,
+    pub modification_time: U32<LittleEndian>,
+    pub number: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirInode {
+    pub start_block: U32<LittleEndian>,
+    pub link_count: U32<LittleEndian>,
+    pub size: U16<LittleEndian>,
+    pub offset: U16<LittleEndian>,
+    pub parent: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtendedDirInode {
+    pub link_count: U32<LittleEndian>,
+    pub size: U32<LittleEndian>,
+    pub start_block: U32<LittleEndian>,
+    pub parent: U32<LittleEndian>,
+    pub index_count: U16<LittleEndian>,
+    pub offset: U16<LittleEndian>,
+    pub xattr: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FileInode {
+    pub start_block: U32<LittleEndian>,
+    pub fragment: U32<LittleEndian>,
+    pub fragment_offset: U32<LittleEndian>,
+    pub size: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtendedFileInode {
+    pub start_block: U64<LittleEndian>,
+    pub size: U64<LittleEndian>,
+    pub sparse: U64<LittleEndian>,
+    pub link_count: U32<LittleEndian>,
+    pub fragment: U32<LittleEndian>,
+    pub fragment_offset: U32<LittleEndian>,
+    pub xattr: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct SymlinkInode {
+    pub link_count: U32<LittleEndian>,
+    pub target_size: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirHeader {
+    pub count: U32<LittleEndian>,
+    pub start_block: U32<LittleEndian>,
+    pub inode_number: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirEntryHeader {
+    pub offset: U16<LittleEndian>,
+    pub inode_delta: U16<LittleEndian>,
+    pub inode_type: U16<LittleEndian>,
+    pub name_size: U16<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FragmentEntry {
+    pub start: U64<LittleEndian>,
+    pub size: U32<LittleEndian>,
+    pub unused: U32<LittleEndian>,
+}
+
+/// Position in a metadata stream, a metadata block and an offset in its decompressed data.
+#[derive(Debug, Copy, Clone)]
+struct Cursor {
+    block: u64,
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+enum InodeData {
+    Directory {
+        start_block: u32,
+        offset: u16,
+        size: u32,
+    },
+    File {
+        /// Position and stored size of each full block.
+        blocks: Vec<(u64, u32)>,
+        fragment: u32,
+        fragment_offset: u32,
+    },
+    Symlink(Vec<u8>),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    file_type: FileType,
+    size: u64,
+    data: InodeData,
+}
+
+pub struct SquashFilesystem {
+    device: Box<dyn BlockDevice>,
+    superblock: Superblock,
+    block_size: u64,
+    /// Decompressed metadata blocks and the position of the block that follows each.
+    metadata: BTreeMap<u64, (Vec<u8>, u64)>,
+    inodes: BTreeMap<NodeId, Inode>,
+    /// Last decompressed data block or fragment block, by position.
+    block_cache: Option<(u64, Vec<u8>)>,
+}
+
+/// SquashFS has no UUID, the modification time is the closest there is to one.
+fn modification_time_id(superblock: &Superblock) -> Uuid {
+    return Uuid::VolumeId32(VolumeId32::from_u32_le(superblock.modification_time.get()));
+}
+
+fn file_type_from_inode(inode_type: u16) -> FileType {
+    return match inode_type {
+        INODE_DIR | INODE_EXTENDED_DIR => FileType::Directory,
+        INODE_FILE | INODE_EXTENDED_FILE => FileType::Regular,
+        INODE_SYMLINK | INODE_EXTENDED_SYMLINK => FileType::Symlink,
+        _ => FileType::Other,
+    };
+}
+
+impl SquashFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut magic = [0u8; 4];
+        return device.read_bytes(0, &mut magic).is_ok()
+            && u32::from_le_bytes(magic) == SQUASHFS_MAGIC;
+    }
+
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let mut raw = [0u8; size_of::<Superblock>()];
+        device.read_bytes(0, &mut raw).ok()?;
+        let superblock = Superblock::read_from_bytes(&raw).ok()?;
+        if superblock.magic.get() != SQUASHFS_MAGIC {
+            return None;
+        }
+
+        return Some(VolumeInfo {
+            fs_type: "squashfs",
+            usage: Usage::Filesystem,
+            label: None,
+            uuid: Some(modification_time_id(&superblock)),
+            size: superblock.bytes_used.get(),
+        });
+    }
+
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<SquashFilesystem, RrubError> {
+        let mut raw = [0u8; size_of::<Superblock>()];
+        device.read_bytes(0, &mut raw)?;
+        let superblock =
+            Superblock::read_from_bytes(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if superblock.magic.get() != SQUASHFS_MAGIC {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+        if superblock.version_major.get() != VERSION_MAJOR
+            || superblock.version_minor.get() != VERSION_MINOR
+        {
+            return Err(FilesystemError::Unsupported.into());
+        }
+        if !matches!(
+            superblock.compression.get(),
+            COMPRESSION_GZIP
+                | COMPRESSION_LZO
+                | COMPRESSION_XZ
+                | COMPRESSION_LZ4
+                | COMPRESSION_ZSTD
+        ) {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let block_log = superblock.block_log.get();
+        let block_size = superblock.block_size.get() as u64;
+        if !(MIN_BLOCK_LOG..=MAX_BLOCK_LOG).contains(&block_log) || block_size != 1 << block_log {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        return Ok(SquashFilesystem {
+            device,
+            superblock,
+            block_size,
+            metadata: BTreeMap::new(),
+            inodes: BTreeMap::new(),
+            block_cache: None,
+        });
+    }
+
+    fn decompress(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, RrubError> {
+        let output = match self.superblock.compression.get() {
+            COMPRESSION_GZIP => zlib_decompress(data, limit)?,
+            COMPRESSION_LZO => {
+                let mut output = Vec::new();
+                lzo::decompress(data, &mut output, limit)?;
+                output
+            }
+            COMPRESSION_XZ => xz::decompress(data, limit)?,
+            COMPRESSION_LZ4 => {
+                let mut output = Vec::new();
+                lz4::decompress_block(data, &mut output, limit)?;
+                output
+            }
+            COMPRESSION_ZSTD => zstd::decompress(data, limit)?,
+            _ => return Err(FilesystemError::Unsupported.into()),
+        };
+        return Ok(output);
+    }
+
+    fn metadata_block(&mut self, position: u64) -> Result<&(Vec<u8>, u64), RrubError> {
+        if !self.metadata.contains_key(&position) {
+            let mut header = [0u8; 2];
+            self.device.read_bytes(position, &mut header)?;
+            let header = u16::from_le_bytes(header);
+
+            let size = (header & !METADATA_UNCOMPRESSED) as usize;
+            if size == 0 || size > METADATA_SIZE {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            let mut stored = vec![0u8; size];
+            self.device.read_bytes(position + 2, &mut stored)?;
+
+            let data = match header & METADATA_UNCOMPRESSED != 0 {
+                true => stored,
+                false => self.decompress(&stored, METADATA_SIZE)?,
+            };
+            self.metadata
+                .insert(position, (data, position + 2 + size as u64));
+        }
+
+        return Ok(&self.metadata[&position]);
+    }
+
+    /// Read `length` bytes of a metadata stream, advancing the cursor past them.
+    fn read_metadata(&mut self, cursor: &mut Cursor, length: usize) -> Result<Vec<u8>, RrubError> {
+        let mut result = Vec::new();
+        while result.len() < length {
+            let (data, next) = self.metadata_block(cursor.block)?;
+            if cursor.offset >= data.len() {
+                if cursor.offset > data.len() {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                *cursor = Cursor {
+                    block: *next,
+                    offset: 0,
+                };
+                continue;
+            }
+
+            let count = (data.len() - cursor.offset).min(length - result.len());
+            result.extend_from_slice(&data[cursor.offset..cursor.offset + count]);
+            cursor.offset += count;
+        }
+
+        return Ok(result);
+    }
+
+    fn read_struct<T: FromBytes>(&mut self, cursor: &mut Cursor) -> Result<T, RrubError> {
+        let raw = self.read_metadata(cursor, size_of::<T>())?;
+        return T::read_from_bytes(&raw).map_err(|_| FilesystemError::Corrupted.into());
+    }
+
+    fn inode(&mut self, node: NodeId) -> Result<&Inode, RrubError> {
+        if !self.inodes.contains_key(&node) {
+            let inode = self.load_inode(node)?;
+            self.inodes.insert(node, inode);
+        }
+        return Ok(&self.inodes[&node]);
+    }
+
+    /// Inodes are referenced by the position of their metadata block relative to the inode table,
+    /// shifted left by 16, and their offset in the block.
+    fn load_inode(&mut self, node: NodeId) -> Result<Inode, RrubError> {
+        let mut cursor = Cursor {
+            block: self.superblock.inode_table.get() + (node.0 >> 16),
+            offset: (node.0 & 0xFFFF) as usize,
+        };
+        let header: InodeHeader = self.read_struct(&mut cursor)?;
+        let inode_type = header.inode_type.get();
+        let file_type = file_type_from_inode(inode_type);
+
+        let (size, data) = match inode_type {
+            INODE_DIR => {
+                let dir: DirInode = self.read_struct(&mut cursor)?;
+                let size = dir.size.get() as u32;
+                let data = InodeData::Directory {
+                    start_block: dir.start_block.get(),
+                    offset: dir.offset.get(),
+                    size,
+                };
+                (size as u64, data)
+            }
+            INODE_EXTENDED_DIR => {
+                let dir: ExtendedDirInode = self.read_struct(&mut cursor)?;
+                let size = dir.size.get();
+                let data = InodeData::Directory {
+                    start_block: dir.start_block.get(),
+                    offset: dir.offset.get(),
+                    size,
+                };
+                (size as u64, data)
+            }
+            INODE_FILE => {
+                let file: FileInode = self.read_struct(&mut cursor)?;
+                let size = file.size.get() as u64;
+                let data = self.file_data(
+                    &mut cursor,
+                    file.start_block.get() as u64,
+                    size,
+                    file.fragment.get(),
+                    file.fragment_offset.get(),
+                )?;
+                (size, data)
+            }
+            INODE_EXTENDED_FILE => {
+                let file: ExtendedFileInode = self.read_struct(&mut cursor)?;
+                let size = file.size.get();
+                let data = self.file_data(
+                    &mut cursor,
+                    file.start_block.get(),
+                    size,
+                    file.fragment.get(),
+                    file.fragment_offset.get(),
+                )?;
+                (size, data)
+            }
+            INODE_SYMLINK | INODE_EXTENDED_SYMLINK => {
+                let link: SymlinkInode = self.read_struct(&mut cursor)?;
+                let size = link.target_size.get() as usize;
+                if size > METADATA_SIZE {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                let target = self.read_metadata(&mut cursor, size)?;
+                (size as u64, InodeData::Symlink(target))
+            }
+            _ => (0, InodeData::Other),
+        };
+
+        return Ok(Inode {
+            file_type,
+            size,
+            data,
+        });
+    }
+
+    /// Read the block list following a file inode. The tail of the file lives in a fragment
+    /// shared with other small files, unless the inode has no fragment.
+    fn file_data(
+        &mut self,
+        cursor: &mut Cursor,
+        start: u64,
+        size: u64,
+        fragment: u32,
+        fragment_offset: u32,
+    ) -> Result<InodeData, RrubError> {
+        let count = match fragment == NO_FRAGMENT {
+            true => size.div_ceil(self.block_size),
+            false => size / self.block_size,
+        };
+        if count > self.superblock.bytes_used.get() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let raw = self.read_metadata(cursor, count as usize * 4)?;
+        let mut blocks = Vec::with_capacity(count as usize);
+        let mut position = start;
+        for entry in raw.chunks_exact(4) {
+            let stored = u32::from_le_bytes(entry.try_into().unwrap());
+            blocks.push((position, stored));
+            position += (stored & !DATA_UNCOMPRESSED) as u64;
+        }
+
+        return Ok(InodeData::File {
+            blocks,
+            fragment,
+            fragment_offset,
+        });
+    }
+
+    fn fragment(&mut self, index: u32) -> Result<FragmentEntry, RrubError> {
+        if index >= self.superblock.fragment_count.get() {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut location = [0u8; 8];
+        let table = self.superblock.fragment_table.get();
+        self.device.read_bytes(
+            table + (index / FRAGMENTS_PER_BLOCK) as u64 * 8,
+            &mut location,
+        )?;
+
+        let mut cursor = Cursor {
+            block: u64::from_le_bytes(location),
+            offset: (index % FRAGMENTS_PER_BLOCK) as usize * size_of::<FragmentEntry>(),
+        };
+        return self.read_struct(&mut cursor);
+    }
+
+    /// Data of a block or fragment block, sparse blocks have a stored size of zero.
+    fn data_block(&mut self, position: u64, stored: u32) -> Result<&[u8], RrubError> {
+        let size = (stored & !DATA_UNCOMPRESSED) as usize;
+        if size == 0 {
+            self.block_cache = Some((u64::MAX, vec![0u8; self.block_size as usize]));
+        } else if self
+            .block_cache
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != position)
+        {
+            if size as u64 > self.block_size {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            let mut data = vec![0u8; size];
+            self.device.read_bytes(position, &mut data)?;
+            if stored & DATA_UNCOMPRESSED == 0 {
+                data = self.decompress(&data, self.block_size as usize)?;
+            }
+            self.block_cache = Some((position, data));
+        }
+
+        return Ok(&self.block_cache.as_ref().unwrap().1);
+    }
+
+    fn read_file(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let inode = self.inode(node)?.clone();
+        let InodeData::File {
+            blocks,
+            fragment,
+            fragment_offset,
+        } = inode.data
+        else {
+            return Err(FilesystemError::Unsupported.into());
+        };
+        if offset >= inode.size {
+            return Ok(0);
+        }
+
+        let block_size = self.block_size;
+        let length = buffer.len().min((inode.size - offset) as usize);
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let index = position / block_size;
+            let within = (position % block_size) as usize;
+
+            let (data, start) = match blocks.get(index as usize) {
+                Some(&(block, stored)) => (self.data_block(block, stored)?, within),
+                None => {
+                    let entry = self.fragment(fragment)?;
+                    let data = self.data_block(entry.start.get(), entry.size.get())?;
+                    (data, fragment_offset as usize + within)
+                }
+            };
+
+            let count = (length - done).min(block_size as usize - within);
+            let available = data
+                .get(start..start + count)
+                .ok_or(FilesystemError::Corrupted)?;
+            buffer[done..done + count].copy_from_slice(available);
+            done += count;
+        }
+
+        return Ok(length);
+    }
+}
+
+impl FilesystemBackend for SquashFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "squashfs";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return modification_time_id(&self.superblock);
+    }
+
+    fn label(&self) -> Option<String> {
+        return None;
+    }
+
+    fn root(&self) -> NodeId {
+        return NodeId(self.superblock.root_inode.get());
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let inode = self.inode(node)?;
+        let size = match inode.file_type {
+            FileType::Directory => inode.size.saturating_sub(DIR_SIZE_OFFSET as u64),
+            _ => inode.size,
+        };
+        return Ok(Metadata {
+            file_type: inode.file_type,
+            size,
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let InodeData::Directory {
+            start_block,
+            offset,
+            size,
+        } = self.inode(dir)?.data
+        else {
+            return Err(FilesystemError::NotADirectory.into());
+        };
+
+        let mut cursor = Cursor {
+            block: self.superblock.directory_table.get() + start_block as u64,
+            offset: offset as usize,
+        };
+        let listing =
+            self.read_metadata(&mut cursor, size.saturating_sub(DIR_SIZE_OFFSET) as usize)?;
+
+        let mut entries = Vec::new();
+        let mut position = 0;
+        while position < listing.len() {
+            let (header, _) = DirHeader::read_from_prefix(&listing[position..])
+                .map_err(|_| FilesystemError::Corrupted)?;
+            position += size_of::<DirHeader>();
+
+            let count = header.count.get() + 1;
+            if count > MAX_DIR_HEADER_ENTRIES {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            for _ in 0..count {
+                let (entry, rest) = DirEntryHeader::read_from_prefix(&listing[position..])
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                let name_size = entry.name_size.get() as usize + 1;
+                let name = rest.get(..name_size).ok_or(FilesystemError::Corrupted)?;
+                position += size_of::<DirEntryHeader>() + name_size;
+
+                entries.push(DirEntry {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    node: NodeId(
+                        (header.start_block.get() as u64) << 16 | entry.offset.get() as u64,
+                    ),
+                    file_type: file_type_from_inode(entry.inode_type.get()),
+                });
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        return match self.inode(node)?.file_type {
+            FileType::Directory => Err(FilesystemError::IsADirectory.into()),
+            FileType::Regular => self.read_file(node, offset, buffer),
+            _ => Err(FilesystemError::Unsupported.into()),
+        };
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        let InodeData::Symlink(target) = &self.inode(node)?.data else {
+            return Err(FilesystemError::InvalidPath.into());
+        };
+        return String::from_utf8(target.clone()).map_err(|_| FilesystemError::Corrupted.into());
+    }
+}
@@ -0,0 +1,1134 @@
+/*
+ * https://docs.kernel.org/filesystems/xfs/xfs-online-fsck-design.html
+ * https://mirrors.edge.kernel.org/pub/linux/utils/fs/xfs/docs/xfs_filesystem_structure.pdf
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use bitflags::bitflags;
+use uuid::Uuid as RealUuid;
+use zerocopy::{
+    BigEndian, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    checksum::crc32c_update,
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+        },
+    },
+    fs::probe::{Usage, VolumeInfo, label_from_bytes},
+};
+
+const XFS_MAGIC: u32 = 0x5846_5342;
+const INODE_MAGIC: u16 = 0x494E;
+const BMAP_MAGIC: u32 = 0x424D_4133;
+const DIR_BLOCK_MAGIC: u32 = 0x5844_4233;
+const DIR_DATA_MAGIC: u32 = 0x5844_4433;
+const DIR_LEAF1_MAGIC: u16 = 0x3DF1;
+const DIR_LEAFN_MAGIC: u16 = 0x3DFF;
+const DA_NODE_MAGIC: u16 = 0x3EBE;
+const SYMLINK_MAGIC: u32 = 0x5853_4C4D;
+
+const VERSION_NUMBER_MASK: u16 = 0xF;
+const VERSION_5: u16 = 5;
+
+const SUPERBLOCK_CRC_OFFSET: usize = 224;
+const INODE_CRC_OFFSET: usize = 100;
+const BMAP_CRC_OFFSET: usize = 64;
+const DIR_DATA_CRC_OFFSET: usize = 4;
+const DA_CRC_OFFSET: usize = 12;
+const SYMLINK_CRC_OFFSET: usize = 12;
+
+const INODE_CORE_SIZE: usize = 176;
+const INODE_VERSION_3: u8 = 3;
+
+const FORMAT_LOCAL: u8 = 1;
+const FORMAT_EXTENTS: u8 = 2;
+const FORMAT_BTREE: u8 = 3;
+
+const DIFLAG_REALTIME: u16 = 0x1;
+const DIFLAG2_NREXT64: u64 = 0x10;
+
+/// Byte offset of the leaf section of a directory, data blocks come before it.
+const DIR_LEAF_OFFSET: u64 = 1 << 35;
+/// Directory entry addresses are stored in units of 8 bytes.
+const DIR_DATA_ALIGN_LOG: u32 = 3;
+const DIR_DATA_HEADER_SIZE: usize = 64;
+const DIR_FREE_TAG: u16 = 0xFFFF;
+
+const FT_REG_FILE: u8 = 1;
+const FT_DIR: u8 = 2;
+const FT_SYMLINK: u8 = 7;
+
+const S_IFMT: u16 = 0xF000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xA000;
+
+const MAX_BTREE_LEVEL: u16 = 9;
+const MAX_DA_LEVEL: u16 = 5;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct IncompatFeatures: u32 {
+        const Ftype = 0x1;
+        const SparseInodes = 0x2;
+        const MetaUuid = 0x4;
+        const BigTime = 0x8;
+        const NeedsRepair = 0x10;
+        const Nrext64 = 0x20;
+        const ExchangeRange = 0x40;
+        const Parent = 0x80;
+        const MetaDir = 0x100;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Superblock {
+    pub magic: U32<BigEndian>,
+    pub block_size: U32<BigEndian>,
+    pub data_blocks: U64<BigEndian>,
+    pub rt_blocks: U64<BigEndian>,
+    pub rt_extents: U64<BigEndian>,
+    pub uuid: [u8; 16],
+    pub log_start: U64<BigEndian>,
+    pub root_inode: U64<BigEndian>,
+    pub rt_bitmap_inode: U64<BigEndian>,
+    pub rt_summary_inode: U64<BigEndian>,
+    pub rt_extent_size: U32<BigEndian>,
+    pub ag_blocks: U32<BigEndian>,
+    pub ag_count: U32<BigEndian>,
+    pub rt_bitmap_blocks: U32<BigEndian>,
+    pub log_blocks: U32<BigEndian>,
+    pub version: U16<BigEndian>,
+    pub sector_size: U16<BigEndian>,
+    pub inode_size: U16<BigEndian>,
+    pub inodes_per_block: U16<BigEndian>,
+    pub fname: [u8; 12],
+    pub block_log: u8,
+    pub sector_log: u8,
+    pub inode_log: u8,
+    pub inodes_per_block_log: u8,
+    pub ag_block_log: u8,
+    pub rt_extents_log: u8,
+    pub in_progress: u8,
+    pub inode_max_pct: u8,
+    pub inode_count: U64<BigEndian>,
+    pub inode_free: U64<BigEndian>,
+    pub free_data_blocks: U64<BigEndian>,
+    pub free_rt_extents: U64<BigEndian>,
+    pub user_quota_inode: U64<BigEndian>,
+    pub group_quota_inode: U64<BigEndian>,
+    pub quota_flags: U16<BigEndian>,
+    pub flags: u8,
+    pub shared_vn: u8,
+    pub inode_alignment: U32<BigEndian>,
+    pub stripe_unit: U32<BigEndian>,
+    pub stripe_width: U32<BigEndian>,
+    pub dir_block_log: u8,
+    pub log_sector_log: u8,
+    pub log_sector_size: U16<BigEndian>,
+    pub log_stripe_unit: U32<BigEndian>,
+    pub features2: U32<BigEndian>,
+    pub bad_features2: U32<BigEndian>,
+    pub features_compat: U32<BigEndian>,
+    pub features_ro_compat: U32<BigEndian>,
+    pub features_incompat: U32<BigEndian>,
+    pub features_log_incompat: U32<BigEndian>,
+    pub crc: [u8; 4],
+    pub sparse_inode_alignment: U32<BigEndian>,
+    pub project_quota_inode: U64<BigEndian>,
+    pub lsn: U64<BigEndian>,
+    pub meta_uuid: [u8; 16],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct InodeCore {
+    pub magic: U16<BigEndian>,
+    pub mode: U16<BigEndian>,
+    pub version: u8,
+    pub format: u8,
+    pub old_link_count: U16<BigEndian>,
+    pub uid: U32<BigEndian>,
+    pub gid: U32<BigEndian>,
+    pub link_count: U32<BigEndian>,
+    pub project_id_lo: U16<BigEndian>,
+    pub project_id_hi: U16<BigEndian>,
+    /// Data fork extent count with large extent counts, padding otherwise.
+    pub big_extent_count: U64<BigEndian>,
+    pub atime: U64<BigEndian>,
+    pub mtime: U64<BigEndian>,
+    pub ctime: U64<BigEndian>,
+    pub size: U64<BigEndian>,
+    pub blocks: U64<BigEndian>,
+    pub extent_size_hint: U32<BigEndian>,
+    pub extent_count: U32<BigEndian>,
+    pub attr_extent_count: U16<BigEndian>,
+    pub fork_offset: u8,
+    pub attr_format: u8,
+    pub dmapi_event_mask: U32<BigEndian>,
+    pub dmapi_state: U16<BigEndian>,
+    pub flags: U16<BigEndian>,
+    pub generation: U32<BigEndian>,
+    pub next_unlinked: U32<BigEndian>,
+    pub crc: [u8; 4],
+    pub change_count: U64<BigEndian>,
+    pub lsn: U64<BigEndian>,
+    pub flags2: U64<BigEndian>,
+    pub cow_extent_size_hint: U32<BigEndian>,
+    pub _pad: [u8; 12],
+    pub crtime: U64<BigEndian>,
+    pub inode_number: U64<BigEndian>,
+    pub uuid: [u8; 16],
+}
+
+const _: () = assert!(size_of::<InodeCore>() == INODE_CORE_SIZE);
+
+/// Header of long pointer B+tree blocks, used by extent maps.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BtreeBlock {
+    pub magic: U32<BigEndian>,
+    pub level: U16<BigEndian>,
+    pub record_count: U16<BigEndian>,
+    pub left_sibling: U64<BigEndian>,
+    pub right_sibling: U64<BigEndian>,
+    pub daddr: U64<BigEndian>,
+    pub lsn: U64<BigEndian>,
+    pub uuid: [u8; 16],
+    pub owner: U64<BigEndian>,
+    pub crc: [u8; 4],
+    pub _pad: U32<BigEndian>,
+}
+
+/// Root of an extent B+tree stored in the inode fork.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BtreeRoot {
+    pub level: U16<BigEndian>,
+    pub record_count: U16<BigEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ExtentRecord {
+    pub high: U64<BigEndian>,
+    pub low: U64<BigEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirDataHeader {
+    pub magic: U32<BigEndian>,
+    pub crc: [u8; 4],
+    pub daddr: U64<BigEndian>,
+    pub lsn: U64<BigEndian>,
+    pub uuid: [u8; 16],
+    pub owner: U64<BigEndian>,
+    pub best_free: [u8; 12],
+    pub _pad: U32<BigEndian>,
+}
+
+const _: () = assert!(size_of::<DirDataHeader>() == DIR_DATA_HEADER_SIZE);
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DirBlockTail {
+    pub count: U32<BigEndian>,
+    pub stale: U32<BigEndian>,
+}
+
+/// Common header of directory leaf and node blocks.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DaHeader {
+    pub forward: U32<BigEndian>,
+    pub back: U32<BigEndian>,
+    pub magic: U16<BigEndian>,
+    pub _pad: U16<BigEndian>,
+    pub crc: [u8; 4],
+    pub daddr: U64<BigEndian>,
+    pub lsn: U64<BigEndian>,
+    pub uuid: [u8; 16],
+    pub owner: U64<BigEndian>,
+    pub count: U16<BigEndian>,
+    /// Stale entries in leaves, tree level in nodes.
+    pub stale_or_level: U16<BigEndian>,
+    pub _pad2: U32<BigEndian>,
+}
+
+/// Leaf entries map a name hash to an entry address, node entries map the highest hash below a
+/// child to the child's directory block.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DaEntry {
+    pub hash: U32<BigEndian>,
+    pub value: U32<BigEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct SymlinkHeader {
+    pub magic: U32<BigEndian>,
+    pub offset: U32<BigEndian>,
+    pub bytes: U32<BigEndian>,
+    pub crc: [u8; 4],
+    pub uuid: [u8; 16],
+    pub owner: U64<BigEndian>,
+    pub daddr: U64<BigEndian>,
+    pub lsn: U64<BigEndian>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Extent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    /// Preallocated but never written, reads back as zeros.
+    unwritten: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    number: u64,
+    raw: Vec<u8>,
+    core: InodeCore,
+}
+
+impl Inode {
+    fn size(&self) -> u64 {
+        return self.core.size.get();
+    }
+
+    fn file_type(&self) -> FileType {
+        return match self.core.mode.get() & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFREG => FileType::Regular,
+            S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        };
+    }
+
+    fn extent_count(&self) -> u64 {
+        return match self.core.flags2.get() & DIFLAG2_NREXT64 != 0 {
+            true => self.core.big_extent_count.get(),
+            false => self.core.extent_count.get() as u64,
+        };
+    }
+
+    /// The data fork takes the space after the core up to the attribute fork, if any.
+    fn data_fork(&self) -> &[u8] {
+        let end = match self.core.fork_offset {
+            0 => self.raw.len(),
+            offset => (INODE_CORE_SIZE + offset as usize * 8).min(self.raw.len()),
+        };
+        return &self.raw[INODE_CORE_SIZE..end];
+    }
+}
+
+/// Directory entry as stored on disk, the address is its byte offset in the directory.
+struct RawDirEntry {
+    address: u64,
+    name: Vec<u8>,
+    number: u64,
+    ftype: u8,
+}
+
+pub struct XfsFilesystem {
+    device: Box<dyn BlockDevice>,
+    superblock: Superblock,
+    incompat: IncompatFeatures,
+    block_size: u64,
+    dir_block_size: u64,
+    /// UUID stamped in metadata blocks, differs from the filesystem UUID after it was changed.
+    meta_uuid: [u8; 16],
+    inodes: BTreeMap<u64, Inode>,
+    extents: BTreeMap<u64, Vec<Extent>>,
+}
+
+/// Verify a CRC32c stored little endian at `offset`, computed with the field itself zeroed.
+fn verify_crc(data: &[u8], offset: usize) -> bool {
+    let Some(stored) = data.get(offset..offset + 4) else {
+        return false;
+    };
+    let mut crc = crc32c_update(!0, &data[..offset]);
+    crc = crc32c_update(crc, &[0; 4]);
+    crc = crc32c_update(crc, &data[offset + 4..]);
+    return stored == (!crc).to_le_bytes();
+}
+
+/// Name hash ordering directory leaf entries.
+fn name_hash(name: &[u8]) -> u32 {
+    let mut hash: u32 = 0;
+    let mut chunks = name.chunks_exact(4);
+
+    for chunk in &mut chunks {
+        hash = (chunk[0] as u32) << 21
+            ^ (chunk[1] as u32) << 14
+            ^ (chunk[2] as u32) << 7
+            ^ chunk[3] as u32
+            ^ hash.rotate_left(7 * 4);
+    }
+
+    return match *chunks.remainder() {
+        [a, b, c] => (a as u32) << 14 ^ (b as u32) << 7 ^ c as u32 ^ hash.rotate_left(7 * 3),
+        [a, b] => (a as u32) << 7 ^ b as u32 ^ hash.rotate_left(7 * 2),
+        [a] => a as u32 ^ hash.rotate_left(7),
+        _ => hash,
+    };
+}
+
+fn file_type_from_ftype(ftype: u8) -> FileType {
+    return match ftype {
+        FT_REG_FILE => FileType::Regular,
+        FT_DIR => FileType::Directory,
+        FT_SYMLINK => FileType::Symlink,
+        _ => FileType::Other,
+    };
+}
+
+impl XfsFilesystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut magic = [0u8; 4];
+        return device.read_bytes(0, &mut magic).is_ok() && u32::from_be_bytes(magic) == XFS_MAGIC;
+    }
+
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let mut raw = [0u8; 512];
+        device.read_bytes(0, &mut raw).ok()?;
+        let (superblock, _) = Superblock::read_from_prefix(&raw).ok()?;
+        if superblock.magic.get() != XFS_MAGIC {
+            return None;
+        }
+
+        return Some(VolumeInfo {
+            fs_type: "xfs",
+            usage: Usage::Filesystem,
+            label: label_from_bytes(&superblock.fname),
+            uuid: Some(Uuid::RealUuid(RealUuid::from_bytes(superblock.uuid))),
+            size: superblock
+                .data_blocks
+                .get()
+                .saturating_mul(superblock.block_size.get() as u64),
+        });
+    }
+
+    /// The log is not replayed, a volume that was not cleanly unmounted may show stale data.
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<XfsFilesystem, RrubError> {
+        let mut raw = [0u8; 512];
+        device.read_bytes(0, &mut raw)?;
+        let (superblock, _) =
+            Superblock::read_from_prefix(&raw).map_err(|_| FilesystemError::UnknownFilesystem)?;
+
+        if superblock.magic.get() != XFS_MAGIC {
+            return Err(FilesystemError::UnknownFilesystem.into());
+        }
+        if superblock.version.get() & VERSION_NUMBER_MASK != VERSION_5 {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let sector_size = superblock.sector_size.get() as usize;
+        if !sector_size.is_power_of_two() || !(512..=32768).contains(&sector_size) {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let mut sector = vec![0u8; sector_size];
+        device.read_bytes(0, &mut sector)?;
+        if !verify_crc(&sector, SUPERBLOCK_CRC_OFFSET) {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+
+        let incompat = IncompatFeatures::from_bits(superblock.features_incompat.get())
+            .ok_or(FilesystemError::Unsupported)?;
+        if incompat.intersects(IncompatFeatures::NeedsRepair | IncompatFeatures::MetaDir) {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let block_size = superblock.block_size.get() as u64;
+        let inode_size = superblock.inode_size.get() as usize;
+        if !block_size.is_power_of_two()
+            || block_size != 1 << superblock.block_log
+            || inode_size != 1 << superblock.inode_log
+            || inode_size < INODE_CORE_SIZE
+            || superblock.inodes_per_block.get() as u64 != block_size / inode_size as u64
+            || superblock.ag_blocks.get() == 0
+            || superblock.ag_block_log > 31
+            || superblock.dir_block_log > 8
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let meta_uuid = match incompat.contains(IncompatFeatures::MetaUuid) {
+            true => superblock.meta_uuid,
+            false => superblock.uuid,
+        };
+
+        return Ok(XfsFilesystem {
+            device,
+            superblock,
+            incompat,
+            block_size,
+            dir_block_size: block_size << superblock.dir_block_log,
+            meta_uuid,
+            inodes: BTreeMap::new(),
+            extents: BTreeMap::new(),
+        });
+    }
+
+    /// Filesystem block numbers encode the allocation group in their high bits.
+    fn linear_block(&self, block: u64) -> Result<u64, RrubError> {
+        let ag_block_log = self.superblock.ag_block_log as u32;
+        let group = block >> ag_block_log;
+        let offset = block & ((1 << ag_block_log) - 1);
+
+        if group >= self.superblock.ag_count.get() as u64
+            || offset >= self.superblock.ag_blocks.get() as u64
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        return Ok(group * self.superblock.ag_blocks.get() as u64 + offset);
+    }
+
+    fn read_block(&mut self, block: u64) -> Result<Vec<u8>, RrubError> {
+        let mut data = vec![0u8; self.block_size as usize];
+        let offset = block
+            .checked_mul(self.block_size)
+            .ok_or(FilesystemError::Corrupted)?;
+        self.device.read_bytes(offset, &mut data)?;
+        return Ok(data);
+    }
+
+    fn inode(&mut self, number: u64) -> Result<&Inode, RrubError> {
+        if !self.inodes.contains_key(&number) {
+            let inode = self.load_inode(number)?;
+            self.inodes.insert(number, inode);
+        }
+        return Ok(&self.inodes[&number]);
+    }
+
+    fn load_inode(&mut self, number: u64) -> Result<Inode, RrubError> {
+        let per_block_log = self.superblock.inodes_per_block_log as u32;
+        let index = number & ((1 << per_block_log) - 1);
+        let block = self.linear_block(number >> per_block_log)?;
+
+        let inode_size = self.superblock.inode_size.get() as u64;
+        let mut raw = vec![0u8; inode_size as usize];
+        self.device
+            .read_bytes(block * self.block_size + index * inode_size, &mut raw)?;
+
+        let (core, _) =
+            InodeCore::read_from_prefix(&raw).map_err(|_| FilesystemError::Corrupted)?;
+        if core.magic.get() != INODE_MAGIC || core.version != INODE_VERSION_3 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        if !verify_crc(&raw, INODE_CRC_OFFSET) {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        if core.inode_number.get() != number || core.uuid != self.meta_uuid {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        return Ok(Inode { number, raw, core });
+    }
+
+    fn push_records(
+        &self,
+        records: &[ExtentRecord],
+        extents: &mut Vec<Extent>,
+    ) -> Result<(), RrubError> {
+        for record in records {
+            let (high, low) = (record.high.get(), record.low.get());
+            let length = low & 0x1F_FFFF;
+            if length == 0 {
+                continue;
+            }
+
+            extents.push(Extent {
+                logical: (high & !(1 << 63)) >> 9,
+                physical: self.linear_block((high & 0x1FF) << 43 | low >> 21)?,
+                length,
+                unwritten: high >> 63 != 0,
+            });
+        }
+        return Ok(());
+    }
+
+    fn collect_btree(
+        &mut self,
+        inode: &Inode,
+        block: u64,
+        level: u16,
+        extents: &mut Vec<Extent>,
+    ) -> Result<(), RrubError> {
+        let physical = self.linear_block(block)?;
+        let data = self.read_block(physical)?;
+
+        let (header, body) =
+            BtreeBlock::read_from_prefix(&data).map_err(|_| FilesystemError::Corrupted)?;
+        if header.magic.get() != BMAP_MAGIC || header.level.get() != level {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        if !verify_crc(&data, BMAP_CRC_OFFSET) {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        if header.owner.get() != inode.number
+            || header.uuid != self.meta_uuid
+            || header.daddr.get() != physical * self.block_size / 512
+        {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let count = header.record_count.get() as usize;
+        if level == 0 {
+            let (records, _) = <[ExtentRecord]>::ref_from_prefix_with_elems(body, count)
+                .map_err(|_| FilesystemError::Corrupted)?;
+            return self.push_records(records, extents);
+        }
+
+        let max_records = body.len() / 16;
+        for pointer in self.btree_pointers(body, max_records, count)? {
+            self.collect_btree(inode, pointer, level - 1, extents)?;
+        }
+        return Ok(());
+    }
+
+    /// Node pointers follow the space reserved for the maximum number of keys.
+    fn btree_pointers(
+        &self,
+        body: &[u8],
+        max_records: usize,
+        count: usize,
+    ) -> Result<Vec<u64>, RrubError> {
+        if count > max_records {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let (pointers, _) =
+            <[U64<BigEndian>]>::ref_from_prefix_with_elems(&body[max_records * 8..], count)
+                .map_err(|_| FilesystemError::Corrupted)?;
+        return Ok(pointers.iter().map(|pointer| pointer.get()).collect());
+    }
+
+    fn extents(&mut self, number: u64) -> Result<&[Extent], RrubError> {
+        if !self.extents.contains_key(&number) {
+            let inode = self.inode(number)?.clone();
+            if inode.core.flags.get() & DIFLAG_REALTIME != 0 {
+                return Err(FilesystemError::Unsupported.into());
+            }
+
+            let fork = inode.data_fork();
+            let mut extents = Vec::new();
+            match inode.core.format {
+                FORMAT_EXTENTS => {
+                    let count = usize::try_from(inode.extent_count())
+                        .map_err(|_| FilesystemError::Corrupted)?;
+                    let (records, _) = <[ExtentRecord]>::ref_from_prefix_with_elems(fork, count)
+                        .map_err(|_| FilesystemError::Corrupted)?;
+                    self.push_records(records, &mut extents)?;
+                }
+                FORMAT_BTREE => {
+                    let (root, body) = BtreeRoot::read_from_prefix(fork)
+                        .map_err(|_| FilesystemError::Corrupted)?;
+                    let level = root.level.get();
+                    if level == 0 || level > MAX_BTREE_LEVEL {
+                        return Err(FilesystemError::Corrupted.into());
+                    }
+
+                    let max_records = body.len() / 16;
+                    let pointers =
+                        self.btree_pointers(body, max_records, root.record_count.get() as usize)?;
+                    for pointer in pointers {
+                        self.collect_btree(&inode, pointer, level - 1, &mut extents)?;
+                    }
+                }
+                _ => return Err(FilesystemError::Corrupted.into()),
+            }
+
+            extents.sort_by_key(|extent| extent.logical);
+            self.extents.insert(number, extents);
+        }
+
+        return Ok(&self.extents[&number]);
+    }
+
+    /// Read through the extent map without clamping to the file size, directories keep their
+    /// leaf blocks past it.
+    fn read_mapped(
+        &mut self,
+        number: u64,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), RrubError> {
+        let block_size = self.block_size;
+        let extents = self.extents(number)?;
+
+        let mut reads = Vec::new();
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let logical = position / block_size;
+            let remaining = buffer.len() - done;
+
+            let index = extents.partition_point(|extent| extent.logical + extent.length <= logical);
+            let count = match extents.get(index) {
+                Some(extent) if extent.logical <= logical => {
+                    let end = (extent.logical + extent.length) * block_size;
+                    let count = ((end - position) as usize).min(remaining);
+                    if !extent.unwritten {
+                        let physical = (extent.physical + logical - extent.logical) * block_size
+                            + position % block_size;
+                        reads.push((physical, done, count));
+                    } else {
+                        buffer[done..done + count].fill(0);
+                    }
+                    count
+                }
+                // Holes read as zeros.
+                next => {
+                    let end = next.map_or(u64::MAX, |extent| extent.logical * block_size);
+                    let count = (end - position).min(remaining as u64) as usize;
+                    buffer[done..done + count].fill(0);
+                    count
+                }
+            };
+            done += count;
+        }
+
+        for (physical, start, count) in reads {
+            self.device
+                .read_bytes(physical, &mut buffer[start..start + count])?;
+        }
+
+        return Ok(());
+    }
+
+    fn read_data(
+        &mut self,
+        number: u64,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let inode = self.inode(number)?;
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let length = buffer.len().min((size - offset) as usize);
+
+        if inode.core.format == FORMAT_LOCAL {
+            let available = inode
+                .data_fork()
+                .get(offset as usize..offset as usize + length)
+                .ok_or(FilesystemError::Corrupted)?;
+            buffer[..length].copy_from_slice(available);
+            return Ok(length);
+        }
+
+        self.read_mapped(number, offset, &mut buffer[..length])?;
+        return Ok(length);
+    }
+
+    /// Remote symlink targets are split over blocks that each carry a header.
+    fn read_remote_symlink(&mut self, number: u64) -> Result<Vec<u8>, RrubError> {
+        let size = self.inode(number)?.size() as usize;
+        let extents = self.extents(number)?.to_vec();
+
+        let mut target = Vec::new();
+        for extent in extents {
+            for block in extent.physical..extent.physical + extent.length {
+                if target.len() >= size {
+                    break;
+                }
+
+                let data = self.read_block(block)?;
+                let (header, body) = SymlinkHeader::read_from_prefix(&data)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                if header.magic.get() != SYMLINK_MAGIC
+                    || header.owner.get() != number
+                    || header.uuid != self.meta_uuid
+                    || header.offset.get() as usize != target.len()
+                {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                if !verify_crc(&data, SYMLINK_CRC_OFFSET) {
+                    return Err(FilesystemError::ChecksumMismatch.into());
+                }
+
+                let bytes = body
+                    .get(..header.bytes.get() as usize)
+                    .ok_or(FilesystemError::Corrupted)?;
+                target.extend_from_slice(bytes);
+            }
+        }
+
+        target.truncate(size);
+        return Ok(target);
+    }
+
+    fn has_ftype(&self) -> bool {
+        return self.incompat.contains(IncompatFeatures::Ftype);
+    }
+
+    /// Entries of a short form directory stored in the inode, inode numbers are 8 bytes wide when
+    /// any of them needs it.
+    fn short_form_entries(&self, inode: &Inode) -> Result<Vec<RawDirEntry>, RrubError> {
+        let fork = inode.data_fork();
+        let (&count, rest) = fork.split_first().ok_or(FilesystemError::Corrupted)?;
+        let (&count8, _) = rest.split_first().ok_or(FilesystemError::Corrupted)?;
+        let width = match count8 {
+            0 => 4,
+            _ => 8,
+        };
+
+        let read_number = |bytes: &[u8]| -> u64 {
+            return bytes
+                .iter()
+                .fold(0, |number, &byte| number << 8 | byte as u64);
+        };
+
+        let mut entries = Vec::new();
+        let mut offset = 2 + width;
+        for _ in 0..count {
+            let name_len = *fork.get(offset).ok_or(FilesystemError::Corrupted)? as usize;
+            let name_start = offset + 3;
+            let ftype_len = self.has_ftype() as usize;
+            let end = name_start + name_len + ftype_len + width;
+            let entry = fork.get(offset..end).ok_or(FilesystemError::Corrupted)?;
+
+            let name = entry[3..3 + name_len].to_vec();
+            let ftype = match self.has_ftype() {
+                true => entry[3 + name_len],
+                false => 0,
+            };
+            let number = read_number(&entry[3 + name_len + ftype_len..]);
+            entries.push(RawDirEntry {
+                address: 0,
+                name,
+                number,
+                ftype,
+            });
+            offset = end;
+        }
+
+        return Ok(entries);
+    }
+
+    fn read_dir_block(&mut self, inode: &Inode, offset: u64) -> Result<Vec<u8>, RrubError> {
+        let mut block = vec![0u8; self.dir_block_size as usize];
+        self.read_mapped(inode.number, offset, &mut block)?;
+        return Ok(block);
+    }
+
+    fn verify_da_block(&self, inode: &Inode, block: &[u8]) -> Result<DaHeader, RrubError> {
+        let (header, _) =
+            DaHeader::read_from_prefix(block).map_err(|_| FilesystemError::Corrupted)?;
+        if !verify_crc(block, DA_CRC_OFFSET) {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        if header.owner.get() != inode.number || header.uuid != self.meta_uuid {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        return Ok(header);
+    }
+
+    /// Parse the entries of a data or single block directory block.
+    fn data_block_entries(
+        &self,
+        inode: &Inode,
+        block: &[u8],
+        entries: &mut Vec<RawDirEntry>,
+        base: u64,
+    ) -> Result<(), RrubError> {
+        let (header, _) =
+            DirDataHeader::read_from_prefix(block).map_err(|_| FilesystemError::Corrupted)?;
+        let end = match header.magic.get() {
+            DIR_DATA_MAGIC => block.len(),
+            // Single block directories keep their leaf entries and a tail at the end.
+            DIR_BLOCK_MAGIC => {
+                let (_, tail) = DirBlockTail::read_from_suffix(block)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+                (block.len() - size_of::<DirBlockTail>())
+                    .checked_sub(tail.count.get() as usize * size_of::<DaEntry>())
+                    .ok_or(FilesystemError::Corrupted)?
+            }
+            _ => return Err(FilesystemError::Corrupted.into()),
+        };
+        if !verify_crc(block, DIR_DATA_CRC_OFFSET) {
+            return Err(FilesystemError::ChecksumMismatch.into());
+        }
+        if header.owner.get() != inode.number || header.uuid != self.meta_uuid {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let ftype_len = self.has_ftype() as usize;
+        let mut offset = DIR_DATA_HEADER_SIZE;
+        while offset < end {
+            let entry = block.get(offset..end).ok_or(FilesystemError::Corrupted)?;
+            if entry.len() < 8 {
+                return Err(FilesystemError::Corrupted.into());
+            }
+
+            if u16::from_be_bytes([entry[0], entry[1]]) == DIR_FREE_TAG {
+                let length = u16::from_be_bytes([entry[2], entry[3]]) as usize;
+                if length == 0 || !length.is_multiple_of(8) {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                offset += length;
+                continue;
+            }
+
+            let name_len = *entry.get(8).ok_or(FilesystemError::Corrupted)? as usize;
+            let length = (8 + 1 + name_len + ftype_len + 2).next_multiple_of(8);
+            let entry = entry.get(..length).ok_or(FilesystemError::Corrupted)?;
+
+            let number = u64::from_be_bytes(entry[..8].try_into().unwrap());
+            let name = entry[9..9 + name_len].to_vec();
+            let ftype = match self.has_ftype() {
+                true => entry[9 + name_len],
+                false => 0,
+            };
+            entries.push(RawDirEntry {
+                address: base + offset as u64,
+                name,
+                number,
+                ftype,
+            });
+            offset += length;
+        }
+
+        return Ok(());
+    }
+
+    /// All entries of a directory with their address, including "." and "..".
+    fn dir_entries(&mut self, number: u64) -> Result<Vec<RawDirEntry>, RrubError> {
+        let inode = self.inode(number)?.clone();
+        if inode.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        if inode.core.format == FORMAT_LOCAL {
+            return self.short_form_entries(&inode);
+        }
+
+        // Data blocks are the mapped blocks before the leaf section.
+        let (block_size, dir_block_size) = (self.block_size, self.dir_block_size);
+        let mut offsets = Vec::new();
+        for extent in self.extents(number)? {
+            let start = (extent.logical * block_size).next_multiple_of(dir_block_size);
+            let end = ((extent.logical + extent.length) * block_size).min(DIR_LEAF_OFFSET);
+            offsets.extend((start..end).step_by(dir_block_size as usize));
+        }
+
+        let mut entries = Vec::new();
+        for offset in offsets {
+            let block = self.read_dir_block(&inode, offset)?;
+            self.data_block_entries(&inode, &block, &mut entries, offset)?;
+        }
+
+        return Ok(entries);
+    }
+
+    /// Addresses of the entries with a given name hash, walking the node tree of large
+    /// directories.
+    fn hashed_addresses(&mut self, inode: &Inode, hash: u32) -> Result<Vec<u64>, RrubError> {
+        let block_size = self.block_size;
+        let has_leaves = self
+            .extents(inode.number)?
+            .iter()
+            .any(|extent| (extent.logical + extent.length) * block_size > DIR_LEAF_OFFSET);
+
+        // Single block directories have no leaf section, their leaf entries close the data block.
+        if !has_leaves {
+            let block = self.read_dir_block(inode, 0)?;
+            let (header, _) =
+                DirDataHeader::read_from_prefix(&block).map_err(|_| FilesystemError::Corrupted)?;
+            if header.magic.get() != DIR_BLOCK_MAGIC {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            if !verify_crc(&block, DIR_DATA_CRC_OFFSET) {
+                return Err(FilesystemError::ChecksumMismatch.into());
+            }
+
+            let (rest, tail) =
+                DirBlockTail::read_from_suffix(&block).map_err(|_| FilesystemError::Corrupted)?;
+            let (_, leaves) =
+                <[DaEntry]>::ref_from_suffix_with_elems(rest, tail.count.get() as usize)
+                    .map_err(|_| FilesystemError::Corrupted)?;
+            return Ok(matching_addresses(leaves, hash));
+        }
+
+        let mut offset = DIR_LEAF_OFFSET;
+        let mut visited = Vec::new();
+        let mut level = MAX_DA_LEVEL + 1;
+        let mut addresses = Vec::new();
+        loop {
+            if visited.contains(&offset) {
+                return Err(FilesystemError::Corrupted.into());
+            }
+            visited.push(offset);
+
+            let block = self.read_dir_block(inode, offset)?;
+            let header = self.verify_da_block(inode, &block)?;
+            let (entries, _) = <[DaEntry]>::ref_from_prefix_with_elems(
+                &block[size_of::<DaHeader>()..],
+                header.count.get() as usize,
+            )
+            .map_err(|_| FilesystemError::Corrupted)?;
+
+            match header.magic.get() {
+                DA_NODE_MAGIC => {
+                    let node_level = header.stale_or_level.get();
+                    if node_level == 0 || node_level >= level {
+                        return Err(FilesystemError::Corrupted.into());
+                    }
+                    level = node_level;
+
+                    // Each entry holds the highest hash found under its child.
+                    let child = entries
+                        .iter()
+                        .find(|entry| entry.hash.get() >= hash)
+                        .or(entries.last())
+                        .ok_or(FilesystemError::Corrupted)?;
+                    offset = child.value.get() as u64 * block_size;
+                }
+                DIR_LEAF1_MAGIC | DIR_LEAFN_MAGIC => {
+                    addresses.extend(matching_addresses(entries, hash));
+
+                    // Entries with the same hash may continue in the next leaf.
+                    let forward = header.forward.get() as u64;
+                    match forward != 0
+                        && entries.last().is_some_and(|entry| entry.hash.get() == hash)
+                    {
+                        true => offset = forward * block_size,
+                        false => return Ok(addresses),
+                    }
+                }
+                _ => return Err(FilesystemError::Corrupted.into()),
+            }
+        }
+    }
+}
+
+/// Byte addresses of the non stale leaf entries with a given hash, entries are sorted by hash.
+fn matching_addresses(entries: &[DaEntry], hash: u32) -> Vec<u64> {
+    let start = entries.partition_point(|entry| entry.hash.get() < hash);
+    return entries[start..]
+        .iter()
+        .take_while(|entry| entry.hash.get() == hash)
+        .filter(|entry| entry.value.get() != 0)
+        .map(|entry| (entry.value.get() as u64) << DIR_DATA_ALIGN_LOG)
+        .collect();
+}
+
+impl FilesystemBackend for XfsFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "xfs";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::RealUuid(RealUuid::from_bytes(self.superblock.uuid));
+    }
+
+    fn label(&self) -> Option<String> {
+        return label_from_bytes(&self.superblock.fname);
+    }
+
+    fn root(&self) -> NodeId {
+        return NodeId(self.superblock.root_inode.get());
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let inode = self.inode(node.0)?;
+        return Ok(Metadata {
+            file_type: inode.file_type(),
+            size: inode.size(),
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let entries = self.dir_entries(dir.0)?;
+        let mut result = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            if entry.name == b"." || entry.name == b".." {
+                continue;
+            }
+            let file_type = match entry.ftype {
+                0 => self.inode(entry.number)?.file_type(),
+                ftype => file_type_from_ftype(ftype),
+            };
+            result.push(DirEntry {
+                name: String::from_utf8_lossy(&entry.name).into_owned(),
+                node: NodeId(entry.number),
+                file_type,
+            });
+        }
+
+        return Ok(result);
+    }
+
+    fn lookup(&mut self, dir: NodeId, name: &str) -> Result<NodeId, RrubError> {
+        let inode = self.inode(dir.0)?.clone();
+        if inode.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        if inode.core.format == FORMAT_LOCAL {
+            return self
+                .short_form_entries(&inode)?
+                .into_iter()
+                .find(|entry| entry.name == name.as_bytes())
+                .map(|entry| NodeId(entry.number))
+                .ok_or(FilesystemError::NotFound.into());
+        }
+
+        for address in self.hashed_addresses(&inode, name_hash(name.as_bytes()))? {
+            let offset = address - address % self.dir_block_size;
+            let block = self.read_dir_block(&inode, offset)?;
+
+            let mut entries = Vec::new();
+            self.data_block_entries(&inode, &block, &mut entries, offset)?;
+            if let Some(entry) = entries
+                .into_iter()
+                .find(|entry| entry.address == address && entry.name == name.as_bytes())
+            {
+                return Ok(NodeId(entry.number));
+            }
+        }
+
+        return Err(FilesystemError::NotFound.into());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        if self.inode(node.0)?.file_type() == FileType::Directory {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+        return self.read_data(node.0, offset, buffer);
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        let inode = self.inode(node.0)?;
+        if inode.file_type() != FileType::Symlink {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let target = match inode.core.format {
+            FORMAT_LOCAL => {
+                let size = inode.size() as usize;
+                inode
+                    .data_fork()
+                    .get(..size)
+                    .ok_or(FilesystemError::Corrupted)?
+                    .to_vec()
+            }
+            _ => self.read_remote_symlink(node.0)?,
+        };
+
+        return String::from_utf8(target).map_err(|_| FilesystemError::Corrupted.into());
+    }
+}
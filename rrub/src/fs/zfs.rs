@@ -0,0 +1,744 @@
+/*
+ * Read-only ZFS: the meta object set, DSL datasets and snapshots, and ZPL files
+ * https://openzfs.github.io/openzfs-docs/Developer%20Resources/OpenZFS%20on-disk%20format.html
+ * https://github.com/openzfs/zfs/blob/master/include/sys/dnode.h
+ * https://github.com/openzfs/zfs/blob/master/include/sys/dsl_dataset.h
+ * https://github.com/openzfs/zfs/blob/master/include/sys/sa_impl.h
+*/
+
+mod nvlist;
+mod pool;
+mod zap;
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use log::warn;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U64},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        block::BlockDevice,
+        filesystem::{
+            DirEntry, FileType, FilesystemBackend, FilesystemError, Metadata, NodeId, Uuid,
+            VolumeId64,
+        },
+    },
+    fs::{
+        probe::{Usage, VolumeInfo},
+        zfs::{
+            pool::{BlockPointer, Pool},
+            zap::Entry,
+        },
+    },
+};
+
+const DNODE_SIZE: usize = 512;
+const DNODE_FLAG_SPILL: u8 = 1 << 2;
+const POINTER_SHIFT: u8 = 7;
+
+/// Objects of the meta object set and of every filesystem that are found by number.
+const OBJECT_DIRECTORY: u64 = 1;
+const MASTER_NODE: u64 = 1;
+
+const BONUS_ZNODE: u8 = 17;
+const BONUS_SA: u8 = 44;
+
+/// Word indexes of the fields of dsl_dir_phys_t and dsl_dataset_phys_t that are read.
+const DIR_HEAD_DATASET: usize = 1;
+const DIR_CHILD_DIRS: usize = 4;
+const DATASET_SNAPSHOTS: usize = 4;
+const DATASET_CREATION_TXG: usize = 7;
+const DATASET_POINTER_OFFSET: usize = 128;
+
+const SA_MAGIC: u32 = 0x2F_505A;
+
+/// Directory entries hold the object number in the low 48 bits and the file type in the top 4.
+const ENTRY_OBJECT_MASK: u64 = (1 << 48) - 1;
+const ENTRY_TYPE_DIRECTORY: u64 = 4;
+const ENTRY_TYPE_REGULAR: u64 = 8;
+const ENTRY_TYPE_SYMLINK: u64 = 10;
+
+const S_IFMT: u64 = 0xF000;
+const S_IFDIR: u64 = 0x4000;
+const S_IFREG: u64 = 0x8000;
+const S_IFLNK: u64 = 0xA000;
+
+/// Node IDs hold the index of the object set in the top 16 bits, object numbers are 48 bits.
+const NODE_OBJSET_SHIFT: u32 = 48;
+
+/// Features that change how the pool is read and that this driver supports when active. Any other
+/// active feature in `features_for_read` makes the pool unreadable.
+const SUPPORTED_FEATURES: [&str; 14] = [
+    "org.illumos:lz4_compress",
+    "org.freebsd:zstd_compress",
+    "com.delphix:hole_birth",
+    "com.delphix:extensible_dataset",
+    "com.delphix:embedded_data",
+    "org.open-zfs:large_blocks",
+    "org.zfsonlinux:large_dnode",
+    "com.datto:bookmark_v2",
+    "com.delphix:bookmark_written",
+    "com.delphix:redaction_bookmarks",
+    "com.delphix:head_errlog",
+    "org.openzfs:longname",
+    // Encrypted blocks are refused as they are read, leaving other datasets readable.
+    "com.datto:encryption",
+    // Only checksums, which are read without verification.
+    "org.openzfs:blake3",
+];
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct DnodeHeader {
+    pub object_type: u8,
+    pub indirect_shift: u8,
+    pub levels: u8,
+    pub pointer_count: u8,
+    pub bonus_type: u8,
+    pub checksum: u8,
+    pub compression: u8,
+    pub flags: u8,
+    pub data_sectors: U16<LittleEndian>,
+    pub bonus_length: U16<LittleEndian>,
+    /// Additional 512 byte slots taken by a large dnode.
+    pub extra_slots: u8,
+    pub _pad: [u8; 3],
+    pub max_block: U64<LittleEndian>,
+    pub used: U64<LittleEndian>,
+    pub _pad2: [U64<LittleEndian>; 4],
+}
+
+#[derive(Debug, Clone)]
+struct Dnode {
+    header: DnodeHeader,
+    pointers: Vec<BlockPointer>,
+    bonus: Vec<u8>,
+    spill: Option<BlockPointer>,
+}
+
+impl Dnode {
+    /// Parse the dnode at the start of `raw`, which holds the rest of the block for large dnodes.
+    fn parse(raw: &[u8]) -> Result<Dnode, RrubError> {
+        let (header, _) =
+            DnodeHeader::read_from_prefix(raw).map_err(|_| FilesystemError::Corrupted)?;
+        if header.object_type == 0 {
+            return Err(FilesystemError::NotFound.into());
+        }
+
+        let raw = raw
+            .get(..(header.extra_slots as usize + 1) * DNODE_SIZE)
+            .ok_or(FilesystemError::Corrupted)?;
+        let pointers_end =
+            size_of::<DnodeHeader>() + header.pointer_count as usize * size_of::<BlockPointer>();
+        let pointers = <[BlockPointer]>::ref_from_bytes(
+            raw.get(size_of::<DnodeHeader>()..pointers_end)
+                .ok_or(FilesystemError::Corrupted)?,
+        )
+        .map_err(|_| FilesystemError::Corrupted)?;
+        let bonus = raw
+            .get(pointers_end..pointers_end + header.bonus_length.get() as usize)
+            .ok_or(FilesystemError::Corrupted)?;
+        let spill = match header.flags & DNODE_FLAG_SPILL {
+            0 => None,
+            _ => Some(
+                BlockPointer::read_from_bytes(&raw[raw.len() - size_of::<BlockPointer>()..])
+                    .map_err(|_| FilesystemError::Corrupted)?,
+            ),
+        };
+
+        return Ok(Dnode {
+            header,
+            pointers: pointers.to_vec(),
+            bonus: bonus.to_vec(),
+            spill,
+        });
+    }
+
+    fn block_size(&self) -> usize {
+        return self.header.data_sectors.get() as usize * 512;
+    }
+
+    /// Fields of a DSL bonus buffer, which are all 64 bit words.
+    fn bonus_word(&self, index: usize) -> Result<u64, RrubError> {
+        let bytes = self
+            .bonus
+            .get(index * 8..index * 8 + 8)
+            .ok_or(FilesystemError::Corrupted)?;
+        return Ok(u64::from_le_bytes(bytes.try_into().unwrap()));
+    }
+}
+
+/// System attributes of a filesystem, which ZPL_ names map to which attribute numbers and the
+/// layouts they are packed in.
+#[derive(Debug, Default)]
+struct Attributes {
+    /// Attribute number to (name, length), a length of 0 meaning variable.
+    registry: BTreeMap<u16, (String, u16)>,
+    layouts: BTreeMap<u16, Vec<u16>>,
+}
+
+#[derive(Debug, Clone)]
+struct Znode {
+    mode: u64,
+    size: u64,
+    /// Short symlink targets are stored as an attribute, longer ones in the file data.
+    symlink: Option<Vec<u8>>,
+}
+
+impl Znode {
+    fn file_type(&self) -> FileType {
+        return match self.mode & S_IFMT {
+            S_IFREG => FileType::Regular,
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        };
+    }
+}
+
+/// A mounted dataset or snapshot.
+struct Objset {
+    dataset: u64,
+    meta: Dnode,
+    root: u64,
+    attributes: Attributes,
+}
+
+pub struct ZfsFilesystem {
+    pool: Pool,
+    /// Meta dnode of the meta object set, which holds the pool wide objects.
+    mos: Dnode,
+    root_dir: u64,
+    /// Node IDs index this list in their top bits.
+    objsets: Vec<Objset>,
+    znodes: BTreeMap<NodeId, (Dnode, Znode)>,
+    root: NodeId,
+}
+
+fn node_id(objset: usize, object: u64) -> NodeId {
+    return NodeId(((objset as u64) << NODE_OBJSET_SHIFT) | object);
+}
+
+impl ZfsFilesystem {
+    pub fn identify(device: &mut dyn BlockDevice) -> Option<VolumeInfo> {
+        let config = pool::read_label(device)?;
+        // Spares, cache devices and destroyed pools also carry labels.
+        if config.get_u64("state")? > 1 {
+            return None;
+        }
+
+        return Some(VolumeInfo {
+            fs_type: "zfs_member",
+            usage: Usage::Filesystem,
+            label: config.get_str("name").map(ToString::to_string),
+            uuid: Some(Uuid::VolumeId64(VolumeId64::from_u64_le(
+                config.get_u64("pool_guid")?,
+            ))),
+            size: device.size(),
+        });
+    }
+
+    /// GUID of the pool a device belongs to, used to group devices before mounting.
+    pub fn pool_guid(device: &mut dyn BlockDevice) -> Result<u64, RrubError> {
+        let config = pool::read_label(device).ok_or(FilesystemError::UnknownFilesystem)?;
+        return Ok(config
+            .get_u64("pool_guid")
+            .ok_or(FilesystemError::Corrupted)?);
+    }
+
+    /// Import a pool read-only and mount its boot filesystem, the `bootfs` property of the pool or
+    /// else its root dataset.
+    pub fn mount(devices: Vec<Box<dyn BlockDevice>>) -> Result<ZfsFilesystem, RrubError> {
+        let mut pool = Pool::open(devices)?;
+        let root = pool.uberblock.root;
+        let mos = Dnode::parse(&pool.read(&root)?)?;
+
+        let mut filesystem = ZfsFilesystem {
+            pool,
+            mos,
+            root_dir: 0,
+            objsets: Vec::new(),
+            znodes: BTreeMap::new(),
+            root: NodeId(0),
+        };
+
+        let directory = filesystem.mos_zap(OBJECT_DIRECTORY)?;
+        if let Some(features) = zap::lookup(&directory, "features_for_read") {
+            for (name, count) in filesystem.mos_zap(features)? {
+                if count.first().is_some_and(|&count| count > 0)
+                    && !SUPPORTED_FEATURES.contains(&name.as_str())
+                {
+                    warn!(
+                        "ZFS pool {} uses unsupported feature {}",
+                        filesystem.pool.name, name
+                    );
+                    return Err(FilesystemError::Unsupported.into());
+                }
+            }
+        }
+
+        filesystem.root_dir =
+            zap::lookup(&directory, "root_dataset").ok_or(FilesystemError::Corrupted)?;
+        let bootfs = match zap::lookup(&directory, "pool_props") {
+            Some(properties) => zap::lookup(&filesystem.mos_zap(properties)?, "bootfs"),
+            None => None,
+        };
+        let dataset = match bootfs {
+            Some(dataset) => dataset,
+            None => filesystem
+                .mos_dnode(filesystem.root_dir)?
+                .bonus_word(DIR_HEAD_DATASET)?,
+        };
+
+        let index = filesystem.objset(dataset)?;
+        filesystem.root = node_id(index, filesystem.objsets[index].root);
+
+        return Ok(filesystem);
+    }
+
+    /// Read block `block` of an object, walking its indirect blocks.
+    fn read_block(&mut self, dnode: &Dnode, block: u64) -> Result<Vec<u8>, RrubError> {
+        let size = dnode.block_size();
+        let levels = dnode.header.levels as u32;
+        let shift = dnode
+            .header
+            .indirect_shift
+            .checked_sub(POINTER_SHIFT)
+            .ok_or(FilesystemError::Corrupted)? as u32;
+        if block > dnode.header.max_block.get() || levels == 0 {
+            return Ok(vec![0u8; size]);
+        }
+
+        let top = block.checked_shr(shift * (levels - 1)).unwrap_or(0);
+        let Some(&(mut pointer)) = dnode.pointers.get(top as usize) else {
+            return Ok(vec![0u8; size]);
+        };
+        for level in (0..levels - 1).rev() {
+            if pointer.is_hole() {
+                return Ok(vec![0u8; size]);
+            }
+            let indirect = self.pool.read(&pointer)?;
+            let index = (block >> (shift * level)) as usize & ((1 << shift) - 1);
+            pointer = indirect
+                .get(index * size_of::<BlockPointer>()..)
+                .and_then(|raw| BlockPointer::read_from_prefix(raw).ok())
+                .ok_or(FilesystemError::Corrupted)?
+                .0;
+        }
+
+        if pointer.is_hole() {
+            return Ok(vec![0u8; size]);
+        }
+        let mut data = self.pool.read(&pointer)?;
+        data.resize(size, 0);
+        return Ok(data);
+    }
+
+    /// Object `object` of the object set whose meta dnode is `meta`.
+    fn dnode(&mut self, meta: &Dnode, object: u64) -> Result<Dnode, RrubError> {
+        let per_block = (meta.block_size() / DNODE_SIZE) as u64;
+        if per_block == 0 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let block = self.read_block(meta, object / per_block)?;
+        return Dnode::parse(&block[(object % per_block) as usize * DNODE_SIZE..]);
+    }
+
+    fn mos_dnode(&mut self, object: u64) -> Result<Dnode, RrubError> {
+        let meta = self.mos.clone();
+        return self.dnode(&meta, object);
+    }
+
+    fn zap(&mut self, dnode: &Dnode) -> Result<Vec<Entry>, RrubError> {
+        let first = self.read_block(dnode, 0)?;
+        return zap::entries(&first, &mut |block| self.read_block(dnode, block));
+    }
+
+    fn mos_zap(&mut self, object: u64) -> Result<Vec<Entry>, RrubError> {
+        let dnode = self.mos_dnode(object)?;
+        return self.zap(&dnode);
+    }
+
+    /// DSL directory of a dataset named `pool/child/...`.
+    fn dsl_dir(&mut self, name: &str) -> Result<u64, RrubError> {
+        let mut components = name.split('/');
+        if components.next() != Some(self.pool.name.as_str()) {
+            return Err(FilesystemError::NotFound.into());
+        }
+
+        let mut dir = self.root_dir;
+        for component in components {
+            let children = self.mos_dnode(dir)?.bonus_word(DIR_CHILD_DIRS)?;
+            dir = zap::lookup(&self.mos_zap(children)?, component)
+                .ok_or(FilesystemError::NotFound)?;
+        }
+
+        return Ok(dir);
+    }
+
+    /// Dataset object of a filesystem or of a snapshot named `filesystem@snapshot`.
+    fn dataset(&mut self, name: &str) -> Result<u64, RrubError> {
+        let (filesystem, snapshot) = match name.split_once('@') {
+            Some((filesystem, snapshot)) => (filesystem, Some(snapshot)),
+            None => (name, None),
+        };
+
+        let dir = self.dsl_dir(filesystem)?;
+        let head = self.mos_dnode(dir)?.bonus_word(DIR_HEAD_DATASET)?;
+        let Some(snapshot) = snapshot else {
+            return Ok(head);
+        };
+
+        let snapshots = self.mos_dnode(head)?.bonus_word(DATASET_SNAPSHOTS)?;
+        return Ok(
+            zap::lookup(&self.mos_zap(snapshots)?, snapshot).ok_or(FilesystemError::NotFound)?
+        );
+    }
+
+    /// Open the object set of a dataset, returning its index in `objsets`.
+    fn objset(&mut self, dataset: u64) -> Result<usize, RrubError> {
+        if let Some(index) = self
+            .objsets
+            .iter()
+            .position(|objset| objset.dataset == dataset)
+        {
+            return Ok(index);
+        }
+        if self.objsets.len() >= 1 << (64 - NODE_OBJSET_SHIFT) {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let bonus = self.mos_dnode(dataset)?.bonus;
+        let pointer = bonus
+            .get(DATASET_POINTER_OFFSET..)
+            .and_then(|raw| BlockPointer::read_from_prefix(raw).ok())
+            .ok_or(FilesystemError::Corrupted)?
+            .0;
+        let meta = Dnode::parse(&self.pool.read(&pointer)?)?;
+
+        let master = self.dnode(&meta, MASTER_NODE)?;
+        let master = self.zap(&master)?;
+        let root = zap::lookup(&master, "ROOT").ok_or(FilesystemError::Corrupted)?;
+        let attributes = match zap::lookup(&master, "SA_ATTRS") {
+            Some(object) => self.attributes(&meta, object)?,
+            // Filesystems older than ZPL version 5 keep znodes in the bonus buffer instead.
+            None => Attributes::default(),
+        };
+
+        self.objsets.push(Objset {
+            dataset,
+            meta,
+            root,
+            attributes,
+        });
+        return Ok(self.objsets.len() - 1);
+    }
+
+    fn attributes(&mut self, meta: &Dnode, object: u64) -> Result<Attributes, RrubError> {
+        let sa = self.dnode(meta, object)?;
+        let sa = self.zap(&sa)?;
+        let mut attributes = Attributes::default();
+
+        if let Some(registry) = zap::lookup(&sa, "REGISTRY") {
+            let registry = self.dnode(meta, registry)?;
+            for (name, value) in self.zap(&registry)? {
+                // Attribute number in the low 16 bits, and length in bits 24 to 39.
+                let value = value.first().copied().unwrap_or(0);
+                attributes
+                    .registry
+                    .insert(value as u16, (name, (value >> 24) as u16));
+            }
+        }
+
+        if let Some(layouts) = zap::lookup(&sa, "LAYOUTS") {
+            let layouts = self.dnode(meta, layouts)?;
+            for (name, value) in self.zap(&layouts)? {
+                let Ok(number) = name.parse::<u16>() else {
+                    continue;
+                };
+                attributes.layouts.insert(
+                    number,
+                    value.iter().map(|&attribute| attribute as u16).collect(),
+                );
+            }
+        }
+
+        return Ok(attributes);
+    }
+
+    fn objset_of(&self, node: NodeId) -> Result<usize, RrubError> {
+        let index = (node.0 >> NODE_OBJSET_SHIFT) as usize;
+        return match index < self.objsets.len() {
+            true => Ok(index),
+            false => Err(FilesystemError::NotFound.into()),
+        };
+    }
+
+    fn znode(&mut self, node: NodeId) -> Result<(Dnode, Znode), RrubError> {
+        if let Some(cached) = self.znodes.get(&node) {
+            return Ok(cached.clone());
+        }
+
+        let index = self.objset_of(node)?;
+        let meta = self.objsets[index].meta.clone();
+        let dnode = self.dnode(&meta, node.0 & ENTRY_OBJECT_MASK)?;
+        if dnode.header.bonus_type == BONUS_ZNODE {
+            return Err(FilesystemError::Unsupported.into());
+        }
+        if dnode.header.bonus_type != BONUS_SA {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut mode = self.attribute(index, &dnode.bonus, "ZPL_MODE")?;
+        let mut size = self.attribute(index, &dnode.bonus, "ZPL_SIZE")?;
+        let mut symlink = self.attribute(index, &dnode.bonus, "ZPL_SYMLINK")?;
+
+        // Attributes that don't fit in the bonus buffer go to the spill block, which has the
+        // same layout.
+        if let Some(spill) = dnode.spill
+            && (mode.is_none() || size.is_none() || symlink.is_none())
+        {
+            let spill = self.pool.read(&spill)?;
+            mode = mode.or(self.attribute(index, &spill, "ZPL_MODE")?);
+            size = size.or(self.attribute(index, &spill, "ZPL_SIZE")?);
+            symlink = symlink.or(self.attribute(index, &spill, "ZPL_SYMLINK")?);
+        }
+
+        let word = |value: Option<Vec<u8>>| -> Result<u64, RrubError> {
+            let value = value.ok_or(FilesystemError::Corrupted)?;
+            return Ok(u64::from_le_bytes(
+                value
+                    .get(..8)
+                    .ok_or(FilesystemError::Corrupted)?
+                    .try_into()
+                    .unwrap(),
+            ));
+        };
+        let znode = Znode {
+            mode: word(mode)?,
+            size: word(size)?,
+            symlink,
+        };
+
+        self.znodes.insert(node, (dnode.clone(), znode.clone()));
+        return Ok((dnode, znode));
+    }
+
+    /// Value of the system attribute called `name` in an SA header and its attributes.
+    fn attribute(
+        &self,
+        objset: usize,
+        data: &[u8],
+        name: &str,
+    ) -> Result<Option<Vec<u8>>, RrubError> {
+        let read_u16 = |offset: usize| -> Result<u16, RrubError> {
+            let bytes = data
+                .get(offset..offset + 2)
+                .ok_or(FilesystemError::Corrupted)?;
+            return Ok(u16::from_le_bytes(bytes.try_into().unwrap()));
+        };
+        let magic = data.get(..4).ok_or(FilesystemError::Corrupted)?;
+        if u32::from_le_bytes(magic.try_into().unwrap()) != SA_MAGIC {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        // Layout number in the low 10 bits, header size in 8 byte units in the upper 6. Lengths of
+        // variable sized attributes follow.
+        let info = read_u16(4)?;
+        let attributes = &self.objsets[objset].attributes;
+        let layout = attributes
+            .layouts
+            .get(&(info & 0x3FF))
+            .ok_or(FilesystemError::Corrupted)?;
+        let mut offset = ((info >> 10) as usize) * 8;
+        let mut variable = 0;
+
+        for number in layout {
+            let (attribute, length) = attributes
+                .registry
+                .get(number)
+                .ok_or(FilesystemError::Corrupted)?;
+            let length = match length {
+                0 => {
+                    variable += 1;
+                    read_u16(4 + variable * 2)? as usize
+                }
+                &length => length as usize,
+            };
+
+            if attribute == name {
+                let value = data
+                    .get(offset..offset + length)
+                    .ok_or(FilesystemError::Corrupted)?;
+                return Ok(Some(value.to_vec()));
+            }
+            offset += length.next_multiple_of(8);
+        }
+
+        return Ok(None);
+    }
+
+    fn read_node(
+        &mut self,
+        node: NodeId,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, RrubError> {
+        let (dnode, znode) = self.znode(node)?;
+        if offset >= znode.size {
+            return Ok(0);
+        }
+
+        let length = buffer.len().min((znode.size - offset) as usize);
+        let block_size = dnode.block_size() as u64;
+        if block_size == 0 {
+            return Err(FilesystemError::Corrupted.into());
+        }
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let within = (position % block_size) as usize;
+            let count = (block_size as usize - within).min(length - done);
+            let block = self.read_block(&dnode, position / block_size)?;
+            buffer[done..done + count].copy_from_slice(&block[within..within + count]);
+            done += count;
+        }
+
+        return Ok(length);
+    }
+}
+
+impl FilesystemBackend for ZfsFilesystem {
+    fn fs_type(&self) -> &'static str {
+        return "zfs";
+    }
+
+    fn uuid(&self) -> Uuid {
+        return Uuid::VolumeId64(VolumeId64::from_u64_le(self.pool.guid));
+    }
+
+    fn label(&self) -> Option<String> {
+        return Some(self.pool.name.clone());
+    }
+
+    /// Top directory of the boot filesystem, other datasets are reached through `subvolume`.
+    fn root(&self) -> NodeId {
+        return self.root;
+    }
+
+    fn metadata(&mut self, node: NodeId) -> Result<Metadata, RrubError> {
+        let (_, znode) = self.znode(node)?;
+        return Ok(Metadata {
+            file_type: znode.file_type(),
+            size: znode.size,
+        });
+    }
+
+    fn read_dir(&mut self, dir: NodeId) -> Result<Vec<DirEntry>, RrubError> {
+        let (dnode, znode) = self.znode(dir)?;
+        if znode.file_type() != FileType::Directory {
+            return Err(FilesystemError::NotADirectory.into());
+        }
+
+        let objset = self.objset_of(dir)?;
+        return Ok(self
+            .zap(&dnode)?
+            .into_iter()
+            .map(|(name, value)| {
+                let value = value.first().copied().unwrap_or(0);
+                let file_type = match value >> 60 {
+                    ENTRY_TYPE_DIRECTORY => FileType::Directory,
+                    ENTRY_TYPE_REGULAR => FileType::Regular,
+                    ENTRY_TYPE_SYMLINK => FileType::Symlink,
+                    _ => FileType::Other,
+                };
+                DirEntry {
+                    name,
+                    node: node_id(objset, value & ENTRY_OBJECT_MASK),
+                    file_type,
+                }
+            })
+            .collect());
+    }
+
+    fn read(&mut self, node: NodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, RrubError> {
+        if self.znode(node)?.1.file_type() == FileType::Directory {
+            return Err(FilesystemError::IsADirectory.into());
+        }
+        return self.read_node(node, offset, buffer);
+    }
+
+    fn read_link(&mut self, node: NodeId) -> Result<String, RrubError> {
+        let (_, znode) = self.znode(node)?;
+        if znode.file_type() != FileType::Symlink {
+            return Err(FilesystemError::InvalidPath.into());
+        }
+
+        let target = match znode.symlink {
+            Some(target) => target,
+            None => {
+                let mut target = vec![0u8; znode.size as usize];
+                let read = self.read_node(node, 0, &mut target)?;
+                target.truncate(read);
+                target
+            }
+        };
+
+        return String::from_utf8(target).map_err(|_| FilesystemError::Corrupted.into());
+    }
+
+    /// Datasets are named with the pool, e.g. "rpool/ROOT/ubuntu", and snapshots by the dataset
+    /// followed by "@" and the snapshot name.
+    fn subvolume(&mut self, name: &str) -> Result<NodeId, RrubError> {
+        let dataset = self.dataset(name)?;
+        let index = self.objset(dataset)?;
+        return Ok(node_id(index, self.objsets[index].root));
+    }
+
+    /// Child datasets by name, then snapshots from oldest to newest.
+    fn subvolumes(&mut self, parent: &str) -> Result<Vec<String>, RrubError> {
+        if parent.contains('@') {
+            return Ok(Vec::new());
+        }
+
+        let dir = self.dsl_dir(parent)?;
+        let dir = self.mos_dnode(dir)?;
+        let mut children: Vec<String> = self
+            .mos_zap(dir.bonus_word(DIR_CHILD_DIRS)?)?
+            .into_iter()
+            .map(|(name, _)| name)
+            // Hidden directories such as $ORIGIN and temporary %recv clones.
+            .filter(|name| !name.starts_with(['$', '%']))
+            .map(|name| format!("{}/{}", parent, name))
+            .collect();
+        children.sort();
+
+        let head = dir.bonus_word(DIR_HEAD_DATASET)?;
+        let snapshots = self.mos_dnode(head)?.bonus_word(DATASET_SNAPSHOTS)?;
+        let mut snapshots = self
+            .mos_zap(snapshots)?
+            .into_iter()
+            .map(|(name, value)| {
+                let dataset = value.first().copied().unwrap_or(0);
+                let txg = self.mos_dnode(dataset)?.bonus_word(DATASET_CREATION_TXG)?;
+                return Ok((txg, format!("{}@{}", parent, name)));
+            })
+            .collect::<Result<Vec<_>, RrubError>>()?;
+        snapshots.sort();
+
+        children.extend(snapshots.into_iter().map(|(_, name)| name));
+        return Ok(children);
+    }
+}
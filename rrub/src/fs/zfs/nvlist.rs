@@ -0,0 +1,150 @@
+/*
+ * XDR encoded name-value lists, as the vdev labels and the pool config are stored
+ * https://github.com/openzfs/zfs/blob/master/module/nvpair/nvpair.c
+*/
+
+use alloc::{string::String, vec::Vec};
+
+const ENCODING_XDR: u8 = 1;
+
+const TYPE_BOOLEAN: u32 = 1;
+const TYPE_UINT64: u32 = 8;
+const TYPE_STRING: u32 = 9;
+const TYPE_NVLIST: u32 = 19;
+const TYPE_NVLIST_ARRAY: u32 = 20;
+
+/// Nested lists are only a few levels deep, the vdev tree being the deepest.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// A name present without a value, used as a flag.
+    Boolean,
+    U64(u64),
+    String(String),
+    List(NvList),
+    ListArray(Vec<NvList>),
+    /// Types nothing here reads.
+    Other,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NvList(Vec<(String, Value)>);
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, length: usize) -> Option<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(length)?)?;
+        self.position += length;
+        return Some(bytes);
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        return Some(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()));
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        return Some(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()));
+    }
+
+    /// Strings and opaque data are padded to a multiple of four bytes.
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        let bytes = self.bytes(length.next_multiple_of(4))?;
+        return String::from_utf8(bytes[..length].to_vec()).ok();
+    }
+
+    fn list(&mut self, depth: usize) -> Option<NvList> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        // Version and flags.
+        self.bytes(8)?;
+
+        let mut pairs = Vec::new();
+        loop {
+            let start = self.position;
+            let encoded_size = self.u32()? as usize;
+            let _decoded_size = self.u32()?;
+            if encoded_size == 0 {
+                return Some(NvList(pairs));
+            }
+
+            let name = self.string()?;
+            let kind = self.u32()?;
+            let count = self.u32()? as usize;
+            let value = match kind {
+                TYPE_BOOLEAN => Value::Boolean,
+                TYPE_UINT64 => Value::U64(self.u64()?),
+                TYPE_STRING => Value::String(self.string()?),
+                TYPE_NVLIST => Value::List(self.list(depth + 1)?),
+                TYPE_NVLIST_ARRAY => Value::ListArray(
+                    (0..count)
+                        .map(|_| self.list(depth + 1))
+                        .collect::<Option<_>>()?,
+                ),
+                _ => Value::Other,
+            };
+            pairs.push((name, value));
+
+            // The encoded size covers the whole pair, nested lists included.
+            self.position = start.checked_add(encoded_size)?;
+        }
+    }
+}
+
+impl NvList {
+    /// Parse a packed list, starting with the header naming its encoding.
+    pub fn parse(raw: &[u8]) -> Option<NvList> {
+        if raw.first() != Some(&ENCODING_XDR) {
+            return None;
+        }
+        let mut reader = Reader {
+            data: raw,
+            position: 4,
+        };
+        return reader.list(0);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        return self
+            .0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value);
+    }
+
+    pub fn get_u64(&self, name: &str) -> Option<u64> {
+        return match self.get(name)? {
+            Value::U64(value) => Some(*value),
+            _ => None,
+        };
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        return match self.get(name)? {
+            Value::String(value) => Some(value),
+            _ => None,
+        };
+    }
+
+    pub fn get_list(&self, name: &str) -> Option<&NvList> {
+        return match self.get(name)? {
+            Value::List(value) => Some(value),
+            _ => None,
+        };
+    }
+
+    pub fn get_lists(&self, name: &str) -> Option<&[NvList]> {
+        return match self.get(name)? {
+            Value::ListArray(value) => Some(value),
+            _ => None,
+        };
+    }
+}
@@ -0,0 +1,718 @@
+/*
+ * ZFS storage pools: vdev labels, uberblocks, the vdev tree and block pointers
+ * https://github.com/openzfs/zfs/blob/master/include/sys/vdev_impl.h
+ * https://github.com/openzfs/zfs/blob/master/include/sys/spa.h
+ * https://github.com/openzfs/zfs/blob/master/module/zfs/vdev_raidz.c
+ * https://openzfs.github.io/openzfs-docs/Developer%20Resources/OpenZFS%20on-disk%20format.html
+*/
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec, vec::Vec};
+
+use log::{debug, warn};
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned, byteorder::U64,
+};
+
+use crate::{
+    checksum::{fletcher2, fletcher4},
+    crypto::HashAlgorithm,
+    decompress::{DecompressError, inflate::zlib_decompress, lz4, lzjb, zle, zstd},
+    error::RrubError,
+    firmware::{block::BlockDevice, filesystem::FilesystemError},
+    fs::zfs::nvlist::NvList,
+};
+
+const LABEL_SIZE: u64 = 256 * 1024;
+const LABEL_COUNT: u64 = 4;
+/// Two labels and the boot block reserve the first 4 MiB of every leaf vdev.
+const LABEL_START_SIZE: u64 = 4 * 1024 * 1024;
+const CONFIG_OFFSET: u64 = 16 * 1024;
+const CONFIG_SIZE: usize = 112 * 1024;
+const UBERBLOCK_RING_OFFSET: u64 = 128 * 1024;
+const UBERBLOCK_RING_SIZE: u64 = 128 * 1024;
+const UBERBLOCK_MIN_SHIFT: u64 = 10;
+const UBERBLOCK_MAX_SHIFT: u64 = 13;
+
+const UBERBLOCK_MAGIC: u64 = 0x00BA_B10C;
+const ECK_MAGIC: u64 = 0x0210_DA7A_B10C_7A11;
+
+const SECTOR_SHIFT: u32 = 9;
+
+const CHECKSUM_OFF: u8 = 2;
+const CHECKSUM_FLETCHER2: u8 = 6;
+const CHECKSUM_FLETCHER4: u8 = 7;
+const CHECKSUM_SHA256: u8 = 8;
+
+const COMPRESS_OFF: u8 = 2;
+const COMPRESS_LZJB: u8 = 3;
+const COMPRESS_EMPTY: u8 = 4;
+const COMPRESS_GZIP_1: u8 = 5;
+const COMPRESS_GZIP_9: u8 = 13;
+const COMPRESS_ZLE: u8 = 14;
+const COMPRESS_LZ4: u8 = 15;
+const COMPRESS_ZSTD: u8 = 16;
+
+/// Object set blocks are authenticated but not encrypted, unlike the data they point to.
+const OBJECT_TYPE_OBJSET: u8 = 11;
+const EMBEDDED_TYPE_DATA: u8 = 0;
+/// ZFS writes zstd frames without their magic number.
+const ZSTD_MAGIC: [u8; 4] = 0xFD2F_B528u32.to_le_bytes();
+
+const BLOCK_CACHE_ENTRIES: usize = 64;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Dva {
+    /// Vdev in the upper 32 bits and allocated size in sectors in the lower 24.
+    pub word0: U64<LittleEndian>,
+    /// Gang flag in the top bit and offset in sectors from the end of the front labels.
+    pub word1: U64<LittleEndian>,
+}
+
+impl Dva {
+    fn vdev(&self) -> u64 {
+        return self.word0.get() >> 32;
+    }
+
+    fn allocated(&self) -> u64 {
+        return (self.word0.get() & 0xFF_FFFF) << SECTOR_SHIFT;
+    }
+
+    fn offset(&self) -> u64 {
+        return (self.word1.get() & !(1 << 63)) << SECTOR_SHIFT;
+    }
+
+    fn is_gang(&self) -> bool {
+        return self.word1.get() >> 63 != 0;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct BlockPointer {
+    pub dva: [Dva; 3],
+    pub properties: U64<LittleEndian>,
+    pub _pad: [U64<LittleEndian>; 2],
+    pub physical_birth: U64<LittleEndian>,
+    pub birth: U64<LittleEndian>,
+    pub fill: U64<LittleEndian>,
+    pub checksum: [U64<LittleEndian>; 4],
+}
+
+const _: () = assert!(size_of::<BlockPointer>() == 128);
+
+impl BlockPointer {
+    fn bits(&self, shift: u32, width: u32) -> u64 {
+        return (self.properties.get() >> shift) & ((1 << width) - 1);
+    }
+
+    fn is_embedded(&self) -> bool {
+        return self.bits(39, 1) != 0;
+    }
+
+    pub fn is_hole(&self) -> bool {
+        return !self.is_embedded() && self.dva.iter().all(|dva| dva.word0.get() == 0);
+    }
+
+    pub fn logical_size(&self) -> usize {
+        return match self.is_embedded() {
+            true => self.bits(0, 25) as usize + 1,
+            false => ((self.bits(0, 16) as usize) + 1) << SECTOR_SHIFT,
+        };
+    }
+
+    fn physical_size(&self) -> usize {
+        return match self.is_embedded() {
+            true => self.bits(25, 7) as usize + 1,
+            false => ((self.bits(16, 16) as usize) + 1) << SECTOR_SHIFT,
+        };
+    }
+
+    fn compression(&self) -> u8 {
+        return self.bits(32, 7) as u8;
+    }
+
+    fn checksum_type(&self) -> u8 {
+        return self.bits(40, 8) as u8;
+    }
+
+    fn object_type(&self) -> u8 {
+        return self.bits(48, 8) as u8;
+    }
+
+    fn is_encrypted(&self) -> bool {
+        return self.bits(61, 1) != 0 && self.bits(56, 5) == 0;
+    }
+
+    fn is_little_endian(&self) -> bool {
+        return self.bits(63, 1) != 0;
+    }
+
+    /// Embedded block pointers hold up to 112 bytes of data in place of the DVAs and checksum.
+    fn embedded_payload(&self) -> Vec<u8> {
+        let words = self.as_bytes();
+        let mut payload = Vec::with_capacity(112);
+        for range in [0..48, 56..80, 88..128] {
+            payload.extend_from_slice(&words[range]);
+        }
+        payload.truncate(self.physical_size());
+        return payload;
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Uberblock {
+    pub magic: U64<LittleEndian>,
+    pub version: U64<LittleEndian>,
+    pub txg: U64<LittleEndian>,
+    pub guid_sum: U64<LittleEndian>,
+    pub timestamp: U64<LittleEndian>,
+    pub root: BlockPointer,
+}
+
+/// Embedded checksum at the end of labels and uberblocks, a SHA-256 taken with `checksum` set
+/// to the offset of the block on the device.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct EmbeddedChecksum {
+    pub magic: U64<LittleEndian>,
+    pub checksum: [U64<LittleEndian>; 4],
+}
+
+/// Checksums are kept as four native endian words, SHA-256 ones are the digest read as big endian
+/// words.
+fn sha256_words(data: &[&[u8]]) -> [u64; 4] {
+    let mut digest = [0u8; 32];
+    HashAlgorithm::Sha256.digest(data, &mut digest);
+    return core::array::from_fn(|i| {
+        u64::from_be_bytes(digest[i * 8..i * 8 + 8].try_into().unwrap())
+    });
+}
+
+fn verify_label_block(block: &[u8], offset: u64) -> bool {
+    let split = block.len() - size_of::<EmbeddedChecksum>();
+    let Ok(trailer) = EmbeddedChecksum::read_from_bytes(&block[split..]) else {
+        return false;
+    };
+    if trailer.magic.get() != ECK_MAGIC {
+        return false;
+    }
+
+    let verifier = [offset, 0, 0, 0];
+    let computed = sha256_words(&[
+        &block[..split],
+        &ECK_MAGIC.to_le_bytes(),
+        verifier.as_bytes(),
+    ]);
+    return trailer.checksum.map(|word| word.get()) == computed;
+}
+
+/// Configuration of a device, read from the first of its four labels that is intact.
+pub fn read_label(device: &mut dyn BlockDevice) -> Option<NvList> {
+    let size = device.size() & !(LABEL_SIZE - 1);
+    if size < LABEL_COUNT * LABEL_SIZE {
+        return None;
+    }
+
+    let mut raw = vec![0u8; CONFIG_SIZE];
+    return (0..LABEL_COUNT).find_map(|index| {
+        let offset = label_offset(size, index) + CONFIG_OFFSET;
+        device.read_bytes(offset, &mut raw).ok()?;
+        if !verify_label_block(&raw, offset) {
+            return None;
+        }
+        return NvList::parse(&raw);
+    });
+}
+
+fn label_offset(size: u64, index: u64) -> u64 {
+    return match index < LABEL_COUNT / 2 {
+        true => index * LABEL_SIZE,
+        false => size - (LABEL_COUNT - index) * LABEL_SIZE,
+    };
+}
+
+/// Every valid uberblock in the labels of a device.
+fn read_uberblocks(device: &mut dyn BlockDevice, ashift: u64) -> Vec<Uberblock> {
+    let size = device.size() & !(LABEL_SIZE - 1);
+    let slot = 1 << ashift.clamp(UBERBLOCK_MIN_SHIFT, UBERBLOCK_MAX_SHIFT);
+    let mut ring = vec![0u8; UBERBLOCK_RING_SIZE as usize];
+
+    let mut uberblocks = Vec::new();
+    for index in 0..LABEL_COUNT {
+        let offset = label_offset(size, index) + UBERBLOCK_RING_OFFSET;
+        if device.read_bytes(offset, &mut ring).is_err() {
+            continue;
+        }
+        for (position, block) in ring.chunks_exact(slot as usize).enumerate() {
+            let Ok((uberblock, _)) = Uberblock::read_from_prefix(block) else {
+                continue;
+            };
+            if uberblock.magic.get() == UBERBLOCK_MAGIC
+                && verify_label_block(block, offset + position as u64 * slot)
+            {
+                uberblocks.push(uberblock);
+            }
+        }
+    }
+
+    return uberblocks;
+}
+
+enum Vdev {
+    Disk(Option<Box<dyn BlockDevice>>),
+    Mirror(Vec<Vdev>),
+    Raidz {
+        parity: usize,
+        ashift: u32,
+        children: Vec<Vdev>,
+    },
+    /// Top level vdevs that no label describes.
+    Missing,
+}
+
+/// One column of a RAID-Z stripe, at `offset` on child `child`.
+struct Column {
+    child: usize,
+    offset: u64,
+    size: usize,
+}
+
+impl Vdev {
+    /// Build the vdev described by `config`, taking its leaf devices from `devices` by GUID.
+    fn new(
+        config: &NvList,
+        devices: &mut BTreeMap<u64, Box<dyn BlockDevice>>,
+    ) -> Result<Vdev, RrubError> {
+        let kind = config.get_str("type").ok_or(FilesystemError::Corrupted)?;
+
+        return match kind {
+            "disk" | "file" => {
+                let guid = config.get_u64("guid").ok_or(FilesystemError::Corrupted)?;
+                let device = devices.remove(&guid);
+                if device.is_none() {
+                    warn!(
+                        "ZFS vdev {} is missing",
+                        config.get_str("path").unwrap_or("")
+                    );
+                }
+                Ok(Vdev::Disk(device))
+            }
+            "mirror" | "replacing" | "spare" => Ok(Vdev::Mirror(Self::children(config, devices)?)),
+            "raidz" => {
+                let parity = config
+                    .get_u64("nparity")
+                    .ok_or(FilesystemError::Corrupted)? as usize;
+                let ashift = config.get_u64("ashift").ok_or(FilesystemError::Corrupted)? as u32;
+                let children = Self::children(config, devices)?;
+                if !(1..=3).contains(&parity) || children.len() <= parity || ashift > 17 {
+                    return Err(FilesystemError::Corrupted.into());
+                }
+                Ok(Vdev::Raidz {
+                    parity,
+                    ashift,
+                    children,
+                })
+            }
+            // Holes are left by removed log devices, and no data is ever read from them.
+            "hole" => Ok(Vdev::Missing),
+            _ => {
+                debug!("Unsupported ZFS vdev type {}", kind);
+                Err(FilesystemError::Unsupported.into())
+            }
+        };
+    }
+
+    fn children(
+        config: &NvList,
+        devices: &mut BTreeMap<u64, Box<dyn BlockDevice>>,
+    ) -> Result<Vec<Vdev>, RrubError> {
+        return config
+            .get_lists("children")
+            .ok_or(FilesystemError::Corrupted)?
+            .iter()
+            .map(|child| Vdev::new(child, devices))
+            .collect();
+    }
+
+    /// Read `buffer` at `offset`, taking the first copy or reconstruction that `verify` accepts.
+    fn read(
+        &mut self,
+        offset: u64,
+        buffer: &mut [u8],
+        verify: &dyn Fn(&[u8]) -> bool,
+    ) -> Result<(), RrubError> {
+        return match self {
+            Vdev::Disk(Some(device)) => {
+                device.read_bytes(LABEL_START_SIZE + offset, buffer)?;
+                match verify(buffer) {
+                    true => Ok(()),
+                    false => Err(FilesystemError::ChecksumMismatch.into()),
+                }
+            }
+            Vdev::Disk(None) | Vdev::Missing => Err(FilesystemError::NotFound.into()),
+            Vdev::Mirror(children) => {
+                let mut result = Err(FilesystemError::NotFound.into());
+                for child in children {
+                    result = child.read(offset, buffer, verify);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result
+            }
+            Vdev::Raidz {
+                parity,
+                ashift,
+                children,
+            } => Self::read_raidz(*parity, *ashift, children, offset, buffer, verify),
+        };
+    }
+
+    /// Columns of the stripe holding `size` bytes at `offset`, parity first, as
+    /// vdev_raidz_map_alloc lays them out.
+    fn raidz_columns(
+        parity: usize,
+        ashift: u32,
+        width: usize,
+        offset: u64,
+        size: usize,
+    ) -> Vec<Column> {
+        let data_width = (width - parity) as u64;
+        let block = offset >> ashift;
+        let sectors = (size as u64).div_ceil(1 << ashift);
+        let first = (block % width as u64) as usize;
+        let row_offset = (block / width as u64) << ashift;
+
+        let rows = sectors / data_width;
+        let remainder = sectors % data_width;
+        let big_columns = match remainder {
+            0 => 0,
+            _ => remainder as usize + parity,
+        };
+        let columns = match rows {
+            0 => big_columns,
+            _ => width,
+        };
+
+        let mut map: Vec<Column> = (0..columns)
+            .map(|index| {
+                let column = first + index;
+                let (child, offset) = match column >= width {
+                    true => (column - width, row_offset + (1 << ashift)),
+                    false => (column, row_offset),
+                };
+                let sectors = match index < big_columns {
+                    true => rows + 1,
+                    false => rows,
+                };
+                Column {
+                    child,
+                    offset,
+                    size: (sectors << ashift) as usize,
+                }
+            })
+            .collect();
+
+        // Single parity stripes alternate which column holds the parity at every 1 MiB, to
+        // spread it over all children.
+        if parity == 1 && offset & (1 << 20) != 0 && map.len() > 1 {
+            let (first, second) = (map[0].child, map[0].offset);
+            map[0].child = map[1].child;
+            map[0].offset = map[1].offset;
+            map[1].child = first;
+            map[1].offset = second;
+        }
+
+        return map;
+    }
+
+    /// Read the data columns of a RAID-Z stripe. A column that can't be read is rebuilt from the
+    /// P parity column, and when the data fails verification each column is rebuilt in turn to
+    /// find the damaged one.
+    fn read_raidz(
+        parity: usize,
+        ashift: u32,
+        children: &mut [Vdev],
+        offset: u64,
+        buffer: &mut [u8],
+        verify: &dyn Fn(&[u8]) -> bool,
+    ) -> Result<(), RrubError> {
+        let columns = Self::raidz_columns(parity, ashift, children.len(), offset, buffer.len());
+        let mut data: Vec<Option<Vec<u8>>> = Vec::new();
+        for column in &columns[parity..] {
+            let mut piece = vec![0u8; column.size];
+            data.push(
+                children[column.child]
+                    .read(column.offset, &mut piece, &|_| true)
+                    .ok()
+                    .map(|_| piece),
+            );
+        }
+
+        let missing: Vec<usize> = (0..data.len())
+            .filter(|&index| data[index].is_none())
+            .collect();
+        let suspects = match missing.as_slice() {
+            [] => {
+                Self::gather(&data, buffer);
+                if verify(buffer) {
+                    return Ok(());
+                }
+                (0..data.len()).collect()
+            }
+            [_] => missing,
+            _ => return Err(FilesystemError::NotFound.into()),
+        };
+
+        let column = &columns[0];
+        let mut parity_column = vec![0u8; column.size];
+        children[column.child].read(column.offset, &mut parity_column, &|_| true)?;
+
+        for index in suspects {
+            let mut rebuilt = parity_column.clone();
+            for piece in data
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != index)
+                .filter_map(|(_, piece)| piece.as_ref())
+            {
+                rebuilt
+                    .iter_mut()
+                    .zip(piece)
+                    .for_each(|(byte, other)| *byte ^= other);
+            }
+            rebuilt.truncate(columns[parity + index].size);
+
+            let original = data[index].replace(rebuilt);
+            Self::gather(&data, buffer);
+            if verify(buffer) {
+                if original.is_some() {
+                    warn!("Repaired damaged RAID-Z column at {:#x}", offset);
+                }
+                return Ok(());
+            }
+            data[index] = original;
+        }
+
+        return Err(FilesystemError::ChecksumMismatch.into());
+    }
+
+    /// Concatenate data columns into `buffer`, which may be shorter than the columns.
+    fn gather(data: &[Option<Vec<u8>>], buffer: &mut [u8]) {
+        let mut done = 0;
+        for piece in data.iter().flatten() {
+            let count = piece.len().min(buffer.len() - done);
+            buffer[done..done + count].copy_from_slice(&piece[..count]);
+            done += count;
+        }
+    }
+}
+
+pub struct Pool {
+    pub name: String,
+    pub guid: u64,
+    /// Top level vdevs by ID, blocks are striped across them.
+    vdevs: Vec<Vdev>,
+    pub uberblock: Uberblock,
+    block_cache: BTreeMap<(u64, u64), Vec<u8>>,
+}
+
+impl Pool {
+    /// Import a pool from all of its devices, leaves that are missing leave the blocks stored on
+    /// them readable through the redundancy of their vdev.
+    pub fn open(devices: Vec<Box<dyn BlockDevice>>) -> Result<Pool, RrubError> {
+        let mut leaves = BTreeMap::new();
+        let mut trees: BTreeMap<u64, (u64, NvList)> = BTreeMap::new();
+        let mut newest: Option<NvList> = None;
+        let mut uberblocks = Vec::new();
+
+        for mut device in devices {
+            let config = read_label(device.as_mut()).ok_or(FilesystemError::UnknownFilesystem)?;
+            let txg = config.get_u64("txg").unwrap_or(0);
+            let guid = config.get_u64("guid").ok_or(FilesystemError::Corrupted)?;
+            let tree = config
+                .get_list("vdev_tree")
+                .ok_or(FilesystemError::Corrupted)?;
+            let id = tree.get_u64("id").ok_or(FilesystemError::Corrupted)?;
+
+            if let Some(existing) = &newest
+                && existing.get_u64("pool_guid") != config.get_u64("pool_guid")
+            {
+                return Err(FilesystemError::Corrupted.into());
+            }
+
+            let ashift = tree.get_u64("ashift").unwrap_or(UBERBLOCK_MIN_SHIFT);
+            uberblocks.extend(read_uberblocks(device.as_mut(), ashift));
+            leaves.insert(guid, device);
+
+            // Each label only describes the top level vdev of its device.
+            if trees.get(&id).is_none_or(|(existing, _)| txg > *existing) {
+                trees.insert(id, (txg, tree.clone()));
+            }
+            if newest
+                .as_ref()
+                .is_none_or(|existing| txg > existing.get_u64("txg").unwrap_or(0))
+            {
+                newest = Some(config);
+            }
+        }
+
+        let config = newest.ok_or(FilesystemError::UnknownFilesystem)?;
+        let name = config
+            .get_str("name")
+            .ok_or(FilesystemError::Corrupted)?
+            .into();
+        let guid = config
+            .get_u64("pool_guid")
+            .ok_or(FilesystemError::Corrupted)?;
+        let children = config
+            .get_u64("vdev_children")
+            .unwrap_or(trees.len() as u64);
+
+        let mut vdevs = Vec::new();
+        for id in 0..children {
+            vdevs.push(match trees.get(&id) {
+                Some((_, tree)) => Vdev::new(tree, &mut leaves)?,
+                None => {
+                    warn!("ZFS pool {} is missing top level vdev {}", name, id);
+                    Vdev::Missing
+                }
+            });
+        }
+
+        // The uberblock with the newest transaction group is the active one.
+        uberblocks.sort_by_key(|uberblock| (uberblock.txg.get(), uberblock.timestamp.get()));
+        let uberblock = uberblocks.pop().ok_or(FilesystemError::Corrupted)?;
+        if !uberblock.root.is_little_endian() {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        return Ok(Pool {
+            name,
+            guid,
+            vdevs,
+            uberblock,
+            block_cache: BTreeMap::new(),
+        });
+    }
+
+    /// Read and decompress the block `pointer` points to, trying each of its copies.
+    pub fn read(&mut self, pointer: &BlockPointer) -> Result<Vec<u8>, RrubError> {
+        if pointer.is_embedded() {
+            if pointer.bits(40, 8) as u8 != EMBEDDED_TYPE_DATA {
+                return Err(FilesystemError::Unsupported.into());
+            }
+            return decompress(
+                pointer.compression(),
+                &pointer.embedded_payload(),
+                pointer.logical_size(),
+            );
+        }
+        if pointer.is_hole() {
+            return Ok(vec![0u8; pointer.logical_size()]);
+        }
+        if pointer.is_encrypted() && pointer.object_type() != OBJECT_TYPE_OBJSET {
+            return Err(FilesystemError::Unsupported.into());
+        }
+
+        let key = (pointer.dva[0].word0.get(), pointer.dva[0].word1.get());
+        if let Some(data) = self.block_cache.get(&key) {
+            return Ok(data.clone());
+        }
+
+        let mut result = Err(FilesystemError::Corrupted.into());
+        for dva in pointer.dva.iter().filter(|dva| dva.allocated() != 0) {
+            if dva.is_gang() {
+                return Err(FilesystemError::Unsupported.into());
+            }
+            result = self.read_copy(pointer, dva);
+            match &result {
+                Ok(_) => break,
+                Err(e) => debug!(
+                    "Failed to read ZFS block copy at {:#x}: {:?}",
+                    dva.offset(),
+                    e
+                ),
+            }
+        }
+
+        let data = result?;
+        if self.block_cache.len() >= BLOCK_CACHE_ENTRIES {
+            self.block_cache.clear();
+        }
+        self.block_cache.insert(key, data.clone());
+        return Ok(data);
+    }
+
+    fn read_copy(&mut self, pointer: &BlockPointer, dva: &Dva) -> Result<Vec<u8>, RrubError> {
+        let vdev = self
+            .vdevs
+            .get_mut(dva.vdev() as usize)
+            .ok_or(FilesystemError::Corrupted)?;
+        let expected = pointer.checksum.map(|word| word.get());
+        let checksum_type = pointer.checksum_type();
+        let verify = |raw: &[u8]| {
+            let computed = match checksum_type {
+                CHECKSUM_FLETCHER2 => fletcher2(raw),
+                CHECKSUM_FLETCHER4 => fletcher4(raw),
+                CHECKSUM_SHA256 => sha256_words(&[raw]),
+                CHECKSUM_OFF => return true,
+                // SHA-512, Skein, Edon-R and BLAKE3 blocks are read without verification.
+                _ => return true,
+            };
+            return computed == expected;
+        };
+
+        let mut raw = vec![0u8; pointer.physical_size()];
+        vdev.read(dva.offset(), &mut raw, &verify)?;
+
+        return decompress(pointer.compression(), &raw, pointer.logical_size());
+    }
+}
+
+fn decompress(compression: u8, data: &[u8], size: usize) -> Result<Vec<u8>, RrubError> {
+    let mut output = match compression {
+        COMPRESS_OFF => data[..size.min(data.len())].to_vec(),
+        COMPRESS_EMPTY => Vec::new(),
+        COMPRESS_LZJB => lzjb::decompress(data, size)?,
+        COMPRESS_GZIP_1..=COMPRESS_GZIP_9 => zlib_decompress(data, size)?,
+        COMPRESS_ZLE => zle::decompress(data, size)?,
+        // Compressed data is prefixed with its big endian length, the rest of the block being
+        // padding.
+        COMPRESS_LZ4 => {
+            let length = u32::from_be_bytes(
+                data.get(..4)
+                    .ok_or(DecompressError::UnexpectedEnd)?
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let input = data
+                .get(4..4 + length)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            let mut output = Vec::with_capacity(size);
+            lz4::decompress_block(input, &mut output, size)?;
+            output
+        }
+        // Preceded by the big endian length and the version and level it was written with.
+        COMPRESS_ZSTD => {
+            let length = u32::from_be_bytes(
+                data.get(..4)
+                    .ok_or(DecompressError::UnexpectedEnd)?
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let frame = data
+                .get(8..8 + length)
+                .ok_or(DecompressError::UnexpectedEnd)?;
+            let mut input = Vec::with_capacity(length + ZSTD_MAGIC.len());
+            input.extend_from_slice(&ZSTD_MAGIC);
+            input.extend_from_slice(frame);
+            zstd::decompress(&input, size)?
+        }
+        _ => return Err(FilesystemError::Unsupported.into()),
+    };
+    output.resize(size, 0);
+    return Ok(output);
+}
@@ -0,0 +1,268 @@
+/*
+ * ZFS attribute processor objects, the name-value maps behind directories and most pool metadata
+ * https://github.com/openzfs/zfs/blob/master/include/sys/zap_impl.h
+ * https://github.com/openzfs/zfs/blob/master/include/sys/zap_leaf.h
+*/
+
+use alloc::{collections::BTreeSet, string::String, vec::Vec};
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{error::RrubError, firmware::filesystem::FilesystemError};
+
+const BLOCK_MICRO: u64 = (1 << 63) + 3;
+const BLOCK_HEADER: u64 = (1 << 63) + 1;
+const BLOCK_LEAF: u64 = 1 << 63;
+
+const ZAP_MAGIC: u64 = 0x2_F52A_B2AB;
+const LEAF_MAGIC: u32 = 0x02AB_1EAF;
+
+const MICRO_NAME_LENGTH: usize = 50;
+
+const CHUNK_SIZE: usize = 24;
+const CHUNK_ENTRY: u8 = 252;
+const CHUNK_ARRAY: u8 = 251;
+const CHUNK_ARRAY_BYTES: usize = 21;
+const CHAIN_END: u16 = 0xFFFF;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct MicroEntry {
+    pub value: U64<LittleEndian>,
+    pub collision: U32<LittleEndian>,
+    pub _pad: U16<LittleEndian>,
+    pub name: [u8; MICRO_NAME_LENGTH],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct FatHeader {
+    pub block_type: U64<LittleEndian>,
+    pub magic: U64<LittleEndian>,
+    /// First block of the pointer table, when it doesn't fit the second half of this block.
+    pub table_block: U64<LittleEndian>,
+    pub table_blocks: U64<LittleEndian>,
+    /// The pointer table has 2^shift entries.
+    pub table_shift: U64<LittleEndian>,
+    pub table_next_block: U64<LittleEndian>,
+    pub table_blocks_copied: U64<LittleEndian>,
+    pub free_block: U64<LittleEndian>,
+    pub leaf_count: U64<LittleEndian>,
+    pub entry_count: U64<LittleEndian>,
+    pub salt: U64<LittleEndian>,
+    pub normalization: U64<LittleEndian>,
+    pub flags: U64<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct LeafHeader {
+    pub block_type: U64<LittleEndian>,
+    pub _pad: U64<LittleEndian>,
+    pub prefix: U64<LittleEndian>,
+    pub magic: U32<LittleEndian>,
+    pub free_count: U16<LittleEndian>,
+    pub entry_count: U16<LittleEndian>,
+    pub prefix_length: U16<LittleEndian>,
+    pub free_list: U16<LittleEndian>,
+    pub flags: u8,
+    pub _pad2: [u8; 11],
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct LeafEntry {
+    pub chunk_type: u8,
+    pub integer_size: u8,
+    pub next: U16<LittleEndian>,
+    pub name_chunk: U16<LittleEndian>,
+    pub name_length: U16<LittleEndian>,
+    pub value_chunk: U16<LittleEndian>,
+    pub value_count: U16<LittleEndian>,
+    pub collision: U32<LittleEndian>,
+    pub hash: U64<LittleEndian>,
+}
+
+/// An entry with its value, each integer widened to 64 bits whatever its size on disk.
+pub type Entry = (String, Vec<u64>);
+
+/// Read every entry of a ZAP object from its first block, `read_block` giving the others.
+/// Lookups go through the full list rather than the hash, names being few in everything read.
+pub fn entries(
+    first: &[u8],
+    read_block: &mut dyn FnMut(u64) -> Result<Vec<u8>, RrubError>,
+) -> Result<Vec<Entry>, RrubError> {
+    let block_type = u64::from_le_bytes(
+        first
+            .get(..8)
+            .ok_or(FilesystemError::Corrupted)?
+            .try_into()
+            .unwrap(),
+    );
+    return match block_type {
+        BLOCK_MICRO => Ok(micro_entries(first)),
+        BLOCK_HEADER => fat_entries(first, read_block),
+        _ => Err(FilesystemError::Corrupted.into()),
+    };
+}
+
+/// Look up a single entry that holds one integer.
+pub fn lookup(entries: &[Entry], name: &str) -> Option<u64> {
+    return entries
+        .iter()
+        .find(|(key, _)| key == name)
+        .and_then(|(_, value)| value.first().copied());
+}
+
+fn micro_entries(block: &[u8]) -> Vec<Entry> {
+    // The header is the size of one entry.
+    return block
+        .chunks_exact(size_of::<MicroEntry>())
+        .skip(1)
+        .filter_map(|raw| MicroEntry::read_from_bytes(raw).ok())
+        .filter(|entry| entry.name[0] != 0)
+        .filter_map(|entry| {
+            let length = entry
+                .name
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(MICRO_NAME_LENGTH);
+            let name = String::from_utf8(entry.name[..length].to_vec()).ok()?;
+            return Some((name, Vec::from([entry.value.get()])));
+        })
+        .collect();
+}
+
+fn fat_entries(
+    first: &[u8],
+    read_block: &mut dyn FnMut(u64) -> Result<Vec<u8>, RrubError>,
+) -> Result<Vec<Entry>, RrubError> {
+    let (header, _) = FatHeader::read_from_prefix(first).map_err(|_| FilesystemError::Corrupted)?;
+    let block_size = first.len();
+    if header.magic.get() != ZAP_MAGIC || !block_size.is_power_of_two() {
+        return Err(FilesystemError::Corrupted.into());
+    }
+
+    // Pointers from hash prefixes to leaf blocks, several prefixes sharing a leaf until it splits.
+    let pointer_count = 1u64
+        .checked_shl(header.table_shift.get() as u32)
+        .filter(|&count| count <= 1 << 32)
+        .ok_or(FilesystemError::Corrupted)? as usize;
+    let mut leaves = BTreeSet::new();
+    match header.table_blocks.get() {
+        0 => {
+            let table = first
+                .get(block_size / 2..)
+                .and_then(|table| table.get(..pointer_count * 8))
+                .ok_or(FilesystemError::Corrupted)?;
+            leaves.extend(table.chunks_exact(8).map(read_u64));
+        }
+        count => {
+            for block in 0..count {
+                let table = read_block(header.table_block.get() + block)?;
+                leaves.extend(table.chunks_exact(8).map(read_u64));
+                if leaves.len() >= pointer_count {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    for leaf in leaves {
+        let block = read_block(leaf)?;
+        leaf_entries(&block, &mut entries)?;
+    }
+
+    return Ok(entries);
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    return u64::from_le_bytes(bytes.try_into().unwrap());
+}
+
+fn leaf_entries(block: &[u8], entries: &mut Vec<Entry>) -> Result<(), RrubError> {
+    let (header, _) =
+        LeafHeader::read_from_prefix(block).map_err(|_| FilesystemError::Corrupted)?;
+    if header.block_type.get() != BLOCK_LEAF || header.magic.get() != LEAF_MAGIC {
+        return Err(FilesystemError::Corrupted.into());
+    }
+
+    // The header is followed by a hash table of 16 bit chunk numbers, 1/32 of the block in
+    // entries, and then the chunks.
+    let hash_entries = block.len() / 32;
+    let chunks = block
+        .get(size_of::<LeafHeader>() + hash_entries * 2..)
+        .ok_or(FilesystemError::Corrupted)?;
+    let chunk_count = ((block.len() - hash_entries * 2) / CHUNK_SIZE).saturating_sub(2);
+    let chunk = |index: u16| {
+        chunks
+            .get(index as usize * CHUNK_SIZE..(index as usize + 1) * CHUNK_SIZE)
+            .filter(|_| (index as usize) < chunk_count)
+    };
+
+    for raw in chunks.chunks_exact(CHUNK_SIZE).take(chunk_count) {
+        if raw[0] != CHUNK_ENTRY {
+            continue;
+        }
+        let entry = LeafEntry::read_from_bytes(raw).map_err(|_| FilesystemError::Corrupted)?;
+
+        let name = read_array(
+            &chunk,
+            entry.name_chunk.get(),
+            entry.name_length.get() as usize,
+        )?;
+        let name = name.strip_suffix(&[0]).unwrap_or(&name);
+        let Ok(name) = String::from_utf8(name.to_vec()) else {
+            continue;
+        };
+
+        // Integers are stored big endian in the arrays, whatever the byte order of the pool.
+        let size = entry.integer_size as usize;
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return Err(FilesystemError::Corrupted.into());
+        }
+        let value = read_array(
+            &chunk,
+            entry.value_chunk.get(),
+            entry.value_count.get() as usize * size,
+        )?;
+        let value = value
+            .chunks_exact(size)
+            .map(|bytes| {
+                bytes
+                    .iter()
+                    .fold(0u64, |value, &byte| value << 8 | byte as u64)
+            })
+            .collect();
+        entries.push((name, value));
+    }
+
+    return Ok(());
+}
+
+/// Gather `length` bytes from a chain of array chunks.
+fn read_array<'a>(
+    chunk: &impl Fn(u16) -> Option<&'a [u8]>,
+    mut index: u16,
+    length: usize,
+) -> Result<Vec<u8>, RrubError> {
+    let mut data = Vec::with_capacity(length);
+    while data.len() < length {
+        let raw = match index {
+            CHAIN_END => None,
+            _ => chunk(index),
+        }
+        .filter(|raw| raw[0] == CHUNK_ARRAY)
+        .ok_or(FilesystemError::Corrupted)?;
+
+        let count = (length - data.len()).min(CHUNK_ARRAY_BYTES);
+        data.extend_from_slice(&raw[1..1 + count]);
+        index = u16::from_le_bytes([raw[CHUNK_SIZE - 2], raw[CHUNK_SIZE - 1]]);
+    }
+
+    return Ok(data);
+}
@@ -0,0 +1,70 @@
+pub mod elf;
+#[cfg(target_arch = "x86_64")]
+pub mod jump;
+pub mod linux;
+#[cfg(target_arch = "x86_64")]
+pub mod multiboot;
+#[cfg(target_arch = "x86_64")]
+pub mod multiboot2;
+
+use alloc::borrow::Cow;
+use core::ptr::NonNull;
+
+use crate::{
+    decompress,
+    error::RrubError,
+    firmware::{
+        Firmware,
+        memory::{AllocationType, PAGE_SIZE},
+    },
+};
+
+/// Larger than any kernel or module, while bounding what a damaged stream can make us allocate.
+pub const IMAGE_SIZE_LIMIT: usize = 96 << 20;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LoaderError {
+    /// Not an image of the expected format, or a damaged one.
+    InvalidImage,
+    /// Boot protocol version or image feature that isn't implemented.
+    Unsupported,
+    /// No memory the image can be loaded at.
+    NoSpace,
+}
+
+/// `data` decompressed when it's in a format `decompress` knows, as it is otherwise.
+pub fn uncompressed(data: &[u8]) -> Result<Cow<'_, [u8]>, RrubError> {
+    return match decompress::decompress(data, IMAGE_SIZE_LIMIT)? {
+        Some(data) => Ok(Cow::Owned(data)),
+        None => Ok(Cow::Borrowed(data)),
+    };
+}
+
+/// `pages` starting at a multiple of `alignment`, ending at or below `limit`.
+pub fn allocate_aligned<F: Firmware>(
+    fw: &mut F,
+    limit: u64,
+    pages: usize,
+    alignment: usize,
+) -> Result<u64, RrubError> {
+    let alignment = alignment.max(PAGE_SIZE);
+
+    // Over allocate to align, then give back the slack on either side.
+    let slack = alignment / PAGE_SIZE - 1;
+    let start = fw
+        .allocate_pages(AllocationType::MaxAddress(limit), pages + slack)
+        .map_err(|_| LoaderError::NoSpace)?;
+    let aligned = (start.as_ptr() as usize).next_multiple_of(alignment);
+    let before = (aligned - start.as_ptr() as usize) / PAGE_SIZE;
+    unsafe {
+        if before != 0 {
+            fw.deallocate_pages(start, before)?;
+        }
+        if slack != before {
+            let end = NonNull::new_unchecked((aligned + pages * PAGE_SIZE) as *mut u8);
+            fw.deallocate_pages(end, slack - before)?;
+        }
+    }
+
+    return Ok(aligned as u64);
+}
@@ -0,0 +1,245 @@
+/*
+ * ELF executables, loaded segment by segment at their physical addresses
+ * https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.eheader.html
+ * https://refspecs.linuxfoundation.org/elf/gabi4+/ch5.pheader.html
+*/
+
+use alloc::vec::Vec;
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, U16, U32, U64, Unaligned,
+};
+
+use crate::{error::RrubError, loaders::LoaderError};
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+pub const ELF_CLASS_32: u8 = 1;
+pub const ELF_CLASS_64: u8 = 2;
+
+pub const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+pub const ELF_DATA_BIG_ENDIAN: u8 = 2;
+
+pub const ELF_TYPE_EXECUTABLE: u16 = 2;
+
+pub const ELF_MACHINE_386: u16 = 3;
+pub const ELF_MACHINE_X86_64: u16 = 62;
+
+pub const PROGRAM_LOAD: u32 = 1;
+
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct Elf32Header {
+    pub mag: [u8; 4],
+    pub class: u8,
+    pub data: u8,
+    pub version: u8,
+    pub os_abi: u8,
+    pub abi_version: u8,
+    _pad: [u8; 7],
+    pub e_type: U16<LittleEndian>,
+    pub e_cpu: U16<LittleEndian>,
+    pub e_version: U32<LittleEndian>,
+    pub e_entry: U32<LittleEndian>,
+    pub e_phoff: U32<LittleEndian>,
+    pub e_shoff: U32<LittleEndian>,
+    pub e_flags: U32<LittleEndian>,
+    pub e_ehsize: U16<LittleEndian>,
+    pub e_phentsize: U16<LittleEndian>,
+    pub e_phnum: U16<LittleEndian>,
+    pub e_shentsize: U16<LittleEndian>,
+    pub e_shnum: U16<LittleEndian>,
+    pub e_shstrndx: U16<LittleEndian>,
+}
+
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct Elf32ProgramHeader {
+    pub p_type: U32<LittleEndian>,
+    pub p_offset: U32<LittleEndian>,
+    pub p_vaddr: U32<LittleEndian>,
+    pub p_paddr: U32<LittleEndian>,
+    pub p_filesz: U32<LittleEndian>,
+    pub p_memsz: U32<LittleEndian>,
+    pub p_flags: U32<LittleEndian>,
+    pub p_align: U32<LittleEndian>,
+}
+
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct Elf64Header {
+    pub mag: [u8; 4],
+    pub class: u8,
+    pub data: u8,
+    pub version: u8,
+    pub os_abi: u8,
+    pub abi_version: u8,
+    _pad: [u8; 7],
+    pub e_type: U16<LittleEndian>,
+    pub e_cpu: U16<LittleEndian>,
+    pub e_version: U32<LittleEndian>,
+    pub e_entry: U64<LittleEndian>,
+    pub e_phoff: U64<LittleEndian>,
+    pub e_shoff: U64<LittleEndian>,
+    pub e_flags: U32<LittleEndian>,
+    pub e_ehsize: U16<LittleEndian>,
+    pub e_phentsize: U16<LittleEndian>,
+    pub e_phnum: U16<LittleEndian>,
+    pub e_shentsize: U16<LittleEndian>,
+    pub e_shnum: U16<LittleEndian>,
+    pub e_shstrndx: U16<LittleEndian>,
+}
+
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct Elf64ProgramHeader {
+    pub p_type: U32<LittleEndian>,
+    pub p_flags: U32<LittleEndian>,
+    pub p_offset: U64<LittleEndian>,
+    pub p_vaddr: U64<LittleEndian>,
+    pub p_paddr: U64<LittleEndian>,
+    pub p_filesz: U64<LittleEndian>,
+    pub p_memsz: U64<LittleEndian>,
+    pub p_align: U64<LittleEndian>,
+}
+
+/// Part of the image to be loaded at `address`, zero filled past `data` up to `memory_size`.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment<'a> {
+    pub address: u64,
+    pub data: &'a [u8],
+    pub memory_size: u64,
+}
+
+/// A little endian executable, as x86 kernels are.
+#[derive(Debug)]
+pub struct Executable<'a> {
+    pub machine: u16,
+    pub entry: u64,
+    pub segments: Vec<Segment<'a>>,
+}
+
+pub fn is_elf(image: &[u8]) -> bool {
+    return image.starts_with(&ELF_MAGIC);
+}
+
+/// Read the loadable segments of a 32 or 64-bit executable.
+pub fn parse(image: &[u8]) -> Result<Executable<'_>, RrubError> {
+    if !is_elf(image) {
+        return Err(LoaderError::InvalidImage.into());
+    }
+
+    let (class, data) = (image.get(4).copied(), image.get(5).copied());
+    if data != Some(ELF_DATA_LITTLE_ENDIAN) {
+        return Err(LoaderError::Unsupported.into());
+    }
+
+    // Both are read as 64-bit, the fields are the same but for their width and order.
+    let (e_type, machine, entry, headers) = match class {
+        Some(ELF_CLASS_32) => {
+            let (header, _) =
+                Elf32Header::read_from_prefix(image).map_err(|_| LoaderError::InvalidImage)?;
+            let headers = program_headers::<Elf32ProgramHeader>(
+                image,
+                header.e_phoff.get() as usize,
+                header.e_phentsize.get(),
+                header.e_phnum.get(),
+            )?
+            .map(|header| {
+                (
+                    header.p_type.get(),
+                    header.p_offset.get() as u64,
+                    header.p_paddr.get() as u64,
+                    header.p_filesz.get() as u64,
+                    header.p_memsz.get() as u64,
+                )
+            })
+            .collect::<Vec<_>>();
+            (
+                header.e_type.get(),
+                header.e_cpu.get(),
+                header.e_entry.get() as u64,
+                headers,
+            )
+        }
+        Some(ELF_CLASS_64) => {
+            let (header, _) =
+                Elf64Header::read_from_prefix(image).map_err(|_| LoaderError::InvalidImage)?;
+            let headers = program_headers::<Elf64ProgramHeader>(
+                image,
+                header.e_phoff.get() as usize,
+                header.e_phentsize.get(),
+                header.e_phnum.get(),
+            )?
+            .map(|header| {
+                (
+                    header.p_type.get(),
+                    header.p_offset.get(),
+                    header.p_paddr.get(),
+                    header.p_filesz.get(),
+                    header.p_memsz.get(),
+                )
+            })
+            .collect::<Vec<_>>();
+            (
+                header.e_type.get(),
+                header.e_cpu.get(),
+                header.e_entry.get(),
+                headers,
+            )
+        }
+        _ => return Err(LoaderError::InvalidImage.into()),
+    };
+    if e_type != ELF_TYPE_EXECUTABLE {
+        return Err(LoaderError::Unsupported.into());
+    }
+
+    let mut segments = Vec::new();
+    for (p_type, offset, address, file_size, memory_size) in headers {
+        if p_type != PROGRAM_LOAD || memory_size == 0 {
+            continue;
+        }
+        if file_size > memory_size || address.checked_add(memory_size).is_none() {
+            return Err(LoaderError::InvalidImage.into());
+        }
+        let data = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(file_size).ok())
+            .and_then(|(offset, size)| image.get(offset..offset.checked_add(size)?))
+            .ok_or(LoaderError::InvalidImage)?;
+        segments.push(Segment {
+            address,
+            data,
+            memory_size,
+        });
+    }
+    if segments.is_empty() {
+        return Err(LoaderError::InvalidImage.into());
+    }
+
+    return Ok(Executable {
+        machine,
+        entry,
+        segments,
+    });
+}
+
+fn program_headers<H: FromBytes + KnownLayout + Immutable>(
+    image: &[u8],
+    offset: usize,
+    entry_size: u16,
+    count: u16,
+) -> Result<impl Iterator<Item = H>, RrubError> {
+    let entry_size = entry_size as usize;
+    if entry_size < size_of::<H>() {
+        return Err(LoaderError::InvalidImage.into());
+    }
+    let table = offset
+        .checked_add(entry_size * count as usize)
+        .and_then(|end| image.get(offset..end))
+        .ok_or(LoaderError::InvalidImage)?;
+
+    return Ok(table
+        .chunks_exact(entry_size)
+        .filter_map(|entry| H::read_from_prefix(entry).ok().map(|(header, _)| header)));
+}
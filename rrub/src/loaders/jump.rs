@@ -0,0 +1,170 @@
+/*
+ * Final jumps into loaded kernels, with the CPU state their boot protocols ask for
+ * https://docs.kernel.org/arch/x86/boot.html#bit-boot-protocol
+ * Leaving long mode follows the Intel SDM, Vol. 3A 10.8.5.4 "Switching Out of IA-32e Mode
+ * Operation".
+*/
+
+use core::{
+    arch::{asm, global_asm},
+    mem::transmute,
+    ptr::{NonNull, copy_nonoverlapping},
+};
+
+/// Flat segments at the selectors Linux expects, `__BOOT_CS` at 0x10 and `__BOOT_DS` at 0x18.
+#[repr(C, align(16))]
+struct Gdt([u64; 4]);
+
+static GDT: Gdt = Gdt([0, 0, 0x00AF_9A00_0000_FFFF, 0x00CF_9200_0000_FFFF]);
+/// Flat 32-bit segments at the same selectors.
+static GDT32: Gdt = Gdt([0, 0, 0x00CF_9A00_0000_FFFF, 0x00CF_9200_0000_FFFF]);
+
+#[repr(C, packed)]
+struct GdtPointer {
+    limit: u16,
+    base: u64,
+}
+
+/// Enter a 64-bit Linux kernel at `entry` with `rsi` pointing to its zeropage. Paging must still
+/// identity map everything the kernel was loaded into, as the firmware leaves it.
+pub unsafe fn linux64(entry: u64, zeropage: u64) -> ! {
+    let gdt = GdtPointer {
+        limit: (size_of::<Gdt>() - 1) as u16,
+        base: &raw const GDT as u64,
+    };
+
+    unsafe {
+        asm!(
+            "cli",
+            "lgdt [rdx]",
+            "mov ax, 0x18",
+            "mov ds, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "mov gs, ax",
+            "mov ss, ax",
+            // Reload CS through a far return.
+            "push 0x10",
+            "lea rax, [rip + 2f]",
+            "push rax",
+            "retfq",
+            "2:",
+            "xor ebp, ebp",
+            "xor edi, edi",
+            "jmp rcx",
+            in("rdx") &raw const gdt,
+            in("rcx") entry,
+            in("rsi") zeropage,
+            options(noreturn),
+        );
+    }
+}
+
+/// Enter a kernel's EFI handover entry point with boot services still running. It takes its
+/// arguments the System V way, even when called from UEFI code.
+pub unsafe fn efi_handover64(entry: u64, image: u64, system_table: u64, zeropage: u64) -> ! {
+    unsafe {
+        let handover: extern "sysv64" fn(u64, u64, u64) -> ! = transmute(entry);
+        asm!("cli");
+        handover(image, system_table, zeropage)
+    }
+}
+
+// Runs in compatibility mode from a copy below 4 GiB, with the entry point in edi, the value for
+// eax in esi and ebx already set. Turning paging off leaves long mode, after which it's plain
+// 32-bit protected mode.
+global_asm!(
+    ".section .text",
+    ".global protected32_start",
+    ".global protected32_end",
+    ".code32",
+    "protected32_start:",
+    "mov ax, 0x18",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov ss, ax",
+    "mov eax, cr0",
+    "and eax, 0x7FFFFFFF",
+    "mov cr0, eax",
+    // Clear EFER.LME, then CR4.PAE which the kernel expects off with paging.
+    "mov ecx, 0xC0000080",
+    "rdmsr",
+    "and eax, 0xFFFFFEFF",
+    "wrmsr",
+    "mov eax, cr4",
+    "and eax, 0xFFFFFFDF",
+    "mov cr4, eax",
+    "mov eax, esi",
+    "jmp edi",
+    "protected32_end:",
+    ".code64",
+);
+
+#[allow(non_upper_case_globals)]
+unsafe extern "C" {
+    static protected32_start: u8;
+    static protected32_end: u8;
+}
+
+/// Enter a 32-bit kernel at `entry` in protected mode with paging off, as Multiboot asks, `eax`
+/// and `ebx` holding what its protocol passes there. The switch runs from `low`, a page below
+/// 4 GiB, as paging can only be turned off from identity mapped code the 32-bit kernel can
+/// reach. Paging must still identity map that page, as the firmware leaves it.
+pub unsafe fn protected32(entry: u32, eax: u32, ebx: u32, low: NonNull<u8>) -> ! {
+    let start = &raw const protected32_start;
+    let size = unsafe { (&raw const protected32_end).offset_from(start) as usize };
+    let gdt_offset = size.next_multiple_of(align_of::<Gdt>());
+
+    let gdt = unsafe {
+        copy_nonoverlapping(start, low.as_ptr(), size);
+        copy_nonoverlapping(
+            (&raw const GDT32).cast::<u8>(),
+            low.as_ptr().add(gdt_offset),
+            size_of::<Gdt>(),
+        );
+        GdtPointer {
+            limit: (size_of::<Gdt>() - 1) as u16,
+            base: low.as_ptr().add(gdt_offset) as u64,
+        }
+    };
+
+    unsafe {
+        asm!(
+            "cli",
+            "lgdt [rdx]",
+            // Paging can't be turned off with process-context identifiers on.
+            "mov rax, cr4",
+            "btr rax, 17",
+            "mov cr4, rax",
+            // rbx can't be an operand.
+            "mov ebx, r8d",
+            // Into compatibility mode through a far return.
+            "push 0x10",
+            "push rcx",
+            "retfq",
+            in("rdx") &raw const gdt,
+            in("rcx") low.as_ptr(),
+            in("rdi") entry,
+            in("rsi") eax,
+            in("r8") ebx,
+            options(noreturn),
+        );
+    }
+}
+
+/// Enter a 64-bit kernel at `entry` with boot services still running, `eax` and `ebx` holding
+/// what its protocol passes there.
+pub unsafe fn efi64(entry: u64, eax: u32, ebx: u32) -> ! {
+    unsafe {
+        asm!(
+            "mov ebx, {ebx:e}",
+            "jmp {entry}",
+            ebx = in(reg) ebx,
+            entry = in(reg) entry,
+            in("eax") eax,
+            options(noreturn),
+        );
+    }
+}
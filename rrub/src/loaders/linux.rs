@@ -0,0 +1,93 @@
+pub mod common;
+pub mod cpio;
+pub mod efi;
+pub mod microcode;
+#[cfg(target_arch = "x86_64")]
+pub mod x86;
+
+use alloc::borrow::Cow;
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::{
+    decompress,
+    error::RrubError,
+    loaders::{IMAGE_SIZE_LIMIT, LoaderError, uncompressed},
+};
+
+/// Each initrd starts aligned, as the kernel expects of concatenated cpio archives.
+const INITRD_ALIGNMENT: usize = 4;
+
+/// EFI zboot images are PE applications that decompress the kernel they carry themselves, their
+/// DOS header saying where that kernel is and how it's compressed.
+/// https://github.com/torvalds/linux/blob/master/drivers/firmware/efi/libstub/zboot-header.S
+const ZBOOT_MAGIC: &[u8; 4] = b"zimg";
+const ZBOOT_MAGIC_OFFSET: usize = 4;
+const ZBOOT_PAYLOAD_OFFSET: usize = 8;
+const ZBOOT_PAYLOAD_SIZE: usize = 12;
+
+/// How a loaded kernel is entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BootMethod {
+    /// Leave the firmware and enter the kernel directly, with the memory map filled in for it.
+    Native,
+    /// Enter the kernel's EFI stub through the handover protocol while boot services are still
+    /// running, the kernel exits them and reads the memory map itself.
+    EfiHandover,
+    /// Load the kernel as an EFI application through the firmware, which works the same for
+    /// every architecture and keeps Secure Boot checking it.
+    EfiStub,
+}
+
+/// Size of `initrds` concatenated, each padded to start aligned.
+pub fn initrds_size(initrds: &[&[u8]]) -> usize {
+    return initrds.iter().fold(0, |offset, initrd| {
+        offset.next_multiple_of(INITRD_ALIGNMENT) + initrd.len()
+    });
+}
+
+/// Concatenate `initrds` into the start of `buffer`, which holds at least `initrds_size` bytes.
+/// The kernel unpacks them in order, so microcode goes first and overlays last.
+pub fn concatenate_initrds(initrds: &[&[u8]], buffer: &mut [u8]) {
+    let mut offset: usize = 0;
+    for initrd in initrds {
+        let start = offset.next_multiple_of(INITRD_ALIGNMENT);
+        buffer[offset..start].fill(0);
+        buffer[start..start + initrd.len()].copy_from_slice(initrd);
+        offset = start + initrd.len();
+    }
+}
+
+fn read_u32(image: &[u8], offset: usize) -> Option<usize> {
+    let bytes = image.get(offset..offset + 4)?;
+    return Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+}
+
+/// Whether `image` is an EFI zboot image.
+pub fn is_zboot(image: &[u8]) -> bool {
+    return image.get(ZBOOT_MAGIC_OFFSET..ZBOOT_MAGIC_OFFSET + ZBOOT_MAGIC.len())
+        == Some(ZBOOT_MAGIC);
+}
+
+/// The kernel in `image` uncompressed, out of an `Image.gz` or similar or out of an EFI zboot
+/// image. Anything else is returned as it is.
+pub fn uncompressed_kernel(image: &[u8]) -> Result<Cow<'_, [u8]>, RrubError> {
+    if is_zboot(image) {
+        let offset = read_u32(image, ZBOOT_PAYLOAD_OFFSET).ok_or(LoaderError::InvalidImage)?;
+        let size = read_u32(image, ZBOOT_PAYLOAD_SIZE).ok_or(LoaderError::InvalidImage)?;
+        let payload = image
+            .get(offset..offset + size)
+            .ok_or(LoaderError::InvalidImage)?;
+
+        return match decompress::decompress(payload, IMAGE_SIZE_LIMIT)? {
+            Some(kernel) => Ok(Cow::Owned(kernel)),
+            None => {
+                warn!("EFI zboot kernel is compressed in an unsupported format");
+                Err(LoaderError::Unsupported.into())
+            }
+        };
+    }
+
+    return uncompressed(image);
+}
@@ -0,0 +1,99 @@
+/*
+ * The newc cpio format initramfs archives are made of, which the kernel unpacks in order
+ * https://docs.kernel.org/driver-api/early-userspace/buffer-format.html
+*/
+
+use alloc::{collections::BTreeSet, format, string::String, vec::Vec};
+
+const MAGIC: &str = "070701";
+const TRAILER: &str = "TRAILER!!!";
+const ALIGNMENT: usize = 4;
+
+const TYPE_DIRECTORY: u32 = 0o040000;
+const TYPE_FILE: u32 = 0o100000;
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+
+/// Builds an archive in memory, entries being unpacked in the order they are added.
+pub struct CpioWriter {
+    buffer: Vec<u8>,
+    inode: u32,
+    directories: BTreeSet<String>,
+}
+
+impl CpioWriter {
+    pub fn new() -> CpioWriter {
+        return CpioWriter {
+            buffer: Vec::new(),
+            inode: 0,
+            directories: BTreeSet::new(),
+        };
+    }
+
+    /// Add a directory with permissions `mode`, along with any parent not added yet. The kernel
+    /// doesn't create missing parents when unpacking, and a directory the archive unpacks over
+    /// takes its mode.
+    pub fn directory(&mut self, path: &str, mode: u32) {
+        let path = path.trim_matches('/');
+        if path.is_empty() || self.directories.contains(path) {
+            return;
+        }
+
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.directory(parent, DEFAULT_DIRECTORY_MODE);
+        }
+        self.entry(path, TYPE_DIRECTORY | mode, 2, &[]);
+        self.directories.insert(String::from(path));
+    }
+
+    /// Add a regular file with permissions `mode`, and its parents.
+    pub fn file(&mut self, path: &str, mode: u32, data: &[u8]) {
+        let path = path.trim_matches('/');
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.directory(parent, DEFAULT_DIRECTORY_MODE);
+        }
+        self.entry(path, TYPE_FILE | mode, 1, data);
+    }
+
+    /// The archive, terminated so more can be concatenated after it.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.entry(TRAILER, 0, 1, &[]);
+        return self.buffer;
+    }
+
+    fn entry(&mut self, path: &str, mode: u32, links: u32, data: &[u8]) {
+        self.inode += 1;
+
+        // Inode, mode, uid, gid, links, mtime, size, device and rdev major and minor, name size and
+        // an unused checksum, each as 8 hex digits.
+        let fields = [
+            self.inode,
+            mode,
+            0,
+            0,
+            links,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            path.len() as u32 + 1,
+            0,
+        ];
+        self.buffer.extend_from_slice(MAGIC.as_bytes());
+        for field in fields {
+            self.buffer
+                .extend_from_slice(format!("{:08X}", field).as_bytes());
+        }
+        self.buffer.extend_from_slice(path.as_bytes());
+        self.buffer.push(0);
+        self.pad();
+        self.buffer.extend_from_slice(data);
+        self.pad();
+    }
+
+    fn pad(&mut self) {
+        self.buffer
+            .resize(self.buffer.len().next_multiple_of(ALIGNMENT), 0);
+    }
+}
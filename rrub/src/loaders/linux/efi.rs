@@ -0,0 +1,77 @@
+/*
+ * Kernels built with CONFIG_EFI_STUB are PE images the firmware can start itself
+ * https://docs.kernel.org/admin-guide/efi-stub.html
+ * https://learn.microsoft.com/en-us/windows/win32/debug/pe-format
+*/
+
+use alloc::borrow::Cow;
+
+use log::warn;
+
+use crate::{
+    error::RrubError,
+    firmware::Firmware,
+    loaders::{
+        LoaderError,
+        linux::{is_zboot, uncompressed_kernel},
+    },
+};
+
+const MZ_MAGIC: &[u8] = b"MZ";
+const PE_MAGIC: &[u8] = b"PE\0\0";
+const PE_OFFSET: usize = 0x3C;
+
+const MACHINE_UNKNOWN: u16 = 0;
+const MACHINE_AMD64: u16 = 0x8664;
+const MACHINE_ARM64: u16 = 0xAA64;
+const MACHINE_RISCV64: u16 = 0x5064;
+
+const NATIVE_MACHINE: u16 = if cfg!(target_arch = "x86_64") {
+    MACHINE_AMD64
+} else if cfg!(target_arch = "aarch64") {
+    MACHINE_ARM64
+} else if cfg!(target_arch = "riscv64") {
+    MACHINE_RISCV64
+} else {
+    MACHINE_UNKNOWN
+};
+
+/// Start a kernel's EFI stub with `cmdline` as its load options, the stub loading `initrds`
+/// concatenated. Only returns when the kernel fails to start.
+pub fn boot<F: Firmware>(
+    fw: &mut F,
+    image: &[u8],
+    initrds: &[&[u8]],
+    cmdline: &str,
+) -> Result<(), RrubError> {
+    // Compressed kernels like arm64's `Image.gz` are PE images once decompressed. EFI zboot images
+    // decompress themselves and are started as they are, which keeps their signature valid.
+    let image = match is_zboot(image) {
+        true => Cow::Borrowed(image),
+        false => uncompressed_kernel(image)?,
+    };
+
+    let machine = pe_machine(&image).ok_or(LoaderError::InvalidImage)?;
+    if NATIVE_MACHINE != MACHINE_UNKNOWN && machine != NATIVE_MACHINE {
+        warn!("Kernel is built for PE machine {:#06X}", machine);
+        return Err(LoaderError::Unsupported.into());
+    }
+
+    // A kernel that starts never comes back to the menu, the keys go before it runs.
+    return fw.start_image(&image, None, cmdline, initrds, true);
+}
+
+fn pe_machine(image: &[u8]) -> Option<u16> {
+    if !image.starts_with(MZ_MAGIC) {
+        return None;
+    }
+
+    let offset = u32::from_le_bytes(image.get(PE_OFFSET..PE_OFFSET + 4)?.try_into().ok()?);
+    let header = image.get(offset as usize..)?;
+    if !header.starts_with(PE_MAGIC) {
+        return None;
+    }
+
+    let machine = header.get(PE_MAGIC.len()..PE_MAGIC.len() + 2)?;
+    return Some(u16::from_le_bytes([machine[0], machine[1]]));
+}
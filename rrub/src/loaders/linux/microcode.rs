@@ -0,0 +1,161 @@
+/*
+ * Early CPU microcode, which the kernel applies from the first initrd before anything else runs
+ * https://docs.kernel.org/arch/x86/microcode.html
+*/
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use log::debug;
+
+use crate::{error::RrubError, firmware::filesystem::Filesystem, loaders::linux::cpio::CpioWriter};
+
+/// Extended family and model fields only count on top of these base families.
+const FAMILY_EXTENDED: u32 = 0xF;
+const FAMILY_INTEL_CORE: u32 = 0x6;
+/// AMD ships one file per family from here on, older families share one.
+const FAMILY_AMD_SPLIT: u32 = 0x15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    Intel,
+    Amd,
+}
+
+impl Vendor {
+    fn id(&self) -> &'static str {
+        return match self {
+            Vendor::Intel => "GenuineIntel",
+            Vendor::Amd => "AuthenticAMD",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cpu {
+    pub vendor: Vendor,
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+impl Cpu {
+    /// The CPU this runs on, if it is one that gets microcode updates.
+    #[cfg(target_arch = "x86_64")]
+    pub fn current() -> Option<Cpu> {
+        use core::arch::x86_64::__cpuid;
+
+        let leaf = __cpuid(0);
+        let mut id = [0; 12];
+        id[0..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+        id[4..8].copy_from_slice(&leaf.edx.to_le_bytes());
+        id[8..12].copy_from_slice(&leaf.ecx.to_le_bytes());
+        let vendor = [Vendor::Intel, Vendor::Amd]
+            .into_iter()
+            .find(|vendor| vendor.id().as_bytes() == id)?;
+
+        let signature = __cpuid(1).eax;
+        let base_family = (signature >> 8) & 0xF;
+        let mut family = base_family;
+        let mut model = (signature >> 4) & 0xF;
+        if base_family == FAMILY_EXTENDED {
+            family += (signature >> 20) & 0xFF;
+        }
+        if base_family == FAMILY_EXTENDED || base_family == FAMILY_INTEL_CORE {
+            model += ((signature >> 16) & 0xF) << 4;
+        }
+
+        return Some(Cpu {
+            vendor,
+            family,
+            model,
+            stepping: signature & 0xF,
+        });
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn current() -> Option<Cpu> {
+        return None;
+    }
+
+    /// Initrds as distributions package them, covering every CPU of the vendor.
+    fn archives(&self, directories: &[&str]) -> Vec<String> {
+        let name = match self.vendor {
+            Vendor::Intel => "intel-ucode.img",
+            Vendor::Amd => "amd-ucode.img",
+        };
+        return directories
+            .iter()
+            .map(|directory| format!("{}/{}", directory, name))
+            .collect();
+    }
+
+    /// Bare microcode files as linux-firmware names them, which still have to be archived.
+    fn files(&self, directories: &[&str]) -> Vec<String> {
+        let name = match self.vendor {
+            Vendor::Intel => format!(
+                "intel-ucode/{:02x}-{:02x}-{:02x}",
+                self.family, self.model, self.stepping
+            ),
+            Vendor::Amd if self.family >= FAMILY_AMD_SPLIT => {
+                format!("amd-ucode/microcode_amd_fam{:02x}h.bin", self.family)
+            }
+            Vendor::Amd => String::from("amd-ucode/microcode_amd.bin"),
+        };
+
+        return directories
+            .iter()
+            .chain(&["/lib/firmware"])
+            .map(|directory| format!("{}/{}", directory, name))
+            .collect();
+    }
+}
+
+/// Directories the microcode may be next to, the kernel's first. `/boot` is the root of its
+/// filesystem when it's a partition of its own or the ESP.
+fn directories(kernel: &str) -> Vec<&str> {
+    let kernel_directory = kernel
+        .rsplit_once('/')
+        .map_or("", |(directory, _)| directory);
+    let mut directories = vec![kernel_directory];
+    for directory in ["/boot", ""] {
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+    return directories;
+}
+
+/// Microcode for this CPU from `filesystem` as an initrd to go first, if it has any, looked up
+/// next to `kernel`.
+pub fn load(filesystem: &mut Filesystem, kernel: &str) -> Result<Option<Vec<u8>>, RrubError> {
+    let Some(cpu) = Cpu::current() else {
+        return Ok(None);
+    };
+    let directories = directories(kernel);
+
+    for path in cpu.archives(&directories) {
+        if filesystem.metadata(&path).is_ok() {
+            debug!("Loading microcode from {}", path);
+            return Ok(Some(filesystem.read_file(&path)?));
+        }
+    }
+
+    for path in cpu.files(&directories) {
+        if filesystem.metadata(&path).is_ok() {
+            debug!("Loading microcode from {}", path);
+            let microcode = filesystem.read_file(&path)?;
+
+            // Where the kernel looks for it in the first initrd.
+            let mut archive = CpioWriter::new();
+            archive.file(
+                &format!("kernel/x86/microcode/{}.bin", cpu.vendor.id()),
+                0o644,
+                &microcode,
+            );
+            return Ok(Some(archive.finish()));
+        }
+    }
+
+    debug!("No microcode found for {:?}", cpu);
+    return Ok(None);
+}
@@ -0,0 +1,560 @@
+/*
+ * The x86 boot protocol, loading a bzImage and entering it at its 64-bit entry point
+ * https://docs.kernel.org/arch/x86/boot.html
+*/
+
+use core::{
+    ptr::{NonNull, copy_nonoverlapping},
+    slice,
+};
+
+use log::{debug, warn};
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    crypto,
+    error::RrubError,
+    firmware::{
+        Firmware,
+        framebuffer::{FramebufferInfo, PixelFormat},
+        memory::{
+            AllocationType, EfiMemoryMap, MemoryMap, PAGE_SIZE,
+            e820::{self, E820Entry},
+        },
+    },
+    loaders::{
+        LoaderError, allocate_aligned, jump,
+        linux::{
+            BootMethod,
+            common::{ApmBiosInfo, EdidInfo, IstInfo, ScreenInfo},
+            concatenate_initrds, initrds_size,
+        },
+    },
+};
+
+pub const E820_MAX_ENTRIES_ZEROPAGE: usize = 128;
+pub const EDD_MBR_SIG_MAX: usize = 16;
+
+const SETUP_HEADER_OFFSET: usize = 0x1F1;
+/// The byte here is the offset of the end of the setup header from the byte after it.
+const HEADER_LENGTH_OFFSET: usize = 0x201;
+const SECTOR_SIZE: usize = 512;
+
+const BOOT_FLAG: u16 = 0xAA55;
+/// "HdrS"
+const HEADER_MAGIC: u32 = 0x5372_6448;
+/// 2.12 added `xloadflags`, the only way to tell a kernel has a 64-bit entry point.
+const MIN_VERSION: u16 = 0x020C;
+/// Command lines were limited to 255 characters before 2.06 added `cmdline_size`.
+const CMDLINE_SIZE_VERSION: u16 = 0x0206;
+const OLD_CMDLINE_SIZE: usize = 255;
+
+const LOADED_HIGH: u8 = 1 << 0;
+const XLF_KERNEL_64: u16 = 1 << 0;
+const XLF_CAN_BE_LOADED_ABOVE_4G: u16 = 1 << 1;
+const XLF_EFI_HANDOVER_64: u16 = 1 << 3;
+
+/// Boot loader ID for loaders without one assigned.
+const LOADER_TYPE_UNDEFINED: u8 = 0xFF;
+
+/// A linear framebuffer set up through GOP, as the EFI stub reports it.
+const VIDEO_TYPE_EFI: u8 = 0x70;
+const VIDEO_CAPABILITY_64BIT_BASE: u32 = 1 << 1;
+/// "EL64", the kernel only reads `efi_info` from 64-bit loaders that sign it.
+const EFI64_LOADER_SIGNATURE: u32 = u32::from_le_bytes(*b"EL64");
+/// The 64-bit entry point is this far into the protected mode kernel.
+const ENTRY_64_OFFSET: u64 = 0x200;
+const SETUP_E820_EXT: u32 = 1;
+const MAX_32BIT: u64 = 0xFFFF_FFFF;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct SetupHeader {
+    pub setup_sects: u8,
+    pub root_flags: U16<LittleEndian>,
+    pub syssize: U32<LittleEndian>,
+    pub ram_size: U16<LittleEndian>,
+    pub vid_mode: U16<LittleEndian>,
+    pub root_dev: U16<LittleEndian>,
+    pub boot_flag: U16<LittleEndian>,
+    pub jump: U16<LittleEndian>,
+    pub header: U32<LittleEndian>,
+    pub version: U16<LittleEndian>,
+    pub realmode_swtch: U32<LittleEndian>,
+    pub start_sys_seg: U16<LittleEndian>,
+    pub kernel_version: U16<LittleEndian>,
+    pub type_of_loader: u8,
+    pub loadflags: u8,
+    pub setup_move_size: U16<LittleEndian>,
+    pub code32_start: U32<LittleEndian>,
+    pub ramdisk_image: U32<LittleEndian>,
+    pub ramdisk_size: U32<LittleEndian>,
+    pub bootsect_kludge: U32<LittleEndian>,
+    pub heap_end_ptr: U16<LittleEndian>,
+    pub ext_loader_ver: u8,
+    pub ext_loader_type: u8,
+    pub cmd_line_ptr: U32<LittleEndian>,
+    pub initrd_addr_max: U32<LittleEndian>,
+    pub kernel_alignment: U32<LittleEndian>,
+    pub relocatable_kernel: u8,
+    pub min_alignment: u8,
+    pub xloadflags: U16<LittleEndian>,
+    pub cmdline_size: U32<LittleEndian>,
+    pub hardware_subarch: U32<LittleEndian>,
+    pub hardware_subarch_data: U64<LittleEndian>,
+    pub payload_offset: U32<LittleEndian>,
+    pub payload_length: U32<LittleEndian>,
+    pub setup_data: U64<LittleEndian>,
+    pub pref_address: U64<LittleEndian>,
+    pub init_size: U32<LittleEndian>,
+    pub handover_offset: U32<LittleEndian>,
+    pub kernel_info_offset: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable)]
+pub struct SysDescTable {
+    length: U16<LittleEndian>,
+    table: [u8; 14],
+}
+
+impl SysDescTable {
+    pub fn empty() -> SysDescTable {
+        SysDescTable {
+            length: U16::ZERO,
+            table: [0u8; 14],
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable)]
+pub struct OlpcOfwHeader {
+    ofw_magic: U32<LittleEndian>,
+    ofw_version: U32<LittleEndian>,
+    cif_handler: U32<LittleEndian>,
+    irq_desc_table: U32<LittleEndian>,
+}
+
+impl OlpcOfwHeader {
+    pub fn empty() -> OlpcOfwHeader {
+        OlpcOfwHeader {
+            ofw_magic: U32::ZERO,
+            ofw_version: U32::ZERO,
+            cif_handler: U32::ZERO,
+            irq_desc_table: U32::ZERO,
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable)]
+pub struct EfiInfo {
+    efi_loader_signature: U32<LittleEndian>,
+    efi_systab: U32<LittleEndian>,
+    efi_memdesc_size: U32<LittleEndian>,
+    efi_memdesc_version: U32<LittleEndian>,
+    efi_memmap: U32<LittleEndian>,
+    efi_memmap_size: U32<LittleEndian>,
+    efi_systab_hi: U32<LittleEndian>,
+    efi_memmap_hi: U32<LittleEndian>,
+}
+
+impl EfiInfo {
+    pub fn empty() -> EfiInfo {
+        EfiInfo {
+            efi_loader_signature: U32::ZERO,
+            efi_systab: U32::ZERO,
+            efi_memdesc_size: U32::ZERO,
+            efi_memdesc_version: U32::ZERO,
+            efi_memmap: U32::ZERO,
+            efi_memmap_size: U32::ZERO,
+            efi_systab_hi: U32::ZERO,
+            efi_memmap_hi: U32::ZERO,
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct Zeropage {
+    pub screen_info: ScreenInfo,
+    pub apm_bios_info: ApmBiosInfo,
+    pub _pad2: [u8; 4],
+    pub tboot_addr: U64<LittleEndian>,
+    pub ist_info: IstInfo,
+    pub acpi_rsdp_addr: U64<LittleEndian>,
+    pub _pad3: [u8; 8],
+    pub hd0_info: [u8; 16],
+    pub hd1_info: [u8; 16],
+    pub sys_desc_table: SysDescTable,
+    pub olpc_ofw_header: OlpcOfwHeader,
+    pub ext_ramdisk_image: U32<LittleEndian>,
+    pub ext_ramdisk_size: U32<LittleEndian>,
+    pub ext_cmd_line_ptr: U32<LittleEndian>,
+    pub _pad4: [u8; 112],
+    pub cc_blob_address: U32<LittleEndian>,
+    pub edid_info: EdidInfo,
+    pub efi_info: EfiInfo,
+    pub alt_mem_k: U32<LittleEndian>,
+    pub scratch: U32<LittleEndian>,
+    pub e820_entries: u8,
+    pub eddbuf_entries: u8,
+    pub edd_mbr_sig_buf_entries: u8,
+    pub kbd_status: u8,
+    pub secure_boot: u8,
+    pub _pad5: [u8; 2],
+    pub sentinel: u8,
+    pub _pad6: u8,
+    pub hdr: SetupHeader,
+    pub _pad7: [u8; 0x290 - 0x1f1 - size_of::<SetupHeader>()],
+    pub edd_mbr_sig_buffer: [U32<LittleEndian>; EDD_MBR_SIG_MAX],
+    pub e820_table: [E820Entry; E820_MAX_ENTRIES_ZEROPAGE],
+    pub _pad8: [u8; 816],
+}
+
+const _: () = assert!(size_of::<Zeropage>() == PAGE_SIZE);
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct SetupData {
+    pub next: U64<LittleEndian>,
+    pub data_type: U32<LittleEndian>,
+    pub length: U32<LittleEndian>,
+}
+
+/// A bzImage in memory with its zeropage, command line and initrd, waiting for the final memory
+/// map.
+pub struct LinuxKernel {
+    zeropage: NonNull<Zeropage>,
+    /// Page for the memory map entries that don't fit the zeropage.
+    e820_extension: NonNull<u8>,
+    /// Start of the protected mode kernel.
+    kernel: u64,
+    entry: Entry,
+}
+
+enum Entry {
+    Native,
+    EfiHandover {
+        offset: u64,
+        image: u64,
+        system_table: u64,
+    },
+}
+
+impl LinuxKernel {
+    /// Load a kernel to be entered by `method`, or through the EFI handover protocol whenever
+    /// both the kernel and firmware support it.
+    pub fn load<F: Firmware>(
+        fw: &mut F,
+        image: &[u8],
+        initrds: &[&[u8]],
+        cmdline: &str,
+        method: Option<BootMethod>,
+    ) -> Result<LinuxKernel, RrubError> {
+        let header = setup_header(image)?;
+        let handover = match fw.efi_system() {
+            Some((image, system_table)) if header.xloadflags.get() & XLF_EFI_HANDOVER_64 != 0 => {
+                Some(Entry::EfiHandover {
+                    offset: header.handover_offset.get() as u64,
+                    image,
+                    system_table,
+                })
+            }
+            _ => None,
+        };
+        let entry = match (method, handover) {
+            // Started through the firmware by `efi::boot` instead.
+            (Some(BootMethod::EfiStub), _) => return Err(LoaderError::Unsupported.into()),
+            (Some(BootMethod::Native), _) | (None, None) => Entry::Native,
+            (_, Some(handover)) => handover,
+            (Some(BootMethod::EfiHandover), None) => {
+                warn!("Kernel can't be entered through the EFI handover protocol here");
+                return Err(LoaderError::Unsupported.into());
+            }
+        };
+        let setup_sectors = match header.setup_sects {
+            0 => 4,
+            count => count as usize,
+        };
+        let payload = image
+            .get((setup_sectors + 1) * SECTOR_SIZE..)
+            .ok_or(LoaderError::InvalidImage)?;
+
+        let kernel = place_kernel(fw, &header, &entry, payload.len())?;
+        unsafe { copy_nonoverlapping(payload.as_ptr(), kernel as *mut u8, payload.len()) };
+        debug!("Loaded kernel at {:#X}", kernel);
+
+        let zeropage = allocate(fw, AllocationType::MaxAddress(MAX_32BIT), PAGE_SIZE)?.cast();
+        let boot_params: &mut Zeropage = unsafe { &mut *zeropage.as_ptr() };
+
+        // The header as the image has it, up to where it says it ends.
+        let header_end = (HEADER_LENGTH_OFFSET + 1 + image[HEADER_LENGTH_OFFSET] as usize)
+            .min(SETUP_HEADER_OFFSET + size_of::<SetupHeader>());
+        boot_params.as_mut_bytes()[SETUP_HEADER_OFFSET..header_end]
+            .copy_from_slice(&image[SETUP_HEADER_OFFSET..header_end]);
+        boot_params.hdr.type_of_loader = LOADER_TYPE_UNDEFINED;
+        if let Entry::EfiHandover { .. } = entry {
+            // Older EFI stubs find the kernel through this.
+            boot_params.hdr.code32_start = U32::new(kernel as u32);
+        }
+
+        let cmdline_limit = match header.version.get() >= CMDLINE_SIZE_VERSION {
+            true => header.cmdline_size.get() as usize,
+            false => OLD_CMDLINE_SIZE,
+        };
+        let mut cmdline = cmdline.as_bytes();
+        if cmdline.len() > cmdline_limit {
+            warn!(
+                "Kernel command line truncated from {} to {} bytes",
+                cmdline.len(),
+                cmdline_limit
+            );
+            cmdline = &cmdline[..cmdline_limit];
+        }
+        // Zeroed, so the copy is terminated.
+        let cmdline_address =
+            allocate(fw, AllocationType::MaxAddress(MAX_32BIT), cmdline.len() + 1)?;
+        unsafe { copy_nonoverlapping(cmdline.as_ptr(), cmdline_address.as_ptr(), cmdline.len()) };
+        boot_params.hdr.cmd_line_ptr = U32::new(cmdline_address.as_ptr() as u32);
+
+        let size = initrds_size(initrds) as u64;
+        if size != 0 {
+            let address = load_initrd(fw, &header, initrds)?;
+            boot_params.hdr.ramdisk_image = U32::new(address as u32);
+            boot_params.hdr.ramdisk_size = U32::new(size as u32);
+            boot_params.ext_ramdisk_image = U32::new((address >> 32) as u32);
+            boot_params.ext_ramdisk_size = U32::new((size >> 32) as u32);
+            debug!("Loaded initrd at {:#X}, {} bytes", address, size);
+        }
+
+        boot_params.acpi_rsdp_addr = U64::new(fw.acpi_rsdp().unwrap_or(0));
+        if let Some(framebuffer) = fw.framebuffer() {
+            boot_params.screen_info = screen_info(&framebuffer);
+        }
+
+        return Ok(LinuxKernel {
+            zeropage,
+            e820_extension: allocate(fw, AllocationType::MaxAddress(MAX_32BIT), PAGE_SIZE)?,
+            kernel,
+            entry,
+        });
+    }
+
+    pub fn boot<F: Firmware>(self, fw: F) -> ! {
+        let entry = self.kernel + ENTRY_64_OFFSET;
+        let zeropage = self.zeropage.as_ptr() as u64;
+        match self.entry {
+            Entry::Native => {
+                let system_table = fw.efi_system().map(|(_, system_table)| system_table);
+                let (map, efi_map) = fw.handover_efi();
+                self.set_memory_map(&map);
+                if let (Some(system_table), Some(efi_map)) = (system_table, &efi_map) {
+                    self.set_efi_info(system_table, efi_map);
+                }
+                unsafe { jump::linux64(entry, zeropage) }
+            }
+            Entry::EfiHandover {
+                offset,
+                image,
+                system_table,
+            } => {
+                // Boot services stay up for the kernel, the keys go all the same.
+                crypto::wipe_keys();
+                unsafe { jump::efi_handover64(entry + offset, image, system_table, zeropage) }
+            }
+        }
+    }
+
+    /// Point the kernel at the system table and the final UEFI memory map, as the EFI stub does
+    /// for itself, so it can still use runtime services.
+    fn set_efi_info(&self, system_table: u64, map: &EfiMemoryMap) {
+        let boot_params = unsafe { &mut *self.zeropage.as_ptr() };
+        let memmap = map.descriptors.as_ptr() as u64;
+        boot_params.efi_info = EfiInfo {
+            efi_loader_signature: U32::new(EFI64_LOADER_SIGNATURE),
+            efi_systab: U32::new(system_table as u32),
+            efi_memdesc_size: U32::new(map.descriptor_size),
+            efi_memdesc_version: U32::new(map.descriptor_version),
+            efi_memmap: U32::new(memmap as u32),
+            efi_memmap_size: U32::new(map.descriptors.len() as u32),
+            efi_systab_hi: U32::new((system_table >> 32) as u32),
+            efi_memmap_hi: U32::new((memmap >> 32) as u32),
+        };
+    }
+
+    /// Fill the zeropage's memory map, chaining what doesn't fit as setup data.
+    fn set_memory_map(&self, map: &MemoryMap) {
+        let boot_params = unsafe { &mut *self.zeropage.as_ptr() };
+        let table = e820::table(map);
+
+        let (entries, rest) = table.split_at(table.len().min(E820_MAX_ENTRIES_ZEROPAGE));
+        boot_params.e820_table[..entries.len()].copy_from_slice(entries);
+        boot_params.e820_entries = entries.len() as u8;
+        if rest.is_empty() {
+            return;
+        }
+
+        let capacity = (PAGE_SIZE - size_of::<SetupData>()) / size_of::<E820Entry>();
+        if rest.len() > capacity {
+            warn!(
+                "Memory map has {} entries, the last {} are left out",
+                table.len(),
+                rest.len() - capacity
+            );
+        }
+        let rest = &rest[..rest.len().min(capacity)];
+        let header = SetupData {
+            next: boot_params.hdr.setup_data,
+            data_type: U32::new(SETUP_E820_EXT),
+            length: U32::new(size_of_val(rest) as u32),
+        };
+        unsafe {
+            let extension = self.e820_extension.as_ptr();
+            copy_nonoverlapping(
+                header.as_bytes().as_ptr(),
+                extension,
+                size_of::<SetupData>(),
+            );
+            copy_nonoverlapping(
+                rest.as_bytes().as_ptr(),
+                extension.add(size_of::<SetupData>()),
+                size_of_val(rest),
+            );
+        }
+        boot_params.hdr.setup_data = U64::new(self.e820_extension.as_ptr() as u64);
+    }
+}
+
+fn screen_info(framebuffer: &FramebufferInfo) -> ScreenInfo {
+    let (red, green, blue) = match framebuffer.format {
+        PixelFormat::Rgb => (0, 8, 16),
+        PixelFormat::Bgr | PixelFormat::NotSupported => (16, 8, 0),
+    };
+    let linelength = framebuffer.stride * 4;
+
+    let mut info = ScreenInfo::empty();
+    info.orig_video_is_vga = VIDEO_TYPE_EFI;
+    info.lfb_width = U16::new(framebuffer.width as u16);
+    info.lfb_height = U16::new(framebuffer.height as u16);
+    info.lfb_depth = U16::new(32);
+    info.lfb_base = U32::new(framebuffer.address as u32);
+    info.lfb_size = U32::new((linelength * framebuffer.height) as u32);
+    info.lfb_linelength = U16::new(linelength as u16);
+    info.red_size = 8;
+    info.red_pos = red;
+    info.green_size = 8;
+    info.green_pos = green;
+    info.blue_size = 8;
+    info.blue_pos = blue;
+    info.rsvd_size = 8;
+    info.rsvd_pos = 24;
+    if framebuffer.address > u32::MAX as u64 {
+        info.ext_lfb_base = U32::new((framebuffer.address >> 32) as u32);
+        info.capabilities = U32::new(VIDEO_CAPABILITY_64BIT_BASE);
+    }
+    return info;
+}
+
+fn setup_header(image: &[u8]) -> Result<SetupHeader, RrubError> {
+    let (header, _) = image
+        .get(SETUP_HEADER_OFFSET..)
+        .and_then(|bytes| SetupHeader::read_from_prefix(bytes).ok())
+        .ok_or(LoaderError::InvalidImage)?;
+    if header.boot_flag.get() != BOOT_FLAG || header.header.get() != HEADER_MAGIC {
+        return Err(LoaderError::InvalidImage.into());
+    }
+
+    let version = header.version.get();
+    if version < MIN_VERSION
+        || header.loadflags & LOADED_HIGH == 0
+        || header.xloadflags.get() & XLF_KERNEL_64 == 0
+    {
+        warn!(
+            "Kernel with boot protocol {}.{:02} has no 64-bit entry point",
+            version >> 8,
+            version & 0xFF
+        );
+        return Err(LoaderError::Unsupported.into());
+    }
+
+    return Ok(header);
+}
+
+/// Room for the kernel to decompress itself in, at its preferred address or aligned anywhere
+/// it can run from when it's relocatable.
+fn place_kernel<F: Firmware>(
+    fw: &mut F,
+    header: &SetupHeader,
+    entry: &Entry,
+    payload_size: usize,
+) -> Result<u64, RrubError> {
+    let pages = (header.init_size.get() as usize)
+        .max(payload_size)
+        .div_ceil(PAGE_SIZE);
+
+    let preferred = header.pref_address.get();
+    if let Ok(start) = fw.allocate_pages(AllocationType::Address(preferred), pages) {
+        return Ok(start.as_ptr() as u64);
+    }
+    if header.relocatable_kernel == 0 {
+        warn!(
+            "Kernel can't be relocated from {:#X}, which is in use",
+            preferred
+        );
+        return Err(LoaderError::NoSpace.into());
+    }
+
+    let alignment = header.kernel_alignment.get() as usize;
+    if !alignment.is_power_of_two() {
+        return Err(LoaderError::InvalidImage.into());
+    }
+    // Older EFI stubs only see the low half of the address, in `code32_start`.
+    let limit = match entry {
+        Entry::Native if header.xloadflags.get() & XLF_CAN_BE_LOADED_ABOVE_4G != 0 => u64::MAX,
+        _ => MAX_32BIT,
+    };
+
+    return allocate_aligned(fw, limit, pages, alignment);
+}
+
+/// Below `initrd_addr_max` for the kernel to reach it early on, or anywhere when it reads the
+/// upper half of the address from `ext_ramdisk_image`. Several initrds are concatenated into one.
+fn load_initrd<F: Firmware>(
+    fw: &mut F,
+    header: &SetupHeader,
+    initrds: &[&[u8]],
+) -> Result<u64, RrubError> {
+    let size = initrds_size(initrds);
+    let pages = size.div_ceil(PAGE_SIZE);
+    let limit = header.initrd_addr_max.get() as u64;
+
+    let start = match fw.allocate_pages(AllocationType::MaxAddress(limit), pages) {
+        Ok(start) => start,
+        Err(_) if header.xloadflags.get() & XLF_CAN_BE_LOADED_ABOVE_4G != 0 => fw
+            .allocate_pages(AllocationType::AnyPages, pages)
+            .map_err(|_| LoaderError::NoSpace)?,
+        Err(_) => return Err(LoaderError::NoSpace.into()),
+    };
+    concatenate_initrds(initrds, unsafe {
+        slice::from_raw_parts_mut(start.as_ptr(), size)
+    });
+
+    return Ok(start.as_ptr() as u64);
+}
+
+/// Zeroed pages holding at least `size` bytes.
+fn allocate<F: Firmware>(
+    fw: &mut F,
+    allocation_type: AllocationType,
+    size: usize,
+) -> Result<NonNull<u8>, RrubError> {
+    let pages = size.div_ceil(PAGE_SIZE);
+    let start = fw.allocate_pages(allocation_type, pages)?;
+    unsafe { start.as_ptr().write_bytes(0, pages * PAGE_SIZE) };
+    return Ok(start);
+}
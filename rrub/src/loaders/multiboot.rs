@@ -0,0 +1,499 @@
+/*
+ * Multiboot, the first version, for legacy kernels that are entered in protected mode with a
+ * single information structure. Loading images and modules works the same in both versions and
+ * is shared with `multiboot2`.
+ * https://www.gnu.org/software/grub/manual/multiboot/multiboot.html
+*/
+
+use alloc::{vec, vec::Vec};
+use core::ptr::{NonNull, copy_nonoverlapping};
+
+use log::{debug, warn};
+use zerocopy::{
+    FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    error::RrubError,
+    firmware::{
+        Firmware,
+        framebuffer::PixelFormat,
+        memory::{
+            AllocationType, MemoryMap, PAGE_SIZE,
+            e820::{self, E820Entry, E820Type},
+        },
+    },
+    loaders::{
+        LoaderError, allocate_aligned,
+        elf::{self, Segment},
+        jump, uncompressed,
+    },
+};
+
+const HEADER_MAGIC: u32 = 0x1BAD_B002;
+/// In eax when the kernel is entered, telling it the boot information is in ebx.
+const BOOTLOADER_MAGIC: u32 = 0x2BAD_B002;
+/// The header is within this many bytes from the start of the image.
+const HEADER_SEARCH_LIMIT: usize = 8192;
+const HEADER_ALIGNMENT: usize = 4;
+
+/// Modules are to be page aligned, which they always are.
+const HEADER_PAGE_ALIGN: u32 = 1 << 0;
+/// Memory information is to be passed, which it always is.
+const HEADER_MEMORY_INFO: u32 = 1 << 1;
+/// A video mode is asked for, which can't be set but the current one is passed.
+const HEADER_VIDEO_MODE: u32 = 1 << 2;
+/// The address fields are valid, and used instead of the ELF headers.
+const HEADER_AOUT_KLUDGE: u32 = 1 << 16;
+/// Flags the kernel can't boot without the boot loader understanding. The rest are optional.
+const HEADER_REQUIRED: u32 = 0xFFFF;
+
+const INFO_MEMORY: u32 = 1 << 0;
+const INFO_CMDLINE: u32 = 1 << 2;
+const INFO_MODULES: u32 = 1 << 3;
+const INFO_MMAP: u32 = 1 << 6;
+const INFO_BOOT_LOADER_NAME: u32 = 1 << 9;
+const INFO_FRAMEBUFFER: u32 = 1 << 12;
+
+pub const BOOT_LOADER_NAME: &str = "rrub";
+pub const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+/// Memory below 1 MiB the kernel is told about, the rest being legacy ROMs and the like.
+const LOWER_MEMORY_END: u64 = 640 << 10;
+const UPPER_MEMORY_START: u64 = 1 << 20;
+/// Room left for the memory map, which is only known once the firmware is left.
+const MMAP_SIZE: usize = 4 * PAGE_SIZE;
+
+pub const MAX_32BIT: u64 = 0xFFFF_FFFF;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct Header {
+    magic: U32<LittleEndian>,
+    flags: U32<LittleEndian>,
+    checksum: U32<LittleEndian>,
+    address: AddressFields,
+    entry_address: U32<LittleEndian>,
+}
+
+/// Where a raw image goes, with the header's address telling which part of the file is loaded.
+/// The same in both versions, the a.out kludge.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct AddressFields {
+    pub header_address: U32<LittleEndian>,
+    pub load_address: U32<LittleEndian>,
+    /// Up to the end of the file when 0.
+    pub load_end_address: U32<LittleEndian>,
+    /// No bss when 0.
+    pub bss_end_address: U32<LittleEndian>,
+}
+
+/// Where each colour is in an RGB framebuffer pixel.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+pub struct ColorInfo {
+    pub red_position: u8,
+    pub red_size: u8,
+    pub green_position: u8,
+    pub green_size: u8,
+    pub blue_position: u8,
+    pub blue_size: u8,
+}
+
+impl From<PixelFormat> for ColorInfo {
+    fn from(value: PixelFormat) -> Self {
+        let (red, green, blue) = match value {
+            PixelFormat::Rgb => (0, 8, 16),
+            PixelFormat::Bgr | PixelFormat::NotSupported => (16, 8, 0),
+        };
+        return ColorInfo {
+            red_position: red,
+            red_size: 8,
+            green_position: green,
+            green_size: 8,
+            blue_position: blue,
+            blue_size: 8,
+        };
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct MultibootInfo {
+    flags: U32<LittleEndian>,
+    mem_lower: U32<LittleEndian>,
+    mem_upper: U32<LittleEndian>,
+    boot_device: U32<LittleEndian>,
+    cmdline: U32<LittleEndian>,
+    mods_count: U32<LittleEndian>,
+    mods_address: U32<LittleEndian>,
+    syms: [U32<LittleEndian>; 4],
+    mmap_length: U32<LittleEndian>,
+    mmap_address: U32<LittleEndian>,
+    drives_length: U32<LittleEndian>,
+    drives_address: U32<LittleEndian>,
+    config_table: U32<LittleEndian>,
+    boot_loader_name: U32<LittleEndian>,
+    apm_table: U32<LittleEndian>,
+    vbe_control_info: U32<LittleEndian>,
+    vbe_mode_info: U32<LittleEndian>,
+    vbe_mode: U16<LittleEndian>,
+    vbe_interface_segment: U16<LittleEndian>,
+    vbe_interface_offset: U16<LittleEndian>,
+    vbe_interface_length: U16<LittleEndian>,
+    framebuffer_address: U64<LittleEndian>,
+    framebuffer_pitch: U32<LittleEndian>,
+    framebuffer_width: U32<LittleEndian>,
+    framebuffer_height: U32<LittleEndian>,
+    framebuffer_bpp: u8,
+    framebuffer_type: u8,
+    color_info: ColorInfo,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct ModuleEntry {
+    start: U32<LittleEndian>,
+    end: U32<LittleEndian>,
+    cmdline: U32<LittleEndian>,
+    reserved: U32<LittleEndian>,
+}
+
+/// Range an image can be moved to when its own address is taken.
+pub struct Relocation {
+    pub min_address: u64,
+    /// Highest address the image can end at.
+    pub max_address: u64,
+    pub alignment: usize,
+}
+
+/// A Multiboot kernel and its modules in memory, waiting for the final memory map.
+pub struct MultibootKernel {
+    entry: u32,
+    /// Boot information followed by what it points to, laid out for `info_address`.
+    info: Vec<u8>,
+    /// Pages below 4 GiB the boot information is copied to once complete.
+    info_address: NonNull<u8>,
+    info_capacity: usize,
+    /// Page below 4 GiB to leave long mode from.
+    trampoline: NonNull<u8>,
+}
+
+impl MultibootKernel {
+    /// Load a kernel, with each module and its command line after it in memory. Compressed
+    /// kernels and modules are loaded decompressed.
+    pub fn load<F: Firmware>(
+        fw: &mut F,
+        image: &[u8],
+        modules: &[(&[u8], &str)],
+        cmdline: &str,
+    ) -> Result<MultibootKernel, RrubError> {
+        let image = uncompressed(image)?;
+        let (offset, header) = find_header(&image)?;
+        let flags = header.flags.get();
+        let unknown =
+            flags & HEADER_REQUIRED & !(HEADER_PAGE_ALIGN | HEADER_MEMORY_INFO | HEADER_VIDEO_MODE);
+        if unknown != 0 {
+            warn!("Kernel needs unsupported Multiboot flags {:#X}", unknown);
+            return Err(LoaderError::Unsupported.into());
+        }
+
+        let (segments, entry) = match flags & HEADER_AOUT_KLUDGE != 0 {
+            true => (
+                vec![kludge_segment(&image, offset, &header.address)?],
+                header.entry_address.get(),
+            ),
+            false => elf_segments(&image)?,
+        };
+        load_segments(fw, &segments, None)?;
+        let trampoline = fw
+            .allocate_pages(AllocationType::MaxAddress(MAX_32BIT), 1)
+            .map_err(|_| LoaderError::NoSpace)?;
+
+        let mut loaded = Vec::with_capacity(modules.len());
+        for (module, module_cmdline) in modules {
+            let (start, end) = load_module(fw, module)?;
+            loaded.push((start, end, *module_cmdline));
+        }
+
+        // The information, then the module list and strings, then the memory map once known.
+        let strings_size = cmdline.len()
+            + BOOT_LOADER_NAME.len()
+            + modules
+                .iter()
+                .map(|(_, cmdline)| cmdline.len())
+                .sum::<usize>()
+            + modules.len()
+            + 2;
+        let size = size_of::<MultibootInfo>()
+            + modules.len() * size_of::<ModuleEntry>()
+            + strings_size.next_multiple_of(HEADER_ALIGNMENT);
+        let info_capacity = (size + MMAP_SIZE).next_multiple_of(PAGE_SIZE);
+        let info_address = fw
+            .allocate_pages(
+                AllocationType::MaxAddress(MAX_32BIT),
+                info_capacity / PAGE_SIZE,
+            )
+            .map_err(|_| LoaderError::NoSpace)?;
+        let base = info_address.as_ptr() as u32;
+
+        let mut info = MultibootInfo::new_zeroed();
+        let mut data = Vec::with_capacity(size);
+        data.resize(size_of::<MultibootInfo>(), 0);
+        let string = |data: &mut Vec<u8>, string: &str| {
+            let address = base + data.len() as u32;
+            data.extend_from_slice(string.as_bytes());
+            data.push(0);
+            return U32::new(address);
+        };
+
+        info.cmdline = string(&mut data, cmdline);
+        info.boot_loader_name = string(&mut data, BOOT_LOADER_NAME);
+        let entries: Vec<ModuleEntry> = loaded
+            .iter()
+            .map(|(start, end, cmdline)| ModuleEntry {
+                start: U32::new(*start),
+                end: U32::new(*end),
+                cmdline: string(&mut data, cmdline),
+                reserved: U32::ZERO,
+            })
+            .collect();
+        data.resize(data.len().next_multiple_of(HEADER_ALIGNMENT), 0);
+        info.mods_count = U32::new(entries.len() as u32);
+        info.mods_address = U32::new(base + data.len() as u32);
+        data.extend_from_slice(entries.as_bytes());
+        let mut info_flags = INFO_CMDLINE | INFO_BOOT_LOADER_NAME | INFO_MODULES;
+
+        if let Some(framebuffer) = fw.framebuffer() {
+            info.framebuffer_address = U64::new(framebuffer.address);
+            info.framebuffer_pitch = U32::new((framebuffer.stride * 4) as u32);
+            info.framebuffer_width = U32::new(framebuffer.width as u32);
+            info.framebuffer_height = U32::new(framebuffer.height as u32);
+            info.framebuffer_bpp = 32;
+            info.framebuffer_type = FRAMEBUFFER_TYPE_RGB;
+            info.color_info = ColorInfo::from(framebuffer.format);
+            info_flags |= INFO_FRAMEBUFFER;
+        } else if flags & HEADER_VIDEO_MODE != 0 {
+            warn!("Kernel asks for a video mode, starting it without one");
+        }
+        info.flags = U32::new(info_flags);
+        data[..size_of::<MultibootInfo>()].copy_from_slice(info.as_bytes());
+
+        return Ok(MultibootKernel {
+            entry,
+            info: data,
+            info_address,
+            info_capacity,
+            trampoline,
+        });
+    }
+
+    pub fn boot<F: Firmware>(mut self, fw: F) -> ! {
+        let map = fw.handover();
+        self.memory(&map);
+
+        unsafe {
+            copy_nonoverlapping(
+                self.info.as_ptr(),
+                self.info_address.as_ptr(),
+                self.info.len(),
+            );
+            jump::protected32(
+                self.entry,
+                BOOTLOADER_MAGIC,
+                self.info_address.as_ptr() as u32,
+                self.trampoline,
+            )
+        }
+    }
+
+    /// Add the memory fields and map, which is only known once the firmware is left.
+    fn memory(&mut self, map: &MemoryMap) {
+        let table = e820::table(map);
+        let (lower, upper) = basic_memory(&table);
+
+        // Each entry follows its size, which doesn't count itself.
+        let room = (self.info_capacity - self.info.len()) / (size_of::<E820Entry>() + 4);
+        if table.len() > room {
+            warn!("Memory map cut to {} of {} entries", room, table.len());
+        }
+        let mmap_address = self.info_address.as_ptr() as u32 + self.info.len() as u32;
+        let start = self.info.len();
+        for entry in table.iter().take(room) {
+            self.info
+                .extend_from_slice(&(size_of::<E820Entry>() as u32).to_le_bytes());
+            self.info.extend_from_slice(entry.as_bytes());
+        }
+        let mmap_length = self.info.len() - start;
+
+        let (info, _) = MultibootInfo::mut_from_prefix(&mut self.info).unwrap();
+        info.mem_lower = U32::new(lower);
+        info.mem_upper = U32::new(upper);
+        info.mmap_address = U32::new(mmap_address);
+        info.mmap_length = U32::new(mmap_length as u32);
+        info.flags = U32::new(info.flags.get() | INFO_MEMORY | INFO_MMAP);
+    }
+}
+
+/// Find the header, 4 byte aligned near the start of the image, returning where it is.
+fn find_header(image: &[u8]) -> Result<(usize, Header), RrubError> {
+    let search = &image[..image.len().min(HEADER_SEARCH_LIMIT)];
+    for offset in (0..search.len()).step_by(HEADER_ALIGNMENT) {
+        let Some(fields) = search.get(offset..offset + 3 * size_of::<u32>()) else {
+            break;
+        };
+        let field = |index: usize| {
+            return u32::from_le_bytes(fields[index * 4..index * 4 + 4].try_into().unwrap());
+        };
+        if field(0) != HEADER_MAGIC || field(0).wrapping_add(field(1)).wrapping_add(field(2)) != 0 {
+            continue;
+        }
+
+        // The address fields are only there with the a.out kludge.
+        let mut header = Header::new_zeroed();
+        let available = (image.len() - offset).min(size_of::<Header>());
+        header.as_mut_bytes()[..available].copy_from_slice(&image[offset..offset + available]);
+        if field(1) & HEADER_AOUT_KLUDGE != 0 && available < size_of::<Header>() {
+            return Err(LoaderError::InvalidImage.into());
+        }
+        return Ok((offset, header));
+    }
+
+    return Err(LoaderError::InvalidImage.into());
+}
+
+/// The part of a raw image the address fields say to load, the header being at `header_offset`
+/// in the file and at `header_address` in memory.
+pub fn kludge_segment<'a>(
+    image: &'a [u8],
+    header_offset: usize,
+    address: &AddressFields,
+) -> Result<Segment<'a>, RrubError> {
+    let load_address = address.load_address.get();
+    let before_header = address
+        .header_address
+        .get()
+        .checked_sub(load_address)
+        .ok_or(LoaderError::InvalidImage)?;
+    let start = header_offset
+        .checked_sub(before_header as usize)
+        .ok_or(LoaderError::InvalidImage)?;
+    let end = match address.load_end_address.get() {
+        0 => image.len(),
+        load_end => {
+            start
+                + load_end
+                    .checked_sub(load_address)
+                    .ok_or(LoaderError::InvalidImage)? as usize
+        }
+    };
+    let data = image.get(start..end).ok_or(LoaderError::InvalidImage)?;
+
+    let memory_size = match address.bss_end_address.get() {
+        0 => data.len() as u64,
+        bss_end => (bss_end as u64)
+            .checked_sub(load_address as u64)
+            .ok_or(LoaderError::InvalidImage)?
+            .max(data.len() as u64),
+    };
+    return Ok(Segment {
+        address: load_address as u64,
+        data,
+        memory_size,
+    });
+}
+
+/// The segments and entry point of an x86 ELF kernel, which has to be entered below 4 GiB.
+pub fn elf_segments(image: &[u8]) -> Result<(Vec<Segment<'_>>, u32), RrubError> {
+    let executable = elf::parse(image)?;
+    if executable.machine != elf::ELF_MACHINE_386 && executable.machine != elf::ELF_MACHINE_X86_64 {
+        return Err(LoaderError::Unsupported.into());
+    }
+    let entry = u32::try_from(executable.entry).map_err(|_| LoaderError::InvalidImage)?;
+    return Ok((executable.segments, entry));
+}
+
+/// Copy the segments to where they ask to be, or anywhere `relocation` allows when that's taken.
+/// Returns the address the image starts at and how far it was moved.
+pub fn load_segments<F: Firmware>(
+    fw: &mut F,
+    segments: &[Segment],
+    relocation: Option<Relocation>,
+) -> Result<(u32, u32), RrubError> {
+    let start = segments
+        .iter()
+        .map(|segment| segment.address)
+        .min()
+        .unwrap_or(0);
+    let end = segments
+        .iter()
+        .map(|segment| segment.address + segment.memory_size)
+        .max()
+        .unwrap_or(0);
+    if end > MAX_32BIT + 1 {
+        return Err(LoaderError::InvalidImage.into());
+    }
+
+    let base = start & !(PAGE_SIZE as u64 - 1);
+    let pages = (end - base).div_ceil(PAGE_SIZE as u64) as usize;
+    let loaded = match fw.allocate_pages(AllocationType::Address(base), pages) {
+        Ok(loaded) => loaded.as_ptr() as u64,
+        Err(_) => match relocation {
+            Some(relocation) => {
+                let loaded =
+                    allocate_aligned(fw, relocation.max_address, pages, relocation.alignment)?;
+                if loaded < relocation.min_address {
+                    return Err(LoaderError::NoSpace.into());
+                }
+                loaded
+            }
+            None => {
+                warn!("Kernel can't be moved from {:#X}, which is in use", base);
+                return Err(LoaderError::NoSpace.into());
+            }
+        },
+    };
+    let offset = loaded - base;
+
+    unsafe {
+        (loaded as *mut u8).write_bytes(0, pages * PAGE_SIZE);
+        for segment in segments {
+            let destination = (segment.address + offset) as *mut u8;
+            copy_nonoverlapping(segment.data.as_ptr(), destination, segment.data.len());
+        }
+    }
+    debug!("Loaded kernel at {:#X}", start + offset);
+
+    return Ok(((start + offset) as u32, offset as u32));
+}
+
+/// Copy a module below 4 GiB, page aligned and decompressed, returning where it starts and ends.
+pub fn load_module<F: Firmware>(fw: &mut F, module: &[u8]) -> Result<(u32, u32), RrubError> {
+    let module = uncompressed(module)?;
+    let pages = module.len().div_ceil(PAGE_SIZE).max(1);
+    let start = fw
+        .allocate_pages(AllocationType::MaxAddress(MAX_32BIT), pages)
+        .map_err(|_| LoaderError::NoSpace)?;
+    unsafe { copy_nonoverlapping(module.as_ptr(), start.as_ptr(), module.len()) };
+
+    let start = start.as_ptr() as u32;
+    debug!("Loaded module at {:#X}, {} bytes", start, module.len());
+    return Ok((start, start + module.len() as u32));
+}
+
+/// Usable memory in KiB from 0 up to 640 KiB and from 1 MiB, each up to the first hole.
+pub fn basic_memory(table: &[E820Entry]) -> (u32, u32) {
+    let usable_from = |start: u64| {
+        table
+            .iter()
+            .find(|entry| {
+                entry.entry_type.get() == E820Type::Ram as u32
+                    && (entry.addr.get()..entry.addr.get() + entry.size.get()).contains(&start)
+            })
+            .map_or(0, |entry| entry.addr.get() + entry.size.get() - start)
+    };
+    let lower = usable_from(0).min(LOWER_MEMORY_END) >> 10;
+    let upper = (usable_from(UPPER_MEMORY_START) >> 10).min(u32::MAX as u64);
+    return (lower as u32, upper as u32);
+}
@@ -0,0 +1,529 @@
+/*
+ * Multiboot2, loading ELF or raw images at the address they ask for with a list of tags
+ * describing the machine. Images and modules are loaded as for the first version.
+ * https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+*/
+
+use alloc::{vec, vec::Vec};
+use core::{
+    ptr::{NonNull, copy_nonoverlapping},
+    slice,
+};
+
+use log::{debug, warn};
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, Unaligned,
+    byteorder::{U16, U32, U64},
+};
+
+use crate::{
+    crypto,
+    error::RrubError,
+    firmware::{
+        Firmware,
+        framebuffer::FramebufferInfo,
+        memory::{AllocationType, EfiMemoryMap, MemoryMap, PAGE_SIZE, e820},
+    },
+    loaders::{
+        LoaderError, jump,
+        multiboot::{
+            AddressFields, BOOT_LOADER_NAME, ColorInfo, FRAMEBUFFER_TYPE_RGB, MAX_32BIT,
+            Relocation, basic_memory, elf_segments, kludge_segment, load_module, load_segments,
+        },
+        uncompressed,
+    },
+};
+
+const HEADER_MAGIC: u32 = 0xE852_50D6;
+/// In eax when the kernel is entered, telling it the boot information is in ebx.
+const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+const ARCHITECTURE_I386: u32 = 0;
+/// The header is within this many bytes from the start of the image.
+const HEADER_SEARCH_LIMIT: usize = 32768;
+const HEADER_ALIGNMENT: usize = 8;
+const TAG_ALIGNMENT: usize = 8;
+
+const HEADER_TAG_END: u16 = 0;
+const HEADER_TAG_INFORMATION_REQUEST: u16 = 1;
+const HEADER_TAG_ADDRESS: u16 = 2;
+const HEADER_TAG_ENTRY_ADDRESS: u16 = 3;
+const HEADER_TAG_CONSOLE_FLAGS: u16 = 4;
+const HEADER_TAG_FRAMEBUFFER: u16 = 5;
+const HEADER_TAG_MODULE_ALIGNMENT: u16 = 6;
+const HEADER_TAG_EFI_BOOT_SERVICES: u16 = 7;
+const HEADER_TAG_ENTRY_ADDRESS_EFI64: u16 = 9;
+const HEADER_TAG_RELOCATABLE: u16 = 10;
+/// Set on header tags the kernel can do without.
+const HEADER_TAG_OPTIONAL: u16 = 1;
+
+/// One of the consoles the kernel supports must be there.
+const CONSOLE_REQUIRED: u32 = 1;
+
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_BOOT_LOADER_NAME: u32 = 2;
+const TAG_MODULE: u32 = 3;
+const TAG_BASIC_MEMINFO: u32 = 4;
+const TAG_MMAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_EFI64: u32 = 12;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+const TAG_EFI_MMAP: u32 = 17;
+const TAG_EFI_BOOT_SERVICES: u32 = 18;
+const TAG_EFI64_IMAGE_HANDLE: u32 = 20;
+const TAG_LOAD_BASE_ADDRESS: u32 = 21;
+
+/// Tags a kernel can ask for, given whenever the firmware has what they describe.
+const PROVIDED_TAGS: [u32; 13] = [
+    TAG_CMDLINE,
+    TAG_BOOT_LOADER_NAME,
+    TAG_MODULE,
+    TAG_BASIC_MEMINFO,
+    TAG_MMAP,
+    TAG_FRAMEBUFFER,
+    TAG_EFI64,
+    TAG_ACPI_OLD,
+    TAG_ACPI_NEW,
+    TAG_EFI_MMAP,
+    TAG_EFI_BOOT_SERVICES,
+    TAG_EFI64_IMAGE_HANDLE,
+    TAG_LOAD_BASE_ADDRESS,
+];
+
+const MMAP_ENTRY_SIZE: usize = 24;
+/// Room left for the memory map tags, which are only known once the firmware is left. UEFI
+/// memory maps are a few hundred descriptors.
+const MEMORY_TAGS_SIZE: usize = 16 * PAGE_SIZE;
+
+const RSDP_V1_SIZE: usize = 20;
+const RSDP_REVISION_OFFSET: usize = 15;
+const RSDP_LENGTH_OFFSET: usize = 20;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct Header {
+    magic: U32<LittleEndian>,
+    architecture: U32<LittleEndian>,
+    header_length: U32<LittleEndian>,
+    checksum: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct HeaderTag {
+    tag_type: U16<LittleEndian>,
+    flags: U16<LittleEndian>,
+    size: U32<LittleEndian>,
+}
+
+/// Range the image can be moved to when its own address is taken.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct RelocatableTag {
+    min_address: U32<LittleEndian>,
+    /// Highest address the image can end at.
+    max_address: U32<LittleEndian>,
+    align: U32<LittleEndian>,
+    preference: U32<LittleEndian>,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Unaligned, Immutable, KnownLayout)]
+struct FramebufferTag {
+    address: U64<LittleEndian>,
+    pitch: U32<LittleEndian>,
+    width: U32<LittleEndian>,
+    height: U32<LittleEndian>,
+    bpp: u8,
+    framebuffer_type: u8,
+    reserved: U16<LittleEndian>,
+    color_info: ColorInfo,
+}
+
+/// What the header's tags ask of the boot loader.
+#[derive(Debug, Default)]
+struct KernelHeader {
+    /// Where in the image the header is.
+    offset: usize,
+    address: Option<AddressFields>,
+    entry: Option<u32>,
+    efi64_entry: Option<u32>,
+    efi_boot_services: bool,
+    relocatable: Option<RelocatableTag>,
+    console_flags: u32,
+    framebuffer: bool,
+}
+
+/// Boot information being put together, tags following the total size and a reserved field.
+#[derive(Debug, Clone)]
+struct BootInformation(Vec<u8>);
+
+impl BootInformation {
+    fn new() -> BootInformation {
+        return BootInformation(vec![0; TAG_ALIGNMENT]);
+    }
+
+    fn tag(&mut self, tag_type: u32, parts: &[&[u8]]) {
+        let size = 2 * size_of::<u32>() + parts.iter().map(|part| part.len()).sum::<usize>();
+        self.0.extend_from_slice(&tag_type.to_le_bytes());
+        self.0.extend_from_slice(&(size as u32).to_le_bytes());
+        for part in parts {
+            self.0.extend_from_slice(part);
+        }
+        self.0
+            .resize(self.0.len().next_multiple_of(TAG_ALIGNMENT), 0);
+    }
+
+    fn string(&mut self, tag_type: u32, string: &str) {
+        self.tag(tag_type, &[string.as_bytes(), &[0]]);
+    }
+
+    fn framebuffer(&mut self, framebuffer: &FramebufferInfo) {
+        let tag = FramebufferTag {
+            address: U64::new(framebuffer.address),
+            pitch: U32::new((framebuffer.stride * 4) as u32),
+            width: U32::new(framebuffer.width as u32),
+            height: U32::new(framebuffer.height as u32),
+            bpp: 32,
+            framebuffer_type: FRAMEBUFFER_TYPE_RGB,
+            reserved: U16::ZERO,
+            color_info: ColorInfo::from(framebuffer.format),
+        };
+        self.tag(TAG_FRAMEBUFFER, &[tag.as_bytes()]);
+    }
+
+    /// Copies of the ACPI root pointer, the 1.0 part of it and all of it when it's newer.
+    fn acpi(&mut self, rsdp: u64) {
+        let v1 = unsafe { slice::from_raw_parts(rsdp as *const u8, RSDP_V1_SIZE) };
+        self.tag(TAG_ACPI_OLD, &[v1]);
+        if v1[RSDP_REVISION_OFFSET] < 2 {
+            return;
+        }
+
+        let length =
+            unsafe { ((rsdp as usize + RSDP_LENGTH_OFFSET) as *const u32).read_unaligned() };
+        let v2 = unsafe { slice::from_raw_parts(rsdp as *const u8, length as usize) };
+        self.tag(TAG_ACPI_NEW, &[v2]);
+    }
+
+    /// Tags describing memory, leaving out the UEFI memory map if the rest would go past
+    /// `capacity`.
+    fn memory(&mut self, map: &MemoryMap, efi_map: Option<&EfiMemoryMap>, capacity: usize) {
+        let table = e820::table(map);
+        let (lower, upper) = basic_memory(&table);
+        self.tag(
+            TAG_BASIC_MEMINFO,
+            &[&lower.to_le_bytes(), &upper.to_le_bytes()],
+        );
+
+        // Entries are laid out like E820 ones, padded to 24 bytes.
+        let mut entries = Vec::with_capacity(table.len() * MMAP_ENTRY_SIZE);
+        for entry in &table {
+            entries.extend_from_slice(entry.as_bytes());
+            entries.resize(entries.len().next_multiple_of(MMAP_ENTRY_SIZE), 0);
+        }
+        self.tag(
+            TAG_MMAP,
+            &[
+                &(MMAP_ENTRY_SIZE as u32).to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &entries,
+            ],
+        );
+
+        let Some(efi_map) = efi_map else {
+            return;
+        };
+        // The tag, its two fields and the end tag.
+        if self.0.len() + efi_map.descriptors.len() + 32 > capacity {
+            warn!(
+                "UEFI memory map of {} bytes left out of the boot information",
+                efi_map.descriptors.len()
+            );
+            return;
+        }
+        self.tag(
+            TAG_EFI_MMAP,
+            &[
+                &efi_map.descriptor_size.to_le_bytes(),
+                &efi_map.descriptor_version.to_le_bytes(),
+                &efi_map.descriptors,
+            ],
+        );
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.tag(TAG_END, &[]);
+        let size = self.0.len() as u32;
+        self.0[..4].copy_from_slice(&size.to_le_bytes());
+        return self.0;
+    }
+}
+
+/// A Multiboot2 kernel and its modules in memory, waiting for the final memory map.
+pub struct Multiboot2Kernel {
+    entry: Entry,
+    info: BootInformation,
+    /// Pages below 4 GiB the boot information is copied to once complete.
+    info_address: NonNull<u8>,
+    info_capacity: usize,
+}
+
+enum Entry {
+    /// 32-bit protected mode with paging off, left to from `trampoline`.
+    I386 {
+        address: u32,
+        trampoline: NonNull<u8>,
+    },
+    /// 64-bit mode with boot services still running.
+    Efi64 { address: u32 },
+}
+
+impl Multiboot2Kernel {
+    /// Load a kernel, with each module and its command line after it in memory. Compressed
+    /// kernels and modules are loaded decompressed.
+    pub fn load<F: Firmware>(
+        fw: &mut F,
+        image: &[u8],
+        modules: &[(&[u8], &str)],
+        cmdline: &str,
+    ) -> Result<Multiboot2Kernel, RrubError> {
+        let image = uncompressed(image)?;
+        let header = find_header(&image)?;
+
+        let framebuffer = fw.framebuffer();
+        if header.console_flags & CONSOLE_REQUIRED != 0
+            && !(header.framebuffer && framebuffer.is_some())
+        {
+            warn!("Kernel needs a text console, which there isn't");
+            return Err(LoaderError::Unsupported.into());
+        }
+
+        let mut info = BootInformation::new();
+        let (load_base, offset, elf_entry) = load_image(fw, &image, &header)?;
+        info.tag(TAG_LOAD_BASE_ADDRESS, &[&load_base.to_le_bytes()]);
+        info.string(TAG_CMDLINE, cmdline);
+        info.string(TAG_BOOT_LOADER_NAME, BOOT_LOADER_NAME);
+
+        let efi_system = fw.efi_system();
+        let entry = match (header.efi64_entry, efi_system) {
+            (Some(address), Some((image_handle, _))) if header.efi_boot_services => {
+                info.tag(TAG_EFI_BOOT_SERVICES, &[]);
+                info.tag(TAG_EFI64_IMAGE_HANDLE, &[&image_handle.to_le_bytes()]);
+                Entry::Efi64 {
+                    address: address.wrapping_add(offset),
+                }
+            }
+            _ => {
+                let address = header
+                    .entry
+                    .or(elf_entry)
+                    .ok_or(LoaderError::InvalidImage)?;
+                let trampoline = fw
+                    .allocate_pages(AllocationType::MaxAddress(MAX_32BIT), 1)
+                    .map_err(|_| LoaderError::NoSpace)?;
+                Entry::I386 {
+                    address: address.wrapping_add(offset),
+                    trampoline,
+                }
+            }
+        };
+
+        for (module, module_cmdline) in modules {
+            let (start, end) = load_module(fw, module)?;
+            info.tag(
+                TAG_MODULE,
+                &[
+                    &start.to_le_bytes(),
+                    &end.to_le_bytes(),
+                    module_cmdline.as_bytes(),
+                    &[0],
+                ],
+            );
+        }
+
+        if let Some(framebuffer) = &framebuffer {
+            info.framebuffer(framebuffer);
+        }
+        if let Some((_, system_table)) = efi_system {
+            info.tag(TAG_EFI64, &[&system_table.to_le_bytes()]);
+        }
+        if let Some(rsdp) = fw.acpi_rsdp() {
+            info.acpi(rsdp);
+        }
+
+        let info_capacity = (info.0.len() + MEMORY_TAGS_SIZE).next_multiple_of(PAGE_SIZE);
+        let info_address = fw
+            .allocate_pages(
+                AllocationType::MaxAddress(MAX_32BIT),
+                info_capacity / PAGE_SIZE,
+            )
+            .map_err(|_| LoaderError::NoSpace)?;
+
+        return Ok(Multiboot2Kernel {
+            entry,
+            info,
+            info_address,
+            info_capacity,
+        });
+    }
+
+    pub fn boot<F: Firmware>(mut self, fw: F) -> ! {
+        match self.entry {
+            Entry::I386 {
+                address,
+                trampoline,
+            } => {
+                let (map, efi_map) = fw.handover_efi();
+                self.info.memory(&map, efi_map.as_ref(), self.info_capacity);
+                let info = self.place_info();
+                unsafe { jump::protected32(address, BOOTLOADER_MAGIC, info, trampoline) }
+            }
+            Entry::Efi64 { address } => {
+                // Boot services stay up for the kernel, the keys go all the same.
+                crypto::wipe_keys();
+                let map = fw.get_memory_map();
+                self.info.memory(&map, None, self.info_capacity);
+                let info = self.place_info();
+                unsafe { jump::efi64(address as u64, BOOTLOADER_MAGIC, info) }
+            }
+        }
+    }
+
+    /// Copy the finished boot information below 4 GiB, returning its address.
+    fn place_info(self) -> u32 {
+        let info = self.info.finish();
+        unsafe {
+            copy_nonoverlapping(
+                info.as_ptr(),
+                self.info_address.as_ptr(),
+                info.len().min(self.info_capacity),
+            )
+        };
+        return self.info_address.as_ptr() as u32;
+    }
+}
+
+/// Find the header, 8 byte aligned near the start of the image, and read its tags.
+fn find_header(image: &[u8]) -> Result<KernelHeader, RrubError> {
+    let search = &image[..image.len().min(HEADER_SEARCH_LIMIT)];
+    for offset in (0..search.len()).step_by(HEADER_ALIGNMENT) {
+        let Ok((header, _)) = Header::read_from_prefix(&search[offset..]) else {
+            break;
+        };
+        let sum = header
+            .magic
+            .get()
+            .wrapping_add(header.architecture.get())
+            .wrapping_add(header.header_length.get())
+            .wrapping_add(header.checksum.get());
+        if header.magic.get() != HEADER_MAGIC || sum != 0 {
+            continue;
+        }
+
+        if header.architecture.get() != ARCHITECTURE_I386 {
+            warn!(
+                "Kernel is built for Multiboot2 architecture {}",
+                header.architecture.get()
+            );
+            return Err(LoaderError::Unsupported.into());
+        }
+        let tags = image
+            .get(offset + size_of::<Header>()..offset + header.header_length.get() as usize)
+            .ok_or(LoaderError::InvalidImage)?;
+        return read_tags(offset, tags);
+    }
+
+    return Err(LoaderError::InvalidImage.into());
+}
+
+fn read_tags(offset: usize, tags: &[u8]) -> Result<KernelHeader, RrubError> {
+    let mut header = KernelHeader {
+        offset,
+        ..Default::default()
+    };
+
+    let mut position = 0;
+    loop {
+        let (tag, _) = tags
+            .get(position..)
+            .and_then(|rest| HeaderTag::read_from_prefix(rest).ok())
+            .ok_or(LoaderError::InvalidImage)?;
+        let size = tag.size.get() as usize;
+        let body = tags
+            .get(position + size_of::<HeaderTag>()..position + size)
+            .ok_or(LoaderError::InvalidImage)?;
+        let optional = tag.flags.get() & HEADER_TAG_OPTIONAL != 0;
+        let read_u32 = || -> Result<u32, RrubError> {
+            let bytes = body.get(..4).ok_or(LoaderError::InvalidImage)?;
+            return Ok(u32::from_le_bytes(bytes.try_into().unwrap()));
+        };
+
+        match tag.tag_type.get() {
+            HEADER_TAG_END => return Ok(header),
+            HEADER_TAG_INFORMATION_REQUEST => {
+                for request in body.chunks_exact(4) {
+                    let request = u32::from_le_bytes(request.try_into().unwrap());
+                    if !optional && !PROVIDED_TAGS.contains(&request) {
+                        warn!("Kernel needs boot information tag {}", request);
+                        return Err(LoaderError::Unsupported.into());
+                    }
+                }
+            }
+            HEADER_TAG_ADDRESS => {
+                let (address, _) =
+                    AddressFields::read_from_prefix(body).map_err(|_| LoaderError::InvalidImage)?;
+                header.address = Some(address);
+            }
+            HEADER_TAG_ENTRY_ADDRESS => header.entry = Some(read_u32()?),
+            HEADER_TAG_CONSOLE_FLAGS => header.console_flags = read_u32()?,
+            HEADER_TAG_FRAMEBUFFER => header.framebuffer = true,
+            // Modules are always page aligned.
+            HEADER_TAG_MODULE_ALIGNMENT => {}
+            HEADER_TAG_EFI_BOOT_SERVICES => header.efi_boot_services = true,
+            HEADER_TAG_ENTRY_ADDRESS_EFI64 => header.efi64_entry = Some(read_u32()?),
+            HEADER_TAG_RELOCATABLE => {
+                let (relocatable, _) = RelocatableTag::read_from_prefix(body)
+                    .map_err(|_| LoaderError::InvalidImage)?;
+                header.relocatable = Some(relocatable);
+            }
+            tag_type if optional => debug!("Ignoring Multiboot2 header tag {}", tag_type),
+            tag_type => {
+                warn!(
+                    "Kernel needs unsupported Multiboot2 header tag {}",
+                    tag_type
+                );
+                return Err(LoaderError::Unsupported.into());
+            }
+        }
+
+        position += size
+            .max(size_of::<HeaderTag>())
+            .next_multiple_of(TAG_ALIGNMENT);
+    }
+}
+
+/// Copy the kernel to where it asks to be, or anywhere it allows when that's taken. Returns the
+/// address it starts at, how far it was moved and the entry point of an ELF kernel.
+fn load_image<F: Firmware>(
+    fw: &mut F,
+    image: &[u8],
+    header: &KernelHeader,
+) -> Result<(u32, u32, Option<u32>), RrubError> {
+    let (segments, entry) = match &header.address {
+        Some(address) => (vec![kludge_segment(image, header.offset, address)?], None),
+        None => {
+            let (segments, entry) = elf_segments(image)?;
+            (segments, Some(entry))
+        }
+    };
+    let relocation = header.relocatable.map(|relocatable| Relocation {
+        min_address: relocatable.min_address.get() as u64,
+        max_address: relocatable.max_address.get() as u64,
+        alignment: relocatable.align.get() as usize,
+    });
+
+    let (start, offset) = load_segments(fw, &segments, relocation)?;
+    return Ok((start, offset, entry));
+}
@@ -2,8 +2,13 @@
 #![no_main]
 #![allow(clippy::needless_return)]
 
+mod checksum;
+mod crypto;
+mod decompress;
 mod error;
 mod firmware;
+mod fs;
+mod loaders;
 mod parser;
 mod scheduler;
 
@@ -12,9 +17,10 @@ extern crate alloc;
 use core::time::Duration;
 
 use conquer_once::spin::OnceCell;
+use log::error;
 use simple_alloc::bump_alloc::LocklessBumpAlloc;
 
-use crate::{error::RrubError, firmware::Firmware};
+use crate::{error::RrubError, firmware::Firmware, fs::probe::lsblk, parser::Config};
 
 const NUM_HEAP_PAGES: usize = 32768;
 static HEAP_START: OnceCell<usize> = OnceCell::uninit();
@@ -50,9 +56,22 @@ mod uefi_entry {
 }
 
 fn main<T: Firmware>() -> Result<(), RrubError> {
-    let fw = T::init()?;
+    let mut fw = T::init()?;
 
     //let mut fb = fw.init_fb(720, 480)?;
 
-    return Ok(());
+    let mut filesystems = fw.get_filesystems()?;
+    let config = match Config::load(&mut filesystems) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "Failed to load the configuration: {:?}\n{}",
+                e,
+                lsblk(&mut fw.get_block_devices()?)
+            );
+            return Err(e);
+        }
+    };
+
+    return config.boot_default(fw, &mut filesystems);
 }
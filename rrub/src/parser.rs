@@ -1,19 +1,60 @@
-use alloc::{string::String, vec::Vec};
-use core::time::Duration;
+use alloc::{boxed::Box, format, rc::Rc, string::String, vec, vec::Vec};
+use core::{cell::RefCell, time::Duration};
 
+use log::{debug, warn};
 use serde::Deserialize;
 
-use crate::firmware::filesystem::Uuid;
+#[cfg(target_arch = "x86_64")]
+use crate::loaders::{
+    linux::x86::LinuxKernel, multiboot::MultibootKernel, multiboot2::Multiboot2Kernel,
+};
+use crate::{
+    crypto,
+    error::RrubError,
+    firmware::{
+        Firmware,
+        filesystem::{FileType, Filesystem, FilesystemError, FilesystemsList, Uuid},
+    },
+    fs::{self, probe::lsblk},
+    loaders::{
+        LoaderError,
+        linux::{BootMethod, cpio::CpioWriter, efi, microcode},
+    },
+};
+
+/// Arguments initramfs tools read to find the image they were booted from, `iso-scan/filename` for
+/// casper and dracut, `findiso` for live-boot.
+const ISO_CMDLINE_KEYS: [&str; 2] = ["iso-scan/filename", "findiso"];
+
+/// Where Debian and Ubuntu keep links to the newest kernel and initrd.
+const DEFAULT_KERNEL: &str = "/boot/vmlinuz";
+const DEFAULT_INITRD: &str = "/boot/initrd.img";
+
+/// Looked up on every filesystem, the first one found is used.
+const CONFIG_PATH: &str = "/EFI/rrub/rrub.toml";
+/// Next to the configuration, names an entry to boot once instead of the default one, as
+/// `grub-reboot` sets.
+const ONESHOT_ENTRY_PATH: &str = "/EFI/rrub/oneshot_entry";
+
+const SNAPSHOTS_TITLE: &str = "Snapshots";
+
+/// Where systemd picks up credentials passed in the initrd, as systemd-stub puts them.
+const CREDENTIALS_DIRECTORY: &str = "/.extra/credentials";
+const CREDENTIAL_EXTENSION: &str = ".cred";
+/// Read by dracut and systemd's initrd generators as if it was on the kernel command line.
+const CMDLINE_D_FILE: &str = "/etc/cmdline.d/90-rrub.conf";
+/// The size of the seed file systemd-random-seed keeps.
+const RANDOM_SEED_SIZE: usize = 512;
 
 #[derive(Deserialize)]
-struct Config {
+pub struct Config {
     /// Enable GUI to display bootselecter.
     enable_gui: bool,
     /// Enable recovery mode to manually enter an entry type.
     enable_recovery: bool,
 
     /// Disk to mount on boot to load associated boot entries.
-    disk: Uuid,
+    disk: Disk,
 
     /// How long to delay display GUI before booting default entry,
     /// if GUI is disabled the default entry would be booted automatically without a delay.
@@ -22,16 +63,799 @@ struct Config {
     default_entry: String,
     /// List of entries to boot.
     entries: Vec<(String, EntryType)>,
+    /// ZFS boot environments on the boot disk, added to the entries.
+    boot_environments: Option<BootEnvironments>,
+    /// Btrfs snapshots on the boot disk, added as a submenu.
+    snapshots: Option<Snapshots>,
+}
+
+impl Config {
+    /// Read the configuration and add the boot environments and snapshots found on the boot disk
+    /// to its entries.
+    pub fn load(filesystems: &mut FilesystemsList) -> Result<Config, RrubError> {
+        let (text, filesystem) = filesystems
+            .iter_mut()
+            .find_map(|(_, filesystem)| {
+                filesystem
+                    .read_file(CONFIG_PATH)
+                    .ok()
+                    .map(|text| (text, filesystem))
+            })
+            .ok_or(FilesystemError::NotFound)?;
+        let text = core::str::from_utf8(&text).map_err(|_| RrubError::InvalidConfig)?;
+        let mut config: Config = toml::from_str(text).map_err(|e| {
+            warn!("Invalid configuration: {}", e);
+            RrubError::InvalidConfig
+        })?;
+        let oneshot = take_oneshot_entry(filesystem);
+
+        // Entries from the configuration alone can still be booted.
+        if let Err(e) = config.add_boot_environments(filesystems) {
+            warn!("Failed to add boot environments: {:?}", e);
+        }
+        if let Err(e) = config.add_snapshots(filesystems) {
+            warn!("Failed to add snapshots: {:?}", e);
+        }
+
+        if let Some(entry) = oneshot {
+            match config.entries.iter().any(|(name, _)| *name == entry) {
+                true => {
+                    debug!("Booting {} once", entry);
+                    config.default_entry = entry;
+                }
+                false => warn!("No entry named {} to boot once", entry),
+            }
+        }
+        return Ok(config);
+    }
+
+    /// Boot the default entry, only returning when a chainloaded application exits or the entry
+    /// fails to load.
+    pub fn boot_default<F: Firmware>(
+        &self,
+        fw: F,
+        filesystems: &mut FilesystemsList,
+    ) -> Result<(), RrubError> {
+        let (_, entry) = self
+            .entries
+            .iter()
+            .find(|(name, _)| *name == self.default_entry)
+            .ok_or(RrubError::InvalidConfig)?;
+        return entry.boot(fw, filesystems, &self.disk);
+    }
+
+    /// Add an entry for each boot environment found on the boot disk.
+    fn add_boot_environments(
+        &mut self,
+        filesystems: &mut FilesystemsList,
+    ) -> Result<(), RrubError> {
+        let Some(environments) = &self.boot_environments else {
+            return Ok(());
+        };
+        let filesystem = self
+            .disk
+            .find(filesystems)
+            .ok_or(FilesystemError::NotFound)?;
+
+        let entries = environments.entries(filesystem)?;
+        self.entries.extend(
+            entries
+                .into_iter()
+                .map(|(name, entry)| (name, EntryType::Linux(entry))),
+        );
+        return Ok(());
+    }
+
+    /// Add a submenu booting the snapshots of the system started by the configured entry.
+    fn add_snapshots(&mut self, filesystems: &mut FilesystemsList) -> Result<(), RrubError> {
+        let Some(snapshots) = &self.snapshots else {
+            return Ok(());
+        };
+        let base = self
+            .entries
+            .iter()
+            .find_map(|(name, entry)| match entry {
+                EntryType::Linux(entry) if *name == snapshots.entry => Some(entry),
+                _ => None,
+            })
+            .ok_or(FilesystemError::NotFound)?;
+        let filesystem = self
+            .disk
+            .find(filesystems)
+            .ok_or(FilesystemError::NotFound)?;
+
+        let entries = snapshots.entries(base, filesystem)?;
+        if !entries.is_empty() {
+            self.entries
+                .push((String::from(SNAPSHOTS_TITLE), EntryType::Submenu(entries)));
+        }
+        return Ok(());
+    }
+}
+
+/// The entry to boot once, cleared before it boots so that one which fails isn't booted again. It
+/// is ignored where it can't be cleared, such as on filesystems rrub can't write to.
+fn take_oneshot_entry(filesystem: &mut Filesystem) -> Option<String> {
+    let text = filesystem.read_file(ONESHOT_ENTRY_PATH).ok()?;
+    let entry = core::str::from_utf8(&text).ok()?.trim();
+    if entry.is_empty() {
+        return None;
+    }
+
+    if let Err(e) = filesystem
+        .write_file(ONESHOT_ENTRY_PATH, &[])
+        .and_then(|_| filesystem.sync())
+    {
+        warn!("Failed to clear the one-shot entry: {:?}", e);
+        return None;
+    }
+    return Some(String::from(entry));
+}
+
+/// A filesystem UUID as in `disk = { RealUuid = "…" }`, or a name as in `disk = "vg/lv"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Disk {
+    Uuid(Uuid),
+    /// LVM logical volume, by `vg/lv` name or LV UUID.
+    LogicalVolume(String),
+}
+
+impl Disk {
+    fn find<'a>(&self, filesystems: &'a mut FilesystemsList) -> Option<&'a mut Filesystem> {
+        return match self {
+            Disk::Uuid(uuid) => filesystems.get_mut(uuid),
+            Disk::LogicalVolume(name) => filesystems.get_by_device_mut(name),
+        };
+    }
 }
 
 #[derive(Deserialize)]
 enum EntryType {
     EfiChainload(EfiChainloadEntry),
     Linux(LinuxEntry),
+    Multiboot(MultibootEntry),
+    Multiboot2(MultibootEntry),
+    /// Entries shown on a menu of their own.
+    Submenu(Vec<(String, EntryType)>),
+    /// List the block devices and what is on them, until a key is pressed.
+    Lsblk,
+}
+
+impl EntryType {
+    /// Boot the entry, only returning when a chainloaded application exits or the entry fails to
+    /// load.
+    fn boot<F: Firmware>(
+        &self,
+        mut fw: F,
+        filesystems: &mut FilesystemsList,
+        disk: &Disk,
+    ) -> Result<(), RrubError> {
+        return match self {
+            EntryType::EfiChainload(entry) => entry.boot(&mut fw, filesystems, disk),
+            EntryType::Linux(entry) => entry.boot(fw, filesystems, disk),
+            #[cfg(target_arch = "x86_64")]
+            EntryType::Multiboot2(entry) => {
+                let filesystem = disk.find(filesystems).ok_or(FilesystemError::NotFound)?;
+                entry.load_multiboot2(&mut fw, filesystem)?.boot(fw)
+            }
+            #[cfg(target_arch = "x86_64")]
+            EntryType::Multiboot(entry) => {
+                let filesystem = disk.find(filesystems).ok_or(FilesystemError::NotFound)?;
+                entry.load_multiboot(&mut fw, filesystem)?.boot(fw)
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            EntryType::Multiboot(_) | EntryType::Multiboot2(_) => {
+                Err(LoaderError::Unsupported.into())
+            }
+            EntryType::Submenu(_) => Err(LoaderError::Unsupported.into()),
+            EntryType::Lsblk => {
+                fw.print(&lsblk(&mut fw.get_block_devices()?));
+                fw.init_input()?.wait_key();
+                Ok(())
+            }
+        };
+    }
+}
+
+/// EFI application started through the firmware, such as the Windows boot manager or the UEFI
+/// shell.
+#[derive(Deserialize)]
+struct EfiChainloadEntry {
+    /// Volume the application is on, the boot disk by default.
+    disk: Option<Disk>,
+    /// e.g. "/EFI/Microsoft/Boot/bootmgfw.efi".
+    path: String,
+    /// Command line passed to the application as its load options.
+    options: Option<String>,
+}
+
+impl EfiChainloadEntry {
+    /// Start the application, returning once it exits to go back to the menu.
+    fn boot<F: Firmware>(
+        &self,
+        fw: &mut F,
+        filesystems: &mut FilesystemsList,
+        disk: &Disk,
+    ) -> Result<(), RrubError> {
+        let filesystem = self
+            .disk
+            .as_ref()
+            .unwrap_or(disk)
+            .find(filesystems)
+            .ok_or(FilesystemError::NotFound)?;
+        let image = filesystem.read_file(&self.path)?;
+
+        return fw.start_image(
+            &image,
+            Some((filesystem, &self.path)),
+            self.options.as_deref().unwrap_or_default(),
+            &[],
+            false,
+        );
+    }
+}
+
+/// Kernel booted through Multiboot, such as Xen or a hobby kernel, with its modules loaded after
+/// it.
+#[derive(Deserialize)]
+struct MultibootEntry {
+    kernel: String,
+    cmdline: Option<String>,
+    /// In the order the kernel gets them, e.g. the dom0 kernel and then its initrd for Xen.
+    modules: Option<Vec<Module>>,
+}
+
+#[derive(Deserialize)]
+struct Module {
+    path: String,
+    /// Passed without the path, which Xen expects a placeholder for in its own modules.
+    cmdline: Option<String>,
+}
+
+/// Either version's loader, taking the kernel, the modules with their command lines and the
+/// kernel's command line.
+#[cfg(target_arch = "x86_64")]
+type MultibootLoad<F, K> = fn(&mut F, &[u8], &[(&[u8], &str)], &str) -> Result<K, RrubError>;
+
+#[cfg(target_arch = "x86_64")]
+impl MultibootEntry {
+    /// Load the kernel and modules from `filesystem` with `load`, to boot once the firmware is
+    /// left.
+    fn load<F: Firmware, K>(
+        &self,
+        fw: &mut F,
+        filesystem: &mut Filesystem,
+        load: MultibootLoad<F, K>,
+    ) -> Result<K, RrubError> {
+        let kernel = filesystem.read_file(&self.kernel)?;
+        let mut modules = Vec::new();
+        for module in self.modules.iter().flatten() {
+            modules.push((
+                filesystem.read_file(&module.path)?,
+                module.cmdline.as_deref().unwrap_or_default(),
+            ));
+        }
+        let modules: Vec<(&[u8], &str)> = modules
+            .iter()
+            .map(|(data, cmdline)| (data.as_slice(), *cmdline))
+            .collect();
+
+        return load(
+            fw,
+            &kernel,
+            &modules,
+            self.cmdline.as_deref().unwrap_or_default(),
+        );
+    }
+
+    fn load_multiboot<F: Firmware>(
+        &self,
+        fw: &mut F,
+        filesystem: &mut Filesystem,
+    ) -> Result<MultibootKernel, RrubError> {
+        return self.load(fw, filesystem, MultibootKernel::load);
+    }
+
+    fn load_multiboot2<F: Firmware>(
+        &self,
+        fw: &mut F,
+        filesystem: &mut Filesystem,
+    ) -> Result<Multiboot2Kernel, RrubError> {
+        return self.load(fw, filesystem, Multiboot2Kernel::load);
+    }
+}
+
+#[derive(Clone, Deserialize)]
+struct LinuxEntry {
+    kernel: String,
+    /// One initrd, or several concatenated in order, e.g. CPU microcode before the initramfs.
+    initrd: Option<Paths>,
+    cmdline: Option<String>,
+    /// Subvolume the kernel and initrd paths are relative to, a btrfs subvolume by path or numeric
+    /// ID, or a ZFS dataset or snapshot by its full name.
+    subvol: Option<String>,
+    /// Disk or ISO image on the boot disk that holds the kernel and initrd, which are then looked up
+    /// on the filesystems inside it.
+    iso: Option<String>,
+    /// SquashFS or EROFS image on the boot disk, or inside `iso`, that holds the kernel and initrd.
+    image: Option<String>,
+    /// How to enter the kernel, by default through the EFI handover protocol when it has one.
+    method: Option<BootMethod>,
+    /// CPU microcode to load before the initrds.
+    microcode: Option<Microcode>,
+    /// Archive generated at boot to load after the initrds.
+    overlay: Option<Box<Overlay>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Microcode {
+    /// Whatever the boot filesystem has for the CPU found through CPUID, so one entry works on
+    /// both Intel and AMD machines.
+    Auto,
+}
+
+/// A single path, or a list of them.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum Paths {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Paths {
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        let paths = match self {
+            Paths::One(path) => core::slice::from_ref(path),
+            Paths::Many(paths) => paths.as_slice(),
+        };
+        return paths.iter().map(String::as_str);
+    }
+}
+
+impl LinuxEntry {
+    /// Kernel command line, with the path of the image added for the initramfs unless the entry
+    /// already passes it.
+    fn cmdline(&self) -> String {
+        let mut cmdline = self.cmdline.clone().unwrap_or_default();
+        let Some(iso) = &self.iso else {
+            return cmdline;
+        };
+
+        let value = match iso.contains(char::is_whitespace) {
+            true => format!("\"{}\"", iso),
+            false => iso.clone(),
+        };
+        for key in ISO_CMDLINE_KEYS {
+            let present = cmdline.split_whitespace().any(|argument| {
+                argument
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name == key)
+            });
+            if present {
+                continue;
+            }
+            if !cmdline.is_empty() {
+                cmdline.push(' ');
+            }
+            cmdline.push_str(&format!("{}={}", key, value));
+        }
+
+        return cmdline;
+    }
+
+    /// Boot the kernel from the boot disk by `method`, only returning if it fails to load or its
+    /// EFI stub exits.
+    fn boot<F: Firmware>(
+        &self,
+        mut fw: F,
+        filesystems: &mut FilesystemsList,
+        disk: &Disk,
+    ) -> Result<(), RrubError> {
+        let filesystem = disk.find(filesystems).ok_or(FilesystemError::NotFound)?;
+        // Also undoes the subvolume an entry booted before left selected.
+        filesystem.set_subvolume(self.subvol.as_deref())?;
+
+        let images: Vec<&str> = self
+            .iso
+            .iter()
+            .chain(&self.image)
+            .map(String::as_str)
+            .collect();
+        let (kernel, initrds) = match images.is_empty() {
+            true => self.read(&fw, filesystem)?,
+            false => {
+                let uuid = filesystem.uuid();
+                let filesystem = filesystems.remove(&uuid).ok_or(FilesystemError::NotFound)?;
+                let filesystem = Rc::new(RefCell::new(filesystem));
+                let result = self.read_from_images(&fw, filesystem.clone(), &images);
+                // Nothing mounted from the images is left, the disk goes back for other entries.
+                if let Ok(filesystem) = Rc::try_unwrap(filesystem) {
+                    filesystems.push(filesystem.into_inner());
+                }
+                result?
+            }
+        };
+        let initrds: Vec<&[u8]> = initrds.iter().map(Vec::as_slice).collect();
+        let cmdline = self.cmdline();
+
+        match self.method {
+            Some(BootMethod::EfiStub) => {}
+            #[cfg(target_arch = "x86_64")]
+            _ => LinuxKernel::load(&mut fw, &kernel, &initrds, &cmdline, self.method)?.boot(fw),
+            // Kernels on other architectures are only started through their EFI stub.
+            #[cfg(not(target_arch = "x86_64"))]
+            None => {}
+            #[cfg(not(target_arch = "x86_64"))]
+            Some(_) => return Err(LoaderError::Unsupported.into()),
+        }
+        return efi::boot(&mut fw, &kernel, &initrds, &cmdline);
+    }
+
+    /// The kernel and initrds read from the filesystem inside the first of `images` that holds
+    /// the next image, or the kernel after the last one. Each image is stored on the filesystem
+    /// before it, the first on `filesystem`.
+    fn read_from_images<F: Firmware>(
+        &self,
+        fw: &F,
+        filesystem: Rc<RefCell<Filesystem>>,
+        images: &[&str],
+    ) -> Result<(Vec<u8>, Vec<Vec<u8>>), RrubError> {
+        let Some((image, rest)) = images.split_first() else {
+            return self.read(fw, &mut filesystem.borrow_mut());
+        };
+
+        let next = rest.first().copied().unwrap_or(&self.kernel);
+        let inner = fs::mount_image(filesystem, image)?
+            .into_iter()
+            .find_map(|mut inner| inner.metadata(next).is_ok().then_some(inner))
+            .ok_or(FilesystemError::NotFound)?;
+        return self.read_from_images(fw, Rc::new(RefCell::new(inner)), rest);
+    }
+
+    fn read<F: Firmware>(
+        &self,
+        fw: &F,
+        filesystem: &mut Filesystem,
+    ) -> Result<(Vec<u8>, Vec<Vec<u8>>), RrubError> {
+        let kernel = filesystem.read_file(&self.kernel)?;
+        return Ok((kernel, self.initrds(fw, filesystem)?));
+    }
+
+    /// Contents of each initrd in the order they are concatenated, microcode first and the
+    /// overlay last.
+    fn initrds<F: Firmware>(
+        &self,
+        fw: &F,
+        filesystem: &mut Filesystem,
+    ) -> Result<Vec<Vec<u8>>, RrubError> {
+        let mut initrds = Vec::new();
+        if self.microcode == Some(Microcode::Auto)
+            && let Some(microcode) = microcode::load(filesystem, &self.kernel)?
+        {
+            initrds.push(microcode);
+        }
+
+        for path in self.initrd.iter().flat_map(Paths::iter) {
+            initrds.push(filesystem.read_file(path)?);
+        }
+        if let Some(overlay) = &self.overlay {
+            initrds.push(overlay.archive(fw, filesystem)?);
+        }
+        return Ok(initrds);
+    }
+}
+
+/// Files put together at boot into a cpio archive, which the kernel unpacks over the initramfs.
+/// Per-machine configuration and secrets get to it without rebuilding the image.
+#[derive(Clone, Deserialize)]
+struct Overlay {
+    /// Directory of `*.cred` files passed as systemd credentials, as systemd-stub does for
+    /// `/loader/credentials` on the ESP. Skipped if it doesn't exist.
+    credentials: Option<String>,
+    /// Files copied in by source and destination path, e.g. a LUKS keyfile from the volume
+    /// unlocked to boot from. Only root can read them.
+    files: Option<Vec<(String, String)>>,
+    /// Kernel arguments for the initramfs to read from `/etc/cmdline.d`, kept out of
+    /// `/proc/cmdline`.
+    cmdline: Option<String>,
+    /// Path to write a seed from the firmware's random number generator to.
+    random_seed: Option<String>,
 }
 
+impl Overlay {
+    fn archive<F: Firmware>(
+        &self,
+        fw: &F,
+        filesystem: &mut Filesystem,
+    ) -> Result<Vec<u8>, RrubError> {
+        let mut archive = CpioWriter::new();
+
+        if let Some(directory) = &self.credentials {
+            match filesystem.read_dir(directory) {
+                Ok(mut entries) => {
+                    entries.sort_by(|a, b| a.name.cmp(&b.name));
+                    archive.directory(CREDENTIALS_DIRECTORY, 0o500);
+                    for entry in entries {
+                        if entry.file_type != FileType::Regular
+                            || !entry.name.ends_with(CREDENTIAL_EXTENSION)
+                        {
+                            continue;
+                        }
+                        let path = format!("{}/{}", directory.trim_end_matches('/'), entry.name);
+                        let destination = format!("{}/{}", CREDENTIALS_DIRECTORY, entry.name);
+                        archive.file(&destination, 0o400, &filesystem.read_file(&path)?);
+                    }
+                }
+                Err(e) => debug!("Skipping credentials in {}: {:?}", directory, e),
+            }
+        }
+
+        for (source, destination) in self.files.iter().flatten() {
+            archive.file(destination, 0o400, &filesystem.read_file(source)?);
+        }
+
+        if let Some(cmdline) = &self.cmdline {
+            archive.file(CMDLINE_D_FILE, 0o644, format!("{}\n", cmdline).as_bytes());
+        }
+
+        if let Some(path) = &self.random_seed {
+            let mut seed = [0; RANDOM_SEED_SIZE];
+            fw.random(&mut seed)?;
+            archive.file(path, 0o600, &seed);
+            crypto::zeroize(&mut seed);
+        }
+
+        return Ok(archive.finish());
+    }
+}
+
+/// Datasets directly below `root`, e.g. "rpool/ROOT", that each hold a system, booted with the
+/// dataset as the root filesystem.
 #[derive(Deserialize)]
-struct EfiChainloadEntry {}
+struct BootEnvironments {
+    root: String,
+    /// Paths inside each dataset, `/boot/vmlinuz` and `/boot/initrd.img` by default.
+    kernel: Option<String>,
+    initrd: Option<String>,
+    /// Added after the `root=ZFS=` argument naming the dataset.
+    cmdline: Option<String>,
+}
+
+impl BootEnvironments {
+    /// An entry for each boot environment with a kernel, followed by one for each of its
+    /// snapshots. The initramfs boots snapshots from a clone, leaving them untouched.
+    fn entries(&self, filesystem: &mut Filesystem) -> Result<Vec<(String, LinuxEntry)>, RrubError> {
+        let kernel = self.kernel.as_deref().unwrap_or(DEFAULT_KERNEL);
+
+        let mut entries = Vec::new();
+        for dataset in filesystem.subvolumes(&self.root)? {
+            // Snapshots of the root itself hold no system.
+            if dataset.contains('@') {
+                continue;
+            }
 
+            let mut datasets = vec![dataset.clone()];
+            datasets.extend(
+                filesystem
+                    .subvolumes(&dataset)?
+                    .into_iter()
+                    .filter(|name| name.contains('@')),
+            );
+
+            for dataset in datasets {
+                filesystem.set_subvolume(Some(&dataset))?;
+                let bootable = filesystem.metadata(kernel).is_ok();
+                filesystem.set_subvolume(None)?;
+                if !bootable {
+                    continue;
+                }
+
+                let name = dataset
+                    .strip_prefix(self.root.as_str())
+                    .unwrap_or(&dataset)
+                    .trim_start_matches('/');
+                entries.push((String::from(name), self.entry(&dataset, kernel)));
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    fn entry(&self, dataset: &str, kernel: &str) -> LinuxEntry {
+        let mut cmdline = format!("root=ZFS={}", dataset);
+        if let Some(extra) = &self.cmdline {
+            cmdline.push(' ');
+            cmdline.push_str(extra);
+        }
+
+        return LinuxEntry {
+            kernel: String::from(kernel),
+            initrd: Some(Paths::One(
+                self.initrd
+                    .clone()
+                    .unwrap_or_else(|| String::from(DEFAULT_INITRD)),
+            )),
+            cmdline: Some(cmdline),
+            subvol: Some(String::from(dataset)),
+            iso: None,
+            image: None,
+            method: None,
+            microcode: None,
+            overlay: None,
+        };
+    }
+}
+
+/// Snapper or timeshift snapshots of a btrfs root, each booted with its own kernel and initrd like
+/// grub-btrfs does.
 #[derive(Deserialize)]
-struct LinuxEntry {}
+struct Snapshots {
+    /// Name of the Linux entry to boot the snapshots with, its `subvol` being the snapshotted
+    /// root.
+    entry: String,
+    /// Directory holding the snapshots, from the top level subvolume, e.g. "@/.snapshots" or
+    /// "@snapshots" for snapper and "timeshift-btrfs/snapshots" for timeshift.
+    path: String,
+}
+
+impl Snapshots {
+    /// An entry for each snapshot with the kernel of `base`, newest first.
+    fn entries(
+        &self,
+        base: &LinuxEntry,
+        filesystem: &mut Filesystem,
+    ) -> Result<Vec<(String, EntryType)>, RrubError> {
+        let path = self.path.trim_matches('/');
+        // Timeshift snapshots every subvolume it manages side by side, e.g. "@" and "@home".
+        let root = base.subvol.as_deref().unwrap_or("@").trim_matches('/');
+
+        filesystem.set_subvolume(None)?;
+        let mut snapshots = Vec::new();
+        for directory in filesystem.read_dir(&format!("/{}", path))? {
+            if directory.file_type != FileType::Directory {
+                continue;
+            }
+            let directory = format!("{}/{}", path, directory.name);
+            let snapshot = match filesystem.read_file(&format!("/{}/info.xml", directory)) {
+                Ok(info) => snapper_snapshot(&directory, &info),
+                Err(_) => timeshift_snapshot(filesystem, &directory, root),
+            };
+            snapshots.extend(snapshot);
+        }
+        snapshots.sort_by(|a, b| b.order.cmp(&a.order));
+
+        let mut entries = Vec::new();
+        for snapshot in snapshots {
+            filesystem.set_subvolume(Some(&snapshot.subvol))?;
+            let bootable = filesystem.metadata(&base.kernel).is_ok();
+            filesystem.set_subvolume(None)?;
+            if !bootable {
+                continue;
+            }
+
+            let mut entry = base.clone();
+            entry.cmdline = Some(rootflags_subvol(
+                base.cmdline.as_deref().unwrap_or_default(),
+                &snapshot.subvol,
+            ));
+            entry.subvol = Some(snapshot.subvol);
+            entries.push((snapshot.title, EntryType::Linux(entry)));
+        }
+
+        return Ok(entries);
+    }
+}
+
+struct Snapshot {
+    subvol: String,
+    title: String,
+    /// Snapper numbers and timeshift dates both grow with time.
+    order: (u64, String),
+}
+
+/// Snapper keeps each snapshot in a numbered directory, the subvolume next to its description.
+fn snapper_snapshot(directory: &str, info: &[u8]) -> Option<Snapshot> {
+    let info = core::str::from_utf8(info).ok()?;
+    let number = xml_element(info, "num")?;
+    let date = xml_element(info, "date").unwrap_or_default();
+    let description = xml_element(info, "description").unwrap_or_default();
+
+    let title = format!("#{} {} {}", number, date, description);
+    return Some(Snapshot {
+        subvol: format!("{}/snapshot", directory),
+        title: String::from(title.trim_end()),
+        order: (number.parse().ok()?, date),
+    });
+}
+
+/// Timeshift names each snapshot directory after its date, e.g. "2024-05-01_10-00-00".
+fn timeshift_snapshot(
+    filesystem: &mut Filesystem,
+    directory: &str,
+    root: &str,
+) -> Option<Snapshot> {
+    let info = filesystem
+        .read_file(&format!("/{}/info.json", directory))
+        .ok()?;
+    let info = core::str::from_utf8(&info).ok()?;
+    let name = directory.rsplit('/').next()?;
+
+    let mut title = match name.split_once('_') {
+        Some((date, time)) => format!("{} {}", date, time.replace('-', ":")),
+        None => String::from(name),
+    };
+    if let Some(comments) = json_string(info, "comments").filter(|c| !c.is_empty()) {
+        title.push(' ');
+        title.push_str(&comments);
+    }
+
+    return Some(Snapshot {
+        subvol: format!("{}/{}", directory, root),
+        title,
+        order: (0, String::from(name)),
+    });
+}
+
+/// Text of the first `<name>` element, with the predefined entities replaced.
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let length = xml[start..].find('<')?;
+    let text = xml[start..start + length]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    return Some(text);
+}
+
+/// Value of a top level string member of a flat JSON object.
+fn json_string(json: &str, key: &str) -> Option<String> {
+    let after_key = &json[json.find(&format!("\"{}\"", key))? + key.len() + 2..];
+    let value = after_key.trim_start().strip_prefix(':')?.trim_start();
+    let mut characters = value.strip_prefix('"')?.chars();
+
+    let mut text = String::new();
+    loop {
+        match characters.next()? {
+            '"' => return Some(text),
+            '\\' => match characters.next()? {
+                'n' => text.push('\n'),
+                't' => text.push('\t'),
+                other => text.push(other),
+            },
+            character => text.push(character),
+        }
+    }
+}
+
+/// Point `rootflags` at `subvol`, keeping the other mount options, so the initramfs mounts the
+/// snapshot as root.
+fn rootflags_subvol(cmdline: &str, subvol: &str) -> String {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut found = false;
+    for argument in cmdline.split_whitespace() {
+        let Some(flags) = argument.strip_prefix("rootflags=") else {
+            arguments.push(String::from(argument));
+            continue;
+        };
+
+        // A leftover subvolid would win over the path.
+        let mut options = vec![format!("subvol={}", subvol)];
+        options.extend(
+            flags
+                .split(',')
+                .filter(|option| !option.starts_with("subvol=") && !option.starts_with("subvolid="))
+                .map(String::from),
+        );
+        arguments.push(format!("rootflags={}", options.join(",")));
+        found = true;
+    }
+    if !found {
+        arguments.push(format!("rootflags=subvol={}", subvol));
+    }
+
+    return arguments.join(" ");
+}